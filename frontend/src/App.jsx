@@ -29,6 +29,25 @@ const darkTheme = createTheme({
 
 const COLORS = ["#8884d8", "#82ca9d", "#ffc658", "#ff8042", "#00C49F", "#FFBB28", "#d72660", "#3f88c5", "#f49d37", "#140f2d"];
 
+const TOKEN_KEY = "apiToken";
+
+// Calls the API with the bearer token from local storage (or VITE_API_TOKEN); on a 401 asks
+// for a token once and retries
+async function apiFetch(url, options = {}) {
+  const send = (token) =>
+    fetch(url, {
+      ...options,
+      headers: { ...(options.headers || {}), ...(token ? { Authorization: `Bearer ${token}` } : {}) },
+    });
+  const token = localStorage.getItem(TOKEN_KEY) || import.meta.env.VITE_API_TOKEN;
+  const res = await send(token);
+  if (res.status !== 401) return res;
+  const entered = window.prompt("API token (from /api/auth/login or /api/auth/tokens):");
+  if (!entered) return res;
+  localStorage.setItem(TOKEN_KEY, entered.trim());
+  return send(entered.trim());
+}
+
 // Helper for formatting numbers as 999,999.99
 function formatNumber(n) {
   if (typeof n !== "number" || isNaN(n)) return "-";
@@ -133,7 +152,9 @@ function App() {
   const fetchAllocations = async () => {
     setLoading(true);
     try {
-      const res = await fetch("http://localhost:3001/api/allocations");
+      // POST also records the snapshot; viewers may only read
+      let res = await apiFetch("http://localhost:3001/api/allocations", { method: "POST" });
+      if (res.status === 403) res = await apiFetch("http://localhost:3001/api/allocations");
       if (!res.ok) throw new Error("Network response was not ok");
       const data = await res.json();
       setAllocations(data.per_asset || []);
@@ -147,21 +168,27 @@ function App() {
     setLoading(false);
   };
 
-  const handleImportWallets = async () => {
+  const handleImportWallets = async (event) => {
+    const file = event.target.files?.[0];
+    event.target.value = "";
+    if (!file) return;
     setImporting(true);
     setImportStatus("");
     try {
-      const res = await fetch("http://localhost:3001/api/import_wallets", {
+      const form = new FormData();
+      form.append("file", file);
+      const res = await apiFetch("http://localhost:3001/api/import_upload", {
         method: "POST",
-        headers: { "Content-Type": "application/json" },
-        body: JSON.stringify({ path: "wallet_allocations.csv" }),
+        body: form,
       });
       const data = await res.json();
       if (!res.ok) {
         throw new Error(data.error || "Import failed");
       }
-      const imported = data.imported ?? 0;
-      setImportStatus(`Imported ${imported} wallet rows from CSV`);
+      const imported = (data.results || []).filter(r => r.status === "imported").length;
+      const errors = data.errors || [];
+      const firstError = errors.length ? ` (line ${errors[0].line}: ${errors[0].error})` : "";
+      setImportStatus(`Imported ${imported} rows from ${file.name}, ${errors.length} rejected${firstError}`);
       await fetchAllocations();
     } catch (err) {
       alert("Failed to import wallet allocations: " + err.message);
@@ -257,7 +284,7 @@ function App() {
     setDashboardLoading(true);
     setDashboardError(null);
     try {
-      const res = await fetch(`http://localhost:3001/api/history?level=${level}`);
+      const res = await apiFetch(`http://localhost:3001/api/history?level=${level}`);
       if (!res.ok) {
         const txt = await res.text().catch(() => '');
         throw new Error(`Failed to fetch history: ${res.status} ${res.statusText} ${txt}`);
@@ -461,11 +488,12 @@ function App() {
         <Button
           variant="outlined"
           color="secondary"
-          onClick={handleImportWallets}
+          component="label"
           disabled={importing}
           sx={{ mb: 3 }}
         >
           {importing ? <CircularProgress size={20} /> : "Import Wallet CSV"}
+          <input type="file" accept=".csv,text/csv" hidden onChange={handleImportWallets} />
         </Button>
         {lastUpdate && (
           <Typography variant="body2" sx={{ color: "#aaa", alignSelf: "center" }}>
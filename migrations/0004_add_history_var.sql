@@ -0,0 +1,20 @@
+-- 0004_add_history_var.sql
+-- Value-at-Risk / Expected Shortfall history (one row per scope, method, confidence and horizon).
+
+CREATE TABLE IF NOT EXISTS history_var (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  timestamp TEXT NOT NULL,
+  scope TEXT NOT NULL,            -- portfolio | barca | group
+  name TEXT NOT NULL,             -- 'portfolio', barca name or group name
+  method TEXT NOT NULL,           -- historical | parametric
+  confidence REAL NOT NULL,       -- e.g. 0.95
+  horizon_days INTEGER NOT NULL,
+  position_value REAL,
+  var_value REAL,
+  es_value REAL,
+  observations INTEGER,
+  extra TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(timestamp, scope, name, method, confidence, horizon_days)
+);
+CREATE INDEX IF NOT EXISTS idx_history_var_timestamp ON history_var(timestamp);
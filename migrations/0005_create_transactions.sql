@@ -0,0 +1,24 @@
+-- 0005_create_transactions.sql
+-- Transaction ledger (buys, sells, deposits, withdrawals, transfers, fees, income).
+-- Holdings per symbol and custody location can be derived from this stream.
+
+CREATE TABLE IF NOT EXISTS transactions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  timestamp TEXT NOT NULL,
+  kind TEXT NOT NULL CHECK (kind IN ('buy', 'sell', 'deposit', 'withdraw', 'transfer', 'fee', 'income')),
+  symbol TEXT NOT NULL,
+  quantity REAL NOT NULL,          -- always positive; direction comes from kind
+  price REAL,                      -- unit price in quote_currency
+  quote_currency TEXT DEFAULT 'USD',
+  fee REAL,
+  fee_symbol TEXT,                 -- defaults to symbol when fee is set
+  venue TEXT,                      -- custody location (source location for transfers)
+  to_venue TEXT,                   -- destination location for transfers
+  group_name TEXT,                 -- optional allocation tags for symbols without a wallet row
+  barca TEXT,
+  external_id TEXT,
+  notes TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+);
+CREATE INDEX IF NOT EXISTS idx_transactions_symbol_timestamp ON transactions(symbol, timestamp);
+CREATE INDEX IF NOT EXISTS idx_transactions_timestamp ON transactions(timestamp);
@@ -0,0 +1,13 @@
+-- 0006_create_fx_rates.sql
+-- Daily conversion rates into BRL (e.g. PTAX for USD), used to value trades for the Brazilian tax report.
+
+CREATE TABLE IF NOT EXISTS fx_rates (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  date TEXT NOT NULL,              -- YYYY-MM-DD
+  currency TEXT NOT NULL,          -- USD, EUR, BTC, ...
+  brl_rate REAL NOT NULL,          -- BRL per 1 unit of currency
+  source TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(date, currency)
+);
+CREATE INDEX IF NOT EXISTS idx_fx_rates_currency_date ON fx_rates(currency, date);
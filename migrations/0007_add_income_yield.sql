@@ -0,0 +1,15 @@
+-- 0007_add_income_yield.sql
+-- Income sub-type on ledger rows and expected yield per position.
+
+ALTER TABLE transactions ADD COLUMN income_type TEXT; -- staking | lending | airdrop | other (kind = 'income')
+
+CREATE TABLE IF NOT EXISTS yield_positions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  symbol TEXT NOT NULL,
+  venue TEXT NOT NULL DEFAULT '',  -- '' = the symbol across every venue
+  barca TEXT,                      -- defaults to the symbol's wallet allocation barca
+  expected_apy REAL NOT NULL,      -- percent per year
+  notes TEXT,
+  updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(symbol, venue)
+);
@@ -0,0 +1,93 @@
+-- 0008_create_accounts.sql
+-- Custody accounts (exchanges, hardware/hot wallets, DeFi) referenced by ledger rows.
+-- Replaces the custody location previously encoded in wallet_allocations.notes.
+
+CREATE TABLE IF NOT EXISTS accounts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+  account_type TEXT NOT NULL DEFAULT 'other'
+    CHECK (account_type IN ('exchange', 'hardware', 'hot_wallet', 'defi', 'other')),
+  address TEXT,
+  notes TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+);
+
+ALTER TABLE wallet_allocations ADD COLUMN account_id INTEGER REFERENCES accounts(id);
+ALTER TABLE transactions ADD COLUMN account_id INTEGER REFERENCES accounts(id);
+ALTER TABLE transactions ADD COLUMN to_account_id INTEGER REFERENCES accounts(id);
+
+-- Backfill one account per distinct location name already in use
+INSERT OR IGNORE INTO accounts (name)
+  SELECT DISTINCT TRIM(notes) FROM wallet_allocations WHERE TRIM(COALESCE(notes, '')) <> '';
+INSERT OR IGNORE INTO accounts (name)
+  SELECT DISTINCT TRIM(venue) FROM transactions WHERE TRIM(COALESCE(venue, '')) <> '';
+INSERT OR IGNORE INTO accounts (name)
+  SELECT DISTINCT TRIM(to_venue) FROM transactions WHERE TRIM(COALESCE(to_venue, '')) <> '';
+
+UPDATE wallet_allocations
+  SET account_id = (SELECT a.id FROM accounts a WHERE a.name = TRIM(wallet_allocations.notes))
+  WHERE account_id IS NULL AND TRIM(COALESCE(notes, '')) <> '';
+UPDATE transactions
+  SET account_id = (SELECT a.id FROM accounts a WHERE a.name = TRIM(transactions.venue))
+  WHERE account_id IS NULL AND TRIM(COALESCE(venue, '')) <> '';
+UPDATE transactions
+  SET to_account_id = (SELECT a.id FROM accounts a WHERE a.name = TRIM(transactions.to_venue))
+  WHERE to_account_id IS NULL AND TRIM(COALESCE(to_venue, '')) <> '';
+
+CREATE INDEX IF NOT EXISTS idx_wallet_allocations_account ON wallet_allocations(account_id);
+CREATE INDEX IF NOT EXISTS idx_transactions_account ON transactions(account_id);
+
+-- Latest row per symbol/group/barca/account (rows without an account fall back to notes)
+DROP VIEW IF EXISTS wallet_allocations_current;
+DROP VIEW IF EXISTS wallet_allocations_current_by_account;
+
+CREATE VIEW wallet_allocations_current_by_account AS
+WITH ranked AS (
+    SELECT
+        id,
+        symbol,
+        group_name,
+        barca,
+        target_percent,
+        current_quantity,
+        last_price,
+        notes,
+        created_at,
+        account_id,
+        ROW_NUMBER() OVER (
+            PARTITION BY symbol, group_name, barca,
+                COALESCE('a:' || account_id, 'n:' || COALESCE(notes, ''))
+            ORDER BY created_at DESC, id DESC
+        ) AS rn
+    FROM wallet_allocations
+)
+SELECT
+    id,
+    symbol,
+    group_name,
+    barca,
+    COALESCE(target_percent, 0) AS target_percent,
+    COALESCE(current_quantity, 0) AS current_quantity,
+    last_price,
+    notes,
+    created_at,
+    account_id
+FROM ranked
+WHERE rn = 1;
+
+-- Aggregated by symbol/group/barca across accounts (account_id kept when there is only one)
+CREATE VIEW wallet_allocations_current AS
+SELECT
+    NULL AS id,
+    symbol,
+    group_name,
+    barca,
+    MAX(target_percent) AS target_percent,
+    SUM(current_quantity) AS current_quantity,
+    MAX(last_price) AS last_price,
+    GROUP_CONCAT(notes, ' | ') AS notes,
+    MAX(created_at) AS created_at,
+    CASE WHEN COUNT(account_id) = COUNT(*) AND COUNT(DISTINCT account_id) = 1
+         THEN MAX(account_id) END AS account_id
+FROM wallet_allocations_current_by_account
+GROUP BY symbol, group_name, barca;
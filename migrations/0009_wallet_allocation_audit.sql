@@ -0,0 +1,64 @@
+-- 0009_wallet_allocation_audit.sql
+-- Who appended each wallet_allocations row and why (API edits, CSV imports).
+
+ALTER TABLE wallet_allocations ADD COLUMN author TEXT;
+ALTER TABLE wallet_allocations ADD COLUMN reason TEXT;
+
+DROP VIEW IF EXISTS wallet_allocations_current;
+DROP VIEW IF EXISTS wallet_allocations_current_by_account;
+
+CREATE VIEW wallet_allocations_current_by_account AS
+WITH ranked AS (
+    SELECT
+        id,
+        symbol,
+        group_name,
+        barca,
+        target_percent,
+        current_quantity,
+        last_price,
+        notes,
+        created_at,
+        account_id,
+        author,
+        reason,
+        ROW_NUMBER() OVER (
+            PARTITION BY symbol, group_name, barca,
+                COALESCE('a:' || account_id, 'n:' || COALESCE(notes, ''))
+            ORDER BY created_at DESC, id DESC
+        ) AS rn
+    FROM wallet_allocations
+)
+SELECT
+    id,
+    symbol,
+    group_name,
+    barca,
+    COALESCE(target_percent, 0) AS target_percent,
+    COALESCE(current_quantity, 0) AS current_quantity,
+    last_price,
+    notes,
+    created_at,
+    account_id,
+    author,
+    reason
+FROM ranked
+WHERE rn = 1;
+
+CREATE VIEW wallet_allocations_current AS
+SELECT
+    NULL AS id,
+    symbol,
+    group_name,
+    barca,
+    MAX(target_percent) AS target_percent,
+    SUM(current_quantity) AS current_quantity,
+    MAX(last_price) AS last_price,
+    GROUP_CONCAT(notes, ' | ') AS notes,
+    MAX(created_at) AS created_at,
+    CASE WHEN COUNT(account_id) = COUNT(*) AND COUNT(DISTINCT account_id) = 1
+         THEN MAX(account_id) END AS account_id,
+    NULL AS author,
+    NULL AS reason
+FROM wallet_allocations_current_by_account
+GROUP BY symbol, group_name, barca;
@@ -0,0 +1,74 @@
+-- 0010_wallet_allocation_events.sql
+-- Explicit events in the append-only wallet ledger. A row whose event is a tombstone
+-- (retire, move_out, merge_out) closes its symbol/group/barca/account holding, so the
+-- holding drops out of the current views instead of lingering with a zero quantity.
+
+ALTER TABLE wallet_allocations ADD COLUMN event TEXT
+  CHECK (event IS NULL OR event IN ('import', 'add', 'update', 'retire', 'move_in', 'move_out', 'merge_in', 'merge_out'));
+
+-- Zero quantity and target was the only way to close a holding so far
+UPDATE wallet_allocations SET event = 'retire'
+  WHERE COALESCE(current_quantity, 0) = 0 AND COALESCE(target_percent, 0) = 0;
+
+DROP VIEW IF EXISTS wallet_allocations_current;
+DROP VIEW IF EXISTS wallet_allocations_current_by_account;
+
+CREATE VIEW wallet_allocations_current_by_account AS
+WITH ranked AS (
+    SELECT
+        id,
+        symbol,
+        group_name,
+        barca,
+        target_percent,
+        current_quantity,
+        last_price,
+        notes,
+        created_at,
+        account_id,
+        author,
+        reason,
+        event,
+        ROW_NUMBER() OVER (
+            PARTITION BY symbol, group_name, barca,
+                COALESCE('a:' || account_id, 'n:' || COALESCE(notes, ''))
+            ORDER BY created_at DESC, id DESC
+        ) AS rn
+    FROM wallet_allocations
+)
+SELECT
+    id,
+    symbol,
+    group_name,
+    barca,
+    COALESCE(target_percent, 0.0) AS target_percent,
+    COALESCE(current_quantity, 0.0) AS current_quantity,
+    last_price,
+    notes,
+    created_at,
+    account_id,
+    author,
+    reason,
+    event
+FROM ranked
+WHERE rn = 1
+  AND COALESCE(event, '') NOT IN ('retire', 'move_out', 'merge_out');
+
+CREATE VIEW wallet_allocations_current AS
+SELECT
+    NULL AS id,
+    symbol,
+    group_name,
+    barca,
+    MAX(target_percent) AS target_percent,
+    SUM(current_quantity) AS current_quantity,
+    MAX(last_price) AS last_price,
+    GROUP_CONCAT(notes, ' | ') AS notes,
+    MAX(created_at) AS created_at,
+    CASE WHEN COUNT(account_id) = COUNT(*) AND COUNT(DISTINCT account_id) = 1
+         THEN MAX(account_id) END AS account_id,
+    NULL AS author,
+    NULL AS reason,
+    NULL AS event
+FROM wallet_allocations_current_by_account
+GROUP BY symbol, group_name, barca;
@@ -0,0 +1,89 @@
+-- 0011_import_batches.sql
+-- Every wallet CSV import is recorded as a batch that its ledger rows reference, so the
+-- same file is not imported twice (content hash) and a batch can be rolled back.
+
+CREATE TABLE IF NOT EXISTS import_batches (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  source TEXT NOT NULL,
+  content_hash TEXT NOT NULL,
+  row_count INTEGER NOT NULL DEFAULT 0,
+  author TEXT,
+  status TEXT NOT NULL DEFAULT 'applied' CHECK (status IN ('applied', 'rolled_back')),
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  rolled_back_at TEXT,
+  rolled_back_by TEXT
+);
+
+-- A rolled back file may be imported again
+CREATE UNIQUE INDEX IF NOT EXISTS idx_import_batches_applied_hash
+  ON import_batches(content_hash) WHERE status = 'applied';
+
+ALTER TABLE wallet_allocations ADD COLUMN import_batch_id INTEGER REFERENCES import_batches(id);
+CREATE INDEX IF NOT EXISTS idx_wallet_allocations_import_batch
+  ON wallet_allocations(import_batch_id);
+
+DROP VIEW IF EXISTS wallet_allocations_current;
+DROP VIEW IF EXISTS wallet_allocations_current_by_account;
+
+CREATE VIEW wallet_allocations_current_by_account AS
+WITH ranked AS (
+    SELECT
+        id,
+        symbol,
+        group_name,
+        barca,
+        target_percent,
+        current_quantity,
+        last_price,
+        notes,
+        created_at,
+        account_id,
+        author,
+        reason,
+        event,
+        import_batch_id,
+        ROW_NUMBER() OVER (
+            PARTITION BY symbol, group_name, barca,
+                COALESCE('a:' || account_id, 'n:' || COALESCE(notes, ''))
+            ORDER BY created_at DESC, id DESC
+        ) AS rn
+    FROM wallet_allocations
+)
+SELECT
+    id,
+    symbol,
+    group_name,
+    barca,
+    COALESCE(target_percent, 0.0) AS target_percent,
+    COALESCE(current_quantity, 0.0) AS current_quantity,
+    last_price,
+    notes,
+    created_at,
+    account_id,
+    author,
+    reason,
+    event,
+    import_batch_id
+FROM ranked
+WHERE rn = 1
+  AND COALESCE(event, '') NOT IN ('retire', 'move_out', 'merge_out');
+
+CREATE VIEW wallet_allocations_current AS
+SELECT
+    NULL AS id,
+    symbol,
+    group_name,
+    barca,
+    MAX(target_percent) AS target_percent,
+    SUM(current_quantity) AS current_quantity,
+    MAX(last_price) AS last_price,
+    GROUP_CONCAT(notes, ' | ') AS notes,
+    MAX(created_at) AS created_at,
+    CASE WHEN COUNT(account_id) = COUNT(*) AND COUNT(DISTINCT account_id) = 1
+         THEN MAX(account_id) END AS account_id,
+    NULL AS author,
+    NULL AS reason,
+    NULL AS event,
+    NULL AS import_batch_id
+FROM wallet_allocations_current_by_account
+GROUP BY symbol, group_name, barca;
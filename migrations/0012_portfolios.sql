@@ -0,0 +1,291 @@
+-- 0012_portfolios.sql
+-- Several portfolios in one database. Every ledger, history and allocation row belongs to a
+-- portfolio; existing data becomes portfolio 1. Each portfolio may set its own market regime
+-- and BARCA targets (wallet_barca.csv is used while it has none).
+
+CREATE TABLE IF NOT EXISTS portfolios (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+  market TEXT,                     -- CURRENT_MARKET syntax; NULL = environment settings
+  market_glide_to TEXT,
+  market_glide_start TEXT,         -- YYYY-MM-DD
+  market_glide_days INTEGER,
+  notes TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+);
+INSERT OR IGNORE INTO portfolios (id, name) VALUES (1, 'Default');
+
+CREATE TABLE IF NOT EXISTS barca_targets (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL REFERENCES portfolios(id),
+  market TEXT NOT NULL,
+  barca TEXT NOT NULL,
+  target_percent REAL NOT NULL,
+  UNIQUE(portfolio_id, market, barca)
+);
+
+-- Append-only tables get the column (SQLite cannot add a REFERENCES column with a default)
+ALTER TABLE wallet_allocations ADD COLUMN portfolio_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE transactions ADD COLUMN portfolio_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE allocations ADD COLUMN portfolio_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE import_batches ADD COLUMN portfolio_id INTEGER NOT NULL DEFAULT 1;
+CREATE INDEX IF NOT EXISTS idx_wallet_allocations_portfolio ON wallet_allocations(portfolio_id);
+CREATE INDEX IF NOT EXISTS idx_transactions_portfolio_timestamp ON transactions(portfolio_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_allocations_portfolio ON allocations(portfolio_id);
+
+-- The same file may be imported into two portfolios
+DROP INDEX IF EXISTS idx_import_batches_applied_hash;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_import_batches_applied_hash
+  ON import_batches(portfolio_id, content_hash) WHERE status = 'applied';
+
+-- Tables whose unique keys now include the portfolio are rebuilt. Ledger rows reference
+-- accounts, so foreign keys are checked at commit, once the accounts are back.
+PRAGMA defer_foreign_keys = ON;
+
+DROP VIEW IF EXISTS asset_variance_history;
+DROP VIEW IF EXISTS barca_variance_history;
+DROP VIEW IF EXISTS group_variance_history;
+DROP VIEW IF EXISTS wallet_allocations_current;
+DROP VIEW IF EXISTS wallet_allocations_current_by_account;
+
+ALTER TABLE history_assets RENAME TO history_assets_old;
+CREATE TABLE history_assets (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  timestamp TEXT NOT NULL,
+  symbol TEXT NOT NULL,
+  group_name TEXT,
+  barca TEXT,
+  price REAL,
+  current_quantity REAL,
+  value REAL,
+  target_percent REAL,
+  current_percent REAL,
+  market_cap REAL,
+  fdv REAL,
+  volume_24h REAL,
+  percent_change_24h REAL,
+  percent_change_7d REAL,
+  extra TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, timestamp, symbol)
+);
+INSERT INTO history_assets (id, timestamp, symbol, group_name, barca, price, current_quantity, value, target_percent, current_percent, market_cap, fdv, volume_24h, percent_change_24h, percent_change_7d, extra, created_at)
+  SELECT id, timestamp, symbol, group_name, barca, price, current_quantity, value, target_percent, current_percent, market_cap, fdv, volume_24h, percent_change_24h, percent_change_7d, extra, created_at
+  FROM history_assets_old;
+DROP TABLE history_assets_old;
+CREATE INDEX IF NOT EXISTS idx_history_assets_timestamp ON history_assets(portfolio_id, timestamp);
+
+ALTER TABLE history_barca RENAME TO history_barca_old;
+CREATE TABLE history_barca (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  timestamp TEXT NOT NULL,
+  barca TEXT NOT NULL,
+  value REAL,
+  current_percent REAL,
+  target_percent REAL,
+  extra TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, timestamp, barca)
+);
+INSERT INTO history_barca (id, timestamp, barca, value, current_percent, target_percent, extra, created_at)
+  SELECT id, timestamp, barca, value, current_percent, target_percent, extra, created_at FROM history_barca_old;
+DROP TABLE history_barca_old;
+CREATE INDEX IF NOT EXISTS idx_history_barca_timestamp ON history_barca(portfolio_id, timestamp);
+
+ALTER TABLE history_groups RENAME TO history_groups_old;
+CREATE TABLE history_groups (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  timestamp TEXT NOT NULL,
+  group_name TEXT NOT NULL,
+  value REAL,
+  current_percent REAL,
+  target_percent REAL,
+  extra TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, timestamp, group_name)
+);
+INSERT INTO history_groups (id, timestamp, group_name, value, current_percent, target_percent, extra, created_at)
+  SELECT id, timestamp, group_name, value, current_percent, target_percent, extra, created_at FROM history_groups_old;
+DROP TABLE history_groups_old;
+CREATE INDEX IF NOT EXISTS idx_history_groups_timestamp ON history_groups(portfolio_id, timestamp);
+
+ALTER TABLE history_totals RENAME TO history_totals_old;
+CREATE TABLE history_totals (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  timestamp TEXT NOT NULL,
+  total_value REAL,
+  extra TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, timestamp)
+);
+INSERT INTO history_totals (id, timestamp, total_value, extra, created_at)
+  SELECT id, timestamp, total_value, extra, created_at FROM history_totals_old;
+DROP TABLE history_totals_old;
+CREATE INDEX IF NOT EXISTS idx_history_totals_timestamp ON history_totals(portfolio_id, timestamp);
+
+ALTER TABLE history_var RENAME TO history_var_old;
+CREATE TABLE history_var (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  timestamp TEXT NOT NULL,
+  scope TEXT NOT NULL,            -- portfolio | barca | group
+  name TEXT NOT NULL,             -- 'portfolio', barca name or group name
+  method TEXT NOT NULL,           -- historical | parametric
+  confidence REAL NOT NULL,       -- e.g. 0.95
+  horizon_days INTEGER NOT NULL,
+  position_value REAL,
+  var_value REAL,
+  es_value REAL,
+  observations INTEGER,
+  extra TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, timestamp, scope, name, method, confidence, horizon_days)
+);
+INSERT INTO history_var (id, timestamp, scope, name, method, confidence, horizon_days, position_value, var_value, es_value, observations, extra, created_at)
+  SELECT id, timestamp, scope, name, method, confidence, horizon_days, position_value, var_value, es_value, observations, extra, created_at FROM history_var_old;
+DROP TABLE history_var_old;
+CREATE INDEX IF NOT EXISTS idx_history_var_timestamp ON history_var(portfolio_id, timestamp);
+
+ALTER TABLE yield_positions RENAME TO yield_positions_old;
+CREATE TABLE yield_positions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  symbol TEXT NOT NULL,
+  venue TEXT NOT NULL DEFAULT '',  -- '' = the symbol across every venue
+  barca TEXT,                      -- defaults to the symbol's wallet allocation barca
+  expected_apy REAL NOT NULL,      -- percent per year
+  notes TEXT,
+  updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, symbol, venue)
+);
+INSERT INTO yield_positions (id, symbol, venue, barca, expected_apy, notes, updated_at)
+  SELECT id, symbol, venue, barca, expected_apy, notes, updated_at FROM yield_positions_old;
+DROP TABLE yield_positions_old;
+
+-- Copied aside and recreated under the same name so the ledger's REFERENCES accounts(id)
+-- keep pointing at it
+CREATE TABLE accounts_old AS SELECT * FROM accounts;
+DROP TABLE accounts;
+CREATE TABLE accounts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL DEFAULT 1 REFERENCES portfolios(id),
+  name TEXT NOT NULL COLLATE NOCASE,
+  account_type TEXT NOT NULL DEFAULT 'other'
+    CHECK (account_type IN ('exchange', 'hardware', 'hot_wallet', 'defi', 'other')),
+  address TEXT,
+  notes TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(portfolio_id, name)
+);
+INSERT INTO accounts (id, name, account_type, address, notes, created_at)
+  SELECT id, name, account_type, address, notes, created_at FROM accounts_old;
+DROP TABLE accounts_old;
+
+CREATE VIEW asset_variance_history AS
+SELECT
+  ha.portfolio_id,
+  ha.timestamp,
+  ha.symbol,
+  ha.group_name,
+  ha.barca,
+  ha.price,
+  ha.current_quantity,
+  ha.value,
+  ha.target_percent,
+  ha.current_percent,
+  (ha.current_percent - ha.target_percent) AS deviation_percent,
+  (ha.value - (COALESCE(ht.total_value, 0) * COALESCE(ha.target_percent, 0) / 100.0)) AS value_deviation
+FROM history_assets ha
+LEFT JOIN history_totals ht ON ht.portfolio_id = ha.portfolio_id AND ht.timestamp = ha.timestamp;
+
+CREATE VIEW barca_variance_history AS
+SELECT
+  hb.portfolio_id,
+  hb.timestamp,
+  hb.barca,
+  hb.value,
+  hb.current_percent,
+  hb.target_percent,
+  (hb.current_percent - hb.target_percent) AS deviation_percent
+FROM history_barca hb;
+
+CREATE VIEW group_variance_history AS
+SELECT
+  hg.portfolio_id,
+  hg.timestamp,
+  hg.group_name,
+  hg.value,
+  hg.current_percent,
+  hg.target_percent,
+  (hg.current_percent - hg.target_percent) AS deviation_percent
+FROM history_groups hg;
+
+CREATE VIEW wallet_allocations_current_by_account AS
+WITH ranked AS (
+    SELECT
+        id,
+        portfolio_id,
+        symbol,
+        group_name,
+        barca,
+        target_percent,
+        current_quantity,
+        last_price,
+        notes,
+        created_at,
+        account_id,
+        author,
+        reason,
+        event,
+        import_batch_id,
+        ROW_NUMBER() OVER (
+            PARTITION BY portfolio_id, symbol, group_name, barca,
+                COALESCE('a:' || account_id, 'n:' || COALESCE(notes, ''))
+            ORDER BY created_at DESC, id DESC
+        ) AS rn
+    FROM wallet_allocations
+)
+SELECT
+    id,
+    portfolio_id,
+    symbol,
+    group_name,
+    barca,
+    COALESCE(target_percent, 0.0) AS target_percent,
+    COALESCE(current_quantity, 0.0) AS current_quantity,
+    last_price,
+    notes,
+    created_at,
+    account_id,
+    author,
+    reason,
+    event,
+    import_batch_id
+FROM ranked
+WHERE rn = 1
+  AND COALESCE(event, '') NOT IN ('retire', 'move_out', 'merge_out');
+
+CREATE VIEW wallet_allocations_current AS
+SELECT
+    NULL AS id,
+    portfolio_id,
+    symbol,
+    group_name,
+    barca,
+    MAX(target_percent) AS target_percent,
+    SUM(current_quantity) AS current_quantity,
+    MAX(last_price) AS last_price,
+    GROUP_CONCAT(notes, ' | ') AS notes,
+    MAX(created_at) AS created_at,
+    CASE WHEN COUNT(account_id) = COUNT(*) AND COUNT(DISTINCT account_id) = 1
+         THEN MAX(account_id) END AS account_id,
+    NULL AS author,
+    NULL AS reason,
+    NULL AS event,
+    NULL AS import_batch_id
+FROM wallet_allocations_current_by_account
+GROUP BY portfolio_id, symbol, group_name, barca;
@@ -0,0 +1,48 @@
+-- 0013_households.sql
+-- Households combine the current holdings of selected portfolios into one view, valued with
+-- a single quote set and measured against their own BARCA targets and market regime.
+
+CREATE TABLE IF NOT EXISTS households (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+  market TEXT,                     -- CURRENT_MARKET syntax; NULL = environment settings
+  market_glide_to TEXT,
+  market_glide_start TEXT,         -- YYYY-MM-DD
+  market_glide_days INTEGER,
+  notes TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+);
+
+-- Deleting a portfolio takes it out of its households
+CREATE TABLE IF NOT EXISTS household_members (
+  household_id INTEGER NOT NULL REFERENCES households(id) ON DELETE CASCADE,
+  portfolio_id INTEGER NOT NULL REFERENCES portfolios(id) ON DELETE CASCADE,
+  PRIMARY KEY (household_id, portfolio_id)
+);
+
+CREATE TABLE IF NOT EXISTS household_targets (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  household_id INTEGER NOT NULL REFERENCES households(id) ON DELETE CASCADE,
+  market TEXT NOT NULL,
+  barca TEXT NOT NULL,
+  target_percent REAL NOT NULL,
+  UNIQUE(household_id, market, barca)
+);
+
+-- One row per household, computation time, level and BARCA/group ('' for totals);
+-- `contributions` is a JSON array with the value of each member portfolio
+CREATE TABLE IF NOT EXISTS household_history (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  household_id INTEGER NOT NULL REFERENCES households(id) ON DELETE CASCADE,
+  timestamp TEXT NOT NULL,
+  level TEXT NOT NULL CHECK (level IN ('totals', 'barca', 'groups')),
+  name TEXT NOT NULL DEFAULT '',
+  value REAL,
+  current_percent REAL,
+  target_percent REAL,
+  contributions TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+  UNIQUE(household_id, timestamp, level, name)
+);
+CREATE INDEX IF NOT EXISTS idx_household_history_level_timestamp
+  ON household_history(household_id, level, timestamp);
@@ -0,0 +1,22 @@
+-- 0015_share_links.sql
+-- Read-only share links for a portfolio's allocation and history. The token handed out is
+-- signed with the server secret and carries its own expiry; the row lets the owner list and
+-- revoke it. `app_secrets` keeps the generated signing key when SHARE_LINK_SECRET is unset.
+
+CREATE TABLE IF NOT EXISTS share_links (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  portfolio_id INTEGER NOT NULL REFERENCES portfolios(id) ON DELETE CASCADE,
+  label TEXT,
+  masked INTEGER NOT NULL DEFAULT 1,   -- 1 = percentages and deviations only
+  expires_at TEXT NOT NULL,
+  created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+  last_used_at TEXT,
+  revoked_at TEXT,
+  created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now'))
+);
+CREATE INDEX IF NOT EXISTS idx_share_links_portfolio ON share_links(portfolio_id);
+
+CREATE TABLE IF NOT EXISTS app_secrets (
+  name TEXT PRIMARY KEY,
+  value TEXT NOT NULL
+);
@@ -0,0 +1,170 @@
+// Export current holdings, BARCA targets or the last allocations report in the layouts the
+// importers read back:
+//   cargo run --bin export_wallet -- holdings --format xlsx --out wallet_allocations.xlsx
+//   cargo run --bin export_wallet -- targets --delimiter semicolon --decimal comma
+//   cargo run --bin export_wallet -- report --level group --format json
+use anyhow::{Result, anyhow, bail};
+use dotenv::dotenv;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::env;
+use std::io::Write;
+
+#[path = "../csv_dialect.rs"]
+mod csv_dialect;
+// The HTTP response helpers (content types) are only used by the server
+#[allow(dead_code)]
+#[path = "../wallet_files.rs"]
+mod wallet_files;
+
+use csv_dialect::CsvDialect;
+use wallet_files::{
+    FileFormat, Holding, Table, holdings_table, read_targets, render, report_table, targets_table,
+};
+
+const USAGE: &str = "usage: export_wallet <holdings|targets|report> [--format csv|json|xlsx] \
+[--level asset|group|barca] [--delimiter C] [--decimal , or .] [--out FILE]";
+
+struct Options {
+    dataset: String,
+    format: FileFormat,
+    level: String,
+    dialect: CsvDialect,
+    out: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let dataset = args.next().ok_or_else(|| anyhow!(USAGE))?;
+    let mut flags: HashMap<String, String> = HashMap::new();
+    while let Some(flag) = args.next() {
+        let name = flag
+            .strip_prefix("--")
+            .ok_or_else(|| anyhow!("unexpected argument '{}'\n{}", flag, USAGE))?;
+        let value = args
+            .next()
+            .ok_or_else(|| anyhow!("--{} needs a value", name))?;
+        flags.insert(name.to_string(), value);
+    }
+    let flag = |name: &str| flags.get(name).map(String::as_str);
+    Ok(Options {
+        dataset,
+        format: FileFormat::parse(flag("format").unwrap_or("csv")).map_err(|e| anyhow!(e))?,
+        level: flag("level").unwrap_or("asset").to_string(),
+        dialect: CsvDialect::default()
+            .with_overrides(flag("delimiter"), flag("decimal"), None)
+            .map_err(|e| anyhow!(e))?,
+        out: flag("out").map(str::to_string),
+    })
+}
+
+type HoldingRow = (
+    String,
+    Option<String>,
+    Option<String>,
+    Option<f64>,
+    Option<f64>,
+    Option<f64>,
+    Option<String>,
+    Option<String>,
+);
+
+// PORTFOLIO_ID picks the portfolio (default 1, the one that owns pre-portfolio data)
+fn portfolio_id() -> Result<i64> {
+    match env::var("PORTFOLIO_ID") {
+        Ok(v) if !v.trim().is_empty() => v
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("invalid PORTFOLIO_ID '{}'", v)),
+        _ => Ok(1),
+    }
+}
+
+async fn table(opts: &Options) -> Result<Table> {
+    let db_url = env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://data/crypto.db".to_string());
+    let pool = SqlitePool::connect(&db_url).await?;
+    let portfolio = portfolio_id()?;
+    match opts.dataset.as_str() {
+        "targets" => {
+            // The portfolio's own targets, or wallet_barca.csv while it has none
+            let mut rows: Vec<(String, String, f64)> = sqlx::query_as(
+                "SELECT market, barca, target_percent FROM barca_targets WHERE portfolio_id = ?1",
+            )
+            .bind(portfolio)
+            .fetch_all(&pool)
+            .await?;
+            if rows.is_empty() {
+                let content = std::fs::read("wallet_barca.csv")?;
+                rows = read_targets(&content).map_err(|e| anyhow!(e))?;
+            }
+            let mut regimes: HashMap<String, HashMap<String, f64>> = HashMap::new();
+            for (market, barca, pct) in rows {
+                regimes.entry(market).or_default().insert(barca, pct);
+            }
+            Ok(targets_table(&regimes))
+        }
+        "holdings" => {
+            // Same rows and order as GET /api/export/holdings: one per account
+            let rows: Vec<HoldingRow> = sqlx::query_as(
+                "SELECT w.symbol, w.group_name, w.barca, w.target_percent, w.current_quantity, \
+                 w.last_price, w.notes, a.name \
+                 FROM wallet_allocations_current_by_account w \
+                 LEFT JOIN accounts a ON a.id = w.account_id \
+                 WHERE w.portfolio_id = ?1 \
+                 ORDER BY w.group_name, w.barca, w.symbol, w.account_id",
+            )
+            .bind(portfolio)
+            .fetch_all(&pool)
+            .await?;
+            let holdings: Vec<Holding> = rows
+                .into_iter()
+                .map(
+                    |(symbol, group, barca, target, qty, price, comments, account)| Holding {
+                        symbol,
+                        group,
+                        barca,
+                        target_percent: target,
+                        current_quantity: qty,
+                        last_price: price,
+                        comments,
+                        account,
+                    },
+                )
+                .collect();
+            Ok(holdings_table(&holdings))
+        }
+        "report" => {
+            let payload: Option<String> = sqlx::query_scalar(
+                "SELECT payload FROM allocations WHERE portfolio_id = ?1 ORDER BY id DESC LIMIT 1",
+            )
+            .bind(portfolio)
+            .fetch_optional(&pool)
+            .await?;
+            let Some(payload) = payload else {
+                bail!("no allocation report computed yet; call /api/allocations first");
+            };
+            report_table(&serde_json::from_str(&payload)?, &opts.level).map_err(|e| anyhow!(e))
+        }
+        other => bail!("unknown export '{}'\n{}", other, USAGE),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+    let opts = parse_args(env::args().skip(1))?;
+    let table = table(&opts).await?;
+    let body = render(&table, opts.format, &opts.dialect).map_err(|e| anyhow!(e))?;
+    // Spreadsheets are binary, so they go to `<dataset name>.xlsx` unless --out says otherwise
+    let out = opts.out.clone().or_else(|| {
+        (opts.format == FileFormat::Xlsx)
+            .then(|| format!("{}.{}", table.name, opts.format.extension()))
+    });
+    match out {
+        Some(path) => {
+            std::fs::write(&path, &body)?;
+            eprintln!("Wrote {} {} rows to {}", table.rows.len(), table.name, path);
+        }
+        None => std::io::stdout().write_all(&body)?,
+    }
+    Ok(())
+}
@@ -1,10 +1,20 @@
-use anyhow::Result;
-use csv::ReaderBuilder;
+use anyhow::{Result, anyhow};
 use dotenv::dotenv;
+use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 use std::env;
 
-#[derive(Debug, serde::Deserialize)]
+#[path = "../csv_dialect.rs"]
+mod csv_dialect;
+// Only the readers are used here; the writers serve the export binary and the server
+#[allow(dead_code)]
+#[path = "../wallet_files.rs"]
+mod wallet_files;
+
+use csv_dialect::CsvDialect;
+use wallet_files::read_records;
+
+#[derive(Debug)]
 struct CsvRow {
     symbol: String,
     group: Option<String>,
@@ -15,6 +25,59 @@ struct CsvRow {
     notes: Option<String>,
 }
 
+// Column positions by header name; the first of several accepted names that is present wins
+struct Columns(Vec<String>);
+
+impl Columns {
+    fn index(&self, names: &[&str]) -> Option<usize> {
+        names
+            .iter()
+            .find_map(|n| self.0.iter().position(|h| h == n))
+    }
+}
+
+fn parse_row(
+    columns: &Columns,
+    record: &csv::StringRecord,
+    dialect: &CsvDialect,
+) -> Result<CsvRow, String> {
+    let text = |names: &[&str]| {
+        columns
+            .index(names)
+            .and_then(|i| record.get(i))
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    let number = |name: &str| {
+        text(&[name])
+            .map(|v| {
+                dialect
+                    .parse_number(&v)
+                    .map_err(|e| format!("{}: {}", name, e))
+            })
+            .transpose()
+    };
+    Ok(CsvRow {
+        symbol: text(&["symbol"]).ok_or("missing symbol")?,
+        group: text(&["group", "group_name"]),
+        barca: text(&["barca"]),
+        target_percent: number("target_percent")?,
+        current_quantity: number("current_quantity")?,
+        last_price: number("last_price")?,
+        notes: text(&["notes", "comments"]),
+    })
+}
+
+fn portfolio_id() -> Result<i64> {
+    match env::var("PORTFOLIO_ID") {
+        Ok(v) if !v.trim().is_empty() => v
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("invalid PORTFOLIO_ID '{}'", v)),
+        _ => Ok(1),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
@@ -24,18 +87,97 @@ async fn main() -> Result<()> {
     let path = env::args()
         .nth(1)
         .unwrap_or_else(|| "wallet_allocations.csv".to_string());
-    println!("Importing '{}' into {}", path, db_url);
+    // PORTFOLIO_ID picks the portfolio (default 1, the one that owns pre-portfolio data)
+    let portfolio = portfolio_id()?;
+    let name: Option<String> = sqlx::query_scalar("SELECT name FROM portfolios WHERE id = ?1")
+        .bind(portfolio)
+        .fetch_optional(&pool)
+        .await?;
+    let Some(name) = name else {
+        return Err(anyhow!("unknown portfolio {}", portfolio));
+    };
+    println!(
+        "Importing '{}' into portfolio '{}' of {}",
+        path, name, db_url
+    );
+
+    // Same content hash as the API import, so a file is only imported once either way
+    let content = std::fs::read(&path)?;
+    let hash: String = Sha256::digest(&content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let existing: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM import_batches WHERE content_hash = ?1 AND status = 'applied' AND portfolio_id = ?2",
+    )
+    .bind(&hash)
+    .bind(portfolio)
+    .fetch_optional(&pool)
+    .await?;
+    if let Some(id) = existing {
+        println!(
+            "'{}' was already imported as batch {}; nothing to do",
+            path, id
+        );
+        return Ok(());
+    }
+
+    // Delimiter and number format are detected (`;` and `60.000,50` in Brazilian exports);
+    // CSV_DELIMITER / CSV_DECIMAL_SEPARATOR / CSV_THOUSANDS_SEPARATOR override the guess.
+    // JSON and XLSX files written by export_wallet are read the same way.
+    let (dialect, records) = read_records(&content).map_err(|e| anyhow!(e))?;
+    let mut records = records.into_iter();
+    let columns = Columns(
+        records
+            .next()
+            .map(|(_, header)| header.iter().map(|h| h.to_lowercase()).collect())
+            .unwrap_or_default(),
+    );
+    if columns.index(&["symbol"]).is_none() {
+        return Err(anyhow!("'{}' has no symbol column", path));
+    }
+    let mut rows = Vec::new();
+    let mut rejected = 0usize;
+    for (line, record) in records {
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        match parse_row(&columns, &record, &dialect) {
+            Ok(row) => rows.push(row),
+            Err(e) => {
+                rejected += 1;
+                eprintln!("line {}: {}", line, e);
+            }
+        }
+    }
 
-    let mut rdr = ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .flexible(true)
-        .has_headers(true)
-        .from_path(&path)?;
+    let mut tx = pool.begin().await?;
+    let batch_id = sqlx::query(
+        "INSERT INTO import_batches (source, content_hash, author, portfolio_id) VALUES (?1, ?2, 'import_wallet_allocations', ?3)",
+    )
+    .bind(&path)
+    .bind(&hash)
+    .bind(portfolio)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
 
     let mut count: usize = 0;
-    for result in rdr.deserialize::<CsvRow>() {
-        let row = result?;
-        sqlx::query("INSERT INTO wallet_allocations (symbol, group_name, barca, target_percent, current_quantity, last_price, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
+    for row in rows {
+        // notes name the custody account; create it on first use
+        let account = row
+            .notes
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty());
+        if let Some(name) = account {
+            sqlx::query("INSERT OR IGNORE INTO accounts (name, portfolio_id) VALUES (?1, ?2)")
+                .bind(name)
+                .bind(portfolio)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("INSERT INTO wallet_allocations (symbol, group_name, barca, target_percent, current_quantity, last_price, notes, account_id, author, reason, event, import_batch_id, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, (SELECT id FROM accounts WHERE name = ?8 AND portfolio_id = ?12), ?9, ?10, 'import', ?11, ?12)")
             .bind(&row.symbol)
             .bind(&row.group)
             .bind(&row.barca)
@@ -43,10 +185,24 @@ async fn main() -> Result<()> {
             .bind(row.current_quantity)
             .bind(row.last_price)
             .bind(&row.notes)
-            .execute(&pool)
+            .bind(account)
+            .bind("import_wallet_allocations")
+            .bind(format!("import {}", path))
+            .bind(batch_id)
+            .bind(portfolio)
+            .execute(&mut *tx)
             .await?;
         count += 1;
     }
-    println!("Inserted {} wallet allocation rows", count);
+    sqlx::query("UPDATE import_batches SET row_count = ?1 WHERE id = ?2")
+        .bind(count as i64)
+        .bind(batch_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    println!(
+        "Inserted {} wallet allocation rows as import batch {} ({} rejected)",
+        count, batch_id, rejected
+    );
     Ok(())
 }
@@ -0,0 +1,289 @@
+// Field delimiter and number format of a CSV file. Spreadsheets exported with a Brazilian
+// locale use `;` between fields, `,` for decimals and `.` for thousands (`60.000,50`).
+// Self-contained so the import binary can include it as well.
+use serde::Serialize;
+
+const BOM: &str = "\u{feff}";
+// Lines sampled when guessing the dialect (exports may start with a preamble)
+const SAMPLE_LINES: usize = 20;
+const CURRENCY_SIGNS: [&str; 5] = ["R$", "US$", "$", "€", "£"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CsvDialect {
+    pub delimiter: char,
+    pub decimal: char,
+    pub thousands: Option<char>,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            decimal: '.',
+            thousands: Some(','),
+        }
+    }
+}
+
+// The content without a leading UTF-8 byte order mark
+pub fn strip_bom(content: &[u8]) -> &[u8] {
+    content.strip_prefix(BOM.as_bytes()).unwrap_or(content)
+}
+
+// 1-based line of a record in `content`. csv positions a record before the blank lines it
+// skips, so count newlines up to the record's first byte instead of using its line counter.
+pub fn record_line(content: &[u8], record: &csv::StringRecord) -> u64 {
+    record.position().map_or(0, |p| {
+        let mut start = (p.byte() as usize).min(content.len());
+        while content
+            .get(start)
+            .is_some_and(|b| *b == b'\n' || *b == b'\r')
+        {
+            start += 1;
+        }
+        content[..start].iter().filter(|b| **b == b'\n').count() as u64 + 1
+    })
+}
+
+impl CsvDialect {
+    // Guess from the first lines: the delimiter that splits some line into the most fields,
+    // then the decimal separator most unambiguous numeric cells agree on
+    pub fn detect(content: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(strip_bom(content));
+        let lines: Vec<&str> = text
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .take(SAMPLE_LINES)
+            .collect();
+        let delimiter = [';', '\t', ',']
+            .into_iter()
+            .max_by_key(|d| {
+                let fields = lines.iter().map(|l| split_fields(l, *d).len()).max();
+                (fields.unwrap_or(0), *d == ',')
+            })
+            .unwrap_or(',');
+        let (mut comma, mut dot) = (0usize, 0usize);
+        for cell in lines.iter().flat_map(|l| split_fields(l, delimiter)) {
+            match decimal_hint(&cell) {
+                Some(',') => comma += 1,
+                Some('.') => dot += 1,
+                _ => {}
+            }
+        }
+        let decimal = if comma > dot { ',' } else { '.' };
+        Self {
+            delimiter,
+            decimal,
+            thousands: Some(if decimal == ',' { '.' } else { ',' }),
+        }
+    }
+
+    // Detected dialect with `CSV_DELIMITER`, `CSV_DECIMAL_SEPARATOR` and
+    // `CSV_THOUSANDS_SEPARATOR` applied on top
+    pub fn from_env(content: &[u8]) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        Self::detect(content).with_overrides(
+            var("CSV_DELIMITER").as_deref(),
+            var("CSV_DECIMAL_SEPARATOR").as_deref(),
+            var("CSV_THOUSANDS_SEPARATOR").as_deref(),
+        )
+    }
+
+    // Explicit settings win over detection. Delimiter: a single character, `tab`, `semicolon`
+    // or `comma`; decimal: `,`/`comma` or `.`/`dot`; thousands: a single character or `none`.
+    // Changing the decimal separator alone switches the thousands separator to the other one.
+    pub fn with_overrides(
+        mut self,
+        delimiter: Option<&str>,
+        decimal: Option<&str>,
+        thousands: Option<&str>,
+    ) -> Result<Self, String> {
+        if let Some(d) = delimiter {
+            self.delimiter = match d {
+                "tab" | "\\t" | "\t" => '\t',
+                "semicolon" => ';',
+                "comma" => ',',
+                _ => single_char(d)
+                    .filter(char::is_ascii)
+                    .ok_or_else(|| format!("invalid CSV delimiter '{}'", d))?,
+            };
+        }
+        if let Some(d) = decimal {
+            self.decimal = match d {
+                "comma" => ',',
+                "dot" => '.',
+                _ => match single_char(d) {
+                    Some(c @ (',' | '.')) => c,
+                    _ => return Err(format!("invalid decimal separator '{}'", d)),
+                },
+            };
+            self.thousands = Some(if self.decimal == ',' { '.' } else { ',' });
+        }
+        if let Some(t) = thousands {
+            self.thousands = match t {
+                "none" | "" => None,
+                "space" => Some(' '),
+                _ => Some(
+                    single_char(t).ok_or_else(|| format!("invalid thousands separator '{}'", t))?,
+                ),
+            };
+        }
+        if self.thousands == Some(self.decimal) {
+            return Err(format!(
+                "decimal and thousands separators are both '{}'",
+                self.decimal
+            ));
+        }
+        Ok(self)
+    }
+
+    pub fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder.delimiter(self.delimiter as u8).trim(csv::Trim::All);
+        builder
+    }
+
+    // A number written in this dialect; currency and percent signs and thousands separators are
+    // dropped ("R$ 60.000,50", "37,5%", "-1,234.5")
+    pub fn parse_number(&self, s: &str) -> Result<f64, String> {
+        let (negative, body) = number_body(s);
+        let cleaned: String = body
+            .chars()
+            .filter(|c| Some(*c) != self.thousands && !c.is_whitespace())
+            .map(|c| if c == self.decimal { '.' } else { c })
+            .collect();
+        cleaned
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && !body.is_empty())
+            .map(|v| if negative { -v } else { v })
+            .ok_or_else(|| format!("invalid number '{}'", s.trim()))
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    chars.next().filter(|_| chars.next().is_none())
+}
+
+// Cells of one line, honouring double quotes
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+// Sign and digits of a number cell without currency and percent signs
+fn number_body(s: &str) -> (bool, &str) {
+    let mut t = s.trim().trim_start_matches(BOM);
+    let mut negative = false;
+    if let Some(rest) = t.strip_prefix('-') {
+        negative = true;
+        t = rest;
+    } else if let Some(rest) = t.strip_prefix('+') {
+        t = rest;
+    }
+    if let Some(rest) = CURRENCY_SIGNS.iter().find_map(|c| t.strip_prefix(c)) {
+        t = rest.trim_start();
+        if let Some(rest) = t.strip_prefix('-') {
+            negative = true;
+            t = rest;
+        }
+    }
+    t = t.strip_suffix('%').unwrap_or(t).trim_end();
+    (negative, t)
+}
+
+// Whether digits group as "1.234.567,89" with these separators
+fn valid_grouping(body: &str, decimal: char, thousands: char) -> bool {
+    let (int, frac) = match body.split_once(decimal) {
+        Some((i, f)) => (i, Some(f)),
+        None => (body, None),
+    };
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let mut groups = int.split(thousands);
+    let first_ok = groups
+        .next()
+        .is_some_and(|g| digits(g) && (g.len() <= 3 || !int.contains(thousands)));
+    first_ok && groups.all(|g| g.len() == 3 && digits(g)) && frac.is_none_or(digits)
+}
+
+// The decimal separator a numeric cell implies, when only one reading of it is valid
+// ("0,65" and "60.000,50" say `,`, "37.89" says `.`, "1,000" and "2024" say nothing)
+fn decimal_hint(cell: &str) -> Option<char> {
+    let (_, body) = number_body(cell);
+    if !body.contains([',', '.']) {
+        return None;
+    }
+    match (
+        valid_grouping(body, ',', '.'),
+        valid_grouping(body, '.', ','),
+    ) {
+        (true, false) => Some(','),
+        (false, true) => Some('.'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_brazilian_spreadsheet_exports() {
+        let br = "\u{feff}symbol;group;barca;target_percent;current_quantity\n\
+USDT;Holding;Caixa;31,94%;60.000,50\n\
+BTC;Holding;Base;37,89;0,65\n";
+        let d = CsvDialect::detect(br.as_bytes());
+        assert_eq!((d.delimiter, d.decimal, d.thousands), (';', ',', Some('.')));
+        assert_eq!(d.parse_number("60.000,50").unwrap(), 60000.5);
+        assert_eq!(d.parse_number("31,94%").unwrap(), 31.94);
+        assert_eq!(d.parse_number("R$ -1.234,5").unwrap(), -1234.5);
+        assert!(d.parse_number("12,3,4").is_err());
+
+        let us = "symbol,target_percent,current_quantity\nBTC,37.89,\"1,000.5\"\nETH,10,2\n";
+        assert_eq!(CsvDialect::detect(us.as_bytes()), CsvDialect::default());
+        // Dates and ambiguous thousands do not count as evidence
+        let dates = "Date;Amount\n15.02.2024;1.000\n";
+        assert_eq!(CsvDialect::detect(dates.as_bytes()).decimal, '.');
+        assert_eq!(
+            CsvDialect::default().parse_number("$1,234.50").unwrap(),
+            1234.5
+        );
+    }
+
+    #[test]
+    fn overrides_replace_detected_settings() {
+        let d = CsvDialect::default()
+            .with_overrides(Some("tab"), Some(","), None)
+            .unwrap();
+        assert_eq!(
+            (d.delimiter, d.decimal, d.thousands),
+            ('\t', ',', Some('.'))
+        );
+        let d = d.with_overrides(None, None, Some("none")).unwrap();
+        assert_eq!(d.parse_number("1234,5").unwrap(), 1234.5);
+        assert!(
+            CsvDialect::default()
+                .with_overrides(Some(";;"), None, None)
+                .is_err()
+        );
+        assert!(
+            CsvDialect::default()
+                .with_overrides(None, Some("x"), None)
+                .is_err()
+        );
+        assert!(
+            CsvDialect::default()
+                .with_overrides(None, None, Some("."))
+                .is_err()
+        );
+    }
+}
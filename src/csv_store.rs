@@ -1,28 +1,42 @@
-use csv::Reader;
-use serde::Deserialize;
 use std::collections::HashMap;
 use std::error::Error;
 
 use crate::domain::models::WalletAllocation as DomainWalletAllocation;
+use crate::usecases::importers::{WALLET_FORMAT, parse_import};
+use crate::wallet_files::read_targets;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 struct BarcaCsv {
     market: String,
     group: String,
     target_percent: f64,
 }
 
+// market,group,target_percent rows in any delimiter / number format (or a JSON / XLSX export of
+// them); every bad row is reported with its line before anything is returned
+fn read_barca_rows(path: &str) -> Result<Vec<BarcaCsv>, Box<dyn Error + Send + Sync>> {
+    let rows = read_targets(&std::fs::read(path)?).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(rows
+        .into_iter()
+        .map(|(market, group, target_percent)| BarcaCsv {
+            market,
+            group,
+            target_percent,
+        })
+        .collect())
+}
+
 pub trait AllocationStore {
     #[allow(dead_code)]
     fn read_wallet_allocations(
         &self,
         path: &str,
     ) -> Result<Vec<DomainWalletAllocation>, Box<dyn Error + Send + Sync>>;
-    fn read_barca_allocations(
+    // All regimes in the file: market -> (barca -> target_percent)
+    fn read_barca_regimes(
         &self,
         path: &str,
-        current_market: &str,
-    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>>;
+    ) -> Result<HashMap<String, HashMap<String, f64>>, Box<dyn Error + Send + Sync>>;
 }
 
 pub struct FileCsvStore;
@@ -32,30 +46,29 @@ impl AllocationStore for FileCsvStore {
         &self,
         path: &str,
     ) -> Result<Vec<DomainWalletAllocation>, Box<dyn Error + Send + Sync>> {
-        let mut rdr = Reader::from_path(path)?;
-        let mut allocations = Vec::new();
-        for result in rdr.deserialize() {
-            let record: DomainWalletAllocation = result?;
-            allocations.push(record);
+        let parsed = parse_import(&std::fs::read(path)?, Some(WALLET_FORMAT), None)?;
+        if !parsed.errors.is_empty() {
+            let errors: Vec<String> = parsed
+                .errors
+                .iter()
+                .map(|e| format!("line {}: {}", e.line, e.error))
+                .collect();
+            return Err(format!("{}: {}", path, errors.join("; ")).into());
         }
-        Ok(allocations)
+        Ok(parsed.balances.into_iter().map(|(_, b)| b.row).collect())
     }
 
-    fn read_barca_allocations(
+    fn read_barca_regimes(
         &self,
         path: &str,
-        current_market: &str,
-    ) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>> {
-        let mut rdr = csv::ReaderBuilder::new()
-            .trim(csv::Trim::All)
-            .from_path(path)?;
-        let mut barca_targets = HashMap::new();
-        for result in rdr.deserialize() {
-            let record: BarcaCsv = result?;
-            if record.market == current_market {
-                barca_targets.insert(record.group.clone(), record.target_percent);
-            }
+    ) -> Result<HashMap<String, HashMap<String, f64>>, Box<dyn Error + Send + Sync>> {
+        let mut regimes: HashMap<String, HashMap<String, f64>> = HashMap::new();
+        for record in read_barca_rows(path)? {
+            regimes
+                .entry(record.market)
+                .or_default()
+                .insert(record.group, record.target_percent);
         }
-        Ok(barca_targets)
+        Ok(regimes)
     }
 }
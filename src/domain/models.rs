@@ -59,6 +59,24 @@ pub struct TotalSnapshot {
     pub created_at: Option<String>,
 }
 
+// Value-at-Risk snapshot row (history_var)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct VarSnapshot {
+    pub id: Option<i64>,
+    pub timestamp: String,
+    pub scope: String,
+    pub name: String,
+    pub method: String,
+    pub confidence: f64,
+    pub horizon_days: i64,
+    pub position_value: Option<f64>,
+    pub var_value: Option<f64>,
+    pub es_value: Option<f64>,
+    pub observations: Option<i64>,
+    pub extra: Option<serde_json::Value>,
+    pub created_at: Option<String>,
+}
+
 // Wallet allocation ledger (append-only) row (wallet_allocations)
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct WalletAllocation {
@@ -71,6 +89,247 @@ pub struct WalletAllocation {
     pub last_price: Option<f64>,
     pub notes: Option<String>,
     pub created_at: Option<String>,
+    // Custody account holding the quantity (notes name the account on older rows)
+    #[serde(default)]
+    pub account_id: Option<i64>,
+    // Who appended the row and why (API edits and imports)
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    // import | add | update | move_in | merge_in, or a tombstone closing the holding:
+    // retire | move_out | merge_out
+    #[serde(default)]
+    pub event: Option<String>,
+    // Import batch that appended the row (None for API edits)
+    #[serde(default)]
+    pub import_batch_id: Option<i64>,
+}
+
+// Transaction ledger row (transactions)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Transaction {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub timestamp: String,
+    pub kind: String,
+    pub symbol: String,
+    pub quantity: f64,
+    #[serde(default)]
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub quote_currency: Option<String>,
+    #[serde(default)]
+    pub fee: Option<f64>,
+    #[serde(default)]
+    pub fee_symbol: Option<String>,
+    #[serde(default)]
+    pub venue: Option<String>,
+    #[serde(default)]
+    pub to_venue: Option<String>,
+    #[serde(default)]
+    pub group_name: Option<String>,
+    #[serde(default)]
+    pub barca: Option<String>,
+    #[serde(default)]
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    // staking | lending | airdrop | other, for kind = income
+    #[serde(default)]
+    pub income_type: Option<String>,
+    // Custody accounts for venue / to_venue (resolved by name when omitted)
+    #[serde(default)]
+    pub account_id: Option<i64>,
+    #[serde(default)]
+    pub to_account_id: Option<i64>,
+}
+
+// Custody account: exchange, hardware wallet, hot wallet or DeFi protocol (accounts)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Account {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub account_type: String,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+// Portfolio that owns the rows created before portfolios existed (migration 0012); the
+// unprefixed API routes use it
+pub const DEFAULT_PORTFOLIO_ID: i64 = 1;
+
+// A portfolio every ledger, history and allocation row belongs to (portfolios). `market` and
+// the glide fields use the CURRENT_MARKET / MARKET_GLIDE_* syntax; unset means the environment.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Portfolio {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub market: Option<String>,
+    #[serde(default)]
+    pub market_glide_to: Option<String>,
+    #[serde(default)]
+    pub market_glide_start: Option<String>,
+    #[serde(default)]
+    pub market_glide_days: Option<i64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+// Target percent of one BARCA in one market regime for a portfolio (barca_targets)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BarcaTarget {
+    pub market: String,
+    // `group` is the wallet_barca.csv column name
+    #[serde(alias = "group")]
+    pub barca: String,
+    pub target_percent: f64,
+}
+
+// Selected portfolios viewed together (households, household_members), with the same
+// market regime settings as a portfolio
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Household {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub name: String,
+    // Member portfolio ids
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub members: Vec<i64>,
+    #[serde(default)]
+    pub market: Option<String>,
+    #[serde(default)]
+    pub market_glide_to: Option<String>,
+    #[serde(default)]
+    pub market_glide_start: Option<String>,
+    #[serde(default)]
+    pub market_glide_days: Option<i64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+// Household snapshot row (household_history); level is totals, barca or groups and
+// contributions the JSON array of member portfolio values
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HouseholdSnapshot {
+    pub timestamp: String,
+    pub level: String,
+    pub name: String,
+    pub value: Option<f64>,
+    pub current_percent: Option<f64>,
+    pub target_percent: Option<f64>,
+    pub contributions: Option<serde_json::Value>,
+}
+
+// API user (users); the password is kept as an Argon2 hash and never serialized
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct User {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub username: String,
+    #[serde(skip)]
+    pub password_hash: String,
+    #[serde(default)]
+    pub is_admin: bool,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+// Bearer token of a user (api_tokens); only its hash is stored, `prefix` identifies it
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiToken {
+    pub id: Option<i64>,
+    pub user_id: i64,
+    pub name: String,
+    pub prefix: String,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+    pub created_at: Option<String>,
+}
+
+// Role of a user on a portfolio (portfolio_roles): owner, editor or viewer
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PortfolioRole {
+    pub user_id: i64,
+    pub username: String,
+    pub portfolio_id: i64,
+    pub role: String,
+}
+
+// Read-only share link of a portfolio (share_links); `masked` links only expose percentages
+// and deviations
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ShareLink {
+    pub id: Option<i64>,
+    pub portfolio_id: i64,
+    pub label: Option<String>,
+    pub masked: bool,
+    pub expires_at: String,
+    pub created_by: Option<i64>,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+    pub created_at: Option<String>,
+}
+
+// One wallet CSV import (import_batches); status is applied or rolled_back
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ImportBatch {
+    pub id: Option<i64>,
+    pub source: String,
+    pub content_hash: String,
+    pub row_count: i64,
+    pub author: Option<String>,
+    pub status: String,
+    pub created_at: Option<String>,
+    pub rolled_back_at: Option<String>,
+    pub rolled_back_by: Option<String>,
+}
+
+// Expected yield of a position (yield_positions)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct YieldPosition {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub symbol: String,
+    #[serde(default)]
+    pub venue: String,
+    #[serde(default)]
+    pub barca: Option<String>,
+    pub expected_apy: f64,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+// Conversion rate into BRL for one currency and day (fx_rates)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FxRate {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub date: String,
+    pub currency: String,
+    pub brl_rate: f64,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
 }
 
 // Persisted allocation computation (allocations)
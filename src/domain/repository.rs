@@ -1,13 +1,20 @@
 use crate::domain::models::{
-    AllocationRecord, AssetHistoryRow, AssetSnapshot, BarcaHistoryRow, BarcaSnapshot,
-    GroupHistoryRow, GroupSnapshot, TotalSnapshot, WalletAllocation,
+    Account, AllocationRecord, ApiToken, AssetHistoryRow, AssetSnapshot, BarcaHistoryRow,
+    BarcaSnapshot, BarcaTarget, FxRate, GroupHistoryRow, GroupSnapshot, Household,
+    HouseholdSnapshot, ImportBatch, Portfolio, PortfolioRole, ShareLink, TotalSnapshot,
+    Transaction, User, VarSnapshot, WalletAllocation, YieldPosition,
 };
 use async_trait::async_trait;
+use std::sync::Arc;
 
 pub type RepoResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+// Every method except the portfolio registry reads and writes the rows of one portfolio
+// (`portfolio_id`); FX rates are market data shared by all portfolios.
 #[async_trait]
 pub trait HistoryRepo: Send + Sync {
+    fn portfolio_id(&self) -> i64;
+
     async fn insert_asset_snapshot(&self, snap: &AssetSnapshot) -> RepoResult<()>;
     async fn insert_barca_snapshot(&self, snap: &BarcaSnapshot) -> RepoResult<()>;
     async fn insert_total_snapshot(&self, snap: &TotalSnapshot) -> RepoResult<()>;
@@ -36,18 +43,184 @@ pub trait HistoryRepo: Send + Sync {
     // Wallet allocations ledger (append-only)
     // Insert a new wallet allocation record (do not delete or update existing rows)
     async fn insert_wallet_allocation(&self, wa: &WalletAllocation) -> RepoResult<()>;
+    // Insert several rows atomically (moves and merges append a row per side)
+    async fn insert_wallet_allocations(&self, rows: &[WalletAllocation]) -> RepoResult<()>;
     // Fetch latest/current wallet allocations (one row per symbol representing the most recent entry)
     async fn fetch_current_wallet_allocations(&self) -> RepoResult<Vec<WalletAllocation>>;
+    // Latest row per symbol/group/barca and custody account (not aggregated across accounts)
+    async fn fetch_current_wallet_allocations_by_account(
+        &self,
+    ) -> RepoResult<Vec<WalletAllocation>>;
+    // Same rows as of `as_of` (rows appended later are ignored); `as_of` uses the created_at format
+    async fn fetch_wallet_allocations_as_of(
+        &self,
+        as_of: &str,
+    ) -> RepoResult<Vec<WalletAllocation>>;
     // Fetch audit/history for a given symbol (all rows for symbol ordered by created_at desc)
-    #[allow(dead_code)]
     async fn fetch_wallet_allocation_history(
         &self,
         symbol: &str,
     ) -> RepoResult<Vec<WalletAllocation>>;
 
+    // Wallet import batches
+    // Record the batch and append its rows (linked to it) atomically; returns the batch id
+    async fn insert_import_batch(
+        &self,
+        batch: &ImportBatch,
+        rows: &[WalletAllocation],
+    ) -> RepoResult<i64>;
+    // Newest first
+    async fn fetch_import_batches(&self) -> RepoResult<Vec<ImportBatch>>;
+    async fn fetch_import_batch(&self, id: i64) -> RepoResult<Option<ImportBatch>>;
+    // Applied (not rolled back) batch imported from content with this hash
+    async fn find_applied_import_batch(
+        &self,
+        content_hash: &str,
+    ) -> RepoResult<Option<ImportBatch>>;
+    // Rows appended by the batch, oldest first
+    async fn fetch_wallet_allocations_by_batch(
+        &self,
+        batch_id: i64,
+    ) -> RepoResult<Vec<WalletAllocation>>;
+    // Append the compensating rows and mark the batch rolled back atomically; returns
+    // false when the batch does not exist or was already rolled back
+    async fn rollback_import_batch(
+        &self,
+        batch_id: i64,
+        by: &str,
+        rows: &[WalletAllocation],
+    ) -> RepoResult<bool>;
+
     // Persist computed allocation payload
     async fn persist_allocation_record(&self, rec: &AllocationRecord) -> RepoResult<()>;
+    // Most recently persisted allocation payload
+    async fn fetch_latest_allocation_record(&self) -> RepoResult<Option<AllocationRecord>>;
 
     // Groups history
     async fn insert_group_snapshot(&self, snap: &GroupSnapshot) -> RepoResult<()>;
+
+    // Value-at-Risk history
+    async fn insert_var_snapshot(&self, snap: &VarSnapshot) -> RepoResult<()>;
+    async fn fetch_var_history(
+        &self,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> RepoResult<Vec<VarSnapshot>>;
+
+    // Transaction ledger
+    async fn insert_transaction(&self, tx: &Transaction) -> RepoResult<i64>;
+    // Transactions ordered by timestamp (oldest first), optionally filtered
+    async fn fetch_transactions(
+        &self,
+        symbol: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> RepoResult<Vec<Transaction>>;
+
+    // BRL conversion rates (insert or replace per date and currency)
+    async fn upsert_fx_rate(&self, rate: &FxRate) -> RepoResult<()>;
+    async fn fetch_fx_rates(&self, currency: Option<&str>) -> RepoResult<Vec<FxRate>>;
+
+    // Expected yield per position (insert or replace per symbol and venue)
+    async fn upsert_yield_position(&self, pos: &YieldPosition) -> RepoResult<()>;
+    async fn fetch_yield_positions(&self) -> RepoResult<Vec<YieldPosition>>;
+    // Returns false when no such position exists
+    async fn delete_yield_position(&self, symbol: &str, venue: &str) -> RepoResult<bool>;
+
+    // Custody accounts
+    async fn insert_account(&self, acc: &Account) -> RepoResult<i64>;
+    async fn fetch_accounts(&self) -> RepoResult<Vec<Account>>;
+    async fn fetch_account(&self, id: i64) -> RepoResult<Option<Account>>;
+    // Returns false when no such account exists
+    async fn update_account(&self, acc: &Account) -> RepoResult<bool>;
+    async fn delete_account(&self, id: i64) -> RepoResult<bool>;
+    // Wallet allocation and transaction rows pointing at the account
+    async fn count_account_references(&self, id: i64) -> RepoResult<i64>;
+    // Id of the account with this name (case-insensitive), created as type 'other' if missing
+    async fn ensure_account(&self, name: &str) -> RepoResult<i64>;
+
+    // BARCA targets per market regime of this portfolio (empty: wallet_barca.csv applies)
+    async fn fetch_barca_targets(&self) -> RepoResult<Vec<BarcaTarget>>;
+    // Replaces the whole set; an empty slice goes back to wallet_barca.csv
+    async fn replace_barca_targets(&self, rows: &[BarcaTarget]) -> RepoResult<()>;
+
+    // Portfolio registry (not scoped)
+    async fn insert_portfolio(&self, p: &Portfolio) -> RepoResult<i64>;
+    async fn fetch_portfolios(&self) -> RepoResult<Vec<Portfolio>>;
+    async fn fetch_portfolio(&self, id: i64) -> RepoResult<Option<Portfolio>>;
+    // Returns false when no such portfolio exists
+    async fn update_portfolio(&self, p: &Portfolio) -> RepoResult<bool>;
+    // Removes the portfolio with its targets, accounts, yield settings and snapshot history
+    async fn delete_portfolio(&self, id: i64) -> RepoResult<bool>;
+    // Wallet allocation, transaction and import batch rows of the portfolio
+    async fn count_portfolio_references(&self, id: i64) -> RepoResult<i64>;
+    // The same repository scoped to another portfolio
+    fn portfolio_repo(&self, portfolio_id: i64) -> Arc<dyn HistoryRepo>;
+
+    // Households (not scoped); `members` is stored with the household
+    async fn insert_household(&self, h: &Household) -> RepoResult<i64>;
+    async fn fetch_households(&self) -> RepoResult<Vec<Household>>;
+    async fn fetch_household(&self, id: i64) -> RepoResult<Option<Household>>;
+    // Returns false when no such household exists
+    async fn update_household(&self, h: &Household) -> RepoResult<bool>;
+    // Removes the household with its targets and snapshot history
+    async fn delete_household(&self, id: i64) -> RepoResult<bool>;
+    // Household BARCA targets per market regime (empty: wallet_barca.csv applies)
+    async fn fetch_household_targets(&self, id: i64) -> RepoResult<Vec<BarcaTarget>>;
+    async fn replace_household_targets(&self, id: i64, rows: &[BarcaTarget]) -> RepoResult<()>;
+    // Snapshot rows of one computation (same timestamp, level and name replaces)
+    async fn insert_household_snapshots(
+        &self,
+        id: i64,
+        rows: &[HouseholdSnapshot],
+    ) -> RepoResult<()>;
+    // Oldest first
+    async fn fetch_household_history(
+        &self,
+        id: i64,
+        level: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> RepoResult<Vec<HouseholdSnapshot>>;
+
+    // Users, API tokens and portfolio roles (not scoped)
+    // Admin user that owns every portfolio, inserted only while there are no users at all;
+    // None once a user exists
+    async fn insert_first_user(&self, user: &User) -> RepoResult<Option<i64>>;
+    async fn insert_user(&self, user: &User) -> RepoResult<i64>;
+    async fn fetch_users(&self) -> RepoResult<Vec<User>>;
+    async fn fetch_user(&self, id: i64) -> RepoResult<Option<User>>;
+    // Case-insensitive
+    async fn fetch_user_by_name(&self, username: &str) -> RepoResult<Option<User>>;
+    async fn update_user_password(&self, id: i64, password_hash: &str) -> RepoResult<bool>;
+    // Removes the user with its tokens and roles
+    async fn delete_user(&self, id: i64) -> RepoResult<bool>;
+    async fn insert_api_token(&self, token: &ApiToken, token_hash: &str) -> RepoResult<i64>;
+    // Newest first, revoked and expired ones included
+    async fn fetch_api_tokens(&self, user_id: i64) -> RepoResult<Vec<ApiToken>>;
+    async fn find_api_token(&self, token_hash: &str) -> RepoResult<Option<ApiToken>>;
+    async fn touch_api_token(&self, id: i64) -> RepoResult<()>;
+    // Returns false when the user has no such active token
+    async fn revoke_api_token(&self, user_id: i64, id: i64) -> RepoResult<bool>;
+    async fn fetch_user_roles(&self, user_id: i64) -> RepoResult<Vec<PortfolioRole>>;
+    async fn fetch_portfolio_roles(&self, portfolio_id: i64) -> RepoResult<Vec<PortfolioRole>>;
+    // Insert or replace the user's role on the portfolio
+    async fn set_portfolio_role(
+        &self,
+        user_id: i64,
+        portfolio_id: i64,
+        role: &str,
+    ) -> RepoResult<()>;
+    async fn delete_portfolio_role(&self, user_id: i64, portfolio_id: i64) -> RepoResult<bool>;
+
+    // Share links of this portfolio, newest first
+    async fn insert_share_link(&self, link: &ShareLink) -> RepoResult<i64>;
+    async fn fetch_share_links(&self) -> RepoResult<Vec<ShareLink>>;
+    // Any portfolio (share tokens name the link they belong to)
+    async fn fetch_share_link(&self, id: i64) -> RepoResult<Option<ShareLink>>;
+    async fn touch_share_link(&self, id: i64) -> RepoResult<()>;
+    // Returns false when this portfolio has no such active link
+    async fn revoke_share_link(&self, id: i64) -> RepoResult<bool>;
+    // Stored secret `name`, saved as `candidate` when there is none yet
+    async fn app_secret(&self, name: &str, candidate: &str) -> RepoResult<String>;
 }
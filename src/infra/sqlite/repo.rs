@@ -1,28 +1,119 @@
 use crate::domain::models::{
-    AllocationRecord, AssetHistoryRow, AssetSnapshot, BarcaHistoryRow, BarcaSnapshot,
-    GroupHistoryRow, GroupSnapshot, TotalSnapshot, WalletAllocation,
+    Account, AllocationRecord, ApiToken, AssetHistoryRow, AssetSnapshot, BarcaHistoryRow,
+    BarcaSnapshot, BarcaTarget, DEFAULT_PORTFOLIO_ID, FxRate, GroupHistoryRow, GroupSnapshot,
+    Household, HouseholdSnapshot, ImportBatch, Portfolio, PortfolioRole, ShareLink, TotalSnapshot,
+    Transaction, User, VarSnapshot, WalletAllocation, YieldPosition,
 };
 use crate::domain::repository::{HistoryRepo, RepoResult};
 use async_trait::async_trait;
-use sqlx::{QueryBuilder, SqlitePool};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use std::sync::Arc;
 
 pub struct SqliteRepo {
     pub pool: SqlitePool,
+    // Every query is restricted to this portfolio
+    pub portfolio_id: i64,
 }
 
 impl SqliteRepo {
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            portfolio_id: DEFAULT_PORTFOLIO_ID,
+        }
+    }
+
+    // Same pool, scoped to another portfolio
+    pub fn for_portfolio(&self, portfolio_id: i64) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            portfolio_id,
+        }
+    }
+
+    async fn household_members(&self, household_id: i64) -> RepoResult<Vec<i64>> {
+        let ids = sqlx::query_scalar(
+            "SELECT portfolio_id FROM household_members WHERE household_id = ?1 ORDER BY portfolio_id ASC",
+        )
+        .bind(household_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids)
+    }
+
+    // Older callers name the custody location in notes; link it to an account (resolved
+    // before a transaction is opened, as ensure_account uses the pool)
+    async fn row_account_ids(&self, rows: &[WalletAllocation]) -> RepoResult<Vec<Option<i64>>> {
+        let mut account_ids = Vec::with_capacity(rows.len());
+        for wa in rows {
+            account_ids.push(match (wa.account_id, wa.notes.as_deref().map(str::trim)) {
+                (Some(id), _) => Some(id),
+                (None, Some(name)) if !name.is_empty() => Some(self.ensure_account(name).await?),
+                _ => None,
+            });
+        }
+        Ok(account_ids)
+    }
+}
+
+async fn insert_wallet_rows(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    portfolio_id: i64,
+    rows: &[WalletAllocation],
+    account_ids: &[Option<i64>],
+    import_batch_id: Option<i64>,
+) -> RepoResult<()> {
+    for (wa, account_id) in rows.iter().zip(account_ids) {
+        sqlx::query("INSERT INTO wallet_allocations (symbol, group_name, barca, target_percent, current_quantity, last_price, notes, account_id, author, reason, event, import_batch_id, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)")
+            .bind(&wa.symbol)
+            .bind(&wa.group_name)
+            .bind(&wa.barca)
+            .bind(wa.target_percent)
+            .bind(wa.current_quantity)
+            .bind(wa.last_price)
+            .bind(wa.notes.as_deref())
+            .bind(account_id)
+            .bind(&wa.author)
+            .bind(&wa.reason)
+            .bind(&wa.event)
+            .bind(import_batch_id.or(wa.import_batch_id))
+            .bind(portfolio_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+// ` WHERE portfolio_id = ? [AND timestamp >= from] [AND timestamp <= to]`
+fn push_scope_and_range(
+    qb: &mut QueryBuilder<'_, Sqlite>,
+    portfolio_id: i64,
+    from: Option<&str>,
+    to: Option<&str>,
+) {
+    qb.push(" WHERE portfolio_id = ");
+    qb.push_bind(portfolio_id);
+    if let Some(f) = from {
+        qb.push(" AND timestamp >= ");
+        qb.push_bind(f.to_string());
+    }
+    if let Some(t) = to {
+        qb.push(" AND timestamp <= ");
+        qb.push_bind(t.to_string());
     }
 }
 
 #[async_trait]
 impl HistoryRepo for SqliteRepo {
+    fn portfolio_id(&self) -> i64 {
+        self.portfolio_id
+    }
+
     async fn insert_asset_snapshot(&self, snap: &AssetSnapshot) -> RepoResult<()> {
         let extra = snap.extra.as_ref().map(|v| v.to_string());
         sqlx::query(
-            r#"INSERT OR IGNORE INTO history_assets (timestamp, symbol, group_name, barca, price, current_quantity, value, target_percent, current_percent, market_cap, fdv, volume_24h, percent_change_24h, percent_change_7d, extra)
-               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+            r#"INSERT OR IGNORE INTO history_assets (timestamp, symbol, group_name, barca, price, current_quantity, value, target_percent, current_percent, market_cap, fdv, volume_24h, percent_change_24h, percent_change_7d, extra, portfolio_id)
+               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)
             "#,
         )
         .bind(&snap.timestamp)
@@ -40,6 +131,7 @@ impl HistoryRepo for SqliteRepo {
         .bind(snap.percent_change_24h)
         .bind(snap.percent_change_7d)
         .bind(extra)
+        .bind(self.portfolio_id)
         .execute(&self.pool)
         .await?;
         Ok(())
@@ -47,13 +139,14 @@ impl HistoryRepo for SqliteRepo {
 
     async fn insert_barca_snapshot(&self, snap: &BarcaSnapshot) -> RepoResult<()> {
         let extra = snap.extra.as_ref().map(|v| v.to_string());
-        sqlx::query("INSERT OR IGNORE INTO history_barca (timestamp, barca, value, current_percent, target_percent, extra) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+        sqlx::query("INSERT OR IGNORE INTO history_barca (timestamp, barca, value, current_percent, target_percent, extra, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
             .bind(&snap.timestamp)
             .bind(&snap.barca)
             .bind(snap.value)
             .bind(snap.current_percent)
             .bind(snap.target_percent)
             .bind(extra)
+            .bind(self.portfolio_id)
             .execute(&self.pool)
             .await?;
         Ok(())
@@ -61,13 +154,14 @@ impl HistoryRepo for SqliteRepo {
 
     async fn insert_group_snapshot(&self, snap: &GroupSnapshot) -> RepoResult<()> {
         let extra = snap.extra.as_ref().map(|v| v.to_string());
-        sqlx::query("INSERT OR IGNORE INTO history_groups (timestamp, group_name, value, current_percent, target_percent, extra) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+        sqlx::query("INSERT OR IGNORE INTO history_groups (timestamp, group_name, value, current_percent, target_percent, extra, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
             .bind(&snap.timestamp)
             .bind(&snap.group_name)
             .bind(snap.value)
             .bind(snap.current_percent)
             .bind(snap.target_percent)
             .bind(extra)
+            .bind(self.portfolio_id)
             .execute(&self.pool)
             .await?;
         Ok(())
@@ -75,10 +169,11 @@ impl HistoryRepo for SqliteRepo {
 
     async fn insert_total_snapshot(&self, snap: &TotalSnapshot) -> RepoResult<()> {
         let extra = snap.extra.as_ref().map(|v| v.to_string());
-        sqlx::query("INSERT OR REPLACE INTO history_totals (timestamp, total_value, extra) VALUES (?1, ?2, ?3)")
+        sqlx::query("INSERT OR REPLACE INTO history_totals (timestamp, total_value, extra, portfolio_id) VALUES (?1, ?2, ?3, ?4)")
             .bind(&snap.timestamp)
             .bind(snap.total_value)
             .bind(extra)
+            .bind(self.portfolio_id)
             .execute(&self.pool)
             .await?;
         Ok(())
@@ -92,22 +187,7 @@ impl HistoryRepo for SqliteRepo {
         let mut qb = QueryBuilder::new(
             "SELECT timestamp, symbol, group_name, barca, price, current_quantity, value, target_percent, current_percent, deviation_percent, value_deviation FROM asset_variance_history",
         );
-        if from.is_some() || to.is_some() {
-            qb.push(" WHERE ");
-            let mut first = true;
-            if let Some(f) = from {
-                qb.push("timestamp >= ");
-                qb.push_bind(f);
-                first = false;
-            }
-            if let Some(t) = to {
-                if !first {
-                    qb.push(" AND ");
-                }
-                qb.push("timestamp <= ");
-                qb.push_bind(t);
-            }
-        }
+        push_scope_and_range(&mut qb, self.portfolio_id, from, to);
         qb.push(" ORDER BY timestamp ASC, symbol ASC");
         let rows = qb
             .build_query_as::<AssetHistoryRow>()
@@ -124,22 +204,7 @@ impl HistoryRepo for SqliteRepo {
         let mut qb = QueryBuilder::new(
             "SELECT timestamp, barca, value, current_percent, target_percent, deviation_percent FROM barca_variance_history",
         );
-        if from.is_some() || to.is_some() {
-            qb.push(" WHERE ");
-            let mut first = true;
-            if let Some(f) = from {
-                qb.push("timestamp >= ");
-                qb.push_bind(f);
-                first = false;
-            }
-            if let Some(t) = to {
-                if !first {
-                    qb.push(" AND ");
-                }
-                qb.push("timestamp <= ");
-                qb.push_bind(t);
-            }
-        }
+        push_scope_and_range(&mut qb, self.portfolio_id, from, to);
         qb.push(" ORDER BY timestamp ASC, barca ASC");
         let rows = qb
             .build_query_as::<BarcaHistoryRow>()
@@ -156,22 +221,7 @@ impl HistoryRepo for SqliteRepo {
         let mut qb = QueryBuilder::new(
             "SELECT timestamp, group_name, value, current_percent, target_percent, deviation_percent FROM group_variance_history",
         );
-        if from.is_some() || to.is_some() {
-            qb.push(" WHERE ");
-            let mut first = true;
-            if let Some(f) = from {
-                qb.push("timestamp >= ");
-                qb.push_bind(f);
-                first = false;
-            }
-            if let Some(t) = to {
-                if !first {
-                    qb.push(" AND ");
-                }
-                qb.push("timestamp <= ");
-                qb.push_bind(t);
-            }
-        }
+        push_scope_and_range(&mut qb, self.portfolio_id, from, to);
         qb.push(" ORDER BY timestamp ASC, group_name ASC");
         let rows = qb
             .build_query_as::<GroupHistoryRow>()
@@ -185,21 +235,11 @@ impl HistoryRepo for SqliteRepo {
         from: Option<&str>,
         to: Option<&str>,
     ) -> RepoResult<Vec<TotalSnapshot>> {
-        let mut q = String::from("SELECT * FROM history_totals");
-        if from.is_some() || to.is_some() {
-            q.push_str(" WHERE ");
-            let mut clauses = Vec::new();
-            if from.is_some() {
-                clauses.push("timestamp >= ?1");
-            }
-            if to.is_some() {
-                clauses.push("timestamp <= ?2");
-            }
-            q.push_str(&clauses.join(" AND "));
-        }
-        let rows = sqlx::query_as::<_, TotalSnapshot>(&q)
-            .bind(from)
-            .bind(to)
+        let mut qb = QueryBuilder::new("SELECT * FROM history_totals");
+        push_scope_and_range(&mut qb, self.portfolio_id, from, to);
+        qb.push(" ORDER BY timestamp ASC");
+        let rows = qb
+            .build_query_as::<TotalSnapshot>()
             .fetch_all(&self.pool)
             .await?;
         Ok(rows)
@@ -207,25 +247,69 @@ impl HistoryRepo for SqliteRepo {
 
     // wallet allocations ledger
     async fn insert_wallet_allocation(&self, wa: &WalletAllocation) -> RepoResult<()> {
-        let extra_notes = wa.notes.as_ref().map(|s| s.as_str());
-        sqlx::query("INSERT INTO wallet_allocations (symbol, group_name, barca, target_percent, current_quantity, last_price, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")
-            .bind(&wa.symbol)
-            .bind(&wa.group_name)
-            .bind(&wa.barca)
-            .bind(wa.target_percent)
-            .bind(wa.current_quantity)
-            .bind(wa.last_price)
-            .bind(extra_notes)
-            .execute(&self.pool)
-            .await?;
+        self.insert_wallet_allocations(std::slice::from_ref(wa))
+            .await
+    }
+
+    async fn insert_wallet_allocations(&self, rows: &[WalletAllocation]) -> RepoResult<()> {
+        let account_ids = self.row_account_ids(rows).await?;
+        let mut tx = self.pool.begin().await?;
+        insert_wallet_rows(&mut tx, self.portfolio_id, rows, &account_ids, None).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     async fn fetch_current_wallet_allocations(&self) -> RepoResult<Vec<WalletAllocation>> {
-        let rows =
-            sqlx::query_as::<_, WalletAllocation>("SELECT * FROM wallet_allocations_current")
-                .fetch_all(&self.pool)
-                .await?;
+        let rows = sqlx::query_as::<_, WalletAllocation>(
+            "SELECT * FROM wallet_allocations_current WHERE portfolio_id = ?1",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_current_wallet_allocations_by_account(
+        &self,
+    ) -> RepoResult<Vec<WalletAllocation>> {
+        let rows = sqlx::query_as::<_, WalletAllocation>(
+            "SELECT * FROM wallet_allocations_current_by_account WHERE portfolio_id = ?1",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_wallet_allocations_as_of(
+        &self,
+        as_of: &str,
+    ) -> RepoResult<Vec<WalletAllocation>> {
+        // wallet_allocations_current_by_account restricted to rows appended up to `as_of`
+        let rows = sqlx::query_as::<_, WalletAllocation>(
+            r#"WITH ranked AS (
+                SELECT *, ROW_NUMBER() OVER (
+                    PARTITION BY symbol, group_name, barca,
+                        COALESCE('a:' || account_id, 'n:' || COALESCE(notes, ''))
+                    ORDER BY created_at DESC, id DESC
+                ) AS rn
+                FROM wallet_allocations
+                WHERE created_at <= ?1 AND portfolio_id = ?2
+            )
+            SELECT id, portfolio_id, symbol, group_name, barca,
+                COALESCE(target_percent, 0.0) AS target_percent,
+                COALESCE(current_quantity, 0.0) AS current_quantity,
+                last_price, notes, created_at, account_id, author, reason, event,
+                import_batch_id
+            FROM ranked
+            WHERE rn = 1
+                AND COALESCE(event, '') NOT IN ('retire', 'move_out', 'merge_out')
+            ORDER BY symbol ASC, group_name ASC, barca ASC"#,
+        )
+        .bind(as_of)
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
         Ok(rows)
     }
 
@@ -234,22 +318,945 @@ impl HistoryRepo for SqliteRepo {
         symbol: &str,
     ) -> RepoResult<Vec<WalletAllocation>> {
         let rows = sqlx::query_as::<_, WalletAllocation>(
-            "SELECT * FROM wallet_allocations WHERE symbol = ?1 ORDER BY created_at DESC",
+            "SELECT * FROM wallet_allocations WHERE symbol = ?1 AND portfolio_id = ?2 ORDER BY created_at DESC, id DESC",
         )
         .bind(symbol)
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn insert_import_batch(
+        &self,
+        batch: &ImportBatch,
+        rows: &[WalletAllocation],
+    ) -> RepoResult<i64> {
+        let account_ids = self.row_account_ids(rows).await?;
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query(
+            "INSERT INTO import_batches (source, content_hash, row_count, author, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&batch.source)
+        .bind(&batch.content_hash)
+        .bind(rows.len() as i64)
+        .bind(&batch.author)
+        .bind(self.portfolio_id)
+        .execute(&mut *tx)
+        .await?;
+        let id = res.last_insert_rowid();
+        insert_wallet_rows(&mut tx, self.portfolio_id, rows, &account_ids, Some(id)).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn fetch_import_batches(&self) -> RepoResult<Vec<ImportBatch>> {
+        let rows = sqlx::query_as::<_, ImportBatch>(
+            "SELECT * FROM import_batches WHERE portfolio_id = ?1 ORDER BY created_at DESC, id DESC",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_import_batch(&self, id: i64) -> RepoResult<Option<ImportBatch>> {
+        let row = sqlx::query_as::<_, ImportBatch>(
+            "SELECT * FROM import_batches WHERE id = ?1 AND portfolio_id = ?2",
+        )
+        .bind(id)
+        .bind(self.portfolio_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn find_applied_import_batch(
+        &self,
+        content_hash: &str,
+    ) -> RepoResult<Option<ImportBatch>> {
+        let row = sqlx::query_as::<_, ImportBatch>(
+            "SELECT * FROM import_batches WHERE content_hash = ?1 AND status = 'applied' AND portfolio_id = ?2",
+        )
+        .bind(content_hash)
+        .bind(self.portfolio_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn fetch_wallet_allocations_by_batch(
+        &self,
+        batch_id: i64,
+    ) -> RepoResult<Vec<WalletAllocation>> {
+        let rows = sqlx::query_as::<_, WalletAllocation>(
+            "SELECT * FROM wallet_allocations WHERE import_batch_id = ?1 AND portfolio_id = ?2 ORDER BY id ASC",
+        )
+        .bind(batch_id)
+        .bind(self.portfolio_id)
         .fetch_all(&self.pool)
         .await?;
         Ok(rows)
     }
 
+    async fn rollback_import_batch(
+        &self,
+        batch_id: i64,
+        by: &str,
+        rows: &[WalletAllocation],
+    ) -> RepoResult<bool> {
+        let account_ids = self.row_account_ids(rows).await?;
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query(
+            "UPDATE import_batches SET status = 'rolled_back', rolled_back_by = ?2, rolled_back_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = ?1 AND status = 'applied' AND portfolio_id = ?3",
+        )
+        .bind(batch_id)
+        .bind(by)
+        .bind(self.portfolio_id)
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        insert_wallet_rows(&mut tx, self.portfolio_id, rows, &account_ids, None).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
     async fn persist_allocation_record(&self, rec: &AllocationRecord) -> RepoResult<()> {
-        sqlx::query("INSERT INTO allocations (computed_at, payload) VALUES (?1, ?2)")
-            .bind(&rec.computed_at)
-            .bind(rec.payload.to_string())
+        sqlx::query(
+            "INSERT INTO allocations (computed_at, payload, portfolio_id) VALUES (?1, ?2, ?3)",
+        )
+        .bind(&rec.computed_at)
+        .bind(rec.payload.to_string())
+        .bind(self.portfolio_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_latest_allocation_record(&self) -> RepoResult<Option<AllocationRecord>> {
+        let row: Option<(i64, String, String, Option<String>)> = sqlx::query_as(
+            "SELECT id, computed_at, payload, created_at FROM allocations WHERE portfolio_id = ?1 ORDER BY id DESC LIMIT 1",
+        )
+        .bind(self.portfolio_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|(id, computed_at, payload, created_at)| {
+            Ok(AllocationRecord {
+                id: Some(id),
+                computed_at,
+                payload: serde_json::from_str(&payload)?,
+                created_at,
+            })
+        })
+        .transpose()
+    }
+
+    async fn insert_var_snapshot(&self, snap: &VarSnapshot) -> RepoResult<()> {
+        let extra = snap.extra.as_ref().map(|v| v.to_string());
+        sqlx::query("INSERT OR REPLACE INTO history_var (timestamp, scope, name, method, confidence, horizon_days, position_value, var_value, es_value, observations, extra, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)")
+            .bind(&snap.timestamp)
+            .bind(&snap.scope)
+            .bind(&snap.name)
+            .bind(&snap.method)
+            .bind(snap.confidence)
+            .bind(snap.horizon_days)
+            .bind(snap.position_value)
+            .bind(snap.var_value)
+            .bind(snap.es_value)
+            .bind(snap.observations)
+            .bind(extra)
+            .bind(self.portfolio_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_var_history(
+        &self,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> RepoResult<Vec<VarSnapshot>> {
+        let mut qb = QueryBuilder::new("SELECT * FROM history_var");
+        push_scope_and_range(&mut qb, self.portfolio_id, from, to);
+        qb.push(" ORDER BY timestamp ASC, scope ASC, name ASC");
+        let rows = qb
+            .build_query_as::<VarSnapshot>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn insert_transaction(&self, tx: &Transaction) -> RepoResult<i64> {
+        // Venues name custody accounts; link both sides when no id was given
+        let mut account_ids = [tx.account_id, tx.to_account_id];
+        for (id, venue) in account_ids.iter_mut().zip([&tx.venue, &tx.to_venue]) {
+            if id.is_none()
+                && let Some(name) = venue.as_deref().map(str::trim).filter(|v| !v.is_empty())
+            {
+                *id = Some(self.ensure_account(name).await?);
+            }
+        }
+        let res = sqlx::query("INSERT INTO transactions (timestamp, kind, symbol, quantity, price, quote_currency, fee, fee_symbol, venue, to_venue, group_name, barca, external_id, notes, income_type, account_id, to_account_id, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)")
+            .bind(&tx.timestamp)
+            .bind(&tx.kind)
+            .bind(&tx.symbol)
+            .bind(tx.quantity)
+            .bind(tx.price)
+            .bind(tx.quote_currency.as_deref().unwrap_or("USD"))
+            .bind(tx.fee)
+            .bind(&tx.fee_symbol)
+            .bind(&tx.venue)
+            .bind(&tx.to_venue)
+            .bind(&tx.group_name)
+            .bind(&tx.barca)
+            .bind(&tx.external_id)
+            .bind(&tx.notes)
+            .bind(&tx.income_type)
+            .bind(account_ids[0])
+            .bind(account_ids[1])
+            .bind(self.portfolio_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn upsert_fx_rate(&self, rate: &FxRate) -> RepoResult<()> {
+        sqlx::query("INSERT INTO fx_rates (date, currency, brl_rate, source) VALUES (?1, ?2, ?3, ?4) ON CONFLICT(date, currency) DO UPDATE SET brl_rate = excluded.brl_rate, source = excluded.source")
+            .bind(&rate.date)
+            .bind(&rate.currency)
+            .bind(rate.brl_rate)
+            .bind(&rate.source)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_fx_rates(&self, currency: Option<&str>) -> RepoResult<Vec<FxRate>> {
+        let mut qb = QueryBuilder::new("SELECT * FROM fx_rates");
+        if let Some(c) = currency {
+            qb.push(" WHERE currency = ");
+            qb.push_bind(c);
+        }
+        qb.push(" ORDER BY currency ASC, date ASC");
+        let rows = qb.build_query_as::<FxRate>().fetch_all(&self.pool).await?;
+        Ok(rows)
+    }
+
+    async fn upsert_yield_position(&self, pos: &YieldPosition) -> RepoResult<()> {
+        sqlx::query("INSERT INTO yield_positions (symbol, venue, barca, expected_apy, notes, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT(portfolio_id, symbol, venue) DO UPDATE SET barca = excluded.barca, expected_apy = excluded.expected_apy, notes = excluded.notes, updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now')")
+            .bind(&pos.symbol)
+            .bind(&pos.venue)
+            .bind(&pos.barca)
+            .bind(pos.expected_apy)
+            .bind(&pos.notes)
+            .bind(self.portfolio_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_yield_positions(&self) -> RepoResult<Vec<YieldPosition>> {
+        let rows = sqlx::query_as::<_, YieldPosition>(
+            "SELECT * FROM yield_positions WHERE portfolio_id = ?1 ORDER BY symbol ASC, venue ASC",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn delete_yield_position(&self, symbol: &str, venue: &str) -> RepoResult<bool> {
+        let res = sqlx::query(
+            "DELETE FROM yield_positions WHERE symbol = ?1 AND venue = ?2 AND portfolio_id = ?3",
+        )
+        .bind(symbol)
+        .bind(venue)
+        .bind(self.portfolio_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn fetch_transactions(
+        &self,
+        symbol: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> RepoResult<Vec<Transaction>> {
+        let mut qb = QueryBuilder::new("SELECT * FROM transactions WHERE portfolio_id = ");
+        qb.push_bind(self.portfolio_id);
+        if let Some(s) = symbol {
+            qb.push(" AND symbol = ");
+            qb.push_bind(s);
+        }
+        if let Some(f) = from {
+            qb.push(" AND timestamp >= ");
+            qb.push_bind(f);
+        }
+        if let Some(t) = to {
+            qb.push(" AND timestamp <= ");
+            qb.push_bind(t);
+        }
+        qb.push(" ORDER BY timestamp ASC, id ASC");
+        let rows = qb
+            .build_query_as::<Transaction>()
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn insert_account(&self, acc: &Account) -> RepoResult<i64> {
+        let res = sqlx::query(
+            "INSERT INTO accounts (name, account_type, address, notes, portfolio_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(&acc.name)
+        .bind(&acc.account_type)
+        .bind(&acc.address)
+        .bind(&acc.notes)
+        .bind(self.portfolio_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn fetch_accounts(&self) -> RepoResult<Vec<Account>> {
+        let rows = sqlx::query_as::<_, Account>(
+            "SELECT * FROM accounts WHERE portfolio_id = ?1 ORDER BY name ASC",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_account(&self, id: i64) -> RepoResult<Option<Account>> {
+        let row = sqlx::query_as::<_, Account>(
+            "SELECT * FROM accounts WHERE id = ?1 AND portfolio_id = ?2",
+        )
+        .bind(id)
+        .bind(self.portfolio_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn update_account(&self, acc: &Account) -> RepoResult<bool> {
+        let res = sqlx::query(
+            "UPDATE accounts SET name = ?1, account_type = ?2, address = ?3, notes = ?4 WHERE id = ?5 AND portfolio_id = ?6",
+        )
+        .bind(&acc.name)
+        .bind(&acc.account_type)
+        .bind(&acc.address)
+        .bind(&acc.notes)
+        .bind(acc.id)
+        .bind(self.portfolio_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_account(&self, id: i64) -> RepoResult<bool> {
+        let res = sqlx::query("DELETE FROM accounts WHERE id = ?1 AND portfolio_id = ?2")
+            .bind(id)
+            .bind(self.portfolio_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn count_account_references(&self, id: i64) -> RepoResult<i64> {
+        let n: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM wallet_allocations WHERE account_id = ?1) + (SELECT COUNT(*) FROM transactions WHERE account_id = ?1 OR to_account_id = ?1)",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(n)
+    }
+
+    async fn ensure_account(&self, name: &str) -> RepoResult<i64> {
+        sqlx::query("INSERT OR IGNORE INTO accounts (name, portfolio_id) VALUES (?1, ?2)")
+            .bind(name.trim())
+            .bind(self.portfolio_id)
+            .execute(&self.pool)
+            .await?;
+        let id: i64 =
+            sqlx::query_scalar("SELECT id FROM accounts WHERE name = ?1 AND portfolio_id = ?2")
+                .bind(name.trim())
+                .bind(self.portfolio_id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(id)
+    }
+
+    async fn fetch_barca_targets(&self) -> RepoResult<Vec<BarcaTarget>> {
+        let rows = sqlx::query_as::<_, BarcaTarget>(
+            "SELECT market, barca, target_percent FROM barca_targets WHERE portfolio_id = ?1 ORDER BY market ASC, barca ASC",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn replace_barca_targets(&self, rows: &[BarcaTarget]) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM barca_targets WHERE portfolio_id = ?1")
+            .bind(self.portfolio_id)
+            .execute(&mut *tx)
+            .await?;
+        for t in rows {
+            sqlx::query("INSERT INTO barca_targets (portfolio_id, market, barca, target_percent) VALUES (?1, ?2, ?3, ?4)")
+                .bind(self.portfolio_id)
+                .bind(&t.market)
+                .bind(&t.barca)
+                .bind(t.target_percent)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_portfolio(&self, p: &Portfolio) -> RepoResult<i64> {
+        let res = sqlx::query(
+            "INSERT INTO portfolios (name, market, market_glide_to, market_glide_start, market_glide_days, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(&p.name)
+        .bind(&p.market)
+        .bind(&p.market_glide_to)
+        .bind(&p.market_glide_start)
+        .bind(p.market_glide_days)
+        .bind(&p.notes)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn fetch_portfolios(&self) -> RepoResult<Vec<Portfolio>> {
+        let rows = sqlx::query_as::<_, Portfolio>("SELECT * FROM portfolios ORDER BY id ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_portfolio(&self, id: i64) -> RepoResult<Option<Portfolio>> {
+        let row = sqlx::query_as::<_, Portfolio>("SELECT * FROM portfolios WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row)
+    }
+
+    async fn update_portfolio(&self, p: &Portfolio) -> RepoResult<bool> {
+        let res = sqlx::query(
+            "UPDATE portfolios SET name = ?1, market = ?2, market_glide_to = ?3, market_glide_start = ?4, market_glide_days = ?5, notes = ?6 WHERE id = ?7",
+        )
+        .bind(&p.name)
+        .bind(&p.market)
+        .bind(&p.market_glide_to)
+        .bind(&p.market_glide_start)
+        .bind(p.market_glide_days)
+        .bind(&p.notes)
+        .bind(p.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_portfolio(&self, id: i64) -> RepoResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        for table in [
+            "barca_targets",
+            "accounts",
+            "yield_positions",
+            "allocations",
+            "history_assets",
+            "history_barca",
+            "history_groups",
+            "history_totals",
+            "history_var",
+        ] {
+            sqlx::query(&format!("DELETE FROM {} WHERE portfolio_id = ?1", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let res = sqlx::query("DELETE FROM portfolios WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn count_portfolio_references(&self, id: i64) -> RepoResult<i64> {
+        let n: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM wallet_allocations WHERE portfolio_id = ?1) + (SELECT COUNT(*) FROM transactions WHERE portfolio_id = ?1) + (SELECT COUNT(*) FROM import_batches WHERE portfolio_id = ?1)",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(n)
+    }
+
+    fn portfolio_repo(&self, portfolio_id: i64) -> Arc<dyn HistoryRepo> {
+        Arc::new(self.for_portfolio(portfolio_id))
+    }
+
+    async fn insert_household(&self, h: &Household) -> RepoResult<i64> {
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query(
+            "INSERT INTO households (name, market, market_glide_to, market_glide_start, market_glide_days, notes) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(&h.name)
+        .bind(&h.market)
+        .bind(&h.market_glide_to)
+        .bind(&h.market_glide_start)
+        .bind(h.market_glide_days)
+        .bind(&h.notes)
+        .execute(&mut *tx)
+        .await?;
+        let id = res.last_insert_rowid();
+        replace_household_members(&mut tx, id, &h.members).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn fetch_households(&self) -> RepoResult<Vec<Household>> {
+        let mut rows = sqlx::query_as::<_, Household>("SELECT * FROM households ORDER BY id ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        for h in rows.iter_mut() {
+            h.members = self.household_members(h.id.unwrap_or_default()).await?;
+        }
+        Ok(rows)
+    }
+
+    async fn fetch_household(&self, id: i64) -> RepoResult<Option<Household>> {
+        let mut row = sqlx::query_as::<_, Household>("SELECT * FROM households WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        if let Some(h) = row.as_mut() {
+            h.members = self.household_members(id).await?;
+        }
+        Ok(row)
+    }
+
+    async fn update_household(&self, h: &Household) -> RepoResult<bool> {
+        let Some(id) = h.id else {
+            return Ok(false);
+        };
+        let mut tx = self.pool.begin().await?;
+        let res = sqlx::query(
+            "UPDATE households SET name = ?1, market = ?2, market_glide_to = ?3, market_glide_start = ?4, market_glide_days = ?5, notes = ?6 WHERE id = ?7",
+        )
+        .bind(&h.name)
+        .bind(&h.market)
+        .bind(&h.market_glide_to)
+        .bind(&h.market_glide_start)
+        .bind(h.market_glide_days)
+        .bind(&h.notes)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+        replace_household_members(&mut tx, id, &h.members).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_household(&self, id: i64) -> RepoResult<bool> {
+        // members, targets and history go with it (ON DELETE CASCADE)
+        let res = sqlx::query("DELETE FROM households WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn fetch_household_targets(&self, id: i64) -> RepoResult<Vec<BarcaTarget>> {
+        let rows = sqlx::query_as::<_, BarcaTarget>(
+            "SELECT market, barca, target_percent FROM household_targets WHERE household_id = ?1 ORDER BY market ASC, barca ASC",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn replace_household_targets(&self, id: i64, rows: &[BarcaTarget]) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM household_targets WHERE household_id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        for t in rows {
+            sqlx::query("INSERT INTO household_targets (household_id, market, barca, target_percent) VALUES (?1, ?2, ?3, ?4)")
+                .bind(id)
+                .bind(&t.market)
+                .bind(&t.barca)
+                .bind(t.target_percent)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_household_snapshots(
+        &self,
+        id: i64,
+        rows: &[HouseholdSnapshot],
+    ) -> RepoResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for r in rows {
+            sqlx::query("INSERT OR REPLACE INTO household_history (household_id, timestamp, level, name, value, current_percent, target_percent, contributions) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")
+                .bind(id)
+                .bind(&r.timestamp)
+                .bind(&r.level)
+                .bind(&r.name)
+                .bind(r.value)
+                .bind(r.current_percent)
+                .bind(r.target_percent)
+                .bind(r.contributions.as_ref().map(|v| v.to_string()))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn fetch_household_history(
+        &self,
+        id: i64,
+        level: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> RepoResult<Vec<HouseholdSnapshot>> {
+        let mut qb = QueryBuilder::<Sqlite>::new(
+            "SELECT timestamp, level, name, value, current_percent, target_percent, contributions FROM household_history WHERE household_id = ",
+        );
+        qb.push_bind(id);
+        qb.push(" AND level = ");
+        qb.push_bind(level.to_string());
+        if let Some(f) = from {
+            qb.push(" AND timestamp >= ");
+            qb.push_bind(f.to_string());
+        }
+        if let Some(t) = to {
+            qb.push(" AND timestamp <= ");
+            qb.push_bind(t.to_string());
+        }
+        qb.push(" ORDER BY timestamp ASC, name ASC");
+        let rows: Vec<HouseholdHistoryRow> = qb.build_query_as().fetch_all(&self.pool).await?;
+        rows.into_iter()
+            .map(
+                |(
+                    timestamp,
+                    level,
+                    name,
+                    value,
+                    current_percent,
+                    target_percent,
+                    contributions,
+                )| {
+                    Ok(HouseholdSnapshot {
+                        timestamp,
+                        level,
+                        name,
+                        value,
+                        current_percent,
+                        target_percent,
+                        contributions: contributions
+                            .map(|c| serde_json::from_str(&c))
+                            .transpose()?,
+                    })
+                },
+            )
+            .collect()
+    }
+    async fn insert_first_user(&self, user: &User) -> RepoResult<Option<i64>> {
+        let mut tx = self.pool.begin().await?;
+        // One statement checks and inserts, so concurrent first runs cannot both succeed
+        let res = sqlx::query(
+            "INSERT INTO users (username, password_hash, is_admin) SELECT ?1, ?2, 1 WHERE NOT EXISTS (SELECT 1 FROM users)",
+        )
+        .bind(&user.username)
+        .bind(&user.password_hash)
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Ok(None);
+        }
+        let id = res.last_insert_rowid();
+        sqlx::query(
+            "INSERT INTO portfolio_roles (user_id, portfolio_id, role) SELECT ?1, id, 'owner' FROM portfolios",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some(id))
+    }
+
+    async fn insert_user(&self, user: &User) -> RepoResult<i64> {
+        let res = sqlx::query(
+            "INSERT INTO users (username, password_hash, is_admin) VALUES (?1, ?2, ?3)",
+        )
+        .bind(&user.username)
+        .bind(&user.password_hash)
+        .bind(user.is_admin)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn fetch_users(&self) -> RepoResult<Vec<User>> {
+        let rows = sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY id ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_user(&self, id: i64) -> RepoResult<Option<User>> {
+        let row = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row)
+    }
+
+    async fn fetch_user_by_name(&self, username: &str) -> RepoResult<Option<User>> {
+        let row = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ?1")
+            .bind(username)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row)
+    }
+
+    async fn update_user_password(&self, id: i64, password_hash: &str) -> RepoResult<bool> {
+        let res = sqlx::query("UPDATE users SET password_hash = ?1 WHERE id = ?2")
+            .bind(password_hash)
+            .bind(id)
             .execute(&self.pool)
             .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_user(&self, id: i64) -> RepoResult<bool> {
+        // tokens and roles go with it (ON DELETE CASCADE)
+        let res = sqlx::query("DELETE FROM users WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn insert_api_token(&self, token: &ApiToken, token_hash: &str) -> RepoResult<i64> {
+        let res = sqlx::query(
+            "INSERT INTO api_tokens (user_id, name, token_hash, prefix, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(token.user_id)
+        .bind(&token.name)
+        .bind(token_hash)
+        .bind(&token.prefix)
+        .bind(&token.expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn fetch_api_tokens(&self, user_id: i64) -> RepoResult<Vec<ApiToken>> {
+        let rows = sqlx::query_as::<_, ApiToken>(
+            "SELECT id, user_id, name, prefix, expires_at, last_used_at, revoked_at, created_at FROM api_tokens WHERE user_id = ?1 ORDER BY id DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn find_api_token(&self, token_hash: &str) -> RepoResult<Option<ApiToken>> {
+        let row = sqlx::query_as::<_, ApiToken>(
+            "SELECT id, user_id, name, prefix, expires_at, last_used_at, revoked_at, created_at FROM api_tokens WHERE token_hash = ?1",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn touch_api_token(&self, id: i64) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE api_tokens SET last_used_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = ?1",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke_api_token(&self, user_id: i64, id: i64) -> RepoResult<bool> {
+        let res = sqlx::query(
+            "UPDATE api_tokens SET revoked_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = ?1 AND user_id = ?2 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn fetch_user_roles(&self, user_id: i64) -> RepoResult<Vec<PortfolioRole>> {
+        let rows = sqlx::query_as::<_, PortfolioRole>(
+            "SELECT r.user_id, u.username, r.portfolio_id, r.role FROM portfolio_roles r JOIN users u ON u.id = r.user_id WHERE r.user_id = ?1 ORDER BY r.portfolio_id ASC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_portfolio_roles(&self, portfolio_id: i64) -> RepoResult<Vec<PortfolioRole>> {
+        let rows = sqlx::query_as::<_, PortfolioRole>(
+            "SELECT r.user_id, u.username, r.portfolio_id, r.role FROM portfolio_roles r JOIN users u ON u.id = r.user_id WHERE r.portfolio_id = ?1 ORDER BY u.username ASC",
+        )
+        .bind(portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn set_portfolio_role(
+        &self,
+        user_id: i64,
+        portfolio_id: i64,
+        role: &str,
+    ) -> RepoResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO portfolio_roles (user_id, portfolio_id, role) VALUES (?1, ?2, ?3)",
+        )
+        .bind(user_id)
+        .bind(portfolio_id)
+        .bind(role)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_portfolio_role(&self, user_id: i64, portfolio_id: i64) -> RepoResult<bool> {
+        let res =
+            sqlx::query("DELETE FROM portfolio_roles WHERE user_id = ?1 AND portfolio_id = ?2")
+                .bind(user_id)
+                .bind(portfolio_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn insert_share_link(&self, link: &ShareLink) -> RepoResult<i64> {
+        let res = sqlx::query(
+            "INSERT INTO share_links (portfolio_id, label, masked, expires_at, created_by) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(self.portfolio_id)
+        .bind(&link.label)
+        .bind(link.masked)
+        .bind(&link.expires_at)
+        .bind(link.created_by)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.last_insert_rowid())
+    }
+
+    async fn fetch_share_links(&self) -> RepoResult<Vec<ShareLink>> {
+        let rows = sqlx::query_as::<_, ShareLink>(
+            "SELECT id, portfolio_id, label, masked, expires_at, created_by, last_used_at, revoked_at, created_at FROM share_links WHERE portfolio_id = ?1 ORDER BY id DESC",
+        )
+        .bind(self.portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_share_link(&self, id: i64) -> RepoResult<Option<ShareLink>> {
+        let row = sqlx::query_as::<_, ShareLink>(
+            "SELECT id, portfolio_id, label, masked, expires_at, created_by, last_used_at, revoked_at, created_at FROM share_links WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row)
+    }
+
+    async fn touch_share_link(&self, id: i64) -> RepoResult<()> {
+        sqlx::query(
+            "UPDATE share_links SET last_used_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = ?1",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
+
+    async fn revoke_share_link(&self, id: i64) -> RepoResult<bool> {
+        let res = sqlx::query(
+            "UPDATE share_links SET revoked_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = ?1 AND portfolio_id = ?2 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .bind(self.portfolio_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn app_secret(&self, name: &str, candidate: &str) -> RepoResult<String> {
+        sqlx::query("INSERT OR IGNORE INTO app_secrets (name, value) VALUES (?1, ?2)")
+            .bind(name)
+            .bind(candidate)
+            .execute(&self.pool)
+            .await?;
+        let value: String = sqlx::query_scalar("SELECT value FROM app_secrets WHERE name = ?1")
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(value)
+    }
+}
+
+type HouseholdHistoryRow = (
+    String,
+    String,
+    String,
+    Option<f64>,
+    Option<f64>,
+    Option<f64>,
+    Option<String>,
+);
+
+async fn replace_household_members(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    household_id: i64,
+    members: &[i64],
+) -> RepoResult<()> {
+    sqlx::query("DELETE FROM household_members WHERE household_id = ?1")
+        .bind(household_id)
+        .execute(&mut **tx)
+        .await?;
+    for portfolio_id in members {
+        sqlx::query("INSERT INTO household_members (household_id, portfolio_id) VALUES (?1, ?2)")
+            .bind(household_id)
+            .bind(portfolio_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -272,6 +1279,11 @@ mod tests {
             last_price: Some(10.0),
             notes: Some("Ledger".to_string()),
             created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
         };
         let wa2 = WalletAllocation {
             current_quantity: Some(0.5),
@@ -289,4 +1301,390 @@ mod tests {
         assert!((row.current_quantity.unwrap() - 1.5).abs() < f64::EPSILON);
         assert_eq!(row.target_percent.unwrap(), 40.0);
     }
+
+    #[tokio::test]
+    async fn notes_and_venues_link_to_accounts() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let repo = SqliteRepo::new(pool.clone());
+
+        let wa = WalletAllocation {
+            id: None,
+            symbol: "ETH".to_string(),
+            group_name: Some("Base".to_string()),
+            barca: Some("Base".to_string()),
+            target_percent: Some(10.0),
+            current_quantity: Some(2.0),
+            last_price: None,
+            notes: Some(" Ledger ".to_string()),
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        };
+        repo.insert_wallet_allocation(&wa).await.unwrap();
+        repo.insert_wallet_allocation(&WalletAllocation {
+            current_quantity: Some(3.0),
+            notes: Some("ledger".to_string()),
+            ..wa.clone()
+        })
+        .await
+        .unwrap();
+
+        let accounts = repo.fetch_accounts().await.unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].account_type, "other");
+        let id = accounts[0].id.unwrap();
+        // same account regardless of spelling: the newer row replaces the older one
+        let rows = repo
+            .fetch_current_wallet_allocations_by_account()
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].account_id, Some(id));
+        assert_eq!(rows[0].current_quantity, Some(3.0));
+        let current = repo.fetch_current_wallet_allocations().await.unwrap();
+        assert_eq!(current[0].account_id, Some(id));
+
+        assert_eq!(repo.ensure_account("LEDGER").await.unwrap(), id);
+        let binance = repo.ensure_account("Binance").await.unwrap();
+        assert_ne!(binance, id);
+        assert_eq!(repo.count_account_references(id).await.unwrap(), 2);
+        assert_eq!(repo.count_account_references(binance).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn wallet_allocations_as_of_ignores_later_rows() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let repo = SqliteRepo::new(pool.clone());
+        for (qty, at) in [
+            (1.0, "2025-01-01T00:00:00.000Z"),
+            (2.0, "2025-02-01T00:00:00.000Z"),
+            (3.0, "2025-03-01T00:00:00.000Z"),
+        ] {
+            sqlx::query("INSERT INTO wallet_allocations (symbol, group_name, barca, current_quantity, created_at) VALUES ('BTC', 'Hodl', 'Core', ?1, ?2)")
+                .bind(qty)
+                .bind(at)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let rows = repo
+            .fetch_wallet_allocations_as_of("2025-02-15T00:00:00.000Z")
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].current_quantity, Some(2.0));
+        let rows = repo
+            .fetch_wallet_allocations_as_of("2024-12-31T00:00:00.000Z")
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn import_batches_link_rows_and_roll_back_once() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let repo = SqliteRepo::new(pool.clone());
+        let batch = ImportBatch {
+            id: None,
+            source: "wallet.csv".to_string(),
+            content_hash: "abc".to_string(),
+            row_count: 0,
+            author: Some("csv-import".to_string()),
+            status: "applied".to_string(),
+            created_at: None,
+            rolled_back_at: None,
+            rolled_back_by: None,
+        };
+        let row = WalletAllocation {
+            id: None,
+            symbol: "BTC".to_string(),
+            group_name: Some("Hodl".to_string()),
+            barca: Some("Core".to_string()),
+            target_percent: Some(40.0),
+            current_quantity: Some(1.0),
+            last_price: None,
+            notes: Some("Ledger".to_string()),
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: Some("import".to_string()),
+            import_batch_id: None,
+        };
+        let id = repo
+            .insert_import_batch(&batch, std::slice::from_ref(&row))
+            .await
+            .unwrap();
+        let stored = repo
+            .find_applied_import_batch("abc")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((stored.id, stored.row_count), (Some(id), 1));
+        let rows = repo.fetch_wallet_allocations_by_batch(id).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].account_id.is_some());
+        let current = repo
+            .fetch_current_wallet_allocations_by_account()
+            .await
+            .unwrap();
+        assert_eq!(current[0].import_batch_id, Some(id));
+
+        let tombstone = WalletAllocation {
+            event: Some("retire".to_string()),
+            ..rows[0].clone()
+        };
+        assert!(
+            repo.rollback_import_batch(id, "ana", std::slice::from_ref(&tombstone))
+                .await
+                .unwrap()
+        );
+        assert!(!repo.rollback_import_batch(id, "ana", &[]).await.unwrap());
+        assert!(
+            repo.find_applied_import_batch("abc")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let batch = repo.fetch_import_batch(id).await.unwrap().unwrap();
+        assert_eq!(batch.status, "rolled_back");
+        assert_eq!(batch.rolled_back_by.as_deref(), Some("ana"));
+        assert!(
+            repo.fetch_current_wallet_allocations_by_account()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn portfolios_keep_rows_apart() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let default = SqliteRepo::new(pool.clone());
+        let id = default
+            .insert_portfolio(&Portfolio {
+                id: None,
+                name: "Daniela".to_string(),
+                market: Some("BearMarket".to_string()),
+                market_glide_to: None,
+                market_glide_start: None,
+                market_glide_days: None,
+                notes: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+        let other = default.for_portfolio(id);
+
+        let row = WalletAllocation {
+            id: None,
+            symbol: "BTC".to_string(),
+            group_name: Some("Hodl".to_string()),
+            barca: Some("Core".to_string()),
+            target_percent: Some(40.0),
+            current_quantity: Some(1.0),
+            last_price: None,
+            notes: Some("Binance".to_string()),
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        };
+        default.insert_wallet_allocation(&row).await.unwrap();
+        other
+            .insert_wallet_allocation(&WalletAllocation {
+                current_quantity: Some(0.25),
+                ..row.clone()
+            })
+            .await
+            .unwrap();
+        // Same account name, one account per portfolio
+        let (a, b) = (
+            default.fetch_accounts().await.unwrap(),
+            other.fetch_accounts().await.unwrap(),
+        );
+        assert_eq!((a.len(), b.len()), (1, 1));
+        assert_ne!(a[0].id, b[0].id);
+        assert!(
+            other
+                .fetch_account(a[0].id.unwrap())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let current = other.fetch_current_wallet_allocations().await.unwrap();
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].current_quantity, Some(0.25));
+
+        // The same snapshot timestamp and file hash are allowed once per portfolio
+        let total = TotalSnapshot {
+            id: None,
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            total_value: Some(100.0),
+            extra: None,
+            created_at: None,
+        };
+        default.insert_total_snapshot(&total).await.unwrap();
+        other
+            .insert_total_snapshot(&TotalSnapshot {
+                total_value: Some(5.0),
+                ..total
+            })
+            .await
+            .unwrap();
+        let totals = other.fetch_totals(None, None).await.unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].total_value, Some(5.0));
+
+        other
+            .replace_barca_targets(&[BarcaTarget {
+                market: "BearMarket".to_string(),
+                barca: "Core".to_string(),
+                target_percent: 100.0,
+            }])
+            .await
+            .unwrap();
+        assert!(default.fetch_barca_targets().await.unwrap().is_empty());
+        assert_eq!(other.fetch_barca_targets().await.unwrap().len(), 1);
+
+        assert_eq!(default.count_portfolio_references(id).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn households_keep_members_targets_and_history() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let repo = SqliteRepo::new(pool.clone());
+        let other = repo
+            .insert_portfolio(&Portfolio {
+                id: None,
+                name: "Daniela".to_string(),
+                market: None,
+                market_glide_to: None,
+                market_glide_start: None,
+                market_glide_days: None,
+                notes: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+        let id = repo
+            .insert_household(&Household {
+                id: None,
+                name: "Family".to_string(),
+                members: vec![DEFAULT_PORTFOLIO_ID, other],
+                market: None,
+                market_glide_to: None,
+                market_glide_start: None,
+                market_glide_days: None,
+                notes: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+        repo.replace_household_targets(
+            id,
+            &[BarcaTarget {
+                market: "BullMarket".to_string(),
+                barca: "Base".to_string(),
+                target_percent: 60.0,
+            }],
+        )
+        .await
+        .unwrap();
+        let snapshot = HouseholdSnapshot {
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+            level: "barca".to_string(),
+            name: "Base".to_string(),
+            value: Some(100.0),
+            current_percent: Some(50.0),
+            target_percent: Some(60.0),
+            contributions: Some(serde_json::json!([{"portfolio_id": other, "value": 100.0}])),
+        };
+        repo.insert_household_snapshots(id, std::slice::from_ref(&snapshot))
+            .await
+            .unwrap();
+        // the same timestamp, level and name replaces the row
+        repo.insert_household_snapshots(
+            id,
+            &[HouseholdSnapshot {
+                value: Some(120.0),
+                ..snapshot.clone()
+            }],
+        )
+        .await
+        .unwrap();
+
+        let rows = repo
+            .fetch_household_history(id, "barca", None, None)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].value, Some(120.0));
+        assert_eq!(
+            rows[0].contributions.as_ref().unwrap()[0]["portfolio_id"],
+            other
+        );
+        assert!(
+            repo.fetch_household_history(id, "groups", None, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(repo.fetch_household_targets(id).await.unwrap().len(), 1);
+
+        // deleting a member portfolio takes it out of the household
+        repo.delete_portfolio(other).await.unwrap();
+        let h = repo.fetch_household(id).await.unwrap().unwrap();
+        assert_eq!(h.members, vec![DEFAULT_PORTFOLIO_ID]);
+
+        assert!(repo.delete_household(id).await.unwrap());
+        assert!(repo.fetch_household_targets(id).await.unwrap().is_empty());
+        assert!(
+            repo.fetch_household_history(id, "barca", None, None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn only_one_first_user_is_inserted() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let repo = SqliteRepo::new(pool.clone());
+        let user = |name: &str| User {
+            id: None,
+            username: name.to_string(),
+            password_hash: "hash".to_string(),
+            is_admin: false,
+            created_at: None,
+        };
+
+        let (ana, bia) = (user("ana"), user("bia"));
+        let (a, b) = tokio::join!(repo.insert_first_user(&ana), repo.insert_first_user(&bia));
+        let ids: Vec<i64> = [a.unwrap(), b.unwrap()].into_iter().flatten().collect();
+        assert_eq!(ids.len(), 1);
+        let users = repo.fetch_users().await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert!(users[0].is_admin);
+        let roles = repo.fetch_user_roles(ids[0]).await.unwrap();
+        assert_eq!(roles.len(), 1);
+        assert_eq!(roles[0].role, "owner");
+        assert!(
+            repo.insert_first_user(&user("caio"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
 }
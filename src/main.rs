@@ -1,7 +1,6 @@
 use infra::sqlite::SqliteRepo;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use tokio;
 mod infra;
 use axum::http::StatusCode;
 use axum::{Router, response::Json, routing::get};
@@ -14,19 +13,49 @@ use tower_http::cors::{Any, CorsLayer};
 use tracing::{error, info, warn};
 
 mod api_client;
+mod csv_dialect;
 mod csv_store;
+mod wallet_files;
 use crate::api_client::{CryptoProvider, ReqwestCryptoProvider};
 use crate::domain::repository::HistoryRepo;
+use wallet_files::{FileFormat, render};
 mod usecases;
+use usecases::accounts_service::AccountsService;
 use usecases::allocations_service::AllocationsService;
+use usecases::analytics_service::{AnalyticsService, AnalyticsWindow};
+use usecases::auth_service::{Access, AuthService, CurrentUser, Role, required_access};
+use usecases::cost_basis::{CostMethod, year_start};
+use usecases::export_service::ExportService;
 use usecases::history_service::HistoryService;
+use usecases::households_service::HouseholdsService;
+use usecases::import_service::{ImportService, resolve_import_path};
+use usecases::ledger_service::LedgerService;
+use usecases::market_blend::MarketSelection;
+use usecases::monte_carlo::{Method, ProjectionConfig};
+use usecases::portfolios_service::PortfoliosService;
+use usecases::returns::FlowSource;
+use usecases::share_service::{
+    ShareClaims, ShareService, mask_allocations, mask_history, share_readable,
+};
+use usecases::simulation::Scenario;
+use usecases::stress_tests::load_scenarios;
+use usecases::tax_br::rows_csv;
+use usecases::tax_service::TaxService;
+use usecases::timeseries::{Interval, window_start};
+use usecases::wallet_changes::{EditError, HoldingChange};
+use usecases::wallet_service::WalletService;
+use usecases::yield_service::YieldService;
 mod domain;
 use axum::extract::State as AxumState;
-use axum::extract::State;
+use axum::extract::{Extension, FromRequestParts, MatchedPath, Request, State};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use domain::models::{DEFAULT_PORTFOLIO_ID, Household, Portfolio};
 // CSV history module kept for legacy utilities (no fallback used)
 // mod csv_history; // legacy CSV helpers removed from runtime flows
 use axum::extract::Query;
-use chrono::Utc;
+use chrono::{Datelike, Utc};
 use serde::Deserialize as SerdeDeserialize;
 
 #[derive(Clone)]
@@ -84,10 +113,21 @@ struct PriceInfo {
 
 // Read wallet allocations from CSV
 
-#[tracing::instrument(skip(state))]
-async fn api_allocations(
-    State(state): AxumState<AppState>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+// API key and market regime selection shared by every handler that prices the wallet
+fn pricing_config(portfolio: &Portfolio) -> Result<(String, MarketSelection), ApiError> {
+    with_api_key(MarketSelection::for_portfolio(portfolio))
+}
+
+// Same for a household, whose own regime settings apply
+fn household_pricing_config(h: &Household) -> Result<(String, MarketSelection), ApiError> {
+    with_api_key(MarketSelection::for_household(h))
+}
+
+fn with_api_key(
+    market: Result<MarketSelection, String>,
+) -> Result<(String, MarketSelection), ApiError> {
     dotenv().ok();
     let api_key = match std::env::var("API_KEY") {
         Ok(k) => k,
@@ -100,15 +140,275 @@ async fn api_allocations(
         }
     };
 
-    let current_market =
-        std::env::var("CURRENT_MARKET").unwrap_or_else(|_| "BullMarket".to_string());
+    // CURRENT_MARKET may name a single regime or a blend (e.g. "BullMarket:70,BearMarket:30"),
+    // optionally gliding towards MARKET_GLIDE_TO over MARKET_GLIDE_DAYS days; a portfolio
+    // with its own `market` uses that instead
+    let market = match market {
+        Ok(m) => m,
+        Err(e) => {
+            error!(error = %e, "Invalid market regime configuration");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Invalid market configuration: {}", e)})),
+            ));
+        }
+    };
+    Ok((api_key, market))
+}
 
-    // Use AllocationsService to fetch cryptos, read barca targets, compute allocations and persist allocation record
-    let alloc_svc = AllocationsService::new(state.provider.clone(), state.history_repo.clone());
-    let result = match alloc_svc
-        .compute_and_record(&api_key, &current_market)
+// Portfolio a request works on, with the repository scoped to it: the id from an
+// `/api/portfolios/{id}/...` path (see `scope_portfolio_path`), otherwise the default portfolio
+struct Scope {
+    portfolio: Portfolio,
+    repo: Arc<SqliteRepo>,
+}
+
+// Set by `scope_portfolio_path` on requests under /api/portfolios/{id}/
+#[derive(Clone, Copy)]
+struct PortfolioId(i64);
+
+impl FromRequestParts<AppState> for Scope {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, ApiError> {
+        let id = parts
+            .extensions
+            .get::<PortfolioId>()
+            .map_or(DEFAULT_PORTFOLIO_ID, |p| p.0);
+        match state.history_repo.fetch_portfolio(id).await {
+            Ok(Some(portfolio)) => Ok(Scope {
+                portfolio,
+                repo: Arc::new(state.history_repo.for_portfolio(id)),
+            }),
+            Ok(None) => Err(unknown_portfolio()),
+            Err(e) => {
+                error!(error = %e, "Failed fetching portfolio");
+                Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": format!("Failed fetching portfolio: {}", e)})),
+                ))
+            }
+        }
+    }
+}
+
+// `/api/portfolios/{id}/<route>` serves `/api/<route>` for that portfolio: the path is
+// rewritten before routing and the id passed on to `Scope`
+async fn scope_portfolio_path(mut req: Request, next: Next) -> Response {
+    let scoped = req
+        .uri()
+        .path()
+        .strip_prefix("/api/portfolios/")
+        .and_then(|rest| rest.split_once('/'))
+        .and_then(|(id, route)| Some((id.parse::<i64>().ok()?, route)))
+        .filter(|(_, route)| !route.is_empty())
+        .map(|(id, route)| (id, format!("/api/{}", route)));
+    if let Some((id, path)) = scoped {
+        let path_and_query = match req.uri().query() {
+            Some(q) => format!("{}?{}", path, q),
+            None => path,
+        };
+        let mut parts = req.uri().clone().into_parts();
+        if let Ok(pq) = path_and_query.parse() {
+            parts.path_and_query = Some(pq);
+            if let Ok(uri) = axum::http::Uri::from_parts(parts) {
+                *req.uri_mut() = uri;
+                req.extensions_mut().insert(PortfolioId(id));
+            }
+        }
+    }
+    next.run(req).await
+}
+
+fn auth_error(status: StatusCode, message: &str) -> Response {
+    let mut res = (status, Json(json!({"error": message}))).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        res.headers_mut().insert(
+            axum::http::header::WWW_AUTHENTICATE,
+            axum::http::HeaderValue::from_static("Bearer"),
+        );
+    }
+    res
+}
+
+// Every route needs `Authorization: Bearer <token>` except login and first-run setup, and the
+// caller's role on the portfolio the request works on must cover what the route needs (see
+// `required_access`). The caller is passed on to the handlers as `CurrentUser`. A share token
+// (`?share=` or `X-Share-Token`) replaces the bearer token on the routes it can read.
+async fn require_auth(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    if let Some(token) = share_token(&req) {
+        return require_share(state, req, next, &route, &token).await;
+    }
+    let portfolio_id = if route == "/api/portfolios/{id}" {
+        req.uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .and_then(|id| id.parse().ok())
+    } else {
+        req.extensions().get::<PortfolioId>().map(|p| p.0)
+    };
+    let access = required_access(req.method().as_str(), &route, portfolio_id);
+    if access == Access::Public {
+        return next.run(req).await;
+    }
+
+    let token = req
+        .headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+    let Some(token) = token else {
+        return auth_error(StatusCode::UNAUTHORIZED, "Missing bearer token");
+    };
+    let user = match AuthService::new(state.history_repo.clone())
+        .authenticate(token)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => return auth_error(StatusCode::UNAUTHORIZED, "Invalid or expired token"),
+        Err(e) => {
+            error!(error = %e, "Failed checking token");
+            return auth_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed checking token: {}", e),
+            );
+        }
+    };
+    let denied = match access {
+        Access::Public | Access::Authenticated => None,
+        Access::Admin => (!user.is_admin).then(|| "Requires an admin user".to_string()),
+        Access::Portfolio(id, role) => (!user.can(id, role))
+            .then(|| format!("Requires the {} role on portfolio {}", role.as_str(), id)),
+    };
+    if let Some(message) = denied {
+        return auth_error(StatusCode::FORBIDDEN, &message);
+    }
+    req.extensions_mut().insert(user);
+    next.run(req).await
+}
+
+fn share_token(req: &Request) -> Option<String> {
+    let from_query = req.uri().query().and_then(|q| {
+        q.split('&')
+            .find_map(|pair| pair.strip_prefix("share="))
+            .map(str::to_string)
+    });
+    from_query
+        .or_else(|| {
+            req.headers()
+                .get("x-share-token")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        })
+        .filter(|t| !t.trim().is_empty())
+}
+
+// Share tokens only read allocations and history of the portfolio they were issued for; the
+// unprefixed routes serve that portfolio. Handlers see the claims as `ShareClaims`.
+async fn require_share(
+    state: AppState,
+    mut req: Request,
+    next: Next,
+    route: &str,
+    token: &str,
+) -> Response {
+    if !share_readable(req.method().as_str(), route) {
+        return auth_error(
+            StatusCode::FORBIDDEN,
+            "Share links only read allocations and history",
+        );
+    }
+    let claims = match ShareService::new(state.history_repo.clone())
+        .resolve(token)
         .await
     {
+        Ok(Ok(claims)) => claims,
+        Ok(Err(e)) => return auth_error(StatusCode::UNAUTHORIZED, &e),
+        Err(e) => {
+            error!(error = %e, "Failed checking share token");
+            return auth_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed checking share token: {}", e),
+            );
+        }
+    };
+    match req.extensions().get::<PortfolioId>() {
+        Some(p) if p.0 != claims.portfolio_id => {
+            return auth_error(
+                StatusCode::FORBIDDEN,
+                &format!("Share link is for portfolio {}", claims.portfolio_id),
+            );
+        }
+        Some(_) => {}
+        None => {
+            req.extensions_mut()
+                .insert(PortfolioId(claims.portfolio_id));
+        }
+    }
+    req.extensions_mut().insert(claims);
+    next.run(req).await
+}
+
+#[derive(SerdeDeserialize, Debug)]
+struct AllocationsQuery {
+    // fifo (default), lifo, average or hifo
+    cost_method: Option<String>,
+}
+
+fn cost_method_param(s: Option<&str>) -> Result<Option<CostMethod>, ApiError> {
+    s.map(CostMethod::parse)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))
+}
+
+// Read-only: computes the current allocations without recording anything
+#[tracing::instrument(skip(state, scope), fields(portfolio = scope.repo.portfolio_id))]
+async fn api_allocations(
+    State(state): AxumState<AppState>,
+    scope: Scope,
+    share: Option<Extension<ShareClaims>>,
+    Query(q): Query<AllocationsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let (api_key, market) = pricing_config(&scope.portfolio)?;
+    let cost_method = cost_method_param(q.cost_method.as_deref())?;
+    let alloc_svc = AllocationsService::new(state.provider.clone(), scope.repo.clone())
+        .with_cost_method(cost_method);
+    match alloc_svc.compute(&api_key, &market).await {
+        Ok(r) if share.is_some_and(|Extension(s)| s.masked) => Ok(Json(mask_allocations(&r))),
+        Ok(r) => Ok(Json(r)),
+        Err(e) => {
+            error!(error = %e, "Failed computing allocations");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing allocations: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Same report, also stored as an allocation record plus history snapshots
+// (a write, so it needs the editor role)
+#[tracing::instrument(skip(state, scope), fields(portfolio = scope.repo.portfolio_id))]
+async fn api_record_allocations(
+    State(state): AxumState<AppState>,
+    scope: Scope,
+    Query(q): Query<AllocationsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let (api_key, market) = pricing_config(&scope.portfolio)?;
+    let cost_method = cost_method_param(q.cost_method.as_deref())?;
+
+    // Use AllocationsService to fetch cryptos, read barca targets, compute allocations and persist allocation record
+    let alloc_svc = AllocationsService::new(state.provider.clone(), scope.repo.clone())
+        .with_cost_method(cost_method);
+    let result = match alloc_svc.compute_and_record(&api_key, &market).await {
         Ok(r) => r,
         Err(e) => {
             error!(error = %e, "Failed computing allocations");
@@ -143,8 +443,7 @@ async fn api_allocations(
         .map(|a| a.get("value").and_then(|v| v.as_f64()).unwrap_or(0.0))
         .sum::<f64>();
     // Persist snapshots into DB via use-case/service (DB-only; CSV legacy persistence removed)
-    let history_svc =
-        crate::usecases::history_service::HistoryService::new(state.history_repo.clone());
+    let history_svc = crate::usecases::history_service::HistoryService::new(scope.repo.clone());
     history_svc
         .persist_snapshots(ts, &per_asset, &per_group, &per_barca, total_value)
         .await;
@@ -153,18 +452,87 @@ async fn api_allocations(
     Ok(Json(result))
 }
 
+// What-if simulation: price shocks and quantity adjustments on top of live data, never persisted
+async fn api_simulate(
+    State(state): AxumState<AppState>,
+    scope: Scope,
+    axum::extract::Json(scenario): axum::extract::Json<Scenario>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let (api_key, market) = pricing_config(&scope.portfolio)?;
+    let alloc_svc = AllocationsService::new(state.provider.clone(), scope.repo.clone());
+    match alloc_svc.simulate(&api_key, &market, &scenario).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed simulating allocations");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed simulating allocations: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct StressQuery {
+    scenario: Option<String>,
+}
+
+// Run the named stress scenarios (STRESS_SCENARIOS_PATH, default stress_scenarios.json)
+async fn api_stress_tests(
+    State(state): AxumState<AppState>,
+    scope: Scope,
+    Query(q): Query<StressQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let (api_key, market) = pricing_config(&scope.portfolio)?;
+    let path = std::env::var("STRESS_SCENARIOS_PATH")
+        .unwrap_or_else(|_| "stress_scenarios.json".to_string());
+    let mut scenarios = match load_scenarios(&path) {
+        Ok(s) => s,
+        Err(e) => {
+            error!(error = %e, path = %path, "Failed loading stress scenarios");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed loading stress scenarios: {}", e)})),
+            ));
+        }
+    };
+    if let Some(name) = &q.scenario {
+        scenarios.retain(|s| &s.name == name);
+        if scenarios.is_empty() {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": format!("Unknown stress scenario '{}'", name)})),
+            ));
+        }
+    }
+    let alloc_svc = AllocationsService::new(state.provider.clone(), scope.repo.clone());
+    match alloc_svc.stress_test(&api_key, &market, &scenarios).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed running stress tests");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed running stress tests: {}", e)})),
+            ))
+        }
+    }
+}
+
 #[derive(SerdeDeserialize)]
 struct HistoryQuery {
     level: Option<String>,
 }
 
 async fn api_history(
-    State(state): AxumState<AppState>,
+    scope: Scope,
+    share: Option<Extension<ShareClaims>>,
     Query(q): Query<HistoryQuery>,
 ) -> Json<serde_json::Value> {
     let level = q.level.unwrap_or_else(|| "totals".to_string());
-    let svc = HistoryService::new(state.history_repo.clone());
+    let svc = HistoryService::new(scope.repo.clone());
     match svc.fetch_history(&level).await {
+        Ok(v) if share.is_some_and(|s| s.masked) => Json(mask_history(&v)),
         Ok(v) => Json(v),
         Err(e) => {
             error!(error = %e, "DB history fetch failed");
@@ -174,82 +542,2165 @@ async fn api_history(
     }
 }
 
-#[derive(serde::Deserialize)]
-struct ImportPayload {
-    path: Option<String>,
+#[derive(SerdeDeserialize)]
+struct AnalyticsQuery {
+    // Lookback such as 30d, 12w, 6m, 1y or all (ignored when `from` is given)
+    window: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    // daily (default) or weekly resampling
+    interval: Option<String>,
+    // Annual risk-free rate in percent
+    risk_free: Option<f64>,
+    // Symbol used for beta (default BTC)
+    benchmark: Option<String>,
+    // Cash flow source for returns: auto (default), ledger or inferred
+    flows: Option<String>,
 }
 
-async fn import_wallets_handler(
-    State(state): AxumState<AppState>,
-    axum::extract::Json(payload): axum::extract::Json<ImportPayload>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    let path = payload
-        .path
-        .unwrap_or_else(|| "wallet_allocations.csv".to_string());
-    let svc = HistoryService::new(state.history_repo.clone());
-    match svc.import_wallet_allocations_from_path(&path).await {
-        Ok(count) => Ok(Json(json!({"imported": count}))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({"error": format!("Failed import: {}", e)})),
+fn analytics_window(q: &AnalyticsQuery) -> Result<AnalyticsWindow, ApiError> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    let interval =
+        Interval::parse(q.interval.as_deref().unwrap_or("daily")).map_err(bad_request)?;
+    let from = match &q.from {
+        Some(f) => Some(f.clone()),
+        None => {
+            window_start(q.window.as_deref().unwrap_or("all"), Utc::now()).map_err(bad_request)?
+        }
+    };
+    Ok(AnalyticsWindow {
+        from,
+        to: q.to.clone(),
+        interval,
+    })
+}
+
+async fn api_analytics_risk(
+    scope: Scope,
+    Query(q): Query<AnalyticsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let window = analytics_window(&q)?;
+    let risk_free = q.risk_free.unwrap_or(0.0) / 100.0;
+    let svc = AnalyticsService::new(scope.repo.clone());
+    match svc.risk_metrics(&window, risk_free).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing risk metrics");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing risk metrics: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_analytics_returns(
+    scope: Scope,
+    Query(q): Query<AnalyticsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let window = analytics_window(&q)?;
+    let source = FlowSource::parse(q.flows.as_deref().unwrap_or("auto"))
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    let svc = AnalyticsService::new(scope.repo.clone());
+    match svc.returns(&window, source).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing returns");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing returns: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_analytics_correlation(
+    scope: Scope,
+    Query(q): Query<AnalyticsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let window = analytics_window(&q)?;
+    let benchmark = q.benchmark.clone().unwrap_or_else(|| "BTC".to_string());
+    let svc = AnalyticsService::new(scope.repo.clone());
+    match svc.correlation(&window, &benchmark).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing correlations");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing correlations: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct ProjectionQuery {
+    window: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    interval: Option<String>,
+    // bootstrap (default) or parametric
+    method: Option<String>,
+    horizon_days: Option<i64>,
+    paths: Option<usize>,
+    rebalance_every_days: Option<i64>,
+    goal: Option<f64>,
+    seed: Option<u64>,
+}
+
+async fn api_analytics_projection(
+    scope: Scope,
+    Query(q): Query<ProjectionQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    let window = analytics_window(&AnalyticsQuery {
+        window: q.window.clone(),
+        from: q.from.clone(),
+        to: q.to.clone(),
+        interval: q.interval.clone(),
+        risk_free: None,
+        benchmark: None,
+        flows: None,
+    })?;
+    let method = Method::parse(q.method.as_deref().unwrap_or("bootstrap")).map_err(bad_request)?;
+    let period_days = match window.interval {
+        Interval::Daily => 1,
+        Interval::Weekly => 7,
+    };
+    let horizon_days = q.horizon_days.unwrap_or(365).clamp(1, 3650);
+    let rebalance_days = q.rebalance_every_days.unwrap_or(30).max(0);
+    let cfg = ProjectionConfig {
+        horizon_periods: (horizon_days / period_days).max(1) as usize,
+        period_days,
+        paths: q.paths.unwrap_or(1000).clamp(1, 10_000),
+        method,
+        // rounded up to whole periods; the response reports the effective interval
+        rebalance_every: (rebalance_days as usize).div_ceil(period_days as usize),
+        goal_value: q.goal,
+        seed: q.seed.unwrap_or_else(|| Utc::now().timestamp() as u64),
+    };
+    let svc = AnalyticsService::new(scope.repo.clone());
+    match svc.projection(&window, &cfg).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({"error": format!("Failed projecting portfolio: {}", e)})),
         )),
+        Err(e) => {
+            error!(error = %e, "Failed projecting portfolio");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed projecting portfolio: {}", e)})),
+            ))
+        }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    dotenv().ok();
+#[derive(SerdeDeserialize)]
+struct VarQuery {
+    window: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    // Comma separated confidence levels in percent (default 95,99)
+    confidence: Option<String>,
+    // Comma separated horizons in days (default 1,10)
+    horizon: Option<String>,
+}
 
-    // Initialize tracing subscriber for logging
-    tracing_subscriber::fmt::init();
+fn parse_list<T: std::str::FromStr>(raw: Option<&str>, default: &str) -> Result<Vec<T>, String> {
+    raw.unwrap_or(default)
+        .split(',')
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| {
+            p.trim()
+                .parse::<T>()
+                .map_err(|_| format!("invalid list value '{}'", p.trim()))
+        })
+        .collect()
+}
 
-    // DB pool and repo
-    let database_url =
-        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://data/crypto.db".to_string());
-    let pool = SqlitePool::connect(&database_url)
+async fn api_analytics_var(
+    scope: Scope,
+    Query(q): Query<VarQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    value_at_risk(scope, q, false).await
+}
+
+// Same figures, stored in history_var (a write, so it needs the editor role)
+async fn api_record_var(
+    scope: Scope,
+    Query(q): Query<VarQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    value_at_risk(scope, q, true).await
+}
+
+async fn value_at_risk(
+    scope: Scope,
+    q: VarQuery,
+    persist: bool,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    let window = analytics_window(&AnalyticsQuery {
+        window: Some(q.window.clone().unwrap_or_else(|| "365d".to_string())),
+        from: q.from.clone(),
+        to: q.to.clone(),
+        interval: None,
+        risk_free: None,
+        benchmark: None,
+        flows: None,
+    })?;
+    let confidences: Vec<f64> = parse_list::<f64>(q.confidence.as_deref(), "95,99")
+        .map_err(bad_request)?
+        .into_iter()
+        .map(|c| if c > 1.0 { c / 100.0 } else { c })
+        .collect();
+    if confidences.iter().any(|c| *c <= 0.0 || *c >= 1.0) {
+        return Err(bad_request(
+            "confidence must be between 0 and 100".to_string(),
+        ));
+    }
+    let horizons: Vec<usize> = parse_list(q.horizon.as_deref(), "1,10").map_err(bad_request)?;
+    if horizons.contains(&0) {
+        return Err(bad_request("horizon must be at least 1 day".to_string()));
+    }
+    let svc = AnalyticsService::new(scope.repo.clone());
+    match svc
+        .value_at_risk(&window, &confidences, &horizons, persist)
         .await
-        .expect("failed to connect to db");
+    {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing value at risk");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing value at risk: {}", e)})),
+            ))
+        }
+    }
+}
 
-    // Run migrations (if any) from ./migrations
-    if let Err(e) = sqlx::migrate!("./migrations").run(&pool).await {
-        error!(error = %e, "Failed to run migrations");
-    } else {
-        info!("Migrations applied");
+// Accepts a single transaction or an array of them
+#[derive(SerdeDeserialize)]
+#[serde(untagged)]
+enum TransactionsPayload {
+    Many(Vec<domain::models::Transaction>),
+    One(Box<domain::models::Transaction>),
+}
+
+async fn api_create_transactions(
+    scope: Scope,
+    axum::extract::Json(payload): axum::extract::Json<TransactionsPayload>,
+) -> Result<(StatusCode, Json<serde_json::Value>), ApiError> {
+    let txs = match payload {
+        TransactionsPayload::Many(v) => v,
+        TransactionsPayload::One(t) => vec![*t],
+    };
+    let svc = LedgerService::new(scope.repo.clone());
+    match svc.record(txs).await {
+        Ok(Ok(ids)) => Ok((StatusCode::CREATED, Json(json!({"inserted": ids})))),
+        Ok(Err(errors)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid transactions", "errors": errors})),
+        )),
+        Err(e) => {
+            error!(error = %e, "Failed recording transactions");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed recording transactions: {}", e)})),
+            ))
+        }
     }
+}
 
-    let history_repo = std::sync::Arc::new(SqliteRepo::new(pool.clone()));
-    ensure_wallet_allocations_seeded(history_repo.clone()).await;
+#[derive(SerdeDeserialize)]
+struct TransactionsQuery {
+    symbol: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
 
-    let backend_repo = history_repo.clone();
-    let backend = async move {
-        // initialize provider and app state
-        let provider = Arc::new(ReqwestCryptoProvider::new());
-        let app_state = AppState {
-            provider: provider.clone(),
-            history_repo: backend_repo.clone(),
-        };
+async fn api_list_transactions(
+    scope: Scope,
+    Query(q): Query<TransactionsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = LedgerService::new(scope.repo.clone());
+    match svc
+        .list(q.symbol.as_deref(), q.from.as_deref(), q.to.as_deref())
+        .await
+    {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching transactions");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching transactions: {}", e)})),
+            ))
+        }
+    }
+}
 
-        let app = Router::new()
-            .route("/api/allocations", get(api_allocations))
-            .route("/api/history", get(api_history))
-            .route(
-                "/api/import_wallets",
-                axum::routing::post(import_wallets_handler),
-            )
-            .with_state(app_state)
-            .layer(
-                CorsLayer::new()
-                    .allow_origin(Any)
-                    .allow_methods(Any)
-                    .allow_headers(Any),
-            );
+#[derive(SerdeDeserialize)]
+struct HoldingsQuery {
+    // RFC3339 timestamp; defaults to now (all transactions)
+    as_of: Option<String>,
+}
 
-        serve(app, 3001).await;
+async fn api_transaction_holdings(
+    scope: Scope,
+    Query(q): Query<HoldingsQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = LedgerService::new(scope.repo.clone());
+    match svc.holdings(q.as_of.as_deref()).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed deriving holdings");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed deriving holdings: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct CostBasisQuery {
+    method: Option<String>,
+    // Calendar year for realized P&L year-to-date (default: current year)
+    year: Option<i32>,
+}
+
+async fn api_cost_basis(
+    scope: Scope,
+    Query(q): Query<CostBasisQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let method = match cost_method_param(q.method.as_deref())? {
+        Some(m) => m,
+        None => CostMethod::from_env()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))))?,
+    };
+    let year = q.year.unwrap_or_else(|| Utc::now().year());
+    let Some(ytd_start) = year_start(year) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("invalid year {}", year)})),
+        ));
+    };
+    let svc = LedgerService::new(scope.repo.clone());
+    match svc.cost_basis(method, ytd_start).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing cost basis");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing cost basis: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Accepts a single rate or an array of them
+#[derive(SerdeDeserialize)]
+#[serde(untagged)]
+enum FxRatesPayload {
+    Many(Vec<domain::models::FxRate>),
+    One(domain::models::FxRate),
+}
+
+async fn api_create_fx_rates(
+    scope: Scope,
+    axum::extract::Json(payload): axum::extract::Json<FxRatesPayload>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let rates = match payload {
+        FxRatesPayload::Many(v) => v,
+        FxRatesPayload::One(r) => vec![r],
     };
+    let svc = TaxService::new(scope.repo.clone());
+    match svc.record_rates(rates).await {
+        Ok(Ok(n)) => Ok(Json(json!({"stored": n}))),
+        Ok(Err(errors)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Invalid rates", "errors": errors})),
+        )),
+        Err(e) => {
+            error!(error = %e, "Failed storing fx rates");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing fx rates: {}", e)})),
+            ))
+        }
+    }
+}
 
-    tokio::join!(backend);
+#[derive(SerdeDeserialize)]
+struct FxRatesQuery {
+    currency: Option<String>,
+}
 
-    Ok(())
+async fn api_list_fx_rates(
+    scope: Scope,
+    Query(q): Query<FxRatesQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = TaxService::new(scope.repo.clone());
+    match svc.rates(q.currency.as_deref()).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching fx rates");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching fx rates: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct TaxQuery {
+    // Calendar year (default: current year)
+    year: Option<i32>,
+    // json (default) or csv
+    format: Option<String>,
+}
+
+// CSV download or JSON summary with the rows under `key`
+fn tax_response<T: Serialize>(
+    rows: &[T],
+    mut summary: serde_json::Value,
+    key: &str,
+    format: Option<&str>,
+    filename: &str,
+) -> Result<axum::response::Response, ApiError> {
+    use axum::response::IntoResponse;
+    if format.is_some_and(|f| f.eq_ignore_ascii_case("csv")) {
+        let body = rows_csv(rows).map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed writing CSV: {}", e)})),
+            )
+        })?;
+        let disposition = format!("attachment; filename=\"{}\"", filename);
+        return Ok((
+            [
+                (
+                    axum::http::header::CONTENT_TYPE,
+                    "text/csv; charset=utf-8".to_string(),
+                ),
+                (axum::http::header::CONTENT_DISPOSITION, disposition),
+            ],
+            body,
+        )
+            .into_response());
+    }
+    if let Some(obj) = summary.as_object_mut() {
+        obj.insert(key.to_string(), json!(rows));
+    }
+    Ok(Json(summary).into_response())
+}
+
+async fn api_tax_br_monthly(
+    scope: Scope,
+    Query(q): Query<TaxQuery>,
+) -> Result<axum::response::Response, ApiError> {
+    let year = q.year.unwrap_or_else(|| Utc::now().year());
+    let svc = TaxService::new(scope.repo.clone());
+    match svc.monthly(year).await {
+        Ok((rows, summary)) => tax_response(
+            &rows,
+            summary,
+            "months",
+            q.format.as_deref(),
+            &format!("apuracao_cripto_{}.csv", year),
+        ),
+        Err(e) => {
+            error!(error = %e, "Failed computing tax report");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing tax report: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_tax_br_bens_direitos(
+    scope: Scope,
+    Query(q): Query<TaxQuery>,
+) -> Result<axum::response::Response, ApiError> {
+    let year = q.year.unwrap_or_else(|| Utc::now().year() - 1);
+    let svc = TaxService::new(scope.repo.clone());
+    match svc.bens_e_direitos(year).await {
+        Ok((rows, summary)) => tax_response(
+            &rows,
+            summary,
+            "items",
+            q.format.as_deref(),
+            &format!("bens_e_direitos_{}.csv", year),
+        ),
+        Err(e) => {
+            error!(error = %e, "Failed computing bens e direitos");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing bens e direitos: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_yield_positions(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = YieldService::new(scope.repo.clone());
+    match svc.positions().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching yield positions");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching yield positions: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_set_yield_position(
+    scope: Scope,
+    axum::extract::Json(pos): axum::extract::Json<domain::models::YieldPosition>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = YieldService::new(scope.repo.clone());
+    match svc.set_position(pos).await {
+        Ok(Ok(p)) => Ok(Json(json!({"position": p}))),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed storing yield position");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing yield position: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct YieldPositionKey {
+    symbol: String,
+    #[serde(default)]
+    venue: String,
+}
+
+async fn api_delete_yield_position(
+    scope: Scope,
+    Query(q): Query<YieldPositionKey>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = YieldService::new(scope.repo.clone());
+    match svc.remove_position(&q.symbol, &q.venue).await {
+        Ok(true) => Ok(Json(json!({"deleted": true}))),
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Unknown yield position"})),
+        )),
+        Err(e) => {
+            error!(error = %e, "Failed deleting yield position");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed deleting yield position: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct YieldQuery {
+    // Lookback such as 90d or 1y (default 1y; ignored when `from` is given)
+    window: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+async fn api_yield_report(
+    scope: Scope,
+    Query(q): Query<YieldQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    let parse = |s: &str| {
+        usecases::timeseries::parse_ts(s)
+            .ok_or_else(|| bad_request(format!("invalid timestamp '{}'", s)))
+    };
+    let to = match &q.to {
+        Some(t) => parse(t)?,
+        None => Utc::now(),
+    };
+    let from = match &q.from {
+        Some(f) => parse(f)?,
+        None => match window_start(q.window.as_deref().unwrap_or("1y"), to).map_err(bad_request)? {
+            Some(f) => parse(&f)?,
+            None => parse("1970-01-01T00:00:00Z")?,
+        },
+    };
+    let svc = YieldService::new(scope.repo.clone());
+    match svc.report(from, to).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing yield report");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing yield report: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_list_accounts(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AccountsService::new(scope.repo.clone());
+    match svc.list().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching accounts");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching accounts: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_create_account(
+    scope: Scope,
+    axum::extract::Json(acc): axum::extract::Json<domain::models::Account>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AccountsService::new(scope.repo.clone());
+    match svc.create(acc).await {
+        Ok(Ok(a)) => Ok(Json(json!({"account": a}))),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed storing account");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing account: {}", e)})),
+            ))
+        }
+    }
+}
+
+fn unknown_account() -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Unknown account"})),
+    )
+}
+
+async fn api_get_account(
+    scope: Scope,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AccountsService::new(scope.repo.clone());
+    match svc.get(id).await {
+        Ok(Some(a)) => Ok(Json(json!({"account": a}))),
+        Ok(None) => Err(unknown_account()),
+        Err(e) => {
+            error!(error = %e, "Failed fetching account");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching account: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_update_account(
+    scope: Scope,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Json(acc): axum::extract::Json<domain::models::Account>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AccountsService::new(scope.repo.clone());
+    match svc.update(id, acc).await {
+        Ok(Ok(Some(a))) => Ok(Json(json!({"account": a}))),
+        Ok(Ok(None)) => Err(unknown_account()),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed updating account");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed updating account: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_delete_account(
+    scope: Scope,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AccountsService::new(scope.repo.clone());
+    match svc.delete(id).await {
+        Ok(Ok(true)) => Ok(Json(json!({"deleted": true}))),
+        Ok(Ok(false)) => Err(unknown_account()),
+        Ok(Err(e)) => Err((StatusCode::CONFLICT, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed deleting account");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed deleting account: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+// First run only: creates the admin user, owner of every existing portfolio, and its token
+async fn api_auth_setup(
+    State(state): AxumState<AppState>,
+    axum::extract::Json(c): axum::extract::Json<Credentials>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.setup(&c.username, &c.password).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::CONFLICT, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed setting up the first user");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed setting up the first user: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_auth_login(
+    State(state): AxumState<AppState>,
+    axum::extract::Json(c): axum::extract::Json<Credentials>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.login(&c.username, &c.password).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::UNAUTHORIZED, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed logging in");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed logging in: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_auth_me(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.user(user.id).await {
+        Ok(v) => Ok(Json(json!({"user": v}))),
+        Err(e) => {
+            error!(error = %e, "Failed fetching user");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching user: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+async fn api_auth_password(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Json(c): axum::extract::Json<PasswordChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc
+        .change_password(user.id, &c.current_password, &c.new_password)
+        .await
+    {
+        Ok(Ok(())) => Ok(Json(json!({"changed": true}))),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed changing password");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed changing password: {}", e)})),
+            ))
+        }
+    }
+}
+
+// The caller's tokens, without their secrets
+async fn api_auth_tokens(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.tokens(user.id).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching tokens");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching tokens: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct NewToken {
+    name: String,
+    expires_in_days: Option<i64>,
+}
+
+async fn api_auth_create_token(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Json(t): axum::extract::Json<NewToken>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.create_token(user.id, &t.name, t.expires_in_days).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed creating token");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed creating token: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_auth_revoke_token(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.revoke_token(user.id, id).await {
+        Ok(true) => Ok(Json(json!({"revoked": true}))),
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Unknown or already revoked token"})),
+        )),
+        Err(e) => {
+            error!(error = %e, "Failed revoking token");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed revoking token: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_list_users(
+    State(state): AxumState<AppState>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.users().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching users");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching users: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct NewUser {
+    username: String,
+    password: String,
+    #[serde(default)]
+    is_admin: bool,
+}
+
+async fn api_create_user(
+    State(state): AxumState<AppState>,
+    axum::extract::Json(u): axum::extract::Json<NewUser>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.create_user(&u.username, &u.password, u.is_admin).await {
+        Ok(Ok(user)) => Ok(Json(json!({"user": user}))),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed creating user");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed creating user: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_delete_user(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(state.history_repo.clone());
+    match svc.delete_user(&user, id).await {
+        Ok(Ok(true)) => Ok(Json(json!({"deleted": true}))),
+        Ok(Ok(false)) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Unknown user"})),
+        )),
+        Ok(Err(e)) => Err((StatusCode::CONFLICT, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed deleting user");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed deleting user: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Who has access to the portfolio and with which role
+async fn api_portfolio_roles(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(scope.repo.clone());
+    match svc.portfolio_roles(scope.repo.portfolio_id).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching roles");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching roles: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct RoleChange {
+    username: String,
+    role: Option<String>,
+}
+
+// `{"username":"bia","role":"viewer"}` grants or changes a role; PUT without `role` or
+// DELETE ?username= removes the user's access
+async fn api_set_portfolio_role(
+    scope: Scope,
+    axum::extract::Json(c): axum::extract::Json<RoleChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    change_portfolio_role(scope, c).await
+}
+
+async fn api_delete_portfolio_role(
+    scope: Scope,
+    Query(c): Query<RoleChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    change_portfolio_role(
+        scope,
+        RoleChange {
+            username: c.username,
+            role: None,
+        },
+    )
+    .await
+}
+
+async fn change_portfolio_role(
+    scope: Scope,
+    c: RoleChange,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = AuthService::new(scope.repo.clone());
+    match svc
+        .set_portfolio_role(scope.repo.portfolio_id, &c.username, c.role.as_deref())
+        .await
+    {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed changing role");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed changing role: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Read-only share links of the portfolio, with the token of each active one
+async fn api_share_links(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = ShareService::new(scope.repo.clone());
+    match svc.list().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching share links");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching share links: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct NewShareLink {
+    label: Option<String>,
+    expires_in_days: Option<i64>,
+    masked: Option<bool>,
+}
+
+async fn api_create_share_link(
+    scope: Scope,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Json(l): axum::extract::Json<NewShareLink>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = ShareService::new(scope.repo.clone());
+    match svc
+        .create(
+            Some(user.id),
+            l.label.as_deref(),
+            l.expires_in_days,
+            l.masked,
+        )
+        .await
+    {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed creating share link");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed creating share link: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_revoke_share_link(
+    scope: Scope,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = ShareService::new(scope.repo.clone());
+    match svc.revoke(id).await {
+        Ok(true) => Ok(Json(json!({"revoked": true}))),
+        Ok(false) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Unknown or already revoked share link"})),
+        )),
+        Err(e) => {
+            error!(error = %e, "Failed revoking share link");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed revoking share link: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Portfolios the caller has a role on (all of them for admins)
+async fn api_list_portfolios(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(state.history_repo.clone());
+    match svc.list().await {
+        Ok(v) => {
+            let visible: Vec<Portfolio> = v
+                .into_iter()
+                .filter(|p| p.id.is_some_and(|id| user.can(id, Role::Viewer)))
+                .collect();
+            Ok(Json(json!({"portfolios": visible})))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed fetching portfolios");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching portfolios: {}", e)})),
+            ))
+        }
+    }
+}
+
+// The caller becomes the owner of the new portfolio
+async fn api_create_portfolio(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Json(p): axum::extract::Json<Portfolio>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(state.history_repo.clone());
+    let created = match svc.create(p).await {
+        Ok(Ok(p)) => {
+            let id = p.id.unwrap_or_default();
+            state
+                .history_repo
+                .set_portfolio_role(user.id, id, Role::Owner.as_str())
+                .await
+                .map(|_| Ok(p))
+        }
+        other => other,
+    };
+    match created {
+        Ok(Ok(p)) => Ok(Json(json!({"portfolio": p}))),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed storing portfolio");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing portfolio: {}", e)})),
+            ))
+        }
+    }
+}
+
+fn unknown_portfolio() -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Unknown portfolio"})),
+    )
+}
+
+async fn api_get_portfolio(
+    State(state): AxumState<AppState>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(state.history_repo.clone());
+    match svc.get(id).await {
+        Ok(Some(p)) => Ok(Json(json!({"portfolio": p}))),
+        Ok(None) => Err(unknown_portfolio()),
+        Err(e) => {
+            error!(error = %e, "Failed fetching portfolio");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching portfolio: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_update_portfolio(
+    State(state): AxumState<AppState>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Json(p): axum::extract::Json<Portfolio>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(state.history_repo.clone());
+    match svc.update(id, p).await {
+        Ok(Ok(Some(p))) => Ok(Json(json!({"portfolio": p}))),
+        Ok(Ok(None)) => Err(unknown_portfolio()),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed updating portfolio");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed updating portfolio: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_delete_portfolio(
+    State(state): AxumState<AppState>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(state.history_repo.clone());
+    match svc.delete(id).await {
+        Ok(Ok(true)) => Ok(Json(json!({"deleted": true}))),
+        Ok(Ok(false)) => Err(unknown_portfolio()),
+        Ok(Err(e)) => Err((StatusCode::CONFLICT, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed deleting portfolio");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed deleting portfolio: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_barca_targets(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(scope.repo.clone());
+    match svc.targets().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching barca targets");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching barca targets: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Replace the portfolio's targets (`[]` goes back to wallet_barca.csv)
+async fn api_set_barca_targets(
+    scope: Scope,
+    axum::extract::Json(rows): axum::extract::Json<Vec<domain::models::BarcaTarget>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = PortfoliosService::new(scope.repo.clone());
+    match svc.set_targets(rows).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed storing barca targets");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing barca targets: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Households whose every member the caller may view
+async fn api_list_households(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    match svc.list().await {
+        Ok(v) => {
+            let visible: Vec<Household> = v
+                .into_iter()
+                .filter(|h| user.can_all(&h.members, Role::Viewer))
+                .collect();
+            Ok(Json(json!({"households": visible})))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed fetching households");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching households: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_create_household(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Json(h): axum::extract::Json<Household>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    household_access(&user, &h, Role::Editor)?;
+    match svc.create(h).await {
+        Ok(Ok(h)) => Ok(Json(json!({"household": h}))),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed storing household");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing household: {}", e)})),
+            ))
+        }
+    }
+}
+
+fn unknown_household() -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({"error": "Unknown household"})),
+    )
+}
+
+// A household spans its member portfolios: the caller needs `role` on each of them
+fn household_access(user: &CurrentUser, h: &Household, role: Role) -> Result<(), ApiError> {
+    if user.can_all(&h.members, role) {
+        return Ok(());
+    }
+    Err((
+        StatusCode::FORBIDDEN,
+        Json(json!({"error": format!(
+            "Requires the {} role on every member portfolio",
+            role.as_str()
+        )})),
+    ))
+}
+
+// The household or a 404, once the caller's `role` on its members is checked, for the
+// handlers below that work on one
+async fn load_household(
+    svc: &HouseholdsService,
+    user: &CurrentUser,
+    id: i64,
+    role: Role,
+) -> Result<Household, ApiError> {
+    match svc.get(id).await {
+        Ok(Some(h)) => household_access(user, &h, role).map(|_| h),
+        Ok(None) => Err(unknown_household()),
+        Err(e) => {
+            error!(error = %e, "Failed fetching household");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching household: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_get_household(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    let h = load_household(&svc, &user, id, Role::Viewer).await?;
+    Ok(Json(json!({"household": h})))
+}
+
+async fn api_update_household(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Json(h): axum::extract::Json<Household>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    load_household(&svc, &user, id, Role::Editor).await?;
+    household_access(&user, &h, Role::Editor)?;
+    match svc.update(id, h).await {
+        Ok(Ok(Some(h))) => Ok(Json(json!({"household": h}))),
+        Ok(Ok(None)) => Err(unknown_household()),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed updating household");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed updating household: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_delete_household(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    load_household(&svc, &user, id, Role::Editor).await?;
+    match svc.delete(id).await {
+        Ok(true) => Ok(Json(json!({"deleted": true}))),
+        Ok(false) => Err(unknown_household()),
+        Err(e) => {
+            error!(error = %e, "Failed deleting household");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed deleting household: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_household_targets(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    load_household(&svc, &user, id, Role::Viewer).await?;
+    match svc.targets(id).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching household targets");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching household targets: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Replace the household's targets (`[]` goes back to wallet_barca.csv)
+async fn api_set_household_targets(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Json(rows): axum::extract::Json<Vec<domain::models::BarcaTarget>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    load_household(&svc, &user, id, Role::Editor).await?;
+    match svc.set_targets(id, rows).await {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed storing household targets");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed storing household targets: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Combined allocation of the member portfolios (viewer role on each member)
+#[tracing::instrument(skip(state, user), fields(user = %user.username))]
+async fn api_household_allocations(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    household_allocations(state, user, id, false).await
+}
+
+// Same report, recorded as a household snapshot (editor role on each member)
+#[tracing::instrument(skip(state, user), fields(user = %user.username))]
+async fn api_record_household_allocations(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    household_allocations(state, user, id, true).await
+}
+
+async fn household_allocations(
+    state: AppState,
+    user: CurrentUser,
+    id: i64,
+    record: bool,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    let role = if record { Role::Editor } else { Role::Viewer };
+    let household = load_household(&svc, &user, id, role).await?;
+    let (api_key, market) = household_pricing_config(&household)?;
+    let result = if record {
+        svc.compute_and_record(&api_key, &household, &market).await
+    } else {
+        svc.compute(&api_key, &household, &market).await
+    };
+    match result {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed computing household allocations");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed computing household allocations: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct HouseholdHistoryQuery {
+    level: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+async fn api_household_history(
+    State(state): AxumState<AppState>,
+    Extension(user): Extension<CurrentUser>,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    Query(q): Query<HouseholdHistoryQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HouseholdsService::new(state.provider.clone(), state.history_repo.clone());
+    load_household(&svc, &user, id, Role::Viewer).await?;
+    let level = q.level.as_deref().unwrap_or("totals");
+    match svc
+        .history(id, level, q.from.as_deref(), q.to.as_deref())
+        .await
+    {
+        Ok(Ok(v)) => Ok(Json(v)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed fetching household history");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching household history: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_wallet_allocations(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    match svc.current().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching wallet allocations");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching wallet allocations: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct WalletHistoryQuery {
+    symbol: String,
+}
+
+async fn api_wallet_allocation_history(
+    scope: Scope,
+    Query(q): Query<WalletHistoryQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    match svc.history(&q.symbol).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching wallet allocation history");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching wallet allocation history: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct WalletAsOfQuery {
+    // Timestamp (default: now)
+    at: Option<String>,
+}
+
+async fn api_wallet_allocations_as_of(
+    scope: Scope,
+    Query(q): Query<WalletAsOfQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let at = match q.at.as_deref() {
+        Some(s) => usecases::timeseries::parse_ts(s).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("invalid timestamp '{}'", s)})),
+            )
+        })?,
+        None => Utc::now(),
+    };
+    let svc = WalletService::new(scope.repo.clone());
+    match svc.as_of(at).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed rebuilding wallet state");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed rebuilding wallet state: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(SerdeDeserialize)]
+struct WalletDiffQuery {
+    from: Option<String>,
+    // Lookback such as 30d or 1m, used when `from` is not given
+    window: Option<String>,
+    // Timestamp (default: now)
+    to: Option<String>,
+}
+
+async fn api_wallet_allocations_diff(
+    scope: Scope,
+    Query(q): Query<WalletDiffQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    let parse = |s: &str| {
+        usecases::timeseries::parse_ts(s)
+            .ok_or_else(|| bad_request(format!("invalid timestamp '{}'", s)))
+    };
+    let to = match &q.to {
+        Some(t) => parse(t)?,
+        None => Utc::now(),
+    };
+    let from = match (&q.from, &q.window) {
+        (Some(f), _) => parse(f)?,
+        (None, Some(w)) => match window_start(w, to).map_err(bad_request)? {
+            Some(f) => parse(&f)?,
+            None => parse("1970-01-01T00:00:00Z")?,
+        },
+        (None, None) => return Err(bad_request("pass from or window".to_string())),
+    };
+    if from > to {
+        return Err(bad_request("from must not be after to".to_string()));
+    }
+    let svc = WalletService::new(scope.repo.clone());
+    match svc.diff(from, to).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed diffing wallet states");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed diffing wallet states: {}", e)})),
+            ))
+        }
+    }
+}
+
+// Shared response for wallet edits: the appended row(s) under `key`
+fn wallet_edit_response<T: Serialize>(
+    res: Result<Result<T, EditError>, Box<dyn Error + Send + Sync>>,
+    key: &str,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    match res {
+        Ok(Ok(rows)) => Ok(Json(json!({ key: rows }))),
+        Ok(Err(e)) => {
+            let status = match e {
+                EditError::Invalid(_) => StatusCode::BAD_REQUEST,
+                EditError::NotFound(_) => StatusCode::NOT_FOUND,
+                EditError::Conflict(_) => StatusCode::CONFLICT,
+            };
+            Err((status, Json(json!({"error": e.to_string()}))))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed updating wallet allocations");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed updating wallet allocations: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_add_wallet_allocation(
+    scope: Scope,
+    axum::extract::Json(change): axum::extract::Json<HoldingChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    wallet_edit_response(svc.add(change).await, "allocation")
+}
+
+async fn api_update_wallet_allocation(
+    scope: Scope,
+    axum::extract::Json(change): axum::extract::Json<HoldingChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    wallet_edit_response(svc.update(change).await, "allocation")
+}
+
+async fn api_retire_wallet_allocation(
+    scope: Scope,
+    axum::extract::Json(change): axum::extract::Json<HoldingChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    wallet_edit_response(svc.retire(change).await, "allocation")
+}
+
+async fn api_move_wallet_allocation(
+    scope: Scope,
+    axum::extract::Json(change): axum::extract::Json<HoldingChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    wallet_edit_response(svc.move_holding(change).await, "appended")
+}
+
+async fn api_merge_wallet_allocations(
+    scope: Scope,
+    axum::extract::Json(change): axum::extract::Json<HoldingChange>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = WalletService::new(scope.repo.clone());
+    wallet_edit_response(svc.merge(change).await, "appended")
+}
+
+// Client-supplied import paths must name a file inside IMPORT_DIR (default: `imports/`, never
+// the working directory with .env and the database); uploads through /api/import_upload need
+// no server-side path at all
+fn read_import_path(requested: &str) -> Result<Vec<u8>, ApiError> {
+    let dir = std::env::var("IMPORT_DIR")
+        .ok()
+        .filter(|d| !d.trim().is_empty())
+        .unwrap_or_else(|| "imports".to_string());
+    let path = resolve_import_path(std::path::Path::new(&dir), requested)
+        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"error": e}))))?;
+    std::fs::read(&path).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Cannot read {}: {}", requested, e)})),
+        )
+    })
+}
+
+// Largest accepted import file, IMPORT_MAX_BYTES (default 5 MiB)
+fn import_max_bytes() -> usize {
+    std::env::var("IMPORT_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5 * 1024 * 1024)
+}
+
+#[derive(serde::Deserialize)]
+struct ImportPayload {
+    path: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn import_wallets_handler(
+    scope: Scope,
+    axum::extract::Json(payload): axum::extract::Json<ImportPayload>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let path = payload
+        .path
+        .unwrap_or_else(|| "wallet_allocations.csv".to_string());
+    let content = read_import_path(&path)?;
+    let svc = HistoryService::new(scope.repo.clone());
+    match svc
+        .import_wallet_allocations(&path, &content, payload.dry_run)
+        .await
+    {
+        Ok(report) => Ok(Json(report)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": format!("Failed import: {}", e)})),
+        )),
+    }
+}
+
+async fn api_import_formats() -> Json<serde_json::Value> {
+    Json(json!({"formats": usecases::importers::formats()}))
+}
+
+fn import_response(
+    res: Result<Result<serde_json::Value, String>, Box<dyn Error + Send + Sync>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    match res {
+        Ok(Ok(report)) => Ok(Json(report)),
+        Ok(Err(e)) => Err((StatusCode::BAD_REQUEST, Json(json!({"error": e})))),
+        Err(e) => {
+            error!(error = %e, "Failed importing file");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed importing file: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ImportFilePayload {
+    path: String,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    venue: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn api_import_file(
+    scope: Scope,
+    axum::extract::Json(payload): axum::extract::Json<ImportFilePayload>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let content = read_import_path(&payload.path)?;
+    let svc = ImportService::new(scope.repo.clone());
+    import_response(
+        svc.import(
+            &payload.path,
+            &content,
+            payload.format.as_deref(),
+            payload.venue.as_deref(),
+            payload.dry_run,
+        )
+        .await,
+    )
+}
+
+// multipart/form-data with a `file` part and optional `format`, `venue` and `dry_run` fields
+async fn api_import_upload(
+    scope: Scope,
+    mut multipart: axum::extract::Multipart,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let max_bytes = import_max_bytes();
+    let multipart_error = |e: axum::extract::multipart::MultipartError| {
+        (e.status(), Json(json!({"error": e.body_text()})))
+    };
+    let mut file: Option<(String, Vec<u8>)> = None;
+    let mut format = None;
+    let mut venue = None;
+    let mut dry_run = false;
+    while let Some(mut field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name().unwrap_or_default() {
+            "file" => {
+                let name = field.file_name().unwrap_or("upload.csv").to_string();
+                let mut content = Vec::new();
+                while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+                    content.extend_from_slice(&chunk);
+                    if content.len() > max_bytes {
+                        return Err((
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            Json(json!({
+                                "error": format!("file exceeds the {} byte import limit", max_bytes)
+                            })),
+                        ));
+                    }
+                }
+                file = Some((name, content));
+            }
+            "format" => format = Some(field.text().await.map_err(multipart_error)?),
+            "venue" => venue = Some(field.text().await.map_err(multipart_error)?),
+            "dry_run" => {
+                let v = field.text().await.map_err(multipart_error)?;
+                dry_run = matches!(v.trim(), "true" | "1" | "on");
+            }
+            _ => {}
+        }
+    }
+    let Some((name, content)) = file else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "missing multipart field 'file'"})),
+        ));
+    };
+    if content.iter().all(u8::is_ascii_whitespace) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("{} is empty", name)})),
+        ));
+    }
+    let svc = ImportService::new(scope.repo.clone());
+    import_response(
+        svc.import(
+            &name,
+            &content,
+            format.as_deref(),
+            venue.as_deref(),
+            dry_run,
+        )
+        .await,
+    )
+}
+
+#[derive(SerdeDeserialize)]
+struct ExportQuery {
+    format: Option<String>,
+    level: Option<String>,
+    delimiter: Option<String>,
+    decimal: Option<String>,
+}
+
+// holdings / targets / report as CSV, JSON or XLSX in the layout the imports read back
+async fn api_export(
+    scope: Scope,
+    axum::extract::Path(dataset): axum::extract::Path<String>,
+    Query(q): Query<ExportQuery>,
+) -> Result<axum::response::Response, ApiError> {
+    use axum::response::IntoResponse;
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    let format = FileFormat::parse(q.format.as_deref().unwrap_or("csv")).map_err(bad_request)?;
+    let dialect = csv_dialect::CsvDialect::default()
+        .with_overrides(q.delimiter.as_deref(), q.decimal.as_deref(), None)
+        .map_err(bad_request)?;
+    let svc = ExportService::new(scope.repo.clone());
+    let table = match svc
+        .table(&dataset, q.level.as_deref().unwrap_or("asset"))
+        .await
+    {
+        Ok(Ok(t)) => t,
+        Ok(Err(e)) => return Err(bad_request(e)),
+        Err(e) => {
+            error!(error = %e, "Failed exporting {}", dataset);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed exporting {}: {}", dataset, e)})),
+            ));
+        }
+    };
+    let body = render(&table, format, &dialect).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": format!("Failed writing {}: {}", format.extension(), e)})),
+        )
+    })?;
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"",
+        table.name,
+        format.extension()
+    );
+    Ok((
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                format.content_type().to_string(),
+            ),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+async fn api_import_batches(scope: Scope) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HistoryService::new(scope.repo.clone());
+    match svc.import_batches().await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            error!(error = %e, "Failed fetching import batches");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching import batches: {}", e)})),
+            ))
+        }
+    }
+}
+
+async fn api_import_batch(
+    scope: Scope,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HistoryService::new(scope.repo.clone());
+    match svc.import_batch(id).await {
+        Ok(Some(v)) => Ok(Json(v)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Unknown import batch"})),
+        )),
+        Err(e) => {
+            error!(error = %e, "Failed fetching import batch");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("Failed fetching import batch: {}", e)})),
+            ))
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RollbackPayload {
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+async fn api_rollback_import_batch(
+    scope: Scope,
+    axum::extract::Path(id): axum::extract::Path<i64>,
+    axum::extract::Json(payload): axum::extract::Json<RollbackPayload>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let svc = HistoryService::new(scope.repo.clone());
+    let res = svc
+        .rollback_import_batch(id, &payload.author, payload.reason.as_deref())
+        .await;
+    wallet_edit_response(res, "rollback")
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    dotenv().ok();
+
+    // Initialize tracing subscriber for logging
+    tracing_subscriber::fmt::init();
+
+    // DB pool and repo
+    let database_url =
+        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://data/crypto.db".to_string());
+    let pool = SqlitePool::connect(&database_url)
+        .await
+        .expect("failed to connect to db");
+
+    // Run migrations (if any) from ./migrations
+    if let Err(e) = sqlx::migrate!("./migrations").run(&pool).await {
+        error!(error = %e, "Failed to run migrations");
+    } else {
+        info!("Migrations applied");
+    }
+
+    let history_repo = std::sync::Arc::new(SqliteRepo::new(pool.clone()));
+    ensure_wallet_allocations_seeded(history_repo.clone()).await;
+
+    let backend_repo = history_repo.clone();
+    let backend = async move {
+        // initialize provider and app state
+        let provider = Arc::new(ReqwestCryptoProvider::new());
+        let app_state = AppState {
+            provider: provider.clone(),
+            history_repo: backend_repo.clone(),
+        };
+
+        let app = Router::new()
+            .route(
+                "/api/allocations",
+                get(api_allocations).post(api_record_allocations),
+            )
+            .route("/api/history", get(api_history))
+            .route("/api/simulate", axum::routing::post(api_simulate))
+            .route("/api/stress_tests", get(api_stress_tests))
+            .route("/api/analytics/risk", get(api_analytics_risk))
+            .route("/api/analytics/correlation", get(api_analytics_correlation))
+            .route("/api/analytics/projection", get(api_analytics_projection))
+            .route(
+                "/api/analytics/var",
+                get(api_analytics_var).post(api_record_var),
+            )
+            .route("/api/analytics/returns", get(api_analytics_returns))
+            .route(
+                "/api/transactions",
+                get(api_list_transactions).post(api_create_transactions),
+            )
+            .route("/api/transactions/holdings", get(api_transaction_holdings))
+            .route("/api/transactions/cost_basis", get(api_cost_basis))
+            .route(
+                "/api/fx_rates",
+                get(api_list_fx_rates).post(api_create_fx_rates),
+            )
+            .route("/api/tax/br/monthly", get(api_tax_br_monthly))
+            .route(
+                "/api/yield/positions",
+                get(api_yield_positions)
+                    .put(api_set_yield_position)
+                    .delete(api_delete_yield_position),
+            )
+            .route("/api/yield/report", get(api_yield_report))
+            .route("/api/tax/br/bens_direitos", get(api_tax_br_bens_direitos))
+            .route(
+                "/api/accounts",
+                get(api_list_accounts).post(api_create_account),
+            )
+            .route(
+                "/api/accounts/{id}",
+                get(api_get_account)
+                    .put(api_update_account)
+                    .delete(api_delete_account),
+            )
+            .route(
+                "/api/portfolios",
+                get(api_list_portfolios).post(api_create_portfolio),
+            )
+            .route(
+                "/api/portfolios/{id}",
+                get(api_get_portfolio)
+                    .put(api_update_portfolio)
+                    .delete(api_delete_portfolio),
+            )
+            .route(
+                "/api/barca_targets",
+                get(api_barca_targets).put(api_set_barca_targets),
+            )
+            .route(
+                "/api/wallet_allocations",
+                get(api_wallet_allocations)
+                    .post(api_add_wallet_allocation)
+                    .put(api_update_wallet_allocation),
+            )
+            .route(
+                "/api/wallet_allocations/retire",
+                axum::routing::post(api_retire_wallet_allocation),
+            )
+            .route(
+                "/api/wallet_allocations/move",
+                axum::routing::post(api_move_wallet_allocation),
+            )
+            .route(
+                "/api/wallet_allocations/merge",
+                axum::routing::post(api_merge_wallet_allocations),
+            )
+            .route(
+                "/api/wallet_allocations/as_of",
+                get(api_wallet_allocations_as_of),
+            )
+            .route(
+                "/api/wallet_allocations/diff",
+                get(api_wallet_allocations_diff),
+            )
+            .route(
+                "/api/wallet_allocations/history",
+                get(api_wallet_allocation_history),
+            )
+            .route(
+                "/api/import_wallets",
+                axum::routing::post(import_wallets_handler),
+            )
+            .route("/api/import_formats", get(api_import_formats))
+            .route("/api/import_file", axum::routing::post(api_import_file))
+            .route(
+                "/api/import_upload",
+                axum::routing::post(api_import_upload)
+                    // Leave room for the other form fields; the file itself is checked against
+                    // the import limit while it streams in
+                    .layer(axum::extract::DefaultBodyLimit::max(
+                        import_max_bytes() + 64 * 1024,
+                    )),
+            )
+            .route("/api/export/{dataset}", get(api_export))
+            .route("/api/import_batches", get(api_import_batches))
+            .route("/api/import_batches/{id}", get(api_import_batch))
+            .route(
+                "/api/import_batches/{id}/rollback",
+                axum::routing::post(api_rollback_import_batch),
+            )
+            .route(
+                "/api/households",
+                get(api_list_households).post(api_create_household),
+            )
+            .route(
+                "/api/households/{id}",
+                get(api_get_household)
+                    .put(api_update_household)
+                    .delete(api_delete_household),
+            )
+            .route(
+                "/api/households/{id}/targets",
+                get(api_household_targets).put(api_set_household_targets),
+            )
+            .route(
+                "/api/households/{id}/allocations",
+                get(api_household_allocations).post(api_record_household_allocations),
+            )
+            .route("/api/households/{id}/history", get(api_household_history))
+            .route("/api/auth/setup", axum::routing::post(api_auth_setup))
+            .route("/api/auth/login", axum::routing::post(api_auth_login))
+            .route("/api/auth/me", get(api_auth_me))
+            .route("/api/auth/password", axum::routing::put(api_auth_password))
+            .route(
+                "/api/auth/tokens",
+                get(api_auth_tokens).post(api_auth_create_token),
+            )
+            .route(
+                "/api/auth/tokens/{id}",
+                axum::routing::delete(api_auth_revoke_token),
+            )
+            .route("/api/users", get(api_list_users).post(api_create_user))
+            .route("/api/users/{id}", axum::routing::delete(api_delete_user))
+            .route(
+                "/api/roles",
+                get(api_portfolio_roles)
+                    .put(api_set_portfolio_role)
+                    .delete(api_delete_portfolio_role),
+            )
+            .route(
+                "/api/share_links",
+                get(api_share_links).post(api_create_share_link),
+            )
+            .route(
+                "/api/share_links/{id}",
+                axum::routing::delete(api_revoke_share_link),
+            )
+            // Every route above needs a bearer token (see `require_auth`)
+            .route_layer(axum::middleware::from_fn_with_state(
+                app_state.clone(),
+                require_auth,
+            ))
+            .with_state(app_state)
+            .layer(cors_layer());
+
+        // Every route also answers under /api/portfolios/{id}/ for that portfolio; the path is
+        // rewritten before the routes above are matched
+        let app = Router::new()
+            .fallback_service(app)
+            .layer(axum::middleware::from_fn(scope_portfolio_path));
+
+        serve(app, 3001).await;
+    };
+
+    tokio::join!(backend);
+
+    Ok(())
+}
+
+// CORS_ALLOWED_ORIGINS (comma separated, e.g. http://localhost:5173) limits the browser
+// origins that may call the API; any origin when unset
+fn cors_layer() -> CorsLayer {
+    let layer = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+    let origins: Vec<axum::http::HeaderValue> = std::env::var("CORS_ALLOWED_ORIGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .filter_map(|o| match o.parse() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                warn!(origin = o, "Ignoring invalid CORS origin");
+                None
+            }
+        })
+        .collect();
+    if origins.is_empty() {
+        layer.allow_origin(Any)
+    } else {
+        layer.allow_origin(origins)
+    }
 }
 
 async fn serve(app: Router, port: u16) {
@@ -284,10 +2735,14 @@ async fn ensure_wallet_allocations_seeded(history_repo: Arc<SqliteRepo>) {
             let path = std::env::var("WALLET_ALLOCATIONS_PATH")
                 .unwrap_or_else(|_| "wallet_allocations.csv".to_string());
             let history_svc = HistoryService::new(history_repo.clone());
-            match history_svc.import_wallet_allocations_from_path(&path).await {
-                Ok(imported) => info!(
+            match history_svc
+                .import_wallet_allocations_from_path(&path, false)
+                .await
+            {
+                Ok(report) => info!(
                     path = %path,
-                    imported,
+                    status = %report["status"],
+                    imported = %report["imported"],
                     "Bootstrapped wallet_allocations from CSV"
                 ),
                 Err(e) => warn!(
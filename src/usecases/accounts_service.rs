@@ -0,0 +1,126 @@
+use crate::domain::models::Account;
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::custody::{ledger_custody, validate_account, wallet_custody};
+use crate::usecases::ledger::{HoldingsSource, derive_holdings};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub struct AccountsService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl AccountsService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // Quantity per (symbol, account name) from the configured holdings source
+    pub async fn custody_positions(
+        &self,
+        accounts: &[Account],
+    ) -> Result<Vec<(String, String, f64)>, Box<dyn std::error::Error + Send + Sync>> {
+        if HoldingsSource::from_env()? == HoldingsSource::Transactions {
+            let txs = self.repo.fetch_transactions(None, None, None).await?;
+            return Ok(ledger_custody(&derive_holdings(&txs)));
+        }
+        let rows = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        Ok(wallet_custody(&rows, accounts))
+    }
+
+    // Every account with the quantities it currently holds
+    pub async fn list(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let accounts = self.repo.fetch_accounts().await?;
+        let mut held: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+        for (symbol, account, qty) in self.custody_positions(&accounts).await? {
+            *held
+                .entry(account.to_lowercase())
+                .or_default()
+                .entry(symbol)
+                .or_insert(0.0) += qty;
+        }
+        let rows: Vec<Value> = accounts
+            .iter()
+            .map(|a| {
+                let holdings = held.remove(&a.name.to_lowercase()).unwrap_or_default();
+                let mut v = json!(a);
+                v["holdings"] = json!(holdings);
+                v
+            })
+            .collect();
+        Ok(json!({"accounts": rows}))
+    }
+
+    pub async fn get(
+        &self,
+        id: i64,
+    ) -> Result<Option<Account>, Box<dyn std::error::Error + Send + Sync>> {
+        let acc = self.repo.fetch_account(id).await?;
+        Ok(acc)
+    }
+
+    pub async fn create(
+        &self,
+        mut acc: Account,
+    ) -> Result<Result<Account, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_account(&mut acc) {
+            return Ok(Err(e));
+        }
+        if let Some(e) = self.name_taken(&acc.name, None).await? {
+            return Ok(Err(e));
+        }
+        let id = self.repo.insert_account(&acc).await?;
+        Ok(Ok(self.repo.fetch_account(id).await?.unwrap_or(acc)))
+    }
+
+    // Ok(Ok(None)) when the account does not exist
+    pub async fn update(
+        &self,
+        id: i64,
+        mut acc: Account,
+    ) -> Result<Result<Option<Account>, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_account(&mut acc) {
+            return Ok(Err(e));
+        }
+        if let Some(e) = self.name_taken(&acc.name, Some(id)).await? {
+            return Ok(Err(e));
+        }
+        acc.id = Some(id);
+        if !self.repo.update_account(&acc).await? {
+            return Ok(Ok(None));
+        }
+        let stored = self.repo.fetch_account(id).await?;
+        Ok(Ok(stored))
+    }
+
+    // Accounts still referenced by holdings or transactions are kept (Ok(Err(reason)))
+    pub async fn delete(
+        &self,
+        id: i64,
+    ) -> Result<Result<bool, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let refs = self.repo.count_account_references(id).await?;
+        if refs > 0 {
+            return Ok(Err(format!(
+                "account {} is referenced by {} wallet or transaction rows",
+                id, refs
+            )));
+        }
+        let deleted = self.repo.delete_account(id).await?;
+        Ok(Ok(deleted))
+    }
+
+    async fn name_taken(
+        &self,
+        name: &str,
+        except: Option<i64>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let accounts = self.repo.fetch_accounts().await?;
+        Ok(accounts
+            .iter()
+            .any(|a| a.name.eq_ignore_ascii_case(name) && a.id != except)
+            .then(|| format!("an account named '{}' already exists", name)))
+    }
+}
@@ -1,36 +1,114 @@
 use crate::api_client::CryptoProvider;
-use crate::csv_store::AllocationStore;
+use crate::domain::models::WalletAllocation;
 use crate::domain::repository::HistoryRepo;
+use crate::usecases::accounts_service::AccountsService;
 use crate::usecases::compute_allocations::compute_allocations;
+use crate::usecases::cost_basis::{CostMethod, attach_pnl, compute_cost_basis, year_start};
+use crate::usecases::custody::{attach_custody, custody_report, max_account_percent_from_env};
+use crate::usecases::ledger::{HoldingsSource, apply_ledger_quantities, derive_holdings};
+use crate::usecases::market_blend::{MarketSelection, blend_targets};
+use crate::usecases::portfolios_service::barca_regimes;
+use crate::usecases::simulation::{Scenario, apply_scenario, compare_reports};
+use crate::usecases::stress_tests::{StressScenario, scenario_impact};
+use chrono::Datelike;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 pub struct AllocationsService {
     pub provider: Arc<dyn CryptoProvider>,
     pub repo: Arc<dyn HistoryRepo>,
+    // Lot relief method for P&L; COST_BASIS_METHOD (or FIFO) when not set
+    pub cost_method: Option<CostMethod>,
+}
+
+// Everything `compute_allocations` needs: live quotes, current holdings and blended targets
+pub struct AllocationInputs {
+    pub cryptos: Vec<crate::CryptoData>,
+    pub allocations: Vec<WalletAllocation>,
+    pub barca_targets: HashMap<String, f64>,
+    pub market: serde_json::Value,
+}
+
+impl AllocationInputs {
+    pub fn report(&self) -> serde_json::Value {
+        let mut res = compute_allocations(&self.allocations, &self.cryptos, &self.barca_targets);
+        if let Some(obj) = res.as_object_mut() {
+            obj.insert("market".to_string(), self.market.clone());
+        }
+        res
+    }
+}
+
+// Current wallet allocations of the repo's portfolio; with HOLDINGS_SOURCE=transactions the
+// quantities are replaced by the ones derived from the transaction ledger
+pub async fn current_holdings(
+    repo: &dyn HistoryRepo,
+) -> Result<Vec<WalletAllocation>, Box<dyn std::error::Error + Send + Sync>> {
+    holdings_from(repo, HoldingsSource::from_env()?).await
+}
+
+// Same with the source given explicitly
+pub async fn holdings_from(
+    repo: &dyn HistoryRepo,
+    source: HoldingsSource,
+) -> Result<Vec<WalletAllocation>, Box<dyn std::error::Error + Send + Sync>> {
+    let allocations = repo.fetch_current_wallet_allocations().await?;
+    if source == HoldingsSource::Transactions {
+        let txs = repo.fetch_transactions(None, None, None).await?;
+        return Ok(apply_ledger_quantities(
+            &allocations,
+            &derive_holdings(&txs),
+            &txs,
+        ));
+    }
+    Ok(allocations)
 }
 
 impl AllocationsService {
     pub fn new(provider: Arc<dyn CryptoProvider>, repo: Arc<dyn HistoryRepo>) -> Self {
-        Self { provider, repo }
+        Self {
+            provider,
+            repo,
+            cost_method: None,
+        }
     }
 
-    pub async fn compute_and_record(
+    pub fn with_cost_method(mut self, method: Option<CostMethod>) -> Self {
+        self.cost_method = method;
+        self
+    }
+
+    pub async fn load_inputs(
         &self,
         api_key: &str,
-        current_market: &str,
-    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        market: &MarketSelection,
+    ) -> Result<AllocationInputs, Box<dyn std::error::Error + Send + Sync>> {
         // fetch cryptos
         let cryptos = self.provider.fetch_latest(api_key).await?;
 
-        // read barca targets from CSV (legacy) — consider migrating to DB
-        let store = crate::csv_store::FileCsvStore;
-        let barca_targets = store.read_barca_allocations("wallet_barca.csv", current_market)?;
+        // the portfolio's barca targets (wallet_barca.csv while it has none), blended across
+        // regimes using the effective market weights for today
+        let regimes = barca_regimes(self.repo.as_ref()).await?;
+        let today = chrono::Utc::now().date_naive();
+        let weights = market.effective_weights(today);
+        let barca_targets = blend_targets(&regimes, &weights)?;
 
-        // get current wallet allocations from repo
-        let allocs = self.repo.fetch_current_wallet_allocations().await?;
+        let allocations = current_holdings(self.repo.as_ref()).await?;
 
-        // compute
-        let res = compute_allocations(&allocs, &cryptos, &barca_targets);
+        Ok(AllocationInputs {
+            cryptos,
+            allocations,
+            barca_targets,
+            market: market.to_json(today),
+        })
+    }
+
+    pub async fn compute_and_record(
+        &self,
+        api_key: &str,
+        market: &MarketSelection,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let res = self.compute(api_key, market).await?;
 
         // persist computed allocation record for audit
         let rec = crate::domain::models::AllocationRecord {
@@ -43,4 +121,115 @@ impl AllocationsService {
 
         Ok(res)
     }
+
+    // The same report without recording it (read-only share links)
+    pub async fn compute(
+        &self,
+        api_key: &str,
+        market: &MarketSelection,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let inputs = self.load_inputs(api_key, market).await?;
+
+        // compute, then extend with cost basis and P&L from the transaction ledger
+        let mut res = inputs.report();
+        let method = match self.cost_method {
+            Some(m) => m,
+            None => CostMethod::from_env()?,
+        };
+        let txs = self.repo.fetch_transactions(None, None, None).await?;
+        let now = chrono::Utc::now();
+        let ytd_start = year_start(now.year()).unwrap_or(now);
+        attach_pnl(
+            &mut res,
+            &compute_cost_basis(&txs, method, ytd_start),
+            method,
+        );
+
+        // and with the breakdown per custody account
+        let accounts = self.repo.fetch_accounts().await?;
+        let positions = AccountsService::new(self.repo.clone())
+            .custody_positions(&accounts)
+            .await?;
+        let prices: HashMap<String, f64> = inputs
+            .cryptos
+            .iter()
+            .map(|c| (c.symbol.clone(), c.quote.usd.price))
+            .collect();
+        attach_custody(
+            &mut res,
+            custody_report(
+                &positions,
+                &accounts,
+                &prices,
+                max_account_percent_from_env()?,
+            ),
+        );
+
+        Ok(res)
+    }
+
+    // What-if run: apply the scenario to copies of quotes/holdings and return the
+    // simulated report next to the real one. Nothing is persisted. The inner Err
+    // is an invalid scenario; the outer one a provider or database failure.
+    pub async fn simulate(
+        &self,
+        api_key: &str,
+        market: &MarketSelection,
+        scenario: &Scenario,
+    ) -> Result<Result<serde_json::Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let inputs = self.load_inputs(api_key, market).await?;
+        let actual = inputs.report();
+
+        let (allocations, cryptos) =
+            match apply_scenario(&inputs.allocations, &inputs.cryptos, scenario) {
+                Ok(applied) => applied,
+                Err(e) => return Ok(Err(e)),
+            };
+        let simulated = AllocationInputs {
+            cryptos,
+            allocations,
+            barca_targets: inputs.barca_targets.clone(),
+            market: inputs.market.clone(),
+        }
+        .report();
+
+        Ok(Ok(serde_json::json!({
+            "scenario": scenario,
+            "comparison": compare_reports(&actual, &simulated),
+            "actual": actual,
+            "simulated": simulated,
+        })))
+    }
+
+    // Run every stress scenario against the current holdings (nothing persisted)
+    pub async fn stress_test(
+        &self,
+        api_key: &str,
+        market: &MarketSelection,
+        scenarios: &[StressScenario],
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let inputs = self.load_inputs(api_key, market).await?;
+        let actual = inputs.report();
+
+        let mut results = Vec::new();
+        for stress in scenarios {
+            let (allocations, cryptos) =
+                apply_scenario(&inputs.allocations, &inputs.cryptos, &stress.scenario)
+                    .map_err(|e| format!("scenario '{}': {}", stress.name, e))?;
+            let simulated = AllocationInputs {
+                cryptos,
+                allocations,
+                barca_targets: inputs.barca_targets.clone(),
+                market: inputs.market.clone(),
+            }
+            .report();
+            results.push(scenario_impact(stress, &actual, &simulated));
+        }
+
+        Ok(serde_json::json!({
+            "total_value": crate::usecases::simulation::report_total_value(&actual),
+            "market": inputs.market,
+            "scenarios": results,
+        }))
+    }
 }
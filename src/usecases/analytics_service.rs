@@ -0,0 +1,663 @@
+use crate::domain::models::{AssetHistoryRow, VarSnapshot, WalletAllocation};
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::allocations_service::current_holdings;
+use crate::usecases::correlation::{beta, concentration, paired_returns, pearson};
+use crate::usecases::monte_carlo::{Holding, ProjectionConfig, project};
+use crate::usecases::returns::{FlowSource, returns_summary, scope_flow_points};
+use crate::usecases::risk_metrics::risk_summary;
+use crate::usecases::timeseries::{Interval, resample_last};
+use crate::usecases::value_at_risk::{historical_var_es, horizon_pnl, parametric_var_es};
+use chrono::NaiveDate;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+// Time range and sampling used by the analytics endpoints
+#[derive(Debug, Clone)]
+pub struct AnalyticsWindow {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub interval: Interval,
+}
+
+impl AnalyticsWindow {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "from": self.from,
+            "to": self.to,
+            "interval": self.interval.as_str(),
+        })
+    }
+}
+
+pub struct AnalyticsService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl AnalyticsService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // Value series from history_totals
+    pub async fn total_series(
+        &self,
+        window: &AnalyticsWindow,
+    ) -> Result<Vec<(String, f64)>, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_totals(window.from.as_deref(), window.to.as_deref())
+            .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|r| r.total_value.map(|v| (r.timestamp, v)))
+            .collect())
+    }
+
+    // Value series per barca from history_barca
+    pub async fn barca_series(
+        &self,
+        window: &AnalyticsWindow,
+    ) -> Result<BTreeMap<String, Vec<(String, f64)>>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let rows = self
+            .repo
+            .fetch_barca(window.from.as_deref(), window.to.as_deref())
+            .await?;
+        let mut out: BTreeMap<String, Vec<(String, f64)>> = BTreeMap::new();
+        for r in rows {
+            if let Some(v) = r.value {
+                out.entry(r.barca).or_default().push((r.timestamp, v));
+            }
+        }
+        Ok(out)
+    }
+
+    // Value series per group from history_groups
+    pub async fn group_series(
+        &self,
+        window: &AnalyticsWindow,
+    ) -> Result<BTreeMap<String, Vec<(String, f64)>>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let rows = self
+            .repo
+            .fetch_groups(window.from.as_deref(), window.to.as_deref())
+            .await?;
+        let mut out: BTreeMap<String, Vec<(String, f64)>> = BTreeMap::new();
+        for r in rows {
+            if let Some(v) = r.value {
+                out.entry(r.group_name).or_default().push((r.timestamp, v));
+            }
+        }
+        Ok(out)
+    }
+
+    // Volatility, Sharpe/Sortino and drawdowns for the portfolio and each barca/group.
+    // `risk_free` is the annual risk-free rate as a fraction.
+    pub async fn risk_metrics(
+        &self,
+        window: &AnalyticsWindow,
+        risk_free: f64,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let totals = resample_last(&self.total_series(window).await?, window.interval);
+        let per_barca: Vec<Value> = self
+            .barca_series(window)
+            .await?
+            .into_iter()
+            .map(|(barca, points)| {
+                let mut summary = risk_summary(
+                    &resample_last(&points, window.interval),
+                    window.interval,
+                    risk_free,
+                );
+                summary["barca"] = json!(barca);
+                summary
+            })
+            .collect();
+        let per_group: Vec<Value> = self
+            .group_series(window)
+            .await?
+            .into_iter()
+            .map(|(group, points)| {
+                let mut summary = risk_summary(
+                    &resample_last(&points, window.interval),
+                    window.interval,
+                    risk_free,
+                );
+                summary["group"] = json!(group);
+                summary
+            })
+            .collect();
+
+        Ok(json!({
+            "window": window.to_json(),
+            "risk_free_rate_percent": risk_free * 100.0,
+            "portfolio": risk_summary(&totals, window.interval, risk_free),
+            "per_barca": per_barca,
+            "per_group": per_group,
+        }))
+    }
+
+    // Time-weighted and money-weighted returns for the portfolio and each barca/group, with
+    // external cash flows from the ledger or inferred from snapshot quantity changes.
+    pub async fn returns(
+        &self,
+        window: &AnalyticsWindow,
+        source: FlowSource,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_assets(window.from.as_deref(), window.to.as_deref())
+            .await?;
+        let txs = match source {
+            FlowSource::Inferred => Vec::new(),
+            FlowSource::Ledger | FlowSource::Auto => {
+                self.repo.fetch_transactions(None, None, None).await?
+            }
+        };
+        let used = match source {
+            FlowSource::Auto if txs.is_empty() => FlowSource::Inferred,
+            FlowSource::Auto => FlowSource::Ledger,
+            other => other,
+        };
+        let ledger = (used == FlowSource::Ledger).then_some(txs.as_slice());
+        let mut portfolio = json!({});
+        let mut per_barca = Vec::new();
+        let mut per_group = Vec::new();
+        for ((scope, name), points) in scope_flow_points(&rows, ledger) {
+            let mut summary = returns_summary(&points);
+            match scope.as_str() {
+                "barca" => {
+                    summary["barca"] = json!(name);
+                    per_barca.push(summary);
+                }
+                "group" => {
+                    summary["group"] = json!(name);
+                    per_group.push(summary);
+                }
+                _ => portfolio = summary,
+            }
+        }
+        Ok(json!({
+            "window": window.to_json(),
+            "flows": used.as_str(),
+            "portfolio": portfolio,
+            "per_barca": per_barca,
+            "per_group": per_group,
+        }))
+    }
+
+    // Rows from the most recent asset snapshot in the window (current holdings)
+    fn latest_snapshot(rows: &[AssetHistoryRow]) -> Vec<&AssetHistoryRow> {
+        let Some(latest) = rows.iter().map(|r| r.timestamp.as_str()).max() else {
+            return Vec::new();
+        };
+        rows.iter()
+            .filter(|r| r.timestamp == latest && r.value.unwrap_or(0.0) > 0.0)
+            .collect()
+    }
+
+    // Pairwise return correlations of held assets, beta to the benchmark (BTC by default),
+    // and Herfindahl concentration / effective number of bets for the portfolio and each barca.
+    pub async fn correlation(
+        &self,
+        window: &AnalyticsWindow,
+        benchmark: &str,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_assets(window.from.as_deref(), window.to.as_deref())
+            .await?;
+
+        let prices = Self::price_series(&rows, window.interval);
+
+        let held = Self::latest_snapshot(&rows);
+        let as_of = held.first().map(|r| r.timestamp.clone());
+        let total_value: f64 = held.iter().filter_map(|r| r.value).sum();
+        let symbols: Vec<String> = held.iter().map(|r| r.symbol.clone()).collect();
+        let empty = BTreeMap::new();
+        let series = |sym: &str| prices.get(sym).unwrap_or(&empty);
+        let bench = series(benchmark);
+
+        let matrix: Vec<Vec<Option<f64>>> = symbols
+            .iter()
+            .map(|a| {
+                symbols
+                    .iter()
+                    .map(|b| {
+                        if a == b {
+                            return Some(1.0);
+                        }
+                        let (ra, rb) = paired_returns(series(a), series(b));
+                        pearson(&ra, &rb)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut betas: BTreeMap<String, Option<f64>> = BTreeMap::new();
+        let assets: Vec<Value> = held
+            .iter()
+            .map(|r| {
+                let (ra, rb) = paired_returns(series(&r.symbol), bench);
+                let b = beta(&ra, &rb);
+                betas.insert(r.symbol.clone(), b);
+                json!({
+                    "symbol": r.symbol,
+                    "group": r.group_name,
+                    "barca": r.barca,
+                    "value": r.value,
+                    "weight_percent": if total_value > 0.0 { r.value.unwrap_or(0.0) / total_value * 100.0 } else { 0.0 },
+                    "observations": ra.len(),
+                    "beta_to_benchmark": b,
+                    "correlation_to_benchmark": pearson(&ra, &rb),
+                })
+            })
+            .collect();
+
+        let (hhi, effective_bets) = concentration(
+            &held
+                .iter()
+                .map(|r| r.value.unwrap_or(0.0))
+                .collect::<Vec<_>>(),
+        );
+
+        let mut barcas: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, r) in held.iter().enumerate() {
+            barcas
+                .entry(r.barca.clone().unwrap_or_default())
+                .or_default()
+                .push(i);
+        }
+        let per_barca: Vec<Value> = barcas
+            .into_iter()
+            .map(|(barca, idx)| {
+                let values: Vec<f64> = idx.iter().map(|i| held[*i].value.unwrap_or(0.0)).collect();
+                let barca_value: f64 = values.iter().sum();
+                let (hhi, n) = concentration(&values);
+                let weighted_beta = idx
+                    .iter()
+                    .map(|i| {
+                        betas
+                            .get(&held[*i].symbol)
+                            .copied()
+                            .flatten()
+                            .map(|b| b * held[*i].value.unwrap_or(0.0))
+                    })
+                    .sum::<Option<f64>>()
+                    .filter(|_| barca_value > 0.0)
+                    .map(|b| b / barca_value);
+                let pair_corrs: Vec<f64> = idx
+                    .iter()
+                    .flat_map(|a| idx.iter().filter(move |b| *b > a).map(move |b| (*a, *b)))
+                    .filter_map(|(a, b)| matrix[a][b])
+                    .collect();
+                json!({
+                    "barca": barca,
+                    "value": barca_value,
+                    "weight_percent": if total_value > 0.0 { barca_value / total_value * 100.0 } else { 0.0 },
+                    "assets": idx.len(),
+                    "herfindahl_index": hhi,
+                    "effective_number_of_bets": n,
+                    "weighted_beta_to_benchmark": weighted_beta,
+                    "average_pairwise_correlation": if pair_corrs.is_empty() { None } else { Some(pair_corrs.iter().sum::<f64>() / pair_corrs.len() as f64) },
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "window": window.to_json(),
+            "benchmark": benchmark,
+            "as_of": as_of,
+            "symbols": symbols,
+            "correlation_matrix": matrix,
+            "assets": assets,
+            "concentration": {
+                "herfindahl_index": hhi,
+                "effective_number_of_bets": effective_bets,
+                "assets": held.len(),
+            },
+            "per_barca": per_barca,
+        }))
+    }
+
+    // Resampled price series per symbol from history_assets
+    fn price_series(
+        rows: &[AssetHistoryRow],
+        interval: Interval,
+    ) -> BTreeMap<String, BTreeMap<NaiveDate, f64>> {
+        let mut price_points: BTreeMap<String, Vec<(String, f64)>> = BTreeMap::new();
+        for r in rows {
+            if let Some(p) = r.price {
+                price_points
+                    .entry(r.symbol.clone())
+                    .or_default()
+                    .push((r.timestamp.clone(), p));
+            }
+        }
+        price_points
+            .into_iter()
+            .map(|(sym, points)| (sym, resample_last(&points, interval).into_iter().collect()))
+            .collect()
+    }
+
+    // Joint per-period return vectors for `symbols`; an asset without prices on both
+    // ends of a period contributes a 0% return for that period.
+    fn joint_returns(
+        prices: &BTreeMap<String, BTreeMap<NaiveDate, f64>>,
+        symbols: &[String],
+    ) -> Vec<Vec<f64>> {
+        let dates: BTreeSet<NaiveDate> = symbols
+            .iter()
+            .filter_map(|s| prices.get(s))
+            .flat_map(|p| p.keys().copied())
+            .collect();
+        let dates: Vec<NaiveDate> = dates.into_iter().collect();
+        dates
+            .windows(2)
+            .map(|w| {
+                symbols
+                    .iter()
+                    .map(|s| {
+                        let p = prices.get(s);
+                        match (p.and_then(|p| p.get(&w[0])), p.and_then(|p| p.get(&w[1]))) {
+                            (Some(a), Some(b)) if *a != 0.0 => b / a - 1.0,
+                            _ => 0.0,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Monte Carlo projection of the latest snapshot's holdings from historical returns
+    pub async fn projection(
+        &self,
+        window: &AnalyticsWindow,
+        cfg: &ProjectionConfig,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_assets(window.from.as_deref(), window.to.as_deref())
+            .await?;
+        let held = Self::latest_snapshot(&rows);
+        let holdings: Vec<Holding> = held
+            .iter()
+            .map(|r| Holding {
+                symbol: r.symbol.clone(),
+                barca: r.barca.clone().unwrap_or_default(),
+                value: r.value.unwrap_or(0.0),
+                target_percent: r.target_percent.unwrap_or(0.0),
+            })
+            .collect();
+        let symbols: Vec<String> = holdings.iter().map(|h| h.symbol.clone()).collect();
+        let prices = Self::price_series(&rows, window.interval);
+        let history = Self::joint_returns(&prices, &symbols);
+
+        // Up to paths x periods simulated steps: keep them off the async workers
+        let cfg = cfg.clone();
+        let mut out =
+            match tokio::task::spawn_blocking(move || project(&holdings, &history, &cfg)).await? {
+                Ok(out) => out,
+                Err(e) => return Ok(Err(e)),
+            };
+        out["window"] = window.to_json();
+        out["as_of"] = json!(held.first().map(|r| r.timestamp.clone()));
+        Ok(Ok(out))
+    }
+
+    // Historical and parametric VaR / Expected Shortfall of the current holdings (as
+    // `current_holdings` reports them, valued at the latest stored price) for the portfolio,
+    // each barca and each group. Every figure is stored in history_var when `persist` is set;
+    // a failed insert fails the whole call, so `persisted: true` means every row was written.
+    pub async fn value_at_risk(
+        &self,
+        window: &AnalyticsWindow,
+        confidences: &[f64],
+        horizons: &[usize],
+        persist: bool,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let allocations = current_holdings(self.repo.as_ref()).await?;
+        self.value_at_risk_of(&allocations, window, confidences, horizons, persist)
+            .await
+    }
+
+    // Same for the given holdings
+    async fn value_at_risk_of(
+        &self,
+        allocations: &[WalletAllocation],
+        window: &AnalyticsWindow,
+        confidences: &[f64],
+        horizons: &[usize],
+        persist: bool,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_assets(window.from.as_deref(), window.to.as_deref())
+            .await?;
+        let prices = Self::price_series(&rows, Interval::Daily);
+
+        let mut symbols: Vec<String> = Vec::new();
+        let mut positions: Vec<(usize, String, String, f64)> = Vec::new();
+        let mut unpriced: BTreeSet<String> = BTreeSet::new();
+        for a in allocations {
+            let qty = a.current_quantity.unwrap_or(0.0);
+            if qty == 0.0 {
+                continue;
+            }
+            let Some(price) = prices.get(&a.symbol).and_then(|p| p.values().last()) else {
+                unpriced.insert(a.symbol.clone());
+                continue;
+            };
+            let idx = match symbols.iter().position(|s| *s == a.symbol) {
+                Some(i) => i,
+                None => {
+                    symbols.push(a.symbol.clone());
+                    symbols.len() - 1
+                }
+            };
+            positions.push((
+                idx,
+                a.group_name.clone().unwrap_or_default(),
+                a.barca.clone().unwrap_or_default(),
+                qty * price,
+            ));
+        }
+        let returns = Self::joint_returns(&prices, &symbols);
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let mut scopes: Vec<(&str, String, Vec<f64>)> = Vec::new();
+        let mut values = vec![0.0; symbols.len()];
+        for (i, _, _, v) in &positions {
+            values[*i] += v;
+        }
+        scopes.push(("portfolio", "portfolio".to_string(), values));
+        let mut by_barca: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        let mut by_group: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for (i, group, barca, v) in &positions {
+            by_barca
+                .entry(barca.clone())
+                .or_insert_with(|| vec![0.0; symbols.len()])[*i] += v;
+            by_group
+                .entry(group.clone())
+                .or_insert_with(|| vec![0.0; symbols.len()])[*i] += v;
+        }
+        scopes.extend(by_barca.into_iter().map(|(n, v)| ("barca", n, v)));
+        scopes.extend(by_group.into_iter().map(|(n, v)| ("group", n, v)));
+
+        let mut snapshots: Vec<VarSnapshot> = Vec::new();
+        for (scope, name, values) in &scopes {
+            let position_value: f64 = values.iter().sum();
+            let daily = horizon_pnl(&returns, values, 1);
+            for h in horizons {
+                let hist = horizon_pnl(&returns, values, *h);
+                for c in confidences {
+                    let results = [
+                        ("historical", historical_var_es(&hist, *c), hist.len()),
+                        ("parametric", parametric_var_es(&daily, *c, *h), daily.len()),
+                    ];
+                    for (method, res, obs) in results {
+                        snapshots.push(VarSnapshot {
+                            id: None,
+                            timestamp: timestamp.clone(),
+                            scope: scope.to_string(),
+                            name: name.clone(),
+                            method: method.to_string(),
+                            confidence: *c,
+                            horizon_days: *h as i64,
+                            position_value: Some(position_value),
+                            var_value: res.map(|(v, _)| v),
+                            es_value: res.map(|(_, e)| e),
+                            observations: Some(obs as i64),
+                            extra: None,
+                            created_at: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        if persist {
+            for snap in &snapshots {
+                self.repo.insert_var_snapshot(snap).await?;
+            }
+        }
+
+        let to_json = |s: &VarSnapshot| {
+            let pct = |x: Option<f64>| match (x, s.position_value) {
+                (Some(x), Some(v)) if v > 0.0 => Some(x / v * 100.0),
+                _ => None,
+            };
+            json!({
+                "name": s.name,
+                "method": s.method,
+                "confidence": s.confidence,
+                "horizon_days": s.horizon_days,
+                "position_value": s.position_value,
+                "var": s.var_value,
+                "es": s.es_value,
+                "var_percent": pct(s.var_value),
+                "es_percent": pct(s.es_value),
+                "observations": s.observations,
+            })
+        };
+        let select = |scope: &str| -> Vec<Value> {
+            snapshots
+                .iter()
+                .filter(|s| s.scope == scope)
+                .map(to_json)
+                .collect()
+        };
+
+        Ok(json!({
+            "timestamp": timestamp,
+            "window": window.to_json(),
+            "confidence_levels": confidences,
+            "horizons_days": horizons,
+            "persisted": persist,
+            "unpriced_symbols": unpriced,
+            "portfolio": select("portfolio"),
+            "per_barca": select("barca"),
+            "per_group": select("group"),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::{AssetSnapshot, Transaction};
+    use crate::infra::sqlite::repo::SqliteRepo;
+    use crate::usecases::allocations_service::holdings_from;
+    use crate::usecases::ledger::HoldingsSource;
+    use sqlx::SqlitePool;
+
+    #[tokio::test]
+    async fn value_at_risk_follows_the_ledger_holdings() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let repo = Arc::new(SqliteRepo::new(pool));
+
+        for (day, price) in [(1, 100.0), (2, 110.0), (3, 99.0), (4, 104.0)] {
+            repo.insert_asset_snapshot(&AssetSnapshot {
+                id: None,
+                timestamp: format!("2025-01-0{}T00:00:00+00:00", day),
+                symbol: "BTC".to_string(),
+                group_name: Some("Base".to_string()),
+                barca: Some("Base".to_string()),
+                price: Some(price),
+                current_quantity: None,
+                value: None,
+                target_percent: None,
+                current_percent: None,
+                market_cap: None,
+                fdv: None,
+                volume_24h: None,
+                percent_change_24h: None,
+                percent_change_7d: None,
+                extra: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+        }
+        // The wallet says 1 BTC, the ledger 3
+        repo.insert_wallet_allocation(&WalletAllocation {
+            id: None,
+            symbol: "BTC".to_string(),
+            group_name: Some("Base".to_string()),
+            barca: Some("Base".to_string()),
+            target_percent: Some(100.0),
+            current_quantity: Some(1.0),
+            last_price: None,
+            notes: None,
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        })
+        .await
+        .unwrap();
+        repo.insert_transaction(&Transaction {
+            id: None,
+            timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+            kind: "buy".to_string(),
+            symbol: "BTC".to_string(),
+            quantity: 3.0,
+            price: Some(100.0),
+            quote_currency: Some("USD".to_string()),
+            fee: None,
+            fee_symbol: None,
+            venue: None,
+            to_venue: None,
+            group_name: None,
+            barca: None,
+            external_id: None,
+            notes: None,
+            created_at: None,
+            income_type: None,
+            account_id: None,
+            to_account_id: None,
+        })
+        .await
+        .unwrap();
+
+        async fn position(repo: Arc<SqliteRepo>, source: HoldingsSource) -> f64 {
+            let window = AnalyticsWindow {
+                from: None,
+                to: None,
+                interval: Interval::Daily,
+            };
+            let held = holdings_from(repo.as_ref(), source).await.unwrap();
+            let v = AnalyticsService::new(repo)
+                .value_at_risk_of(&held, &window, &[0.95], &[1], false)
+                .await
+                .unwrap();
+            v["portfolio"][0]["position_value"].as_f64().unwrap()
+        }
+        assert_eq!(position(repo.clone(), HoldingsSource::Wallet).await, 104.0);
+        assert_eq!(position(repo, HoldingsSource::Transactions).await, 312.0);
+    }
+}
@@ -0,0 +1,602 @@
+use crate::domain::models::{ApiToken, DEFAULT_PORTFOLIO_ID, PortfolioRole, User};
+use crate::domain::repository::HistoryRepo;
+use argon2::Argon2;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct AuthService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+// Role of a user on a portfolio; each one includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            other => Err(format!(
+                "unknown role '{}' (owner, editor or viewer)",
+                other
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+// The authenticated caller with its role per portfolio; admins may do everything
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub id: i64,
+    pub username: String,
+    pub is_admin: bool,
+    pub roles: HashMap<i64, Role>,
+}
+
+impl CurrentUser {
+    pub fn can(&self, portfolio_id: i64, needed: Role) -> bool {
+        self.is_admin
+            || self
+                .roles
+                .get(&portfolio_id)
+                .is_some_and(|role| *role >= needed)
+    }
+
+    // Households span several portfolios: the role is needed on each of them
+    pub fn can_all(&self, portfolio_ids: &[i64], needed: Role) -> bool {
+        portfolio_ids.iter().all(|id| self.can(*id, needed))
+    }
+}
+
+// What a route needs from the caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    // login and first-run setup
+    Public,
+    // any valid token; the handler checks anything else (portfolio list, households)
+    Authenticated,
+    Admin,
+    Portfolio(i64, Role),
+}
+
+// Access needed for `method` on the matched route pattern. `portfolio_id` is the portfolio the
+// request works on: the `/api/portfolios/{id}/` prefix or `{id}` of /api/portfolios/{id}.
+// Reads need viewer, writes editor, and changing the portfolio itself or its roles, or managing
+// its share links, owner; POST /api/simulate only reads. FX rates are global, so only admins
+// write them.
+pub fn required_access(method: &str, route: &str, portfolio_id: Option<i64>) -> Access {
+    let read = matches!(method, "GET" | "HEAD" | "OPTIONS");
+    let portfolio = portfolio_id.unwrap_or(DEFAULT_PORTFOLIO_ID);
+    match route {
+        "/api/auth/login" | "/api/auth/setup" => Access::Public,
+        r if r.starts_with("/api/auth/") => Access::Authenticated,
+        r if r == "/api/users" || r.starts_with("/api/users/") => Access::Admin,
+        // Rates are shared by every portfolio's tax reports
+        "/api/fx_rates" if !read => Access::Admin,
+        "/api/portfolios" => Access::Authenticated,
+        r if r == "/api/households" || r.starts_with("/api/households/") => Access::Authenticated,
+        "/api/portfolios/{id}" | "/api/roles" if !read => Access::Portfolio(portfolio, Role::Owner),
+        r if r == "/api/share_links" || r.starts_with("/api/share_links/") => {
+            Access::Portfolio(portfolio, Role::Owner)
+        }
+        "/api/simulate" => Access::Portfolio(portfolio, Role::Viewer),
+        _ if read => Access::Portfolio(portfolio, Role::Viewer),
+        _ => Access::Portfolio(portfolio, Role::Editor),
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    if password.chars().count() < 8 {
+        return Err("password must have at least 8 characters".to_string());
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| format!("could not hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|h| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &h)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+// A new secret bearer token: "cmt_" and 32 random bytes in hex
+pub fn new_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("cmt_{}", hex)
+}
+
+// What is stored and looked up instead of the token itself
+pub fn token_hash(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn token_active(token: &ApiToken, now: DateTime<Utc>) -> bool {
+    if token.revoked_at.is_some() {
+        return false;
+    }
+    match token.expires_at.as_deref() {
+        Some(exp) => DateTime::parse_from_rfc3339(exp)
+            .map(|exp| exp.with_timezone(&Utc) > now)
+            .unwrap_or(false),
+        None => true,
+    }
+}
+
+// Lifetime of the tokens issued by /api/auth/login: AUTH_TOKEN_TTL_DAYS (default 30)
+pub fn login_ttl_days_from_env() -> Result<i64, String> {
+    match std::env::var("AUTH_TOKEN_TTL_DAYS") {
+        Ok(v) if !v.trim().is_empty() => v
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|d| *d > 0)
+            .ok_or_else(|| format!("invalid AUTH_TOKEN_TTL_DAYS '{}'", v)),
+        _ => Ok(30),
+    }
+}
+
+fn user_json(user: &User, roles: &[PortfolioRole]) -> Value {
+    json!({
+        "id": user.id,
+        "username": user.username,
+        "is_admin": user.is_admin,
+        "created_at": user.created_at,
+        "roles": roles
+            .iter()
+            .map(|r| json!({"portfolio_id": r.portfolio_id, "role": r.role}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+impl AuthService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // First run: the first user is an admin and owns every existing portfolio
+    pub async fn setup(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let username = username.trim();
+        if username.is_empty() {
+            return Ok(Err("username is required".to_string()));
+        }
+        let password_hash = match hash_password(password) {
+            Ok(h) => h,
+            Err(e) => return Ok(Err(e)),
+        };
+        let user = User {
+            id: None,
+            username: username.to_string(),
+            password_hash,
+            is_admin: true,
+            created_at: None,
+        };
+        let Some(user_id) = self.repo.insert_first_user(&user).await? else {
+            return Ok(Err("already set up; log in instead".to_string()));
+        };
+        let token = self
+            .issue_token(user_id, "setup", Some(login_ttl_days_from_env()?))
+            .await?;
+        Ok(Ok(
+            json!({"user": self.user(user_id).await?, "token": token}),
+        ))
+    }
+
+    // Username and password for a token that expires after AUTH_TOKEN_TTL_DAYS
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let user = self.repo.fetch_user_by_name(username.trim()).await?;
+        let Some(user) = user.filter(|u| verify_password(password, &u.password_hash)) else {
+            return Ok(Err("invalid username or password".to_string()));
+        };
+        let user_id = user.id.unwrap_or_default();
+        let token = self
+            .issue_token(user_id, "login", Some(login_ttl_days_from_env()?))
+            .await?;
+        Ok(Ok(
+            json!({"user": self.user(user_id).await?, "token": token}),
+        ))
+    }
+
+    // The caller behind a bearer token, None when it is unknown, revoked or expired
+    pub async fn authenticate(
+        &self,
+        token: &str,
+    ) -> Result<Option<CurrentUser>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(stored) = self.repo.find_api_token(&token_hash(token)).await? else {
+            return Ok(None);
+        };
+        if !token_active(&stored, Utc::now()) {
+            return Ok(None);
+        }
+        let Some(user) = self.repo.fetch_user(stored.user_id).await? else {
+            return Ok(None);
+        };
+        if let Some(id) = stored.id {
+            self.repo.touch_api_token(id).await?;
+        }
+        let roles = self
+            .repo
+            .fetch_user_roles(stored.user_id)
+            .await?
+            .into_iter()
+            .filter_map(|r| Some((r.portfolio_id, Role::parse(&r.role).ok()?)))
+            .collect();
+        Ok(Some(CurrentUser {
+            id: stored.user_id,
+            username: user.username,
+            is_admin: user.is_admin,
+            roles,
+        }))
+    }
+
+    // The user with its portfolio roles
+    pub async fn user(&self, id: i64) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(user) = self.repo.fetch_user(id).await? else {
+            return Ok(Value::Null);
+        };
+        let roles = self.repo.fetch_user_roles(id).await?;
+        Ok(user_json(&user, &roles))
+    }
+
+    pub async fn users(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let mut out = Vec::new();
+        for user in self.repo.fetch_users().await? {
+            let roles = self
+                .repo
+                .fetch_user_roles(user.id.unwrap_or_default())
+                .await?;
+            out.push(user_json(&user, &roles));
+        }
+        Ok(json!({"users": out}))
+    }
+
+    pub async fn create_user(
+        &self,
+        username: &str,
+        password: &str,
+        is_admin: bool,
+    ) -> Result<Result<User, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let username = username.trim();
+        if username.is_empty() {
+            return Ok(Err("username is required".to_string()));
+        }
+        if self.repo.fetch_user_by_name(username).await?.is_some() {
+            return Ok(Err(format!("user '{}' already exists", username)));
+        }
+        let password_hash = match hash_password(password) {
+            Ok(h) => h,
+            Err(e) => return Ok(Err(e)),
+        };
+        let mut user = User {
+            id: None,
+            username: username.to_string(),
+            password_hash,
+            is_admin,
+            created_at: None,
+        };
+        user.id = Some(self.repo.insert_user(&user).await?);
+        Ok(Ok(user))
+    }
+
+    // An admin cannot delete itself, so there is always one left, nor the last owner of a
+    // portfolio
+    pub async fn delete_user(
+        &self,
+        caller: &CurrentUser,
+        id: i64,
+    ) -> Result<Result<bool, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if caller.id == id {
+            return Ok(Err("you cannot delete your own user".to_string()));
+        }
+        // Portfolios this user alone owns would be left without anyone to manage them
+        let mut sole_owner = Vec::new();
+        for r in self.repo.fetch_user_roles(id).await? {
+            if r.role != Role::Owner.as_str() {
+                continue;
+            }
+            let owners = self
+                .repo
+                .fetch_portfolio_roles(r.portfolio_id)
+                .await?
+                .iter()
+                .filter(|o| o.role == Role::Owner.as_str())
+                .count();
+            if owners <= 1 {
+                sole_owner.push(r.portfolio_id.to_string());
+            }
+        }
+        if !sole_owner.is_empty() {
+            return Ok(Err(format!(
+                "user is the only owner of portfolio(s) {}; grant the owner role to someone else first",
+                sole_owner.join(", ")
+            )));
+        }
+        Ok(Ok(self.repo.delete_user(id).await?))
+    }
+
+    pub async fn change_password(
+        &self,
+        user_id: i64,
+        current: &str,
+        new: &str,
+    ) -> Result<Result<(), String>, Box<dyn std::error::Error + Send + Sync>> {
+        let user = self.repo.fetch_user(user_id).await?;
+        if !user.is_some_and(|u| verify_password(current, &u.password_hash)) {
+            return Ok(Err("current password is wrong".to_string()));
+        }
+        match hash_password(new) {
+            Ok(hash) => {
+                self.repo.update_user_password(user_id, &hash).await?;
+                Ok(Ok(()))
+            }
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    // A new token for the user; the secret is only returned here
+    pub async fn issue_token(
+        &self,
+        user_id: i64,
+        name: &str,
+        expires_in_days: Option<i64>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let secret = new_token();
+        let token = ApiToken {
+            id: None,
+            user_id,
+            name: name.trim().to_string(),
+            prefix: secret.chars().take(12).collect(),
+            expires_at: expires_in_days.map(|d| (Utc::now() + Duration::days(d)).to_rfc3339()),
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None,
+        };
+        let id = self
+            .repo
+            .insert_api_token(&token, &token_hash(&secret))
+            .await?;
+        Ok(json!({
+            "id": id,
+            "name": token.name,
+            "prefix": token.prefix,
+            "expires_at": token.expires_at,
+            "token": secret,
+        }))
+    }
+
+    // A named token for scripts and integrations, expiring after `expires_in_days` if given
+    pub async fn create_token(
+        &self,
+        user_id: i64,
+        name: &str,
+        expires_in_days: Option<i64>,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if name.trim().is_empty() {
+            return Ok(Err("name is required".to_string()));
+        }
+        if expires_in_days.is_some_and(|d| d <= 0) {
+            return Ok(Err("expires_in_days must be positive".to_string()));
+        }
+        Ok(Ok(self.issue_token(user_id, name, expires_in_days).await?))
+    }
+
+    pub async fn tokens(
+        &self,
+        user_id: i64,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let now = Utc::now();
+        let rows: Vec<Value> = self
+            .repo
+            .fetch_api_tokens(user_id)
+            .await?
+            .into_iter()
+            .map(|t| {
+                let active = token_active(&t, now);
+                let mut v = json!(t);
+                if let Some(obj) = v.as_object_mut() {
+                    obj.insert("active".to_string(), json!(active));
+                }
+                v
+            })
+            .collect();
+        Ok(json!({"tokens": rows}))
+    }
+
+    pub async fn revoke_token(
+        &self,
+        user_id: i64,
+        id: i64,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let revoked = self.repo.revoke_api_token(user_id, id).await?;
+        Ok(revoked)
+    }
+
+    pub async fn portfolio_roles(
+        &self,
+        portfolio_id: i64,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let roles = self.repo.fetch_portfolio_roles(portfolio_id).await?;
+        Ok(json!({"portfolio_id": portfolio_id, "roles": roles}))
+    }
+
+    // Grant `role` (owner, editor or viewer) on the portfolio to the named user, or remove
+    // their access with None; the portfolio keeps at least one owner
+    pub async fn set_portfolio_role(
+        &self,
+        portfolio_id: i64,
+        username: &str,
+        role: Option<&str>,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let role = match role.map(Role::parse).transpose() {
+            Ok(r) => r,
+            Err(e) => return Ok(Err(e)),
+        };
+        let Some(user) = self.repo.fetch_user_by_name(username.trim()).await? else {
+            return Ok(Err(format!("unknown user '{}'", username.trim())));
+        };
+        let user_id = user.id.unwrap_or_default();
+        let current = self.repo.fetch_portfolio_roles(portfolio_id).await?;
+        let other_owners = current
+            .iter()
+            .filter(|r| r.role == Role::Owner.as_str() && r.user_id != user_id)
+            .count();
+        let was_owner = current
+            .iter()
+            .any(|r| r.role == Role::Owner.as_str() && r.user_id == user_id);
+        if was_owner && other_owners == 0 && role != Some(Role::Owner) {
+            return Ok(Err(format!(
+                "'{}' is the only owner of portfolio {}",
+                user.username, portfolio_id
+            )));
+        }
+        match role {
+            Some(r) => {
+                self.repo
+                    .set_portfolio_role(user_id, portfolio_id, r.as_str())
+                    .await?
+            }
+            None => {
+                self.repo
+                    .delete_portfolio_role(user_id, portfolio_id)
+                    .await?;
+            }
+        }
+        Ok(Ok(self.portfolio_roles(portfolio_id).await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_need_the_right_role() {
+        assert_eq!(
+            required_access("POST", "/api/auth/login", None),
+            Access::Public
+        );
+        assert_eq!(
+            required_access("GET", "/api/auth/tokens", None),
+            Access::Authenticated
+        );
+        assert_eq!(required_access("GET", "/api/users", None), Access::Admin);
+        assert_eq!(
+            required_access("POST", "/api/fx_rates", Some(2)),
+            Access::Admin
+        );
+        assert_eq!(
+            required_access("GET", "/api/allocations", None),
+            Access::Portfolio(DEFAULT_PORTFOLIO_ID, Role::Viewer)
+        );
+        assert_eq!(
+            required_access("POST", "/api/allocations", Some(2)),
+            Access::Portfolio(2, Role::Editor)
+        );
+        assert_eq!(
+            required_access("POST", "/api/simulate", Some(2)),
+            Access::Portfolio(2, Role::Viewer)
+        );
+        assert_eq!(
+            required_access("POST", "/api/import_upload", Some(2)),
+            Access::Portfolio(2, Role::Editor)
+        );
+        assert_eq!(
+            required_access("GET", "/api/portfolios/{id}", Some(3)),
+            Access::Portfolio(3, Role::Viewer)
+        );
+        assert_eq!(
+            required_access("DELETE", "/api/portfolios/{id}", Some(3)),
+            Access::Portfolio(3, Role::Owner)
+        );
+        assert_eq!(
+            required_access("PUT", "/api/roles", Some(3)),
+            Access::Portfolio(3, Role::Owner)
+        );
+        assert_eq!(
+            required_access("GET", "/api/share_links", Some(3)),
+            Access::Portfolio(3, Role::Owner)
+        );
+
+        let user = CurrentUser {
+            id: 1,
+            username: "ana".to_string(),
+            is_admin: false,
+            roles: HashMap::from([(1, Role::Editor), (2, Role::Viewer)]),
+        };
+        assert!(user.can(1, Role::Viewer) && user.can(1, Role::Editor));
+        assert!(!user.can(1, Role::Owner) && !user.can(3, Role::Viewer));
+        assert!(user.can_all(&[1, 2], Role::Viewer));
+        assert!(!user.can_all(&[1, 2], Role::Editor));
+    }
+
+    #[test]
+    fn passwords_and_tokens_are_hashed() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("wrong horse", &hash));
+        assert!(hash_password("short").is_err());
+
+        let token = new_token();
+        assert_eq!(token.len(), 4 + 64);
+        assert_ne!(token, new_token());
+        assert_eq!(token_hash(&token).len(), 64);
+        assert_ne!(token_hash(&token), token);
+
+        let now = Utc::now();
+        let mut t = ApiToken {
+            id: Some(1),
+            user_id: 1,
+            name: "cli".to_string(),
+            prefix: token[..12].to_string(),
+            expires_at: None,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None,
+        };
+        assert!(token_active(&t, now));
+        t.expires_at = Some((now - Duration::days(1)).to_rfc3339());
+        assert!(!token_active(&t, now));
+        t.expires_at = Some((now + Duration::days(1)).to_rfc3339());
+        assert!(token_active(&t, now));
+        t.revoked_at = Some(now.to_rfc3339());
+        assert!(!token_active(&t, now));
+    }
+}
@@ -3,8 +3,8 @@ use serde_json::json;
 use std::collections::HashMap;
 
 pub fn compute_allocations(
-    allocations: &Vec<DomainWalletAllocation>,
-    cryptos: &Vec<crate::CryptoData>,
+    allocations: &[DomainWalletAllocation],
+    cryptos: &[crate::CryptoData],
     barca_targets: &HashMap<String, f64>,
 ) -> serde_json::Value {
     // Build crypto lookup map
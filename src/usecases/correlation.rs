@@ -0,0 +1,110 @@
+use crate::usecases::timeseries::mean;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+// Minimum overlapping returns before a correlation/beta is reported
+pub const MIN_OBSERVATIONS: usize = 3;
+
+// Returns over the dates both series have a price for (pairwise complete observations)
+pub fn paired_returns(
+    a: &BTreeMap<NaiveDate, f64>,
+    b: &BTreeMap<NaiveDate, f64>,
+) -> (Vec<f64>, Vec<f64>) {
+    let common: Vec<(f64, f64)> = a
+        .iter()
+        .filter_map(|(d, pa)| b.get(d).map(|pb| (*pa, *pb)))
+        .collect();
+    let mut ra = Vec::new();
+    let mut rb = Vec::new();
+    for w in common.windows(2) {
+        let ((a0, b0), (a1, b1)) = (w[0], w[1]);
+        if a0 != 0.0 && b0 != 0.0 {
+            ra.push(a1 / a0 - 1.0);
+            rb.push(b1 / b0 - 1.0);
+        }
+    }
+    (ra, rb)
+}
+
+fn covariance(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < 2 || a.len() != b.len() {
+        return 0.0;
+    }
+    let (ma, mb) = (mean(a), mean(b));
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - ma) * (y - mb))
+        .sum::<f64>()
+        / (a.len() - 1) as f64
+}
+
+pub fn pearson(a: &[f64], b: &[f64]) -> Option<f64> {
+    if a.len() < MIN_OBSERVATIONS || a.len() != b.len() {
+        return None;
+    }
+    let (va, vb) = (covariance(a, a), covariance(b, b));
+    if va == 0.0 || vb == 0.0 {
+        return None;
+    }
+    Some(covariance(a, b) / (va.sqrt() * vb.sqrt()))
+}
+
+// Beta of `asset` returns against `benchmark` returns
+pub fn beta(asset: &[f64], benchmark: &[f64]) -> Option<f64> {
+    if asset.len() < MIN_OBSERVATIONS || asset.len() != benchmark.len() {
+        return None;
+    }
+    let vb = covariance(benchmark, benchmark);
+    if vb == 0.0 {
+        return None;
+    }
+    Some(covariance(asset, benchmark) / vb)
+}
+
+// Herfindahl-Hirschman index of the weights (normalized to sum 1) and the
+// effective number of bets (1 / HHI)
+pub fn concentration(weights: &[f64]) -> (f64, f64) {
+    let total: f64 = weights.iter().filter(|w| **w > 0.0).sum();
+    if total <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let hhi: f64 = weights
+        .iter()
+        .filter(|w| **w > 0.0)
+        .map(|w| (w / total).powi(2))
+        .sum();
+    (hhi, 1.0 / hhi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(prices: &[f64]) -> BTreeMap<NaiveDate, f64> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (NaiveDate::from_ymd_opt(2025, 1, 1 + i as u32).unwrap(), *p))
+            .collect()
+    }
+
+    #[test]
+    fn leveraged_asset_has_full_correlation_and_beta_two() {
+        let btc = series(&[100.0, 110.0, 99.0, 108.9, 119.79]);
+        // alt moves exactly twice as much as BTC every day
+        let alt = series(&[10.0, 12.0, 9.6, 11.52, 13.824]);
+        let (ra, rb) = paired_returns(&alt, &btc);
+        assert_eq!(ra.len(), 4);
+        assert!((pearson(&ra, &rb).unwrap() - 1.0).abs() < 1e-9);
+        assert!((beta(&ra, &rb).unwrap() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn concentration_of_equal_and_single_weights() {
+        let (hhi, n) = concentration(&[25.0, 25.0, 25.0, 25.0]);
+        assert!((hhi - 0.25).abs() < 1e-12);
+        assert!((n - 4.0).abs() < 1e-12);
+        assert_eq!(concentration(&[10.0]), (1.0, 1.0));
+        assert_eq!(concentration(&[]), (0.0, 0.0));
+    }
+}
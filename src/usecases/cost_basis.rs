@@ -0,0 +1,597 @@
+use crate::domain::models::Transaction;
+use crate::usecases::ledger::{TransactionKind, is_fiat};
+use crate::usecases::timeseries::parse_ts;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+// Quote currencies whose units are taken as 1 USD when valuing trades
+const USD_EQUIVALENT: &[&str] = &["USD", "USDT", "USDC", "BUSD", "DAI", "FDUSD", "TUSD"];
+
+pub fn usd_equivalent(symbol: &str) -> bool {
+    USD_EQUIVALENT
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case(symbol))
+}
+
+// Lot relief order when disposing of a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostMethod {
+    Fifo,
+    Lifo,
+    // Single pool at the weighted average unit cost
+    Average,
+    // Highest unit cost first (minimizes realized gains)
+    Hifo,
+}
+
+impl CostMethod {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fifo" => Ok(Self::Fifo),
+            "lifo" => Ok(Self::Lifo),
+            "average" | "avg" | "average_cost" => Ok(Self::Average),
+            "hifo" => Ok(Self::Hifo),
+            other => Err(format!("unsupported cost basis method '{}'", other)),
+        }
+    }
+
+    // COST_BASIS_METHOD, defaulting to FIFO
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("COST_BASIS_METHOD") {
+            Ok(s) if !s.trim().is_empty() => Self::parse(&s),
+            _ => Ok(Self::Fifo),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fifo => "fifo",
+            Self::Lifo => "lifo",
+            Self::Average => "average",
+            Self::Hifo => "hifo",
+        }
+    }
+}
+
+// Start of a calendar year (UTC), the cut-off for realized P&L year-to-date
+pub fn year_start(year: i32) -> Option<DateTime<Utc>> {
+    NaiveDate::from_ymd_opt(year, 1, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lot {
+    pub quantity: f64,
+    pub unit_cost: f64,
+    pub acquired: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub lots: Vec<Lot>,
+    pub realized_total: f64,
+    pub realized_ytd: f64,
+}
+
+impl Position {
+    pub fn quantity(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity).sum()
+    }
+
+    pub fn cost_basis(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity * l.unit_cost).sum()
+    }
+
+    pub fn average_entry_price(&self) -> Option<f64> {
+        let q = self.quantity();
+        (q > 1e-12).then(|| self.cost_basis() / q)
+    }
+
+    fn acquire(&mut self, method: CostMethod, quantity: f64, cost: f64, ts: &str) {
+        if quantity <= 0.0 {
+            return;
+        }
+        if method == CostMethod::Average && !self.lots.is_empty() {
+            let lot = &mut self.lots[0];
+            let total_cost = lot.quantity * lot.unit_cost + cost;
+            lot.quantity += quantity;
+            lot.unit_cost = total_cost / lot.quantity;
+            return;
+        }
+        self.lots.push(Lot {
+            quantity,
+            unit_cost: cost / quantity,
+            acquired: ts.to_string(),
+        });
+    }
+
+    // Remove `quantity` from the lots in `method` order; returns (cost relieved, uncovered quantity)
+    fn relieve(&mut self, method: CostMethod, mut quantity: f64) -> (f64, f64) {
+        let mut cost = 0.0;
+        while quantity > 1e-12 && !self.lots.is_empty() {
+            let idx = match method {
+                CostMethod::Fifo | CostMethod::Average => 0,
+                CostMethod::Lifo => self.lots.len() - 1,
+                CostMethod::Hifo => self
+                    .lots
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.unit_cost.total_cmp(&b.1.unit_cost))
+                    .map(|(i, _)| i)
+                    .unwrap_or(0),
+            };
+            let lot = &mut self.lots[idx];
+            let take = lot.quantity.min(quantity);
+            cost += take * lot.unit_cost;
+            lot.quantity -= take;
+            quantity -= take;
+            if lot.quantity <= 1e-12 {
+                self.lots.remove(idx);
+            }
+        }
+        (cost, quantity.max(0.0))
+    }
+}
+
+// A realized disposal (sale, swap leg or fee paid in crypto)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disposal {
+    pub timestamp: String,
+    pub symbol: String,
+    pub venue: Option<String>,
+    pub quantity: f64,
+    pub proceeds: f64,
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CostBasisReport {
+    pub positions: BTreeMap<String, Position>,
+    pub disposals: Vec<Disposal>,
+    pub warnings: Vec<String>,
+}
+
+// Converts `amount` of `currency` at `timestamp` into the reporting currency
+pub type Converter<'a> = dyn Fn(&str, f64, &str) -> Result<f64, String> + 'a;
+
+struct Replay<'a> {
+    method: CostMethod,
+    ytd_start: DateTime<Utc>,
+    convert: &'a Converter<'a>,
+    report: &'a mut CostBasisReport,
+}
+
+impl Replay<'_> {
+    // Amount in the reporting currency; conversion failures are reported and the face value kept
+    fn value(&mut self, tx: &Transaction, amount: f64, currency: &str) -> f64 {
+        if amount == 0.0 {
+            return 0.0;
+        }
+        match (self.convert)(&tx.timestamp, amount, currency) {
+            Ok(v) => v,
+            Err(e) => {
+                self.report
+                    .warnings
+                    .push(format!("transaction {:?}: {}", tx.id, e));
+                amount
+            }
+        }
+    }
+
+    fn acquire(&mut self, symbol: &str, quantity: f64, cost: f64, ts: &str) {
+        self.report
+            .positions
+            .entry(symbol.to_string())
+            .or_default()
+            .acquire(self.method, quantity, cost, ts);
+    }
+
+    // Dispose of `quantity` for `proceeds`; `realize = false` just moves it out of the portfolio
+    fn dispose(
+        &mut self,
+        tx: &Transaction,
+        symbol: &str,
+        quantity: f64,
+        proceeds: f64,
+        realize: bool,
+    ) {
+        let pos = self.report.positions.entry(symbol.to_string()).or_default();
+        let (cost, uncovered) = pos.relieve(self.method, quantity);
+        if realize {
+            let pnl = proceeds - cost;
+            pos.realized_total += pnl;
+            if parse_ts(&tx.timestamp).is_some_and(|t| t >= self.ytd_start) {
+                pos.realized_ytd += pnl;
+            }
+            self.report.disposals.push(Disposal {
+                timestamp: tx.timestamp.clone(),
+                symbol: symbol.to_string(),
+                venue: tx.venue.clone(),
+                quantity,
+                proceeds,
+                cost,
+            });
+        }
+        if uncovered > 1e-9 {
+            self.report.warnings.push(format!(
+                "transaction {:?}: {} {} disposed without matching lots (cost basis 0)",
+                tx.id, uncovered, symbol
+            ));
+        }
+    }
+}
+
+// Amounts in USD, taking USD stablecoins at par; any other currency is an error
+fn usd_converter(_ts: &str, amount: f64, currency: &str) -> Result<f64, String> {
+    if usd_equivalent(currency) {
+        Ok(amount)
+    } else {
+        Err(format!(
+            "currency {} is not USD-equivalent; amount taken at face value",
+            currency
+        ))
+    }
+}
+
+// Replay trades into tax lots valued in USD (quotes must be USD or a USD stablecoin).
+pub fn compute_cost_basis(
+    txs: &[Transaction],
+    method: CostMethod,
+    ytd_start: DateTime<Utc>,
+) -> CostBasisReport {
+    replay_lots(txs, method, ytd_start, &usd_converter)
+}
+
+// Replay trades into tax lots, valuing every amount through `convert`. Transfers only move custody
+// and do not touch lots; withdrawals leave the portfolio at cost; fees paid in crypto are realized
+// as a loss of the relieved cost; spending a held quote currency (e.g. USDT) is a disposal.
+pub fn replay_lots(
+    txs: &[Transaction],
+    method: CostMethod,
+    ytd_start: DateTime<Utc>,
+    convert: &Converter,
+) -> CostBasisReport {
+    let mut report = CostBasisReport::default();
+    let mut r = Replay {
+        method,
+        ytd_start,
+        convert,
+        report: &mut report,
+    };
+    for tx in txs {
+        let Ok(kind) = TransactionKind::parse(&tx.kind) else {
+            continue;
+        };
+        let quote = tx.quote_currency.as_deref().unwrap_or("USD");
+        let raw_notional = tx.quantity * tx.price.unwrap_or(0.0);
+        let notional = r.value(tx, raw_notional, quote);
+        // Fees in cash (fiat or stablecoin) adjust cost/proceeds; fees in crypto are disposals
+        let fee_symbol = tx.fee_symbol.as_deref().unwrap_or(&tx.symbol);
+        let fee = tx.fee.unwrap_or(0.0);
+        let cash_fee = usd_equivalent(fee_symbol) || is_fiat(fee_symbol);
+        let fiat_fee = if cash_fee {
+            r.value(tx, fee, fee_symbol)
+        } else {
+            0.0
+        };
+        match kind {
+            TransactionKind::Buy => {
+                r.acquire(&tx.symbol, tx.quantity, notional + fiat_fee, &tx.timestamp);
+                if !is_fiat(quote) {
+                    // quote currency held as a position (e.g. USDT) is spent
+                    r.dispose(tx, quote, raw_notional, notional, true);
+                }
+            }
+            TransactionKind::Sell => {
+                r.dispose(tx, &tx.symbol, tx.quantity, notional - fiat_fee, true);
+                if !is_fiat(quote) {
+                    r.acquire(quote, raw_notional, notional, &tx.timestamp);
+                }
+            }
+            TransactionKind::Deposit | TransactionKind::Income => {
+                // stablecoins without a price are valued at par
+                let notional = match tx.price {
+                    None if usd_equivalent(&tx.symbol) => r.value(tx, tx.quantity, "USD"),
+                    _ => notional,
+                };
+                if tx.price.is_none() && kind == TransactionKind::Deposit && notional == 0.0 {
+                    r.report.warnings.push(format!(
+                        "transaction {:?}: deposit of {} {} without price (cost basis 0)",
+                        tx.id, tx.quantity, tx.symbol
+                    ));
+                }
+                r.acquire(&tx.symbol, tx.quantity, notional, &tx.timestamp);
+            }
+            TransactionKind::Withdraw => r.dispose(tx, &tx.symbol, tx.quantity, 0.0, false),
+            TransactionKind::Fee => r.dispose(tx, &tx.symbol, tx.quantity, 0.0, true),
+            TransactionKind::Transfer => {}
+        }
+        if fee > 0.0 && !is_fiat(fee_symbol) {
+            // stablecoin fees were already counted in cost/proceeds, so they only leave the pool
+            r.dispose(tx, fee_symbol, fee, 0.0, !cash_fee);
+        }
+    }
+    report
+        .positions
+        .retain(|symbol, p| !(is_fiat(symbol) || p.lots.is_empty() && p.realized_total == 0.0));
+    report
+}
+
+// Share of a symbol's position attributable to one per_asset row
+struct SymbolPnl {
+    quantity: f64,
+    avg_entry: Option<f64>,
+    realized_ytd: f64,
+}
+
+fn add(obj: &mut serde_json::Map<String, Value>, key: &str, v: f64) {
+    let cur = obj.get(key).and_then(|x| x.as_f64()).unwrap_or(0.0);
+    obj.insert(key.to_string(), json!(cur + v));
+}
+
+fn finish_return(obj: &mut serde_json::Map<String, Value>) {
+    let cost = obj
+        .get("cost_basis")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.0);
+    let unrealized = obj
+        .get("unrealized_pnl")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.0);
+    let ret = (cost > 0.0).then(|| unrealized / cost * 100.0);
+    obj.insert("return_percent".to_string(), json!(ret));
+}
+
+// Extend a compute_allocations report with cost basis, average entry price, unrealized P&L,
+// realized P&L year-to-date and return percentage per asset, aggregated to groups and barcas.
+// Rows sharing a symbol split realized P&L in proportion to their quantities.
+pub fn attach_pnl(report: &mut Value, basis: &CostBasisReport, method: CostMethod) {
+    let Some(obj) = report.as_object_mut() else {
+        return;
+    };
+    let mut row_qty: HashMap<String, f64> = HashMap::new();
+    if let Some(rows) = obj.get("per_asset").and_then(|v| v.as_array()) {
+        for r in rows {
+            let symbol = r.get("symbol").and_then(|v| v.as_str()).unwrap_or("");
+            let q = r
+                .get("current_quantity")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0);
+            *row_qty.entry(symbol.to_string()).or_insert(0.0) += q;
+        }
+    }
+    let pnl: HashMap<&str, SymbolPnl> = basis
+        .positions
+        .iter()
+        .map(|(s, p)| {
+            (
+                s.as_str(),
+                SymbolPnl {
+                    quantity: row_qty.get(s).copied().unwrap_or(0.0),
+                    avg_entry: p.average_entry_price(),
+                    realized_ytd: p.realized_ytd,
+                },
+            )
+        })
+        .collect();
+
+    let mut groups: HashMap<String, serde_json::Map<String, Value>> = HashMap::new();
+    let mut barcas: HashMap<String, serde_json::Map<String, Value>> = HashMap::new();
+    let (mut total_cost, mut total_unrealized, mut total_ytd) = (0.0, 0.0, 0.0);
+    if let Some(rows) = obj.get_mut("per_asset").and_then(|v| v.as_array_mut()) {
+        for row in rows {
+            let Some(r) = row.as_object_mut() else {
+                continue;
+            };
+            let symbol = r
+                .get("symbol")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let qty = r
+                .get("current_quantity")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0);
+            let value = r.get("value").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            let Some(p) = pnl.get(symbol.as_str()) else {
+                for key in [
+                    "cost_basis",
+                    "average_entry_price",
+                    "unrealized_pnl",
+                    "realized_pnl_ytd",
+                    "return_percent",
+                ] {
+                    r.insert(key.to_string(), Value::Null);
+                }
+                continue;
+            };
+            let share = if p.quantity > 0.0 {
+                qty / p.quantity
+            } else {
+                0.0
+            };
+            let cost = p.avg_entry.map(|a| a * qty);
+            let unrealized = cost.map(|c| value - c);
+            let realized = p.realized_ytd * share;
+            r.insert("cost_basis".to_string(), json!(cost));
+            r.insert("average_entry_price".to_string(), json!(p.avg_entry));
+            r.insert("unrealized_pnl".to_string(), json!(unrealized));
+            r.insert("realized_pnl_ytd".to_string(), json!(realized));
+            r.insert(
+                "return_percent".to_string(),
+                json!(cost.filter(|c| *c > 0.0).map(|c| (value - c) / c * 100.0)),
+            );
+            let (cost, unrealized) = (cost.unwrap_or(0.0), unrealized.unwrap_or(0.0));
+            for (map, key) in [(&mut groups, "group"), (&mut barcas, "barca")] {
+                let name = r
+                    .get(key)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                let agg = map.entry(name).or_default();
+                add(agg, "cost_basis", cost);
+                add(agg, "unrealized_pnl", unrealized);
+                add(agg, "realized_pnl_ytd", realized);
+            }
+            total_cost += cost;
+            total_unrealized += unrealized;
+            total_ytd += realized;
+        }
+    }
+    for (list, key, aggs) in [
+        ("per_group", "group", &groups),
+        ("per_barca", "barca", &barcas),
+        ("per_barca_actual", "barca", &barcas),
+    ] {
+        let Some(rows) = obj.get_mut(list).and_then(|v| v.as_array_mut()) else {
+            continue;
+        };
+        for row in rows {
+            let Some(r) = row.as_object_mut() else {
+                continue;
+            };
+            let name = r.get(key).and_then(|v| v.as_str()).unwrap_or("");
+            let agg = aggs.get(name).cloned().unwrap_or_default();
+            for k in ["cost_basis", "unrealized_pnl", "realized_pnl_ytd"] {
+                r.insert(k.to_string(), agg.get(k).cloned().unwrap_or(json!(0.0)));
+            }
+            finish_return(r);
+        }
+    }
+
+    // Positions closed during the year still carry realized P&L
+    let closed: Vec<Value> = basis
+        .positions
+        .iter()
+        .filter(|(s, p)| row_qty.get(*s).copied().unwrap_or(0.0) <= 0.0 && p.realized_ytd != 0.0)
+        .map(|(s, p)| json!({"symbol": s, "realized_pnl_ytd": p.realized_ytd}))
+        .collect();
+    total_ytd += closed
+        .iter()
+        .filter_map(|c| c["realized_pnl_ytd"].as_f64())
+        .sum::<f64>();
+    obj.insert(
+        "pnl".to_string(),
+        json!({
+            "method": method.as_str(),
+            "cost_basis": total_cost,
+            "unrealized_pnl": total_unrealized,
+            "realized_pnl_ytd": total_ytd,
+            "return_percent": (total_cost > 0.0).then(|| total_unrealized / total_cost * 100.0),
+            "closed_positions": closed,
+            "warnings": basis.warnings,
+        }),
+    );
+}
+
+impl CostBasisReport {
+    pub fn to_json(&self, method: CostMethod) -> Value {
+        let positions: Vec<Value> = self
+            .positions
+            .iter()
+            .map(|(symbol, p)| {
+                json!({
+                    "symbol": symbol,
+                    "quantity": p.quantity(),
+                    "cost_basis": p.cost_basis(),
+                    "average_entry_price": p.average_entry_price(),
+                    "realized_pnl_total": p.realized_total,
+                    "realized_pnl_ytd": p.realized_ytd,
+                    "lots": p.lots.iter().map(|l| json!({
+                        "quantity": l.quantity,
+                        "unit_cost": l.unit_cost,
+                        "acquired": l.acquired,
+                    })).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        json!({"method": method.as_str(), "positions": positions, "warnings": self.warnings})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(kind: &str, ts: &str, qty: f64, price: f64) -> Transaction {
+        Transaction {
+            id: None,
+            timestamp: ts.to_string(),
+            kind: kind.to_string(),
+            symbol: "BTC".to_string(),
+            quantity: qty,
+            price: Some(price),
+            quote_currency: Some("USD".to_string()),
+            fee: None,
+            fee_symbol: None,
+            venue: None,
+            to_venue: None,
+            group_name: None,
+            barca: None,
+            external_id: None,
+            notes: None,
+            created_at: None,
+            income_type: None,
+            account_id: None,
+            to_account_id: None,
+        }
+    }
+
+    fn ledger() -> Vec<Transaction> {
+        vec![
+            trade("buy", "2024-01-01T00:00:00Z", 1.0, 100.0),
+            trade("buy", "2024-02-01T00:00:00Z", 1.0, 300.0),
+            trade("buy", "2024-03-01T00:00:00Z", 1.0, 200.0),
+            trade("sell", "2025-06-01T00:00:00Z", 1.0, 250.0),
+        ]
+    }
+
+    fn ytd() -> DateTime<Utc> {
+        parse_ts("2025-01-01T00:00:00Z").unwrap()
+    }
+
+    #[test]
+    fn methods_relieve_different_lots() {
+        let realized = |m| compute_cost_basis(&ledger(), m, ytd()).positions["BTC"].realized_ytd;
+        assert!((realized(CostMethod::Fifo) - 150.0).abs() < 1e-9);
+        assert!((realized(CostMethod::Lifo) - 50.0).abs() < 1e-9);
+        assert!((realized(CostMethod::Hifo) - (-50.0)).abs() < 1e-9);
+        assert!((realized(CostMethod::Average) - 50.0).abs() < 1e-9);
+        let avg = compute_cost_basis(&ledger(), CostMethod::Average, ytd());
+        assert!((avg.positions["BTC"].cost_basis() - 400.0).abs() < 1e-9);
+        let before = compute_cost_basis(
+            &ledger(),
+            CostMethod::Fifo,
+            parse_ts("2026-01-01T00:00:00Z").unwrap(),
+        );
+        assert_eq!(before.positions["BTC"].realized_ytd, 0.0);
+        assert!((before.positions["BTC"].realized_total - 150.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pnl_is_attached_and_aggregated() {
+        let basis = compute_cost_basis(&ledger(), CostMethod::Fifo, ytd());
+        // remaining lots: 1 @ 300 and 1 @ 200 -> average entry 250
+        let mut report = json!({
+            "per_asset": [
+                {"symbol": "BTC", "group": "G", "barca": "B", "current_quantity": 1.5, "value": 600.0},
+                {"symbol": "BTC", "group": "H", "barca": "B", "current_quantity": 0.5, "value": 200.0},
+                {"symbol": "XYZ", "group": "H", "barca": "B", "current_quantity": 1.0, "value": 10.0}
+            ],
+            "per_group": [{"group": "G"}, {"group": "H"}],
+            "per_barca": [{"barca": "B"}],
+        });
+        attach_pnl(&mut report, &basis, CostMethod::Fifo);
+        let a = &report["per_asset"];
+        assert!((a[0]["cost_basis"].as_f64().unwrap() - 375.0).abs() < 1e-9);
+        assert!((a[0]["unrealized_pnl"].as_f64().unwrap() - 225.0).abs() < 1e-9);
+        assert!((a[0]["realized_pnl_ytd"].as_f64().unwrap() - 112.5).abs() < 1e-9);
+        assert!(a[2]["cost_basis"].is_null());
+        let b = &report["per_barca"][0];
+        assert!((b["cost_basis"].as_f64().unwrap() - 500.0).abs() < 1e-9);
+        assert!((b["return_percent"].as_f64().unwrap() - 60.0).abs() < 1e-9);
+        assert!((report["pnl"]["realized_pnl_ytd"].as_f64().unwrap() - 150.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::domain::models::{Account, Transaction, WalletAllocation};
+use crate::usecases::correlation::concentration;
+use crate::usecases::ledger::{LedgerHoldings, UNSPECIFIED_VENUE};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+pub const ACCOUNT_TYPES: &[&str] = &["exchange", "hardware", "hot_wallet", "defi", "other"];
+// Share of the portfolio above which a single account is flagged
+const DEFAULT_MAX_ACCOUNT_PERCENT: f64 = 50.0;
+
+// Normalize and validate an account before it is stored
+pub fn validate_account(acc: &mut Account) -> Result<(), String> {
+    acc.name = acc.name.trim().to_string();
+    if acc.name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    let kind = acc
+        .account_type
+        .trim()
+        .to_ascii_lowercase()
+        .replace([' ', '-'], "_");
+    acc.account_type = match kind.as_str() {
+        "" => "other".to_string(),
+        "hardware_wallet" | "cold_wallet" => "hardware".to_string(),
+        "hot" | "software_wallet" => "hot_wallet".to_string(),
+        k if ACCOUNT_TYPES.contains(&k) => k.to_string(),
+        other => return Err(format!("unsupported account type '{}'", other)),
+    };
+    acc.address = acc
+        .address
+        .as_deref()
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(str::to_string);
+    Ok(())
+}
+
+// Fill venue / to_venue from the referenced accounts; venues stay the ledger's location key
+pub fn link_accounts(tx: &mut Transaction, accounts: &[Account]) -> Result<(), String> {
+    for (id, venue) in [
+        (tx.account_id, &mut tx.venue),
+        (tx.to_account_id, &mut tx.to_venue),
+    ] {
+        let Some(id) = id else { continue };
+        let Some(acc) = accounts.iter().find(|a| a.id == Some(id)) else {
+            return Err(format!("unknown account {}", id));
+        };
+        match venue.as_deref().map(str::trim) {
+            None | Some("") => *venue = Some(acc.name.clone()),
+            Some(v) if v.eq_ignore_ascii_case(&acc.name) => {}
+            Some(v) => {
+                return Err(format!(
+                    "venue '{}' does not match account {} ('{}')",
+                    v, id, acc.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Warning threshold for a single account (env CUSTODY_MAX_ACCOUNT_PERCENT)
+pub fn max_account_percent_from_env() -> Result<f64, String> {
+    match std::env::var("CUSTODY_MAX_ACCOUNT_PERCENT") {
+        Ok(s) if !s.trim().is_empty() => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|p| *p > 0.0 && *p <= 100.0)
+            .ok_or_else(|| format!("invalid CUSTODY_MAX_ACCOUNT_PERCENT '{}'", s)),
+        _ => Ok(DEFAULT_MAX_ACCOUNT_PERCENT),
+    }
+}
+
+// Quantity held per (symbol, account name); rows without an account fall back to their notes
+pub fn wallet_custody(
+    rows: &[WalletAllocation],
+    accounts: &[Account],
+) -> Vec<(String, String, f64)> {
+    let names: HashMap<i64, &str> = accounts
+        .iter()
+        .filter_map(|a| a.id.map(|id| (id, a.name.as_str())))
+        .collect();
+    rows.iter()
+        .map(|r| {
+            let account = r
+                .account_id
+                .and_then(|id| names.get(&id).map(|n| n.to_string()))
+                .or_else(|| {
+                    r.notes
+                        .as_deref()
+                        .map(str::trim)
+                        .filter(|n| !n.is_empty())
+                        .map(str::to_string)
+                })
+                .unwrap_or_else(|| UNSPECIFIED_VENUE.to_string());
+            (r.symbol.clone(), account, r.current_quantity.unwrap_or(0.0))
+        })
+        .collect()
+}
+
+// Quantity held per (symbol, account name) from the ledger, where venues name the accounts
+pub fn ledger_custody(holdings: &LedgerHoldings) -> Vec<(String, String, f64)> {
+    holdings
+        .positions
+        .iter()
+        .map(|((symbol, venue), qty)| (symbol.clone(), venue.clone(), *qty))
+        .collect()
+}
+
+// Value per custody account and how concentrated custody is across accounts and account types
+pub fn custody_report(
+    positions: &[(String, String, f64)],
+    accounts: &[Account],
+    prices: &HashMap<String, f64>,
+    max_account_percent: f64,
+) -> Value {
+    let by_name: HashMap<String, &Account> = accounts
+        .iter()
+        .map(|a| (a.name.to_lowercase(), a))
+        .collect();
+    let mut per_account: BTreeMap<String, (f64, Vec<Value>)> = BTreeMap::new();
+    let mut warnings = Vec::new();
+    for (symbol, account, qty) in positions {
+        if qty.abs() < 1e-12 {
+            continue;
+        }
+        let Some(price) = prices.get(symbol) else {
+            warnings.push(format!("no price for {} held at {}", symbol, account));
+            continue;
+        };
+        let value = qty * price;
+        // Venue spellings differ in case; report under the account's own name
+        let name = by_name
+            .get(&account.to_lowercase())
+            .map_or_else(|| account.clone(), |a| a.name.clone());
+        let entry = per_account.entry(name).or_default();
+        entry.0 += value;
+        entry
+            .1
+            .push(json!({"symbol": symbol, "quantity": qty, "value": value}));
+    }
+    let total: f64 = per_account.values().map(|(v, _)| v).sum();
+    let percent = |v: f64| if total > 0.0 { v / total * 100.0 } else { 0.0 };
+
+    let mut by_type: BTreeMap<String, f64> = BTreeMap::new();
+    let mut rows = Vec::new();
+    for (name, (value, assets)) in &per_account {
+        let acc = by_name.get(&name.to_lowercase());
+        let kind = acc.map_or("unknown", |a| a.account_type.as_str());
+        *by_type.entry(kind.to_string()).or_insert(0.0) += value;
+        if acc.is_none() && name != UNSPECIFIED_VENUE {
+            warnings.push(format!(
+                "holdings at '{}' are not linked to an account",
+                name
+            ));
+        }
+        let pct = percent(*value);
+        if pct > max_account_percent {
+            warnings.push(format!(
+                "{:.1}% of the portfolio is held at {} (limit {:.1}%)",
+                pct, name, max_account_percent
+            ));
+        }
+        rows.push(json!({
+            "account": name,
+            "account_id": acc.and_then(|a| a.id),
+            "account_type": kind,
+            "value": value,
+            "current_percent": pct,
+            "assets": assets,
+        }));
+    }
+
+    let values: Vec<f64> = per_account.values().map(|(v, _)| *v).collect();
+    let (hhi, effective) = concentration(&values);
+    let largest = per_account
+        .iter()
+        .max_by(|a, b| a.1.0.total_cmp(&b.1.0))
+        .map(|(name, (v, _))| json!({"account": name, "current_percent": percent(*v)}));
+    let by_type: BTreeMap<String, Value> = by_type
+        .into_iter()
+        .map(|(k, v)| (k, json!({"value": v, "current_percent": percent(v)})))
+        .collect();
+    json!({
+        "per_account": rows,
+        "concentration": {
+            "total_value": total,
+            "accounts": per_account.len(),
+            "hhi": hhi,
+            "effective_accounts": effective,
+            "largest_account": largest,
+            "max_account_percent": max_account_percent,
+            "by_type": by_type,
+        },
+        "warnings": warnings,
+    })
+}
+
+// Add the custody breakdown to an allocations report
+pub fn attach_custody(report: &mut Value, custody: Value) {
+    if let Some(obj) = report.as_object_mut() {
+        obj.insert("custody".to_string(), custody);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: i64, name: &str, kind: &str) -> Account {
+        Account {
+            id: Some(id),
+            name: name.to_string(),
+            account_type: kind.to_string(),
+            address: None,
+            notes: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn account_types_are_normalized() {
+        let mut acc = account(1, "  Trezor ", "Hardware Wallet");
+        validate_account(&mut acc).unwrap();
+        assert_eq!(acc.name, "Trezor");
+        assert_eq!(acc.account_type, "hardware");
+        let mut acc = account(1, "Metamask", "hot wallet");
+        validate_account(&mut acc).unwrap();
+        assert_eq!(acc.account_type, "hot_wallet");
+        assert!(validate_account(&mut account(1, "x", "bank")).is_err());
+        assert!(validate_account(&mut account(1, " ", "")).is_err());
+    }
+
+    #[test]
+    fn custody_concentration_per_account_and_type() {
+        let accounts = vec![
+            account(1, "Binance", "exchange"),
+            account(2, "Ledger", "hardware"),
+        ];
+        let positions = vec![
+            ("BTC".to_string(), "ledger".to_string(), 1.0),
+            ("BTC".to_string(), "Binance".to_string(), 0.5),
+            ("ETH".to_string(), "Binance".to_string(), 10.5),
+            ("SOL".to_string(), "Phantom".to_string(), 10.0),
+        ];
+        let prices = HashMap::from([
+            ("BTC".to_string(), 60_000.0),
+            ("ETH".to_string(), 3_000.0),
+            ("SOL".to_string(), 100.0),
+        ]);
+        let r = custody_report(&positions, &accounts, &prices, 50.0);
+        let c = &r["concentration"];
+        assert_eq!(c["total_value"], json!(122_500.0));
+        assert_eq!(c["largest_account"]["account"], json!("Binance"));
+        assert!((c["by_type"]["hardware"]["value"].as_f64().unwrap() - 60_000.0).abs() < 1e-9);
+        assert!((c["by_type"]["unknown"]["value"].as_f64().unwrap() - 1_000.0).abs() < 1e-9);
+        // two near-equal accounts plus a small one: close to two effective accounts
+        assert!((c["effective_accounts"].as_f64().unwrap() - 2.03).abs() < 0.01);
+        let warnings: Vec<&str> = r["warnings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|w| w.as_str())
+            .collect();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("50.2% of the portfolio is held at Binance"));
+        assert!(warnings[1].contains("'Phantom' are not linked"));
+        assert_eq!(r["per_account"][1]["account_id"], json!(2));
+    }
+}
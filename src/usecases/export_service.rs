@@ -0,0 +1,75 @@
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::portfolios_service::barca_regimes;
+use crate::wallet_files::{Holding, Table, holdings_table, report_table, targets_table};
+use std::sync::Arc;
+
+pub struct ExportService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl ExportService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // `holdings` (wallet_allocations.csv layout, one row per account), `targets` (the
+    // portfolio's BARCA targets in the wallet_barca.csv layout) or `report` (the last computed
+    // allocations at `level`). Ok(Err) for an unknown dataset or level, or when no report was
+    // computed yet.
+    pub async fn table(
+        &self,
+        dataset: &str,
+        level: &str,
+    ) -> Result<Result<Table, String>, Box<dyn std::error::Error + Send + Sync>> {
+        match dataset {
+            "holdings" => {
+                let accounts = self.repo.fetch_accounts().await?;
+                let mut rows = self
+                    .repo
+                    .fetch_current_wallet_allocations_by_account()
+                    .await?;
+                rows.sort_by(|a, b| {
+                    (&a.group_name, &a.barca, &a.symbol, a.account_id).cmp(&(
+                        &b.group_name,
+                        &b.barca,
+                        &b.symbol,
+                        b.account_id,
+                    ))
+                });
+                let holdings: Vec<Holding> = rows
+                    .into_iter()
+                    .map(|wa| Holding {
+                        account: wa.account_id.and_then(|id| {
+                            accounts
+                                .iter()
+                                .find(|a| a.id == Some(id))
+                                .map(|a| a.name.clone())
+                        }),
+                        symbol: wa.symbol,
+                        group: wa.group_name,
+                        barca: wa.barca,
+                        target_percent: wa.target_percent,
+                        current_quantity: wa.current_quantity,
+                        last_price: wa.last_price,
+                        comments: wa.notes,
+                    })
+                    .collect();
+                Ok(Ok(holdings_table(&holdings)))
+            }
+            "targets" => {
+                let regimes = barca_regimes(self.repo.as_ref()).await?;
+                Ok(Ok(targets_table(&regimes)))
+            }
+            "report" => match self.repo.fetch_latest_allocation_record().await? {
+                Some(rec) => Ok(report_table(&rec.payload, level)),
+                None => Ok(Err(
+                    "no allocation report computed yet; call /api/allocations first".to_string(),
+                )),
+            },
+            other => Ok(Err(format!(
+                "unknown export '{}' (holdings, targets or report)",
+                other
+            ))),
+        }
+    }
+}
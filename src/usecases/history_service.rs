@@ -1,10 +1,15 @@
 use crate::domain::models::{
-    AssetSnapshot, BarcaSnapshot, GroupSnapshot, TotalSnapshot, WalletAllocation,
+    AssetSnapshot, BarcaSnapshot, GroupSnapshot, ImportBatch, TotalSnapshot,
 };
 use crate::domain::repository::HistoryRepo;
+use crate::usecases::import_batches::{
+    compensating_rows, complete_from_current, content_hash, projected_rows,
+};
+use crate::usecases::importers::{Balance, RowResult, WALLET_FORMAT, attach_results, parse_import};
+use crate::usecases::wallet_changes::{EVENT_IMPORT, EditError};
+use crate::usecases::wallet_diff::diff_wallets;
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{Value, json};
 use std::sync::Arc;
 
 pub struct HistoryService {
@@ -175,6 +180,26 @@ impl HistoryService {
                     .collect();
                 Ok(serde_json::json!({"level": "groups", "rows": out}))
             }
+            "var" => {
+                let rows = self.repo.fetch_var_history(None, None).await?;
+                let out: Vec<serde_json::Value> = rows
+                    .into_iter()
+                    .map(|r| {
+                        serde_json::json!({
+                            "timestamp": r.timestamp,
+                            "scope": r.scope,
+                            "name": r.name,
+                            "method": r.method,
+                            "confidence": r.confidence,
+                            "horizon_days": r.horizon_days,
+                            "position_value": r.position_value,
+                            "var": r.var_value,
+                            "es": r.es_value
+                        })
+                    })
+                    .collect();
+                Ok(serde_json::json!({"level": "var", "rows": out}))
+            }
             _ => {
                 let rows = self.repo.fetch_totals(None, None).await?;
                 let out: Vec<serde_json::Value> = rows.into_iter().map(|r| serde_json::json!({"timestamp": r.timestamp, "total_value": r.total_value})).collect();
@@ -186,46 +211,211 @@ impl HistoryService {
     pub async fn import_wallet_allocations_from_path(
         &self,
         path: &str,
-    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
-        let mut rdr = csv::ReaderBuilder::new()
-            .trim(csv::Trim::All)
-            .flexible(true)
-            .has_headers(true)
-            .from_path(path)?;
-        let mut count = 0usize;
-        for result in rdr.deserialize::<WalletCsvRow>() {
-            let row = result?;
-            let wa = WalletAllocation {
-                id: None,
-                symbol: row.symbol,
-                group_name: row.group,
-                barca: row.barca,
-                target_percent: row.target_percent,
-                current_quantity: row.current_quantity,
-                last_price: row.last_price,
-                notes: row.comments,
-                created_at: None,
-            };
-            self.repo.insert_wallet_allocation(&wa).await?;
-            count += 1;
+        dry_run: bool,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let content = std::fs::read(path)?;
+        self.import_wallet_allocations(path, &content, dry_run)
+            .await
+    }
+
+    // Import a wallet CSV as one batch. A file whose content was already imported (and not
+    // rolled back) is skipped; a dry run only reports the diff against current holdings.
+    pub async fn import_wallet_allocations(
+        &self,
+        source: &str,
+        content: &[u8],
+        dry_run: bool,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let parsed = parse_import(content, Some(WALLET_FORMAT), None)?;
+        let mut results = parsed.unmapped_results();
+        let mut report = self
+            .import_balances(
+                source,
+                &content_hash(content),
+                parsed.balances,
+                &mut results,
+                dry_run,
+            )
+            .await?;
+        attach_results(&mut report, results);
+        report["dialect"] = json!(parsed.dialect);
+        Ok(report)
+    }
+
+    // Append balances as one import batch. Content already imported (and not rolled back) is
+    // skipped; a dry run only reports the diff against current holdings. The outcome of each
+    // balance row is appended to `results`.
+    pub async fn import_balances(
+        &self,
+        source: &str,
+        hash: &str,
+        balances: Vec<(u64, Balance)>,
+        results: &mut Vec<RowResult>,
+        dry_run: bool,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(batch) = self.repo.find_applied_import_batch(hash).await? {
+            results.extend(
+                balances
+                    .iter()
+                    .map(|(line, b)| RowResult::new(*line, "duplicate", &b.row.symbol)),
+            );
+            return Ok(json!({
+                "status": "duplicate",
+                "dry_run": dry_run,
+                "imported": 0,
+                "batch": batch,
+            }));
         }
-        Ok(count)
+
+        let accounts = self.repo.fetch_accounts().await?;
+        let current = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        let mut rows = Vec::with_capacity(balances.len());
+        let mut new_accounts: Vec<String> = Vec::new();
+        let status = if dry_run { "would_import" } else { "imported" };
+        for (
+            line,
+            Balance {
+                row: mut wa,
+                account,
+            },
+        ) in balances
+        {
+            results.push(RowResult::new(line, status, &wa.symbol));
+            wa.author.get_or_insert_with(|| "csv-import".to_string());
+            wa.reason
+                .get_or_insert_with(|| format!("import {}", source));
+            wa.event = Some(EVENT_IMPORT.to_string());
+            // An explicit account column wins over the location named in comments
+            if let Some(name) = account.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+                let known = accounts
+                    .iter()
+                    .find(|a| a.name.eq_ignore_ascii_case(name))
+                    .and_then(|a| a.id);
+                wa.account_id = match (known, dry_run) {
+                    (Some(id), _) => Some(id),
+                    (None, false) => Some(self.repo.ensure_account(name).await?),
+                    // Not created by a dry run: label the projected holding by name instead
+                    (None, true) => {
+                        if !new_accounts.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                            new_accounts.push(name.to_string());
+                        }
+                        wa.notes = Some(name.to_string());
+                        None
+                    }
+                };
+            } else if let Some(name) = wa.notes.as_deref().map(str::trim) {
+                wa.account_id = accounts
+                    .iter()
+                    .find(|a| a.name.eq_ignore_ascii_case(name))
+                    .and_then(|a| a.id);
+            }
+            complete_from_current(&mut wa, &current);
+            rows.push(wa);
+        }
+
+        if dry_run {
+            let projected = projected_rows(&current, &rows);
+            return Ok(json!({
+                "status": "dry_run",
+                "dry_run": true,
+                "rows": rows.len(),
+                "content_hash": hash,
+                "new_accounts": new_accounts,
+                "diff": diff_wallets(&current, &projected, &accounts),
+            }));
+        }
+
+        let batch = ImportBatch {
+            id: None,
+            source: source.to_string(),
+            content_hash: hash.to_string(),
+            row_count: rows.len() as i64,
+            author: Some("csv-import".to_string()),
+            status: "applied".to_string(),
+            created_at: None,
+            rolled_back_at: None,
+            rolled_back_by: None,
+        };
+        let batch_id = self.repo.insert_import_batch(&batch, &rows).await?;
+        Ok(json!({
+            "status": "imported",
+            "dry_run": false,
+            "imported": rows.len(),
+            "batch_id": batch_id,
+            "content_hash": hash,
+        }))
+    }
+
+    pub async fn import_batches(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let batches = self.repo.fetch_import_batches().await?;
+        Ok(json!({"batches": batches}))
     }
-}
 
-#[derive(Debug, Deserialize)]
-struct WalletCsvRow {
-    symbol: String,
-    #[serde(default)]
-    group: Option<String>,
-    #[serde(default)]
-    barca: Option<String>,
-    #[serde(default)]
-    target_percent: Option<f64>,
-    #[serde(default)]
-    current_quantity: Option<f64>,
-    #[serde(default)]
-    last_price: Option<f64>,
-    #[serde(default, alias = "comments")]
-    comments: Option<String>,
+    // The batch with the rows it appended; None when it does not exist
+    pub async fn import_batch(
+        &self,
+        id: i64,
+    ) -> Result<Option<Value>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(batch) = self.repo.fetch_import_batch(id).await? else {
+            return Ok(None);
+        };
+        let rows = self.repo.fetch_wallet_allocations_by_batch(id).await?;
+        Ok(Some(json!({"batch": batch, "rows": rows})))
+    }
+
+    // Undo a batch by appending compensating rows; the batch rows themselves stay in the ledger
+    pub async fn rollback_import_batch(
+        &self,
+        id: i64,
+        author: &str,
+        reason: Option<&str>,
+    ) -> Result<Result<Value, EditError>, Box<dyn std::error::Error + Send + Sync>> {
+        let author = author.trim();
+        if author.is_empty() {
+            return Ok(Err(EditError::Invalid("author is required".to_string())));
+        }
+        let Some(batch) = self.repo.fetch_import_batch(id).await? else {
+            return Ok(Err(EditError::NotFound(format!(
+                "import batch {} not found",
+                id
+            ))));
+        };
+        if batch.status != "applied" {
+            return Ok(Err(EditError::Conflict(format!(
+                "import batch {} was already rolled back",
+                id
+            ))));
+        }
+        let batch_rows = self.repo.fetch_wallet_allocations_by_batch(id).await?;
+        let current = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        let mut history = Vec::new();
+        let mut symbols: Vec<&str> = batch_rows.iter().map(|r| r.symbol.as_str()).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        for symbol in symbols {
+            history.extend(self.repo.fetch_wallet_allocation_history(symbol).await?);
+        }
+        let reason = reason
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("rollback of import batch {}", id));
+        let rows = match compensating_rows(id, &batch_rows, &current, &history, author, &reason) {
+            Ok(r) => r,
+            Err(e) => return Ok(Err(e)),
+        };
+        if !self.repo.rollback_import_batch(id, author, &rows).await? {
+            return Ok(Err(EditError::Conflict(format!(
+                "import batch {} was already rolled back",
+                id
+            ))));
+        }
+        Ok(Ok(json!({"batch_id": id, "appended": rows})))
+    }
 }
@@ -0,0 +1,550 @@
+use crate::api_client::CryptoProvider;
+use crate::domain::models::{
+    BarcaTarget, Household, HouseholdSnapshot, Portfolio, WalletAllocation,
+};
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::allocations_service::current_holdings;
+use crate::usecases::compute_allocations::compute_allocations;
+use crate::usecases::market_blend::{MarketSelection, blend_targets};
+use crate::usecases::portfolios_service::{
+    regimes_or_default, target_rows, trim_market_settings, validate_targets,
+};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct HouseholdsService {
+    pub provider: Arc<dyn CryptoProvider>,
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+// A member portfolio and its current holdings
+pub struct MemberHoldings {
+    pub portfolio: Portfolio,
+    pub allocations: Vec<WalletAllocation>,
+}
+
+// Trimmed name, members that exist (sorted, once each) and the regime settings checked by
+// parsing them
+pub fn validate_household(h: &mut Household, portfolios: &[i64]) -> Result<(), String> {
+    h.name = h.name.trim().to_string();
+    if h.name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    h.members.sort_unstable();
+    h.members.dedup();
+    if h.members.is_empty() {
+        return Err("members must list at least one portfolio".to_string());
+    }
+    if let Some(id) = h.members.iter().find(|id| !portfolios.contains(id)) {
+        return Err(format!("unknown portfolio {}", id));
+    }
+    if !trim_market_settings(
+        &mut h.market,
+        &mut h.market_glide_to,
+        &mut h.market_glide_start,
+    )? {
+        return Ok(());
+    }
+    MarketSelection::for_household(h).map(|_| ())
+}
+
+// Allocation report of the members' holdings merged into one wallet and valued with one quote
+// set, against the household BARCA targets. A member's asset targets are percentages of its
+// own portfolio, so they are weighted by its share of the household value. Every BARCA and
+// group row lists the members' `contributions` to it.
+pub fn household_report(
+    members: &[MemberHoldings],
+    cryptos: &[crate::CryptoData],
+    barca_targets: &HashMap<String, f64>,
+) -> Value {
+    let prices: HashMap<&str, f64> = cryptos
+        .iter()
+        .map(|c| (c.symbol.as_str(), c.quote.usd.price))
+        .collect();
+
+    // value of each member by BARCA and by group (holdings without a quote are left out, as
+    // in compute_allocations)
+    let mut member_totals = Vec::with_capacity(members.len());
+    let mut by_barca: Vec<HashMap<String, f64>> = Vec::with_capacity(members.len());
+    let mut by_group: Vec<HashMap<String, f64>> = Vec::with_capacity(members.len());
+    for m in members {
+        let mut total = 0.0;
+        let mut barcas: HashMap<String, f64> = HashMap::new();
+        let mut groups: HashMap<String, f64> = HashMap::new();
+        for a in &m.allocations {
+            let Some(price) = prices.get(a.symbol.as_str()) else {
+                continue;
+            };
+            let value = a.current_quantity.unwrap_or(0.0) * price;
+            total += value;
+            *barcas
+                .entry(a.barca.clone().unwrap_or_default())
+                .or_insert(0.0) += value;
+            *groups
+                .entry(a.group_name.clone().unwrap_or_default())
+                .or_insert(0.0) += value;
+        }
+        member_totals.push(total);
+        by_barca.push(barcas);
+        by_group.push(groups);
+    }
+    let household_total: f64 = member_totals.iter().sum();
+    let share = |total: f64| {
+        if household_total > 0.0 {
+            total / household_total
+        } else {
+            0.0
+        }
+    };
+
+    let merged: Vec<WalletAllocation> = members
+        .iter()
+        .zip(&member_totals)
+        .flat_map(|(m, total)| {
+            let weight = share(*total);
+            m.allocations.iter().map(move |a| WalletAllocation {
+                target_percent: a.target_percent.map(|t| t * weight),
+                ..a.clone()
+            })
+        })
+        .collect();
+    let mut res = compute_allocations(&merged, cryptos, barca_targets);
+
+    let contributions = |values: &[HashMap<String, f64>], name: &str, bucket_value: f64| {
+        members
+            .iter()
+            .zip(values)
+            .filter_map(|(m, v)| {
+                let value = v.get(name).copied().filter(|v| *v != 0.0)?;
+                Some(json!({
+                    "portfolio_id": m.portfolio.id,
+                    "portfolio": m.portfolio.name,
+                    "value": value,
+                    "percent_of_bucket": if bucket_value != 0.0 { value / bucket_value * 100.0 } else { 0.0 },
+                    "percent_of_household": share(value) * 100.0,
+                }))
+            })
+            .collect::<Vec<_>>()
+    };
+    for (table, key, values) in [
+        ("per_barca", "barca", &by_barca),
+        ("per_barca_actual", "barca", &by_barca),
+        ("per_group", "group", &by_group),
+    ] {
+        let Some(rows) = res.get_mut(table).and_then(|v| v.as_array_mut()) else {
+            continue;
+        };
+        for row in rows.iter_mut() {
+            let name = row
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let bucket_value = row.get("value").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            if let Some(obj) = row.as_object_mut() {
+                obj.insert(
+                    "contributions".to_string(),
+                    json!(contributions(values, &name, bucket_value)),
+                );
+            }
+        }
+    }
+
+    let member_rows: Vec<Value> = members
+        .iter()
+        .zip(&member_totals)
+        .map(|(m, total)| {
+            json!({
+                "portfolio_id": m.portfolio.id,
+                "portfolio": m.portfolio.name,
+                "value": total,
+                "percent_of_household": share(*total) * 100.0,
+            })
+        })
+        .collect();
+    if let Some(obj) = res.as_object_mut() {
+        obj.insert("total_value".to_string(), json!(household_total));
+        obj.insert("members".to_string(), json!(member_rows));
+    }
+    res
+}
+
+// Snapshot rows of a household report: the total, every BARCA (targeted or held) and every
+// group, each with its member contributions
+pub fn snapshot_rows(timestamp: &str, report: &Value) -> Vec<HouseholdSnapshot> {
+    let table = |name: &str| {
+        report
+            .get(name)
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default()
+    };
+    let row = |level: &str, key: &str, r: &Value| HouseholdSnapshot {
+        timestamp: timestamp.to_string(),
+        level: level.to_string(),
+        name: r
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        value: r.get("value").and_then(|v| v.as_f64()),
+        current_percent: r.get("current_percent").and_then(|v| v.as_f64()),
+        target_percent: r.get("target_percent").and_then(|v| v.as_f64()),
+        contributions: r.get("contributions").cloned(),
+    };
+
+    let mut rows = vec![HouseholdSnapshot {
+        timestamp: timestamp.to_string(),
+        level: "totals".to_string(),
+        name: String::new(),
+        value: report.get("total_value").and_then(|v| v.as_f64()),
+        current_percent: Some(100.0),
+        target_percent: None,
+        contributions: report.get("members").cloned(),
+    }];
+    let targeted = table("per_barca");
+    for b in &targeted {
+        rows.push(row("barca", "barca", b));
+    }
+    for b in table("per_barca_actual") {
+        let name = b.get("barca").and_then(|v| v.as_str());
+        if !targeted
+            .iter()
+            .any(|t| t.get("barca").and_then(|v| v.as_str()) == name)
+        {
+            rows.push(row("barca", "barca", &b));
+        }
+    }
+    for g in table("per_group") {
+        rows.push(row("groups", "group", &g));
+    }
+    rows
+}
+
+impl HouseholdsService {
+    pub fn new(provider: Arc<dyn CryptoProvider>, repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { provider, repo }
+    }
+
+    pub async fn list(&self) -> Result<Vec<Household>, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self.repo.fetch_households().await?;
+        Ok(rows)
+    }
+
+    pub async fn get(
+        &self,
+        id: i64,
+    ) -> Result<Option<Household>, Box<dyn std::error::Error + Send + Sync>> {
+        let h = self.repo.fetch_household(id).await?;
+        Ok(h)
+    }
+
+    pub async fn create(
+        &self,
+        mut h: Household,
+    ) -> Result<Result<Household, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(e) = self.check(&mut h, None).await? {
+            return Ok(Err(e));
+        }
+        let id = self.repo.insert_household(&h).await?;
+        Ok(Ok(self.repo.fetch_household(id).await?.unwrap_or(h)))
+    }
+
+    // Ok(Ok(None)) when the household does not exist
+    pub async fn update(
+        &self,
+        id: i64,
+        mut h: Household,
+    ) -> Result<Result<Option<Household>, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(e) = self.check(&mut h, Some(id)).await? {
+            return Ok(Err(e));
+        }
+        h.id = Some(id);
+        if !self.repo.update_household(&h).await? {
+            return Ok(Ok(None));
+        }
+        let stored = self.repo.fetch_household(id).await?;
+        Ok(Ok(stored))
+    }
+
+    pub async fn delete(&self, id: i64) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let deleted = self.repo.delete_household(id).await?;
+        Ok(deleted)
+    }
+
+    // The household's BARCA targets and where they come from
+    pub async fn targets(
+        &self,
+        id: i64,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let own = self.repo.fetch_household_targets(id).await?;
+        let source = if own.is_empty() {
+            "wallet_barca.csv"
+        } else {
+            "household"
+        };
+        Ok(json!({
+            "household_id": id,
+            "source": source,
+            "targets": target_rows(regimes_or_default(own)?),
+        }))
+    }
+
+    pub async fn set_targets(
+        &self,
+        id: i64,
+        mut rows: Vec<BarcaTarget>,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_targets(&mut rows) {
+            return Ok(Err(e));
+        }
+        self.repo.replace_household_targets(id, &rows).await?;
+        Ok(Ok(self.targets(id).await?))
+    }
+
+    // Values the members' current holdings with one quote set, reports them against the
+    // household targets for today's market weights and records the snapshot
+    pub async fn compute_and_record(
+        &self,
+        api_key: &str,
+        household: &Household,
+        market: &MarketSelection,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let res = self.compute(api_key, household, market).await?;
+        let ts = chrono::Utc::now().to_rfc3339();
+        self.repo
+            .insert_household_snapshots(household.id.unwrap_or_default(), &snapshot_rows(&ts, &res))
+            .await?;
+        Ok(res)
+    }
+
+    // The same report without recording a snapshot
+    pub async fn compute(
+        &self,
+        api_key: &str,
+        household: &Household,
+        market: &MarketSelection,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let id = household.id.unwrap_or_default();
+        let cryptos = self.provider.fetch_latest(api_key).await?;
+
+        let regimes = regimes_or_default(self.repo.fetch_household_targets(id).await?)?;
+        let today = chrono::Utc::now().date_naive();
+        let barca_targets = blend_targets(&regimes, &market.effective_weights(today))?;
+
+        let mut members = Vec::with_capacity(household.members.len());
+        for portfolio_id in &household.members {
+            let Some(portfolio) = self.repo.fetch_portfolio(*portfolio_id).await? else {
+                continue;
+            };
+            let scoped = self.repo.portfolio_repo(*portfolio_id);
+            members.push(MemberHoldings {
+                portfolio,
+                allocations: current_holdings(scoped.as_ref()).await?,
+            });
+        }
+
+        let mut res = household_report(&members, &cryptos, &barca_targets);
+        if let Some(obj) = res.as_object_mut() {
+            obj.insert(
+                "household".to_string(),
+                json!({"id": id, "name": household.name}),
+            );
+            obj.insert("market".to_string(), market.to_json(today));
+        }
+        Ok(res)
+    }
+
+    // Snapshot history of one level (totals, barca or groups), oldest first
+    pub async fn history(
+        &self,
+        id: i64,
+        level: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let key = match level {
+            "totals" => None,
+            "barca" => Some("barca"),
+            "groups" => Some("group"),
+            other => {
+                return Ok(Err(format!(
+                    "unknown level '{}' (totals, barca or groups)",
+                    other
+                )));
+            }
+        };
+        let rows: Vec<Value> = self
+            .repo
+            .fetch_household_history(id, level, from, to)
+            .await?
+            .into_iter()
+            .map(|r| {
+                let deviation = match (r.current_percent, r.target_percent) {
+                    (Some(c), Some(t)) => Some(c - t),
+                    _ => None,
+                };
+                let mut row = json!({
+                    "timestamp": r.timestamp,
+                    "value": r.value,
+                    "current_percent": r.current_percent,
+                    "target_percent": r.target_percent,
+                    "deviation": deviation,
+                    "contributions": r.contributions,
+                });
+                if let Some(key) = key
+                    && let Some(obj) = row.as_object_mut()
+                {
+                    obj.insert(key.to_string(), json!(r.name));
+                }
+                row
+            })
+            .collect();
+        Ok(Ok(
+            json!({"household_id": id, "level": level, "rows": rows}),
+        ))
+    }
+
+    // Validation plus the name check against the other households; Some(reason) when invalid
+    async fn check(
+        &self,
+        h: &mut Household,
+        except: Option<i64>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let portfolios: Vec<i64> = self
+            .repo
+            .fetch_portfolios()
+            .await?
+            .iter()
+            .filter_map(|p| p.id)
+            .collect();
+        if let Err(e) = validate_household(h, &portfolios) {
+            return Ok(Some(e));
+        }
+        let taken = self
+            .repo
+            .fetch_households()
+            .await?
+            .iter()
+            .any(|o| o.name.eq_ignore_ascii_case(&h.name) && o.id != except);
+        Ok(taken.then(|| format!("a household named '{}' already exists", h.name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usecases::simulation::synthetic_quote;
+
+    fn member(id: i64, name: &str, holdings: &[(&str, &str, &str, f64, f64)]) -> MemberHoldings {
+        MemberHoldings {
+            portfolio: Portfolio {
+                id: Some(id),
+                name: name.to_string(),
+                market: None,
+                market_glide_to: None,
+                market_glide_start: None,
+                market_glide_days: None,
+                notes: None,
+                created_at: None,
+            },
+            allocations: holdings
+                .iter()
+                .map(|(symbol, group, barca, target, qty)| WalletAllocation {
+                    id: None,
+                    symbol: symbol.to_string(),
+                    group_name: Some(group.to_string()),
+                    barca: Some(barca.to_string()),
+                    target_percent: Some(*target),
+                    current_quantity: Some(*qty),
+                    last_price: None,
+                    notes: None,
+                    created_at: None,
+                    account_id: None,
+                    author: None,
+                    reason: None,
+                    event: None,
+                    import_batch_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn find<'a>(report: &'a Value, table: &str, key: &str, name: &str) -> &'a Value {
+        report[table]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|r| r[key] == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn members_merge_with_weighted_targets_and_contributions() {
+        // Ana: 300 in BTC (target 100% of her portfolio); Bia: 100 in BTC, 600 in USDT (50/50)
+        let members = vec![
+            member(1, "Ana", &[("BTC", "Holding", "Base", 100.0, 3.0)]),
+            member(
+                2,
+                "Bia",
+                &[
+                    ("BTC", "Holding", "Base", 50.0, 1.0),
+                    ("USDT", "Caixa", "Caixa", 50.0, 600.0),
+                ],
+            ),
+        ];
+        let cryptos = vec![synthetic_quote("BTC", 100.0), synthetic_quote("USDT", 1.0)];
+        let targets = HashMap::from([("Base".to_string(), 60.0), ("Caixa".to_string(), 40.0)]);
+        let report = household_report(&members, &cryptos, &targets);
+
+        assert_eq!(report["total_value"], 1000.0);
+        let base = find(&report, "per_barca", "barca", "Base");
+        assert_eq!(base["value"], 400.0);
+        assert_eq!(base["target_percent"], 60.0);
+        let contributions = base["contributions"].as_array().unwrap();
+        assert_eq!(contributions.len(), 2);
+        assert_eq!(contributions[0]["portfolio"], "Ana");
+        assert_eq!(contributions[0]["percent_of_bucket"], 75.0);
+        assert_eq!(contributions[1]["percent_of_household"], 10.0);
+        let caixa = find(&report, "per_group", "group", "Caixa");
+        assert_eq!(caixa["contributions"].as_array().unwrap().len(), 1);
+
+        // member targets weighted by value share: Ana 30% * 100 + Bia 70% * 50 = 65% BTC
+        let btc = find(&report, "per_asset", "symbol", "BTC");
+        assert!((btc["target_percent"].as_f64().unwrap() - 65.0).abs() < 1e-9);
+        let holding = find(&report, "per_group", "group", "Holding");
+        assert!((holding["target_percent"].as_f64().unwrap() - 65.0).abs() < 1e-9);
+
+        let rows = snapshot_rows("2026-01-01T00:00:00Z", &report);
+        assert_eq!(rows[0].level, "totals");
+        assert_eq!(rows[0].value, Some(1000.0));
+        assert_eq!(rows.iter().filter(|r| r.level == "barca").count(), 2);
+        assert_eq!(rows.iter().filter(|r| r.level == "groups").count(), 2);
+    }
+
+    #[test]
+    fn household_needs_known_members() {
+        let mut h = Household {
+            id: None,
+            name: " Family ".to_string(),
+            members: vec![2, 1, 2],
+            market: Some(" ".to_string()),
+            market_glide_to: None,
+            market_glide_start: None,
+            market_glide_days: None,
+            notes: None,
+            created_at: None,
+        };
+        validate_household(&mut h, &[1, 2]).unwrap();
+        assert_eq!((h.name.as_str(), h.members.clone()), ("Family", vec![1, 2]));
+        assert_eq!(h.market, None);
+
+        assert!(validate_household(&mut h, &[1]).is_err());
+        h.members.clear();
+        assert!(validate_household(&mut h, &[1, 2]).is_err());
+        h.members = vec![1];
+        h.market = Some("NoSuchMarket:abc".to_string());
+        assert!(validate_household(&mut h, &[1]).is_err());
+    }
+}
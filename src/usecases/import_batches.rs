@@ -0,0 +1,231 @@
+use crate::domain::models::WalletAllocation;
+use crate::usecases::ledger::UNASSIGNED;
+use crate::usecases::wallet_changes::{
+    EVENT_MERGE_OUT, EVENT_MOVE_OUT, EVENT_RETIRE, EVENT_UPDATE, EditError,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+
+// Partition of the wallet_allocations_current_by_account view: symbol, group, barca and
+// the account (or, on rows not linked to one, the notes)
+type LedgerKey = (String, Option<String>, Option<String>, String);
+
+fn ledger_key(row: &WalletAllocation) -> LedgerKey {
+    let account = match row.account_id {
+        Some(id) => format!("a:{}", id),
+        None => format!("n:{}", row.notes.as_deref().unwrap_or("")),
+    };
+    (
+        row.symbol.clone(),
+        row.group_name.clone(),
+        row.barca.clone(),
+        account,
+    )
+}
+
+// Hex SHA-256 of the imported file, used to recognise a file that was already imported
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Current holdings once the imported rows are appended (a later row replaces the holding
+// with the same key, as in the ledger views)
+pub fn projected_rows(
+    current: &[WalletAllocation],
+    imported: &[WalletAllocation],
+) -> Vec<WalletAllocation> {
+    let mut by_key: BTreeMap<LedgerKey, WalletAllocation> = BTreeMap::new();
+    for row in current.iter().chain(imported) {
+        by_key.insert(ledger_key(row), row.clone());
+    }
+    by_key.into_values().collect()
+}
+
+// Balances exports carry no group, barca or target: take group and barca from the current
+// holding of the symbol (at the same account if there is one, else Unassigned) and keep the
+// target of the holding the row replaces
+pub fn complete_from_current(row: &mut WalletAllocation, current: &[WalletAllocation]) {
+    if row.group_name.is_none() && row.barca.is_none() {
+        let same_symbol = || current.iter().filter(|c| c.symbol == row.symbol);
+        let placed = same_symbol()
+            .find(|c| row.account_id.is_some() && c.account_id == row.account_id)
+            .or_else(|| same_symbol().next());
+        row.group_name = Some(
+            placed
+                .and_then(|c| c.group_name.clone())
+                .unwrap_or_else(|| UNASSIGNED.to_string()),
+        );
+        row.barca = Some(
+            placed
+                .and_then(|c| c.barca.clone())
+                .unwrap_or_else(|| UNASSIGNED.to_string()),
+        );
+    }
+    if row.target_percent.is_none() {
+        let key = ledger_key(row);
+        row.target_percent = current
+            .iter()
+            .find(|c| ledger_key(c) == key)
+            .and_then(|c| c.target_percent);
+    }
+}
+
+// Rows that undo a batch: every holding it touched goes back to the row it had before the
+// batch, or is retired when the batch created it. `current` are the current rows by account
+// and `history` every ledger row of the symbols involved. Holdings edited since the import
+// are reported as a conflict instead of being overwritten.
+pub fn compensating_rows(
+    batch_id: i64,
+    batch_rows: &[WalletAllocation],
+    current: &[WalletAllocation],
+    history: &[WalletAllocation],
+    author: &str,
+    reason: &str,
+) -> Result<Vec<WalletAllocation>, EditError> {
+    let Some(first_id) = batch_rows.iter().filter_map(|r| r.id).min() else {
+        return Ok(Vec::new());
+    };
+    let current: HashMap<LedgerKey, &WalletAllocation> =
+        current.iter().map(|r| (ledger_key(r), r)).collect();
+    let mut touched: BTreeMap<LedgerKey, &WalletAllocation> = BTreeMap::new();
+    for row in batch_rows {
+        touched.insert(ledger_key(row), row);
+    }
+
+    let mut conflicts = Vec::new();
+    let mut out = Vec::new();
+    for (key, imported) in touched {
+        if current
+            .get(&key)
+            .is_none_or(|r| r.import_batch_id != Some(batch_id))
+        {
+            conflicts.push(format!(
+                "{} (group {}, barca {})",
+                key.0,
+                key.1.as_deref().unwrap_or("-"),
+                key.2.as_deref().unwrap_or("-")
+            ));
+            continue;
+        }
+        let before = history
+            .iter()
+            .filter(|r| r.id.is_some_and(|id| id < first_id) && ledger_key(r) == key)
+            .max_by(|a, b| (&a.created_at, a.id).cmp(&(&b.created_at, b.id)));
+        let mut row = match before {
+            Some(prev) if !is_tombstone(prev) => {
+                let mut row = prev.clone();
+                row.event = Some(EVENT_UPDATE.to_string());
+                row
+            }
+            _ => {
+                let mut row = imported.clone();
+                row.current_quantity = Some(0.0);
+                row.target_percent = Some(0.0);
+                row.event = Some(EVENT_RETIRE.to_string());
+                row
+            }
+        };
+        row.id = None;
+        row.created_at = None;
+        row.import_batch_id = None;
+        row.author = Some(author.to_string());
+        row.reason = Some(reason.to_string());
+        out.push(row);
+    }
+    if !conflicts.is_empty() {
+        return Err(EditError::Conflict(format!(
+            "holdings changed after import batch {}: {}",
+            batch_id,
+            conflicts.join("; ")
+        )));
+    }
+    Ok(out)
+}
+
+fn is_tombstone(row: &WalletAllocation) -> bool {
+    row.event
+        .as_deref()
+        .is_some_and(|e| [EVENT_RETIRE, EVENT_MOVE_OUT, EVENT_MERGE_OUT].contains(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i64, qty: f64, batch: Option<i64>, event: &str) -> WalletAllocation {
+        WalletAllocation {
+            id: Some(id),
+            symbol: "BTC".to_string(),
+            group_name: Some("Hodl".to_string()),
+            barca: Some("Core".to_string()),
+            target_percent: Some(40.0),
+            current_quantity: Some(qty),
+            last_price: None,
+            notes: None,
+            created_at: Some(format!("2025-01-0{}T00:00:00.000Z", id)),
+            account_id: Some(1),
+            author: None,
+            reason: None,
+            event: Some(event.to_string()),
+            import_batch_id: batch,
+        }
+    }
+
+    #[test]
+    fn rollback_restores_previous_rows_and_retires_new_holdings() {
+        let before = row(1, 1.0, None, "add");
+        let imported = row(2, 2.0, Some(7), "import");
+        let new_holding = WalletAllocation {
+            id: Some(3),
+            account_id: Some(2),
+            ..row(3, 5.0, Some(7), "import")
+        };
+        let current = vec![imported.clone(), new_holding.clone()];
+        let history = vec![before, imported.clone(), new_holding.clone()];
+        let rows = compensating_rows(
+            7,
+            &[imported, new_holding],
+            &current,
+            &history,
+            "ana",
+            "bad file",
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].current_quantity, Some(1.0));
+        assert_eq!(rows[0].event.as_deref(), Some("update"));
+        assert_eq!(rows[1].account_id, Some(2));
+        assert_eq!(rows[1].current_quantity, Some(0.0));
+        assert_eq!(rows[1].event.as_deref(), Some("retire"));
+        assert!(
+            rows.iter()
+                .all(|r| r.import_batch_id.is_none() && r.id.is_none())
+        );
+        assert_eq!(rows[0].author.as_deref(), Some("ana"));
+    }
+
+    #[test]
+    fn rollback_refuses_holdings_edited_after_the_batch() {
+        let imported = row(2, 2.0, Some(7), "import");
+        let edited = row(4, 3.0, None, "update");
+        let res = compensating_rows(
+            7,
+            std::slice::from_ref(&imported),
+            std::slice::from_ref(&edited),
+            &[imported.clone(), edited.clone()],
+            "ana",
+            "bad file",
+        );
+        assert!(matches!(res, Err(EditError::Conflict(_))));
+
+        // importing the same rows again leaves the projected holdings unchanged
+        let same = std::slice::from_ref(&imported);
+        let projected = projected_rows(same, same);
+        assert_eq!(projected.len(), 1);
+        assert_eq!(content_hash(b"abc").len(), 64);
+        assert!(content_hash(b"abc").starts_with("ba7816bf"));
+    }
+}
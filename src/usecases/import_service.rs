@@ -0,0 +1,146 @@
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::history_service::HistoryService;
+use crate::usecases::import_batches::content_hash;
+use crate::usecases::importers::{RowResult, attach_results, parse_import};
+use crate::usecases::ledger::validate_transaction;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub struct ImportService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl ImportService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // Import an exchange or tracker export: transactions go to the ledger (rows whose
+    // external_id is already recorded are skipped), balances to wallet_allocations as an
+    // import batch. Ok(Err) when the format is unknown or the file is not a valid CSV.
+    pub async fn import(
+        &self,
+        source: &str,
+        content: &[u8],
+        format: Option<&str>,
+        venue: Option<&str>,
+        dry_run: bool,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let parsed = match parse_import(content, format, venue) {
+            Ok(p) => p,
+            Err(e) => return Ok(Err(e)),
+        };
+        let mut results = parsed.unmapped_results();
+
+        let mut seen: HashSet<String> = self
+            .repo
+            .fetch_transactions(None, None, None)
+            .await?
+            .into_iter()
+            .filter_map(|t| t.external_id)
+            .collect();
+        let mut txs = Vec::new();
+        let mut duplicates = 0usize;
+        for (line, mut tx) in parsed.transactions {
+            if let Err(error) = validate_transaction(&mut tx) {
+                results.push(RowResult {
+                    line,
+                    status: "error",
+                    symbol: Some(tx.symbol),
+                    error: Some(error),
+                });
+                continue;
+            }
+            if let Some(id) = &tx.external_id
+                && !seen.insert(id.clone())
+            {
+                duplicates += 1;
+                results.push(RowResult::new(line, "duplicate", &tx.symbol));
+                continue;
+            }
+            let status = if dry_run { "would_import" } else { "imported" };
+            results.push(RowResult::new(line, status, &tx.symbol));
+            txs.push(tx);
+        }
+        let mut ids = Vec::with_capacity(txs.len());
+        if !dry_run {
+            for tx in &txs {
+                ids.push(self.repo.insert_transaction(tx).await?);
+            }
+        }
+
+        let balances = if parsed.balances.is_empty() {
+            Value::Null
+        } else {
+            HistoryService::new(self.repo.clone())
+                .import_balances(
+                    source,
+                    &content_hash(content),
+                    parsed.balances,
+                    &mut results,
+                    dry_run,
+                )
+                .await?
+        };
+        let mut report = json!({
+            "format": parsed.format,
+            "dialect": parsed.dialect,
+            "source": source,
+            "dry_run": dry_run,
+            "rows": parsed.rows,
+            "transactions": {
+                "imported": if dry_run { 0 } else { txs.len() },
+                "would_import": if dry_run { txs.len() } else { 0 },
+                "skipped_duplicates": duplicates,
+                "ids": ids,
+                "rows": if dry_run { json!(txs) } else { Value::Null },
+            },
+            "balances": balances,
+        });
+        attach_results(&mut report, results);
+        Ok(Ok(report))
+    }
+}
+
+// Resolve a client-supplied import path inside `dir`; paths that escape it (`..`, absolute
+// paths elsewhere, symlinks pointing out) are refused
+pub fn resolve_import_path(dir: &Path, requested: &str) -> Result<PathBuf, String> {
+    let root = dir
+        .canonicalize()
+        .map_err(|e| format!("import directory {} is not available: {}", dir.display(), e))?;
+    let path = root
+        .join(requested.trim())
+        .canonicalize()
+        .map_err(|e| format!("cannot read {}: {}", requested, e))?;
+    if !path.starts_with(&root) || !path.is_file() {
+        return Err(format!(
+            "{} is not a file in the import directory",
+            requested
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_paths_stay_inside_the_import_dir() {
+        let root = std::env::temp_dir().join(format!("import_dir_{}", std::process::id()));
+        let dir = root.join("imports");
+        std::fs::create_dir_all(dir.join("kraken")).unwrap();
+        std::fs::write(dir.join("kraken/balances.csv"), "Asset,Balance\n").unwrap();
+        std::fs::write(root.join("secret.csv"), "x\n").unwrap();
+
+        let ok = resolve_import_path(&dir, "kraken/balances.csv").unwrap();
+        assert!(ok.ends_with("kraken/balances.csv"));
+        assert!(resolve_import_path(&dir, "../secret.csv").is_err());
+        assert!(resolve_import_path(&dir, root.join("secret.csv").to_str().unwrap()).is_err());
+        assert!(resolve_import_path(&dir, "kraken").is_err());
+        assert!(resolve_import_path(&dir, "missing.csv").is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use super::{
+    Headers, Importer, Record, Row, balance, normalize_symbol, normalize_timestamp, transaction,
+};
+use crate::usecases::ledger::TransactionKind;
+
+const VENUE: &str = "Binance";
+
+// Spot trade history: Date(UTC), Pair, Side, Price, Executed ("0.5BTC"), Amount ("15000USDT"),
+// Fee ("0.001BNB")
+pub struct BinanceTrades;
+
+impl Importer for BinanceTrades {
+    fn name(&self) -> &'static str {
+        "binance_trades"
+    }
+
+    fn description(&self) -> &'static str {
+        "Binance spot trade history export"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["date(utc)", "pair", "side", "executed", "amount"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let kind = match row.text(&["side"])?.to_ascii_uppercase().as_str() {
+            "BUY" => TransactionKind::Buy,
+            "SELL" => TransactionKind::Sell,
+            other => return Err(format!("unknown side '{}'", other)),
+        };
+        let (qty, base) = row.required_amount(&["executed"])?;
+        let (total, quote) = row.required_amount(&["amount"])?;
+        if qty <= 0.0 {
+            return Err("executed quantity must be positive".to_string());
+        }
+        let mut tx = transaction(
+            kind,
+            normalize_timestamp(row.text(&["date(utc)"])?)?,
+            &base,
+            qty,
+        );
+        tx.price = Some(row.number(&["price"])?.unwrap_or(total / qty));
+        tx.quote_currency = Some(quote);
+        if let Some((fee, fee_symbol)) = row.amount(&["fee"])? {
+            tx.fee = Some(fee);
+            tx.fee_symbol = Some(fee_symbol);
+        }
+        tx.venue = row.venue(Some(VENUE));
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+// Transaction history statement: UTC_Time, Account, Operation, Coin, Change, Remark.
+// Deposits, withdrawals and rewards are mapped; trade legs come from the trade history.
+pub struct BinanceStatement;
+
+impl Importer for BinanceStatement {
+    fn name(&self) -> &'static str {
+        "binance_statement"
+    }
+
+    fn description(&self) -> &'static str {
+        "Binance transaction history statement (deposits, withdrawals, staking and earn rewards, distributions)"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["utc_time", "operation", "coin", "change"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let op = row.text(&["operation"])?;
+        let lower = op.to_ascii_lowercase();
+        let (kind, income_type) = if lower == "deposit" {
+            (TransactionKind::Deposit, None)
+        } else if lower == "withdraw" || lower == "withdrawal" {
+            (TransactionKind::Withdraw, None)
+        } else if lower.contains("staking") {
+            (TransactionKind::Income, Some("staking"))
+        } else if lower.contains("interest") || lower.contains("earn") {
+            (TransactionKind::Income, Some("lending"))
+        } else if lower.contains("distribution") || lower.contains("airdrop") {
+            (TransactionKind::Income, Some("airdrop"))
+        } else if lower.contains("buy")
+            || lower.contains("sell")
+            || lower.contains("transaction")
+            || lower == "fee"
+        {
+            return Err(format!(
+                "'{}' is a trade leg; import the trade history export instead",
+                op
+            ));
+        } else {
+            return Err(format!("unsupported operation '{}'", op));
+        };
+        let mut tx = transaction(
+            kind,
+            normalize_timestamp(row.text(&["utc_time"])?)?,
+            row.text(&["coin"])?,
+            row.required_number(&["change"])?,
+        );
+        tx.income_type = income_type.map(str::to_string);
+        tx.notes = row.get(&["remark"]).map(str::to_string);
+        tx.venue = row.venue(Some(VENUE));
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+// Wallet balances: Coin, Total (or Free + Locked)
+pub struct BinanceBalances;
+
+impl Importer for BinanceBalances {
+    fn name(&self) -> &'static str {
+        "binance_balances"
+    }
+
+    fn description(&self) -> &'static str {
+        "Binance wallet balances (Coin, Total or Free/Locked)"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["coin"])
+            && headers.has_any(&["total", "free"])
+            && !headers.has_any(&["change", "operation"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let qty = match row.number(&["total"])? {
+            Some(t) => t,
+            None => {
+                row.number(&["free", "available"])?.unwrap_or(0.0)
+                    + row.number(&["locked", "freeze"])?.unwrap_or(0.0)
+            }
+        };
+        if qty <= 0.0 {
+            return Ok(Vec::new());
+        }
+        let coin = normalize_symbol(row.text(&["coin"])?);
+        Ok(vec![Record::Balance(balance(
+            &coin,
+            qty,
+            row.venue(Some(VENUE)),
+        ))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usecases::importers::parse_import;
+
+    #[test]
+    fn trades_carry_quote_price_and_fee_asset() {
+        let csv = "Date(UTC),Pair,Side,Price,Executed,Amount,Fee\n\
+2024-02-01 09:30:00,BTCUSDT,BUY,42000,0.5BTC,21000USDT,0.0005BNB\n\
+2024-02-02 09:30:00,ETHBTC,SELL,,2ETH,0.11BTC,0.0001BTC\n\
+2024-02-03 09:30:00,ETHBTC,HOLD,,2ETH,0.11BTC,\n";
+        let p = parse_import(csv.as_bytes(), None, None).unwrap();
+        assert_eq!(p.format, "binance_trades");
+        let (_, buy) = &p.transactions[0];
+        assert_eq!((buy.kind.as_str(), buy.symbol.as_str()), ("buy", "BTC"));
+        assert_eq!(buy.quote_currency.as_deref(), Some("USDT"));
+        assert_eq!(buy.fee_symbol.as_deref(), Some("BNB"));
+        assert_eq!(buy.timestamp, "2024-02-01T09:30:00Z");
+        let (_, sell) = &p.transactions[1];
+        assert!((sell.price.unwrap() - 0.055).abs() < 1e-12);
+        assert_eq!(p.errors[0].line, 4);
+    }
+
+    #[test]
+    fn statement_maps_rewards_and_rejects_trade_legs() {
+        let csv = "User_ID,UTC_Time,Account,Operation,Coin,Change,Remark\n\
+1,2024-02-01 00:00:00,Spot,Deposit,SOL,10,\n\
+1,2024-02-02 00:00:00,Earn,Simple Earn Flexible Interest,USDT,0.42,\n\
+1,2024-02-03 00:00:00,Spot,Withdraw,SOL,-4,\n\
+1,2024-02-04 00:00:00,Spot,Transaction Related,SOL,-1,\n";
+        let p = parse_import(csv.as_bytes(), None, Some("Binance Main")).unwrap();
+        assert_eq!(p.format, "binance_statement");
+        let kinds: Vec<&str> = p
+            .transactions
+            .iter()
+            .map(|(_, t)| t.kind.as_str())
+            .collect();
+        assert_eq!(kinds, ["deposit", "income", "withdraw"]);
+        assert_eq!(p.transactions[1].1.income_type.as_deref(), Some("lending"));
+        assert_eq!(p.transactions[2].1.quantity, 4.0);
+        assert_eq!(p.transactions[0].1.venue.as_deref(), Some("Binance Main"));
+        assert_eq!(p.errors.len(), 1);
+    }
+}
@@ -0,0 +1,93 @@
+use super::{Headers, Importer, Record, Row, normalize_symbol, normalize_timestamp, transaction};
+use crate::usecases::ledger::TransactionKind;
+
+const VENUE: &str = "Coinbase";
+
+// Transaction history report: Timestamp, Transaction Type, Asset, Quantity Transacted,
+// Spot Price Currency, Spot Price at Transaction, Subtotal, Total, Fees, Notes
+pub struct CoinbaseTransactions;
+
+impl Importer for CoinbaseTransactions {
+    fn name(&self) -> &'static str {
+        "coinbase"
+    }
+
+    fn description(&self) -> &'static str {
+        "Coinbase transaction history report (buys, sells, sends, receives, rewards)"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&[
+            "timestamp",
+            "transaction type",
+            "asset",
+            "quantity transacted",
+        ])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let kind_text = row.text(&["transaction type"])?;
+        let (kind, income_type) = match kind_text.to_ascii_lowercase().as_str() {
+            "buy" | "advanced trade buy" => (TransactionKind::Buy, None),
+            "sell" | "advanced trade sell" => (TransactionKind::Sell, None),
+            "receive" | "deposit" => (TransactionKind::Deposit, None),
+            "send" | "withdrawal" => (TransactionKind::Withdraw, None),
+            "staking income" | "rewards income" | "inflation reward" => {
+                (TransactionKind::Income, Some("staking"))
+            }
+            "learning reward" | "coinbase earn" => (TransactionKind::Income, Some("airdrop")),
+            "convert" => {
+                return Err(
+                    "convert rows are not supported; record them as a sell and a buy".to_string(),
+                );
+            }
+            other => return Err(format!("unsupported transaction type '{}'", other)),
+        };
+        let mut tx = transaction(
+            kind,
+            normalize_timestamp(row.text(&["timestamp"])?)?,
+            row.text(&["asset"])?,
+            row.required_number(&["quantity transacted"])?,
+        );
+        let quote = row
+            .get(&["spot price currency", "price currency"])
+            .map(normalize_symbol);
+        tx.price = row.number(&["spot price at transaction", "price at transaction"])?;
+        if matches!(kind, TransactionKind::Buy | TransactionKind::Sell) {
+            tx.fee = row
+                .number(&["fees and/or spread", "fees"])?
+                .map(f64::abs)
+                .filter(|f| *f > 0.0);
+            tx.fee_symbol = tx.fee.and(quote.clone());
+        }
+        tx.quote_currency = quote;
+        tx.income_type = income_type.map(str::to_string);
+        tx.external_id = row.get(&["id"]).map(|id| format!("coinbase:{}", id));
+        tx.notes = row.get(&["notes"]).map(str::to_string);
+        tx.venue = row.venue(Some(VENUE));
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usecases::importers::parse_import;
+
+    #[test]
+    fn maps_trades_transfers_and_rewards() {
+        let csv = "ID,Timestamp,Transaction Type,Asset,Quantity Transacted,Price Currency,Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n\
+a1,2024-01-05 10:00:00 UTC,Advanced Trade Sell,ETH,-1.5,EUR,€2000.00,€3000.00,€2990.00,€10.00,\n\
+a2,2024-01-06 10:00:00 UTC,Send,ETH,-0.5,EUR,€2100.00,,,,to ledger\n\
+a3,2024-01-07 10:00:00 UTC,Staking Income,SOL,0.01,EUR,€90.00,€0.90,€0.90,€0.00,\n";
+        let p = parse_import(csv.as_bytes(), None, None).unwrap();
+        assert!(p.errors.is_empty(), "{:?}", p.errors);
+        let sell = &p.transactions[0].1;
+        assert_eq!((sell.kind.as_str(), sell.quantity), ("sell", 1.5));
+        assert_eq!(sell.price, Some(2000.0));
+        assert_eq!(sell.fee, Some(10.0));
+        assert_eq!(sell.fee_symbol.as_deref(), Some("EUR"));
+        assert_eq!(sell.external_id.as_deref(), Some("coinbase:a1"));
+        assert_eq!(p.transactions[1].1.kind, "withdraw");
+        assert_eq!(p.transactions[2].1.income_type.as_deref(), Some("staking"));
+    }
+}
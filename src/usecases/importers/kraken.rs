@@ -0,0 +1,198 @@
+use super::{
+    Headers, Importer, Record, Row, balance, normalize_symbol, normalize_timestamp, transaction,
+};
+use crate::usecases::ledger::TransactionKind;
+
+const VENUE: &str = "Kraken";
+
+// Quote assets a Kraken pair can end with, longest codes first
+const QUOTES: &[&str] = &[
+    "ZUSD", "ZEUR", "ZGBP", "ZCAD", "ZJPY", "USDT", "USDC", "XXBT", "XETH", "USD", "EUR", "GBP",
+    "CAD", "JPY", "XBT", "ETH", "DAI",
+];
+
+// "XXBTZUSD" / "SOLUSD" / "ETH/EUR" -> (base, quote)
+fn split_pair(pair: &str) -> Result<(String, String), String> {
+    let p = pair.trim().to_ascii_uppercase();
+    if let Some((base, quote)) = p.split_once('/') {
+        return Ok((normalize_symbol(base), normalize_symbol(quote)));
+    }
+    QUOTES
+        .iter()
+        .find_map(|q| {
+            p.strip_suffix(q)
+                .filter(|base| !base.is_empty())
+                .map(|base| (normalize_symbol(base), normalize_symbol(q)))
+        })
+        .ok_or_else(|| format!("cannot split pair '{}'", pair))
+}
+
+// Trades export: txid, pair, time, type, price, cost, fee, vol
+pub struct KrakenTrades;
+
+impl Importer for KrakenTrades {
+    fn name(&self) -> &'static str {
+        "kraken_trades"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kraken trades export"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["txid", "pair", "time", "type", "price", "vol"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let kind = match row.text(&["type"])?.to_ascii_lowercase().as_str() {
+            "buy" => TransactionKind::Buy,
+            "sell" => TransactionKind::Sell,
+            other => return Err(format!("unknown trade type '{}'", other)),
+        };
+        let (base, quote) = split_pair(row.text(&["pair"])?)?;
+        let mut tx = transaction(
+            kind,
+            normalize_timestamp(row.text(&["time"])?)?,
+            &base,
+            row.required_number(&["vol"])?,
+        );
+        tx.price = Some(row.required_number(&["price"])?);
+        tx.fee = row.number(&["fee"])?.filter(|f| *f > 0.0);
+        tx.fee_symbol = tx.fee.map(|_| quote.clone());
+        tx.quote_currency = Some(quote);
+        tx.external_id = row.get(&["txid"]).map(|id| format!("kraken:{}", id));
+        tx.venue = row.venue(Some(VENUE));
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+// Ledgers export: txid, refid, time, type, subtype, aclass, asset, amount, fee, balance.
+// Deposits, withdrawals and staking rewards are mapped; trade legs come from the trades export.
+pub struct KrakenLedgers;
+
+impl Importer for KrakenLedgers {
+    fn name(&self) -> &'static str {
+        "kraken_ledgers"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kraken ledgers export (deposits, withdrawals, staking rewards)"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["refid", "time", "type", "asset", "amount"])
+            && !headers.has_any(&["pair"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let kind_text = row.text(&["type"])?.to_ascii_lowercase();
+        let (kind, income_type) = match kind_text.as_str() {
+            "deposit" => (TransactionKind::Deposit, None),
+            "withdrawal" => (TransactionKind::Withdraw, None),
+            "staking" | "earn" | "reward" => (TransactionKind::Income, Some("staking")),
+            "trade" | "spend" | "receive" => {
+                return Err(format!(
+                    "'{}' is a trade leg; import the trades export instead",
+                    kind_text
+                ));
+            }
+            // Moves between spot and staking wallets do not change holdings
+            "transfer" => return Ok(Vec::new()),
+            other => return Err(format!("unsupported ledger type '{}'", other)),
+        };
+        let mut tx = transaction(
+            kind,
+            normalize_timestamp(row.text(&["time"])?)?,
+            row.text(&["asset"])?,
+            row.required_number(&["amount"])?,
+        );
+        tx.fee = row.number(&["fee"])?.filter(|f| *f > 0.0);
+        tx.income_type = income_type.map(str::to_string);
+        tx.external_id = row.get(&["txid"]).map(|id| format!("kraken:{}", id));
+        tx.venue = row.venue(Some(VENUE));
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+// Balances: Asset, Balance
+pub struct KrakenBalances;
+
+impl Importer for KrakenBalances {
+    fn name(&self) -> &'static str {
+        "kraken_balances"
+    }
+
+    fn description(&self) -> &'static str {
+        "Kraken account balances (Asset, Balance)"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["asset", "balance"]) && !headers.has_any(&["refid", "txid"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let qty = row.required_number(&["balance"])?;
+        if qty <= 0.0 {
+            return Ok(Vec::new());
+        }
+        let asset = normalize_symbol(row.text(&["asset"])?);
+        Ok(vec![Record::Balance(balance(
+            &asset,
+            qty,
+            row.venue(Some(VENUE)),
+        ))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usecases::importers::parse_import;
+
+    #[test]
+    fn splits_kraken_pairs() {
+        assert_eq!(
+            split_pair("XXBTZUSD").unwrap(),
+            ("BTC".to_string(), "USD".to_string())
+        );
+        assert_eq!(
+            split_pair("SOLUSDT").unwrap(),
+            ("SOL".to_string(), "USDT".to_string())
+        );
+        assert_eq!(
+            split_pair("XETHXXBT").unwrap(),
+            ("ETH".to_string(), "BTC".to_string())
+        );
+        assert_eq!(split_pair("ADA/EUR").unwrap().1, "EUR");
+        assert!(split_pair("USD").is_err());
+    }
+
+    #[test]
+    fn ledgers_map_staking_and_skip_internal_transfers() {
+        let csv = "\"txid\",\"refid\",\"time\",\"type\",\"subtype\",\"aclass\",\"asset\",\"amount\",\"fee\",\"balance\"\n\
+\"L1\",\"R1\",\"2024-03-01 12:00:00\",\"deposit\",\"\",\"currency\",\"XXBT\",\"0.2\",\"0\",\"0.2\"\n\
+\"L2\",\"R2\",\"2024-03-02 12:00:00\",\"transfer\",\"spottostaking\",\"currency\",\"DOT\",\"-5\",\"0\",\"0\"\n\
+\"L3\",\"R3\",\"2024-03-03 12:00:00\",\"staking\",\"\",\"currency\",\"DOT.S\",\"0.05\",\"0\",\"5.05\"\n\
+\"L4\",\"R4\",\"2024-03-04 12:00:00\",\"trade\",\"\",\"currency\",\"ZUSD\",\"-100\",\"0\",\"0\"\n";
+        let p = parse_import(csv.as_bytes(), None, None).unwrap();
+        assert_eq!(p.format, "kraken_ledgers");
+        assert_eq!(p.rows, 4);
+        assert_eq!(p.transactions.len(), 2);
+        assert_eq!(p.transactions[0].1.symbol, "BTC");
+        assert_eq!(
+            p.transactions[0].1.external_id.as_deref(),
+            Some("kraken:L1")
+        );
+        assert_eq!(p.transactions[1].1.symbol, "DOT");
+        assert_eq!(p.transactions[1].1.kind, "income");
+        assert_eq!(p.errors.len(), 1);
+        assert_eq!(p.errors[0].line, 5);
+        assert_eq!(p.skipped, [3]);
+        let statuses: Vec<(u64, &str)> = p
+            .unmapped_results()
+            .iter()
+            .map(|r| (r.line, r.status))
+            .collect();
+        assert_eq!(statuses, [(5, "error"), (3, "skipped")]);
+    }
+}
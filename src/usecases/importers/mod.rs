@@ -0,0 +1,491 @@
+// CSV importers for our wallet layout and for exchange / tax tracker exports. Each importer
+// recognises its export by the header row and maps data rows to ledger transactions or to
+// balances (wallet_allocations rows); rows it cannot map are reported with their line.
+use crate::csv_dialect::CsvDialect;
+use crate::domain::models::{Transaction, WalletAllocation};
+use crate::usecases::import_batches::content_hash;
+use crate::usecases::ledger::TransactionKind;
+use crate::wallet_files::read_records;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+mod binance;
+mod coinbase;
+mod kraken;
+mod trackers;
+mod wallet;
+
+pub use wallet::WALLET_FORMAT;
+
+// Exports may start with a preamble (Coinbase) before the header row
+const HEADER_SCAN_ROWS: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub line: u64,
+    pub error: String,
+}
+
+// Outcome of one data row as reported by the import endpoints: imported, would_import (dry
+// run), duplicate, skipped (nothing to import) or error
+#[derive(Debug, Clone, Serialize)]
+pub struct RowResult {
+    pub line: u64,
+    pub status: &'static str,
+    pub symbol: Option<String>,
+    pub error: Option<String>,
+}
+
+impl RowResult {
+    pub fn new(line: u64, status: &'static str, symbol: &str) -> Self {
+        Self {
+            line,
+            status,
+            symbol: Some(symbol.to_string()),
+            error: None,
+        }
+    }
+}
+
+// Sorted per-row results plus the error rows on their own, added to an import report
+pub fn attach_results(report: &mut Value, mut results: Vec<RowResult>) {
+    results.sort_by_key(|r| r.line);
+    let errors: Vec<RowError> = results
+        .iter()
+        .filter_map(|r| {
+            r.error.clone().map(|error| RowError {
+                line: r.line,
+                error,
+            })
+        })
+        .collect();
+    report["results"] = json!(results);
+    report["errors"] = json!(errors);
+}
+
+// Quantity held according to a balances export or a wallet CSV row. `account` names the
+// custody account explicitly; otherwise the row notes name it, as in older wallet files.
+#[derive(Debug, Clone)]
+pub struct Balance {
+    pub row: WalletAllocation,
+    pub account: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Record {
+    Transaction(Transaction),
+    Balance(Balance),
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedImport {
+    pub format: &'static str,
+    pub dialect: CsvDialect,
+    // Data rows read (blank lines excluded)
+    pub rows: usize,
+    // Mapped transactions and balances with the line they came from
+    pub transactions: Vec<(u64, Transaction)>,
+    pub balances: Vec<(u64, Balance)>,
+    pub errors: Vec<RowError>,
+    // Lines the importer mapped to nothing (zero balances, internal transfers)
+    pub skipped: Vec<u64>,
+}
+
+impl ParsedImport {
+    // Results for the rows that produced nothing to import
+    pub fn unmapped_results(&self) -> Vec<RowResult> {
+        let errors = self.errors.iter().map(|e| RowResult {
+            line: e.line,
+            status: "error",
+            symbol: None,
+            error: Some(e.error.clone()),
+        });
+        let skipped = self.skipped.iter().map(|line| RowResult {
+            line: *line,
+            status: "skipped",
+            symbol: None,
+            error: None,
+        });
+        errors.chain(skipped).collect()
+    }
+}
+
+pub trait Importer: Sync {
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    // Whether the header row belongs to this export
+    fn detect(&self, headers: &Headers) -> bool;
+    // Map one data row; an empty Vec skips rows with nothing to import
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String>;
+}
+
+static IMPORTERS: &[&dyn Importer] = &[
+    &wallet::WalletCsv,
+    &binance::BinanceTrades,
+    &binance::BinanceStatement,
+    &binance::BinanceBalances,
+    &coinbase::CoinbaseTransactions,
+    &kraken::KrakenTrades,
+    &kraken::KrakenLedgers,
+    &kraken::KrakenBalances,
+    &trackers::Koinly,
+    &trackers::CoinTracking,
+];
+
+pub fn formats() -> Vec<Value> {
+    IMPORTERS
+        .iter()
+        .map(|i| json!({"name": i.name(), "description": i.description()}))
+        .collect()
+}
+
+// Header names, lowercased; some exports repeat a name (CoinTracking's `Cur.`)
+pub struct Headers {
+    names: Vec<String>,
+}
+
+impl Headers {
+    fn new(record: &StringRecord) -> Self {
+        Self {
+            names: record
+                .iter()
+                .map(|h| h.trim().trim_start_matches('\u{feff}').to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn has_all(&self, names: &[&str]) -> bool {
+        names.iter().all(|n| self.names.iter().any(|h| h == n))
+    }
+
+    pub fn has_any(&self, names: &[&str]) -> bool {
+        names.iter().any(|n| self.names.iter().any(|h| h == n))
+    }
+
+    fn nth_index(&self, name: &str, n: usize) -> Option<usize> {
+        self.names
+            .iter()
+            .enumerate()
+            .filter(|(_, h)| *h == name)
+            .nth(n)
+            .map(|(i, _)| i)
+    }
+}
+
+pub struct Row<'a> {
+    headers: &'a Headers,
+    record: &'a StringRecord,
+    venue: Option<&'a str>,
+    dialect: &'a CsvDialect,
+}
+
+impl<'a> Row<'a> {
+    // Value of the first column with any of the names; blank cells count as missing
+    pub fn get(&self, names: &[&str]) -> Option<&'a str> {
+        names.iter().find_map(|n| self.nth(n, 0))
+    }
+
+    // Value of the n-th column with this name (0-based)
+    pub fn nth(&self, name: &str, n: usize) -> Option<&'a str> {
+        self.headers
+            .nth_index(name, n)
+            .and_then(|i| self.record.get(i))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    }
+
+    pub fn text(&self, names: &[&str]) -> Result<&'a str, String> {
+        self.get(names)
+            .ok_or_else(|| format!("missing {}", names[0]))
+    }
+
+    // Number in the file's dialect (decimal and thousands separators, currency and % signs)
+    pub fn number(&self, names: &[&str]) -> Result<Option<f64>, String> {
+        self.get(names).map(|v| self.parse(names[0], v)).transpose()
+    }
+
+    pub fn required_number(&self, names: &[&str]) -> Result<f64, String> {
+        self.parse(names[0], self.text(names)?)
+    }
+
+    // "0.5BTC" -> (0.5, "BTC"), as Binance writes executed amounts and fees
+    pub fn amount(&self, names: &[&str]) -> Result<Option<(f64, String)>, String> {
+        let Some(v) = self.get(names) else {
+            return Ok(None);
+        };
+        let at = v
+            .find(|c: char| c.is_ascii_alphabetic())
+            .ok_or_else(|| format!("{}: amount '{}' has no asset", names[0], v))?;
+        let (qty, asset) = v.split_at(at);
+        Ok(Some((self.parse(names[0], qty)?, normalize_symbol(asset))))
+    }
+
+    pub fn required_amount(&self, names: &[&str]) -> Result<(f64, String), String> {
+        self.amount(names)?
+            .ok_or_else(|| format!("missing {}", names[0]))
+    }
+
+    fn parse(&self, column: &str, value: &str) -> Result<f64, String> {
+        self.dialect
+            .parse_number(value)
+            .map_err(|e| format!("{}: {}", column, e))
+    }
+
+    // Venue given with the import, else the exchange the export comes from
+    pub fn venue(&self, default: Option<&str>) -> Option<String> {
+        self.venue.or(default).map(str::to_string)
+    }
+}
+
+// Exchange asset codes to the symbols we track (Kraken's X/Z prefixes and staking suffixes)
+pub fn normalize_symbol(s: &str) -> String {
+    let upper = s.trim().to_ascii_uppercase();
+    let base = [".S", ".F", ".M", ".B", ".P"]
+        .iter()
+        .find_map(|suffix| upper.strip_suffix(suffix))
+        .unwrap_or(&upper);
+    match base {
+        "XBT" | "XXBT" => "BTC",
+        "XETH" => "ETH",
+        "XDG" | "XXDG" => "DOGE",
+        "XLTC" => "LTC",
+        "XXRP" => "XRP",
+        "XXLM" => "XLM",
+        "XXMR" => "XMR",
+        "XETC" => "ETC",
+        "XZEC" => "ZEC",
+        "ZUSD" => "USD",
+        "ZEUR" => "EUR",
+        "ZGBP" => "GBP",
+        "ZCAD" => "CAD",
+        "ZJPY" => "JPY",
+        other => other,
+    }
+    .to_string()
+}
+
+// Export timestamps (UTC unless they carry an offset) as RFC3339
+pub fn normalize_timestamp(s: &str) -> Result<String, String> {
+    let t = s.trim();
+    if let Ok(d) = DateTime::parse_from_rfc3339(t) {
+        return Ok(d
+            .with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+    let naive = t.trim_end_matches(" UTC").trim_end_matches('Z');
+    const FORMATS: &[&str] = &[
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%d.%m.%Y %H:%M:%S",
+        "%d.%m.%Y %H:%M",
+        "%Y/%m/%d %H:%M:%S",
+        "%d/%m/%Y %H:%M:%S",
+        "%d/%m/%Y %H:%M",
+    ];
+    FORMATS
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(naive, f).ok())
+        .or_else(|| {
+            ["%Y-%m-%d", "%d/%m/%Y"]
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(naive, f).ok())
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .map(|n| n.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true))
+        .ok_or_else(|| format!("invalid timestamp '{}'", s))
+}
+
+pub fn transaction(
+    kind: TransactionKind,
+    timestamp: String,
+    symbol: &str,
+    quantity: f64,
+) -> Transaction {
+    Transaction {
+        id: None,
+        timestamp,
+        kind: kind.as_str().to_string(),
+        symbol: normalize_symbol(symbol),
+        quantity: quantity.abs(),
+        price: None,
+        quote_currency: None,
+        fee: None,
+        fee_symbol: None,
+        venue: None,
+        to_venue: None,
+        group_name: None,
+        barca: None,
+        external_id: None,
+        notes: None,
+        created_at: None,
+        income_type: None,
+        account_id: None,
+        to_account_id: None,
+    }
+}
+
+pub fn balance(symbol: &str, quantity: f64, account: Option<String>) -> Balance {
+    Balance {
+        row: WalletAllocation {
+            id: None,
+            symbol: normalize_symbol(symbol),
+            group_name: None,
+            barca: None,
+            target_percent: None,
+            current_quantity: Some(quantity),
+            last_price: None,
+            notes: None,
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        },
+        account,
+    }
+}
+
+// Parse an export with the named importer, or the first one recognising its header row.
+// `venue` overrides the exchange name used as custody location.
+pub fn parse_import(
+    content: &[u8],
+    format: Option<&str>,
+    venue: Option<&str>,
+) -> Result<ParsedImport, String> {
+    let candidates: Vec<&dyn Importer> = match format.map(str::trim).filter(|f| !f.is_empty()) {
+        Some(f) => vec![
+            *IMPORTERS
+                .iter()
+                .find(|i| i.name().eq_ignore_ascii_case(f))
+                .ok_or_else(|| format!("unknown import format '{}'", f))?,
+        ],
+        None => IMPORTERS.to_vec(),
+    };
+    // CSV in any dialect, or a JSON / XLSX export of the same table
+    let (dialect, records) = read_records(content)?;
+    let (header_at, importer, headers) = records
+        .iter()
+        .take(HEADER_SCAN_ROWS)
+        .enumerate()
+        .find_map(|(i, (_, r))| {
+            let headers = Headers::new(r);
+            candidates
+                .iter()
+                .find(|imp| imp.detect(&headers))
+                .map(|imp| (i, *imp, headers))
+        })
+        .ok_or_else(|| match candidates.as_slice() {
+            [only] => format!(
+                "no {} header row found in the first {} rows; expected {}",
+                only.name(),
+                HEADER_SCAN_ROWS,
+                only.description()
+            ),
+            _ => "unrecognised file: no known import format matches its header row".to_string(),
+        })?;
+    let venue = venue.map(str::trim).filter(|v| !v.is_empty());
+
+    let mut out = ParsedImport {
+        format: importer.name(),
+        dialect,
+        ..ParsedImport::default()
+    };
+    for (line, record) in &records[header_at + 1..] {
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        out.rows += 1;
+        let line = *line;
+        let row = Row {
+            headers: &headers,
+            record,
+            venue,
+            dialect: &dialect,
+        };
+        match importer.map_row(&row) {
+            Ok(mapped) if mapped.is_empty() => out.skipped.push(line),
+            Ok(mapped) => {
+                for rec in mapped {
+                    match rec {
+                        // Exports without a transaction id get one from the row content, so
+                        // importing an overlapping export again skips rows already recorded
+                        Record::Transaction(mut tx) => {
+                            if tx.external_id.is_none() {
+                                let raw: Vec<&str> = record.iter().collect();
+                                tx.external_id = Some(format!(
+                                    "{}:{}",
+                                    importer.name(),
+                                    &content_hash(raw.join(",").as_bytes())[..16]
+                                ));
+                            }
+                            out.transactions.push((line, tx));
+                        }
+                        Record::Balance(b) => out.balances.push((line, b)),
+                    }
+                }
+            }
+            Err(error) => out.errors.push(RowError { line, error }),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_symbols_amounts_and_timestamps() {
+        assert_eq!(normalize_symbol("XXBT"), "BTC");
+        assert_eq!(normalize_symbol("dot.s"), "DOT");
+        assert_eq!(normalize_symbol("ZEUR"), "EUR");
+        assert_eq!(
+            normalize_timestamp("15/02/2024").unwrap(),
+            "2024-02-15T00:00:00Z"
+        );
+        assert_eq!(
+            normalize_timestamp("2024-03-01 14:05:09").unwrap(),
+            "2024-03-01T14:05:09Z"
+        );
+        assert_eq!(
+            normalize_timestamp("2024-03-01 14:05 UTC").unwrap(),
+            "2024-03-01T14:05:00Z"
+        );
+        assert_eq!(
+            normalize_timestamp("01.03.2024 14:05:09").unwrap(),
+            "2024-03-01T14:05:09Z"
+        );
+        assert_eq!(
+            normalize_timestamp("2024-03-01T14:05:09-03:00").unwrap(),
+            "2024-03-01T17:05:09Z"
+        );
+        assert!(normalize_timestamp("yesterday").is_err());
+    }
+
+    #[test]
+    fn detects_format_after_preamble_and_reports_bad_rows() {
+        let csv = "\u{feff}Transactions\nUser,you@example.com\n\
+Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes\n\
+2024-01-05T10:00:00Z,Buy,BTC,0.01,USD,42000,420,425,5,Bought\n\
+\n\
+2024-01-06T10:00:00Z,Convert,ETH,1,USD,2200,2200,2200,0,Converted\n";
+        let parsed = parse_import(csv.as_bytes(), None, None).unwrap();
+        assert_eq!(parsed.format, "coinbase");
+        assert_eq!(parsed.rows, 2);
+        assert_eq!(parsed.transactions.len(), 1);
+        let (line, tx) = &parsed.transactions[0];
+        assert_eq!(*line, 4);
+        assert_eq!(tx.kind, "buy");
+        assert_eq!(tx.venue.as_deref(), Some("Coinbase"));
+        assert!(tx.external_id.as_deref().unwrap().starts_with("coinbase:"));
+        assert_eq!(parsed.errors.len(), 1);
+        assert_eq!(parsed.errors[0].line, 6);
+
+        assert!(parse_import(b"foo,bar\n1,2\n", None, None).is_err());
+        assert!(parse_import(csv.as_bytes(), Some("kraken_trades"), None).is_err());
+    }
+}
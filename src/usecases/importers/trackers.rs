@@ -0,0 +1,214 @@
+use super::{Headers, Importer, Record, Row, normalize_symbol, normalize_timestamp, transaction};
+use crate::domain::models::Transaction;
+use crate::usecases::ledger::{TransactionKind, is_fiat};
+
+// Income sub-type of a tracker label / type, or None when it is not income
+fn income_type(label: &str) -> Option<&'static str> {
+    let l = label.to_ascii_lowercase();
+    if l.contains("staking") || l.contains("mining") || l.contains("reward") {
+        Some("staking")
+    } else if l.contains("lending") || l.contains("interest") {
+        Some("lending")
+    } else if l.contains("airdrop") || l.contains("gift") {
+        Some("airdrop")
+    } else if l.contains("income") {
+        Some("other")
+    } else {
+        None
+    }
+}
+
+// A trade from what was sent and received: buying with fiat, selling for fiat, or buying one
+// crypto quoted in the other (the ledger then moves the quote balance)
+fn trade(timestamp: String, sent: (f64, String), received: (f64, String)) -> Transaction {
+    let (sent_qty, sent_cur) = sent;
+    let (recv_qty, recv_cur) = received;
+    if is_fiat(&recv_cur) && !is_fiat(&sent_cur) {
+        let mut tx = transaction(TransactionKind::Sell, timestamp, &sent_cur, sent_qty);
+        tx.price = Some(recv_qty / sent_qty);
+        tx.quote_currency = Some(recv_cur);
+        tx
+    } else {
+        let mut tx = transaction(TransactionKind::Buy, timestamp, &recv_cur, recv_qty);
+        tx.price = Some(sent_qty / recv_qty);
+        tx.quote_currency = Some(sent_cur);
+        tx
+    }
+}
+
+fn amount(row: &Row, qty: &[&str], cur: Option<&str>) -> Result<Option<(f64, String)>, String> {
+    match (row.number(qty)?, cur) {
+        (Some(q), Some(c)) if q.abs() > 0.0 => Ok(Some((q.abs(), normalize_symbol(c)))),
+        (Some(q), None) if q.abs() > 0.0 => Err(format!("{} has no currency", qty[0])),
+        _ => Ok(None),
+    }
+}
+
+// Sent/received pair to a transaction; `label` decides between deposit and income
+fn movement(
+    timestamp: String,
+    sent: Option<(f64, String)>,
+    received: Option<(f64, String)>,
+    label: &str,
+) -> Result<Transaction, String> {
+    Ok(match (sent, received) {
+        (Some(s), Some(r)) => trade(timestamp, s, r),
+        (None, Some((qty, cur))) => {
+            let income = income_type(label);
+            let kind = if income.is_some() {
+                TransactionKind::Income
+            } else {
+                TransactionKind::Deposit
+            };
+            let mut tx = transaction(kind, timestamp, &cur, qty);
+            tx.income_type = income.map(str::to_string);
+            tx
+        }
+        (Some((qty, cur)), None) => transaction(TransactionKind::Withdraw, timestamp, &cur, qty),
+        (None, None) => return Err("row has neither a sent nor a received amount".to_string()),
+    })
+}
+
+// Koinly universal format: Date, Sent Amount, Sent Currency, Received Amount, Received Currency,
+// Fee Amount, Fee Currency, Net Worth Amount, Net Worth Currency, Label, Description, TxHash
+pub struct Koinly;
+
+impl Importer for Koinly {
+    fn name(&self) -> &'static str {
+        "koinly"
+    }
+
+    fn description(&self) -> &'static str {
+        "Koinly universal transaction format"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&[
+            "date",
+            "sent amount",
+            "sent currency",
+            "received amount",
+            "received currency",
+        ])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let sent = amount(row, &["sent amount"], row.get(&["sent currency"]))?;
+        let received = amount(row, &["received amount"], row.get(&["received currency"]))?;
+        let label = row.get(&["label"]).unwrap_or("");
+        let mut tx = movement(
+            normalize_timestamp(row.text(&["date"])?)?,
+            sent,
+            received,
+            label,
+        )?;
+        if let Some((fee, cur)) = amount(row, &["fee amount"], row.get(&["fee currency"]))? {
+            tx.fee = Some(fee);
+            tx.fee_symbol = Some(cur);
+        }
+        tx.external_id = row.get(&["txhash"]).map(|h| format!("koinly:{}", h));
+        tx.notes = row.get(&["description"]).map(str::to_string);
+        tx.venue = row.venue(None);
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+// CoinTracking export: Type, Buy, Cur., Sell, Cur., Fee, Cur., Exchange, Group, Comment, Date
+// (the three currency columns share one name and are told apart by position)
+pub struct CoinTracking;
+
+impl Importer for CoinTracking {
+    fn name(&self) -> &'static str {
+        "cointracking"
+    }
+
+    fn description(&self) -> &'static str {
+        "CoinTracking trade table export"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["type", "buy", "cur.", "sell", "exchange", "date"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let kind = row.text(&["type"])?;
+        let sent = amount(row, &["sell"], row.nth("cur.", 1))?;
+        let received = amount(row, &["buy"], row.nth("cur.", 0))?;
+        let timestamp = normalize_timestamp(row.text(&["date"])?)?;
+        let mut tx = match kind.to_ascii_lowercase().as_str() {
+            "trade" => match (sent, received) {
+                (Some(s), Some(r)) => trade(timestamp, s, r),
+                _ => return Err("trade needs both a buy and a sell amount".to_string()),
+            },
+            "deposit" => movement(timestamp, None, received, "")?,
+            "withdrawal" => movement(timestamp, sent, None, "")?,
+            other if income_type(other).is_some() => movement(timestamp, None, received, other)?,
+            other => return Err(format!("unsupported type '{}'", other)),
+        };
+        if let Some((fee, cur)) = amount(row, &["fee"], row.nth("cur.", 2))? {
+            tx.fee = Some(fee);
+            tx.fee_symbol = Some(cur);
+        }
+        tx.notes = row.get(&["comment"]).map(str::to_string);
+        tx.venue = row
+            .get(&["exchange"])
+            .map(str::to_string)
+            .or_else(|| row.venue(None));
+        Ok(vec![Record::Transaction(tx)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usecases::importers::parse_import;
+
+    #[test]
+    fn koinly_rows_become_buys_sells_swaps_and_income() {
+        let csv = "Date,Sent Amount,Sent Currency,Received Amount,Received Currency,Fee Amount,Fee Currency,Net Worth Amount,Net Worth Currency,Label,Description,TxHash\n\
+2024-01-01 10:00 UTC,1000,USD,0.025,BTC,1,USD,1000,USD,,,h1\n\
+2024-01-02 10:00 UTC,0.01,BTC,,,,,,,,,\n\
+2024-01-03 10:00 UTC,1,ETH,0.05,BTC,,,,,,,\n\
+2024-01-04 10:00 UTC,,,2,DOT,,,,,staking,,\n\
+2024-01-05 10:00 UTC,,,,,,,,,,,\n";
+        let p = parse_import(csv.as_bytes(), None, Some("Ledger")).unwrap();
+        assert_eq!(p.format, "koinly");
+        let t: Vec<(&str, &str)> = p
+            .transactions
+            .iter()
+            .map(|(_, t)| (t.kind.as_str(), t.symbol.as_str()))
+            .collect();
+        assert_eq!(
+            t,
+            [
+                ("buy", "BTC"),
+                ("withdraw", "BTC"),
+                ("buy", "BTC"),
+                ("income", "DOT")
+            ]
+        );
+        assert_eq!(p.transactions[0].1.price, Some(40000.0));
+        assert_eq!(p.transactions[2].1.quote_currency.as_deref(), Some("ETH"));
+        assert_eq!(p.transactions[3].1.venue.as_deref(), Some("Ledger"));
+        assert_eq!(p.errors.len(), 1);
+    }
+
+    #[test]
+    fn cointracking_reads_repeated_currency_columns_by_position() {
+        let csv = "\"Type\",\"Buy\",\"Cur.\",\"Sell\",\"Cur.\",\"Fee\",\"Cur.\",\"Exchange\",\"Group\",\"Comment\",\"Date\"\n\
+\"Trade\",\"0.5\",\"ETH\",\"1000\",\"EUR\",\"2\",\"EUR\",\"Kraken\",\"\",\"\",\"15.02.2024 08:00:00\"\n\
+\"Trade\",\"300\",\"EUR\",\"0.1\",\"ETH\",\"\",\"\",\"Kraken\",\"\",\"\",\"16.02.2024 08:00:00\"\n\
+\"Staking\",\"0.3\",\"ADA\",\"\",\"\",\"\",\"\",\"\",\"\",\"\",\"17.02.2024 08:00:00\"\n";
+        let p = parse_import(csv.as_bytes(), None, None).unwrap();
+        assert_eq!(p.format, "cointracking");
+        assert!(p.errors.is_empty(), "{:?}", p.errors);
+        let buy = &p.transactions[0].1;
+        assert_eq!((buy.kind.as_str(), buy.symbol.as_str()), ("buy", "ETH"));
+        assert_eq!(buy.price, Some(2000.0));
+        assert_eq!(buy.fee_symbol.as_deref(), Some("EUR"));
+        assert_eq!(buy.venue.as_deref(), Some("Kraken"));
+        let sell = &p.transactions[1].1;
+        assert_eq!((sell.kind.as_str(), sell.price), ("sell", Some(3000.0)));
+        assert_eq!(p.transactions[2].1.income_type.as_deref(), Some("staking"));
+        assert_eq!(p.transactions[2].1.timestamp, "2024-02-17T08:00:00Z");
+    }
+}
@@ -0,0 +1,80 @@
+use super::{Balance, Headers, Importer, Record, Row};
+use crate::domain::models::WalletAllocation;
+
+pub const WALLET_FORMAT: &str = "wallet_allocations";
+
+// Our own wallet_allocations.csv layout: one holding per row with group, barca and target
+pub struct WalletCsv;
+
+impl Importer for WalletCsv {
+    fn name(&self) -> &'static str {
+        WALLET_FORMAT
+    }
+
+    fn description(&self) -> &'static str {
+        "wallet_allocations.csv holdings (symbol, group, barca, target_percent, current_quantity, last_price, comments, account)"
+    }
+
+    fn detect(&self, headers: &Headers) -> bool {
+        headers.has_all(&["symbol"]) && headers.has_any(&["current_quantity", "target_percent"])
+    }
+
+    fn map_row(&self, row: &Row) -> Result<Vec<Record>, String> {
+        let text = |names: &[&str]| row.get(names).map(str::to_string);
+        Ok(vec![Record::Balance(Balance {
+            row: WalletAllocation {
+                id: None,
+                symbol: row.text(&["symbol"])?.to_string(),
+                group_name: text(&["group", "group_name"]),
+                barca: text(&["barca"]),
+                target_percent: row.number(&["target_percent"])?,
+                current_quantity: row.number(&["current_quantity"])?,
+                last_price: row.number(&["last_price", "price"])?,
+                notes: text(&["comments", "notes"]),
+                created_at: None,
+                account_id: None,
+                author: None,
+                reason: None,
+                event: None,
+                import_batch_id: None,
+            },
+            account: text(&["account"]),
+        })])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usecases::importers::{WALLET_FORMAT, parse_import};
+
+    #[test]
+    fn reads_brazilian_spreadsheet_exports() {
+        let csv = "\u{feff}symbol;group;barca;target_percent;current_quantity;comments\n\
+USDT;Holding;Caixa;31,94%;60.000,50;Binance\n\
+BTC;Holding;Base;37,89;0,65;Ledger\n\
+ETH;Holding;Base;dez;1;Ledger\n";
+        let p = parse_import(csv.as_bytes(), Some(WALLET_FORMAT), None).unwrap();
+        assert_eq!(p.dialect.delimiter, ';');
+        let rows: Vec<(u64, &str, Option<f64>, Option<f64>)> = p
+            .balances
+            .iter()
+            .map(|(line, b)| {
+                (
+                    *line,
+                    b.row.symbol.as_str(),
+                    b.row.target_percent,
+                    b.row.current_quantity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (2, "USDT", Some(31.94), Some(60000.5)),
+                (3, "BTC", Some(37.89), Some(0.65))
+            ]
+        );
+        assert_eq!(p.errors[0].line, 4);
+        assert_eq!(p.errors[0].error, "target_percent: invalid number 'dez'");
+    }
+}
@@ -0,0 +1,419 @@
+use crate::domain::models::{Transaction, WalletAllocation};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+// Location used for rows without a venue
+pub const UNSPECIFIED_VENUE: &str = "unspecified";
+// Group/barca given to symbols that only exist in the ledger
+pub const UNASSIGNED: &str = "Unassigned";
+// Quote currencies that are not tracked as holdings (buys/sells do not move them)
+const FIAT: &[&str] = &["USD", "BRL", "EUR"];
+// Sub-types of income rows
+pub const INCOME_TYPES: &[&str] = &["staking", "lending", "airdrop", "other"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+    Deposit,
+    Withdraw,
+    Transfer,
+    Fee,
+    Income,
+}
+
+impl TransactionKind {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "buy" => Ok(Self::Buy),
+            "sell" => Ok(Self::Sell),
+            "deposit" => Ok(Self::Deposit),
+            "withdraw" | "withdrawal" => Ok(Self::Withdraw),
+            "transfer" => Ok(Self::Transfer),
+            "fee" => Ok(Self::Fee),
+            "income" => Ok(Self::Income),
+            other => Err(format!("unsupported transaction kind '{}'", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Buy => "buy",
+            Self::Sell => "sell",
+            Self::Deposit => "deposit",
+            Self::Withdraw => "withdraw",
+            Self::Transfer => "transfer",
+            Self::Fee => "fee",
+            Self::Income => "income",
+        }
+    }
+}
+
+// Where holdings come from when computing allocations (env HOLDINGS_SOURCE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldingsSource {
+    // Manually maintained quantities in wallet_allocations
+    Wallet,
+    // Quantities derived from the transaction ledger
+    Transactions,
+}
+
+impl HoldingsSource {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "wallet" | "manual" => Ok(Self::Wallet),
+            "transactions" | "ledger" => Ok(Self::Transactions),
+            other => Err(format!("unsupported holdings source '{}'", other)),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&std::env::var("HOLDINGS_SOURCE").unwrap_or_default())
+    }
+}
+
+pub fn is_fiat(symbol: &str) -> bool {
+    FIAT.iter().any(|f| f.eq_ignore_ascii_case(symbol))
+}
+
+fn venue_of(v: &Option<String>) -> String {
+    match v.as_deref().map(str::trim) {
+        Some(s) if !s.is_empty() => s.to_string(),
+        _ => UNSPECIFIED_VENUE.to_string(),
+    }
+}
+
+// Normalize and validate a transaction before it is stored
+pub fn validate_transaction(tx: &mut Transaction) -> Result<(), String> {
+    let kind = TransactionKind::parse(&tx.kind)?;
+    tx.kind = kind.as_str().to_string();
+    tx.symbol = tx.symbol.trim().to_ascii_uppercase();
+    if tx.symbol.is_empty() {
+        return Err("symbol is required".to_string());
+    }
+    if crate::usecases::timeseries::parse_ts(&tx.timestamp).is_none() {
+        return Err(format!(
+            "invalid timestamp '{}' (expected RFC3339)",
+            tx.timestamp
+        ));
+    }
+    if !(tx.quantity.is_finite() && tx.quantity > 0.0) {
+        return Err("quantity must be a positive number".to_string());
+    }
+    if tx.price.is_some_and(|p| !p.is_finite() || p < 0.0) {
+        return Err("price must be a non-negative number".to_string());
+    }
+    if tx.fee.is_some_and(|f| !f.is_finite() || f < 0.0) {
+        return Err("fee must be a non-negative number".to_string());
+    }
+    if kind == TransactionKind::Transfer
+        && tx.to_venue.as_deref().is_none_or(|v| v.trim().is_empty())
+    {
+        return Err("transfer requires to_venue".to_string());
+    }
+    if matches!(kind, TransactionKind::Buy | TransactionKind::Sell) && tx.price.is_none() {
+        return Err(format!("{} requires price", kind.as_str()));
+    }
+    tx.income_type = match (
+        kind,
+        tx.income_type
+            .as_deref()
+            .map(|t| t.trim().to_ascii_lowercase()),
+    ) {
+        (TransactionKind::Income, None) => Some("other".to_string()),
+        (TransactionKind::Income, Some(t)) if INCOME_TYPES.contains(&t.as_str()) => Some(t),
+        (TransactionKind::Income, Some(t)) => {
+            return Err(format!(
+                "unsupported income_type '{}' (expected one of {})",
+                t,
+                INCOME_TYPES.join(", ")
+            ));
+        }
+        (_, Some(_)) => return Err("income_type is only valid for income".to_string()),
+        (_, None) => None,
+    };
+    tx.quote_currency = Some(
+        tx.quote_currency
+            .as_deref()
+            .map(|q| q.trim().to_ascii_uppercase())
+            .filter(|q| !q.is_empty())
+            .unwrap_or_else(|| "USD".to_string()),
+    );
+    tx.fee_symbol = tx
+        .fee_symbol
+        .as_deref()
+        .map(|s| s.trim().to_ascii_uppercase());
+    Ok(())
+}
+
+// Quantities per (symbol, venue) derived by replaying the ledger in order.
+// Buys/sells quoted in a non-fiat currency (e.g. USDT) also move the quote balance.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerHoldings {
+    pub positions: BTreeMap<(String, String), f64>,
+    // Transactions that drove a position below zero
+    pub warnings: Vec<String>,
+}
+
+impl LedgerHoldings {
+    fn apply(&mut self, symbol: &str, venue: &str, delta: f64) {
+        *self
+            .positions
+            .entry((symbol.to_string(), venue.to_string()))
+            .or_insert(0.0) += delta;
+    }
+
+    pub fn per_symbol(&self) -> BTreeMap<String, f64> {
+        let mut out = BTreeMap::new();
+        for ((symbol, _), q) in &self.positions {
+            *out.entry(symbol.clone()).or_insert(0.0) += q;
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> Value {
+        let positions: Vec<Value> = self
+            .positions
+            .iter()
+            .filter(|(_, q)| q.abs() > 1e-12)
+            .map(|((symbol, venue), q)| json!({"symbol": symbol, "venue": venue, "quantity": q}))
+            .collect();
+        let totals: Vec<Value> = self
+            .per_symbol()
+            .into_iter()
+            .filter(|(_, q)| q.abs() > 1e-12)
+            .map(|(symbol, q)| json!({"symbol": symbol, "quantity": q}))
+            .collect();
+        json!({"positions": positions, "per_symbol": totals, "warnings": self.warnings})
+    }
+}
+
+pub fn derive_holdings(txs: &[Transaction]) -> LedgerHoldings {
+    let mut h = LedgerHoldings::default();
+    for tx in txs {
+        let Ok(kind) = TransactionKind::parse(&tx.kind) else {
+            h.warnings.push(format!(
+                "transaction {:?}: unknown kind '{}'",
+                tx.id, tx.kind
+            ));
+            continue;
+        };
+        let venue = venue_of(&tx.venue);
+        let quote = tx.quote_currency.as_deref().unwrap_or("USD");
+        let notional = tx.quantity * tx.price.unwrap_or(0.0);
+        let mut touched = vec![(tx.symbol.clone(), venue.clone())];
+        match kind {
+            TransactionKind::Buy => {
+                h.apply(&tx.symbol, &venue, tx.quantity);
+                if !is_fiat(quote) {
+                    h.apply(quote, &venue, -notional);
+                    touched.push((quote.to_string(), venue.clone()));
+                }
+            }
+            TransactionKind::Sell => {
+                h.apply(&tx.symbol, &venue, -tx.quantity);
+                if !is_fiat(quote) {
+                    h.apply(quote, &venue, notional);
+                }
+            }
+            TransactionKind::Deposit | TransactionKind::Income => {
+                h.apply(&tx.symbol, &venue, tx.quantity)
+            }
+            TransactionKind::Withdraw | TransactionKind::Fee => {
+                h.apply(&tx.symbol, &venue, -tx.quantity)
+            }
+            TransactionKind::Transfer => {
+                h.apply(&tx.symbol, &venue, -tx.quantity);
+                h.apply(&tx.symbol, &venue_of(&tx.to_venue), tx.quantity);
+            }
+        }
+        if let Some(fee) = tx.fee.filter(|f| *f > 0.0) {
+            let fee_symbol = tx.fee_symbol.as_deref().unwrap_or(&tx.symbol);
+            if !is_fiat(fee_symbol) {
+                h.apply(fee_symbol, &venue, -fee);
+                touched.push((fee_symbol.to_string(), venue.clone()));
+            }
+        }
+        for key in touched {
+            if h.positions.get(&key).is_some_and(|q| *q < -1e-9) {
+                h.warnings.push(format!(
+                    "transaction {:?} ({} {} {}) leaves {} at {} negative",
+                    tx.id,
+                    kind.as_str(),
+                    tx.quantity,
+                    tx.symbol,
+                    key.0,
+                    key.1
+                ));
+            }
+        }
+    }
+    h
+}
+
+// Replace wallet quantities with ledger-derived ones. A symbol's derived total is split across
+// its existing allocation rows in proportion to their current quantities (evenly when all are
+// zero); symbols only present in the ledger get a row tagged from their latest transaction.
+pub fn apply_ledger_quantities(
+    allocations: &[WalletAllocation],
+    holdings: &LedgerHoldings,
+    txs: &[Transaction],
+) -> Vec<WalletAllocation> {
+    let totals = holdings.per_symbol();
+    let mut by_symbol: HashMap<&str, Vec<&WalletAllocation>> = HashMap::new();
+    for a in allocations {
+        by_symbol.entry(a.symbol.as_str()).or_default().push(a);
+    }
+    let mut out = Vec::with_capacity(allocations.len());
+    for a in allocations {
+        let rows = &by_symbol[a.symbol.as_str()];
+        let total = totals.get(&a.symbol).copied().unwrap_or(0.0).max(0.0);
+        let row_sum: f64 = rows.iter().map(|r| r.current_quantity.unwrap_or(0.0)).sum();
+        let share = if row_sum > 0.0 {
+            a.current_quantity.unwrap_or(0.0) / row_sum
+        } else {
+            1.0 / rows.len() as f64
+        };
+        let mut row = a.clone();
+        row.current_quantity = Some(total * share);
+        out.push(row);
+    }
+    for (symbol, qty) in &totals {
+        if *qty <= 1e-12 || by_symbol.contains_key(symbol.as_str()) || is_fiat(symbol) {
+            continue;
+        }
+        let tagged = txs.iter().rev().find(|t| &t.symbol == symbol);
+        out.push(WalletAllocation {
+            id: None,
+            symbol: symbol.clone(),
+            group_name: Some(
+                tagged
+                    .and_then(|t| t.group_name.clone())
+                    .unwrap_or_else(|| UNASSIGNED.to_string()),
+            ),
+            barca: Some(
+                tagged
+                    .and_then(|t| t.barca.clone())
+                    .unwrap_or_else(|| UNASSIGNED.to_string()),
+            ),
+            target_percent: Some(0.0),
+            current_quantity: Some(*qty),
+            last_price: None,
+            notes: Some("derived from transactions".to_string()),
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(kind: &str, symbol: &str, qty: f64, venue: &str) -> Transaction {
+        Transaction {
+            id: None,
+            timestamp: "2025-01-01T00:00:00Z".to_string(),
+            kind: kind.to_string(),
+            symbol: symbol.to_string(),
+            quantity: qty,
+            price: None,
+            quote_currency: None,
+            fee: None,
+            fee_symbol: None,
+            venue: Some(venue.to_string()),
+            to_venue: None,
+            group_name: None,
+            barca: None,
+            external_id: None,
+            notes: None,
+            created_at: None,
+            income_type: None,
+            account_id: None,
+            to_account_id: None,
+        }
+    }
+
+    #[test]
+    fn replays_ledger_per_symbol_and_venue() {
+        let mut buy = tx("buy", "SOL", 10.0, "binance");
+        buy.price = Some(20.0);
+        buy.quote_currency = Some("USDT".to_string());
+        let mut transfer = tx("transfer", "SOL", 4.0, "binance");
+        transfer.to_venue = Some("ledger".to_string());
+        transfer.fee = Some(0.01);
+        let txs = vec![
+            tx("deposit", "USDT", 500.0, "binance"),
+            buy,
+            transfer,
+            tx("income", "SOL", 0.5, "ledger"),
+        ];
+        let h = derive_holdings(&txs);
+        let get = |s: &str, v: &str| h.positions[&(s.to_string(), v.to_string())];
+        assert!((get("USDT", "binance") - 300.0).abs() < 1e-9);
+        assert!((get("SOL", "binance") - 5.99).abs() < 1e-9);
+        assert!((get("SOL", "ledger") - 4.5).abs() < 1e-9);
+        assert!((h.per_symbol()["SOL"] - 10.49).abs() < 1e-9);
+        assert!(h.warnings.is_empty());
+
+        let oversold = derive_holdings(&[tx("withdraw", "BTC", 1.0, "kraken")]);
+        assert_eq!(oversold.warnings.len(), 1);
+    }
+
+    #[test]
+    fn ledger_quantities_split_across_allocation_rows() {
+        let row = |group: &str, q: f64| WalletAllocation {
+            id: None,
+            symbol: "BTC".to_string(),
+            group_name: Some(group.to_string()),
+            barca: Some("Core".to_string()),
+            target_percent: Some(10.0),
+            current_quantity: Some(q),
+            last_price: None,
+            notes: None,
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        };
+        let allocs = vec![row("A", 1.0), row("B", 3.0)];
+        let mut eth = tx("deposit", "ETH", 2.0, "hw");
+        eth.barca = Some("Growth".to_string());
+        let txs = vec![tx("deposit", "BTC", 2.0, "hw"), eth];
+        let out = apply_ledger_quantities(&allocs, &derive_holdings(&txs), &txs);
+        assert_eq!(out.len(), 3);
+        assert!((out[0].current_quantity.unwrap() - 0.5).abs() < 1e-12);
+        assert!((out[1].current_quantity.unwrap() - 1.5).abs() < 1e-12);
+        assert_eq!(out[2].symbol, "ETH");
+        assert_eq!(out[2].barca.as_deref(), Some("Growth"));
+        assert_eq!(out[2].group_name.as_deref(), Some(UNASSIGNED));
+    }
+
+    #[test]
+    fn validation_normalizes_and_rejects() {
+        let mut t = tx("Withdrawal", "btc", 1.0, "x");
+        validate_transaction(&mut t).unwrap();
+        assert_eq!(t.kind, "withdraw");
+        assert_eq!(t.symbol, "BTC");
+        assert_eq!(t.quote_currency.as_deref(), Some("USD"));
+        assert!(validate_transaction(&mut tx("transfer", "BTC", 1.0, "x")).is_err());
+        assert!(validate_transaction(&mut tx("buy", "BTC", 1.0, "x")).is_err());
+        assert!(validate_transaction(&mut tx("deposit", "BTC", -1.0, "x")).is_err());
+        let mut reward = tx("income", "SOL", 0.1, "x");
+        validate_transaction(&mut reward).unwrap();
+        assert_eq!(reward.income_type.as_deref(), Some("other"));
+        reward.income_type = Some("Staking".to_string());
+        validate_transaction(&mut reward).unwrap();
+        assert_eq!(reward.income_type.as_deref(), Some("staking"));
+        let mut deposit = tx("deposit", "SOL", 1.0, "x");
+        deposit.income_type = Some("staking".to_string());
+        assert!(validate_transaction(&mut deposit).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use crate::domain::models::Transaction;
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::cost_basis::{CostMethod, compute_cost_basis};
+use crate::usecases::custody::link_accounts;
+use crate::usecases::ledger::{derive_holdings, validate_transaction};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct LedgerService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl LedgerService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // Validate every transaction first so a bad row rejects the whole batch, then store in order
+    pub async fn record(
+        &self,
+        mut txs: Vec<Transaction>,
+    ) -> Result<Result<Vec<i64>, Vec<Value>>, Box<dyn std::error::Error + Send + Sync>> {
+        let accounts = if txs
+            .iter()
+            .any(|t| t.account_id.is_some() || t.to_account_id.is_some())
+        {
+            self.repo.fetch_accounts().await?
+        } else {
+            Vec::new()
+        };
+        let errors: Vec<Value> = txs
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, tx)| {
+                link_accounts(tx, &accounts)
+                    .and_then(|_| validate_transaction(tx))
+                    .err()
+                    .map(|e| json!({"index": i, "error": e}))
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Ok(Err(errors));
+        }
+        let mut ids = Vec::with_capacity(txs.len());
+        for tx in &txs {
+            ids.push(self.repo.insert_transaction(tx).await?);
+        }
+        Ok(Ok(ids))
+    }
+
+    pub async fn list(
+        &self,
+        symbol: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let symbol = symbol.map(|s| s.trim().to_ascii_uppercase());
+        let rows = self
+            .repo
+            .fetch_transactions(symbol.as_deref(), from, to)
+            .await?;
+        Ok(json!({"transactions": rows}))
+    }
+
+    // Holdings per symbol and venue, optionally as of a timestamp
+    pub async fn holdings(
+        &self,
+        as_of: Option<&str>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self.repo.fetch_transactions(None, None, as_of).await?;
+        let mut out = derive_holdings(&rows).to_json();
+        if let Some(obj) = out.as_object_mut() {
+            obj.insert("as_of".to_string(), json!(as_of));
+            obj.insert("transactions".to_string(), json!(rows.len()));
+        }
+        Ok(out)
+    }
+
+    // Open lots and realized P&L per symbol; realized YTD counts from `ytd_start`
+    pub async fn cost_basis(
+        &self,
+        method: CostMethod,
+        ytd_start: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self.repo.fetch_transactions(None, None, None).await?;
+        Ok(compute_cost_basis(&rows, method, ytd_start).to_json(method))
+    }
+}
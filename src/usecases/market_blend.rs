@@ -0,0 +1,354 @@
+use crate::domain::models::{Household, Portfolio};
+use chrono::NaiveDate;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+
+// Weighted mix of market regimes (e.g. 70% BullMarket / 30% BearMarket).
+// Weights are always normalized so they sum to 1.0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketBlend {
+    pub weights: Vec<(String, f64)>,
+}
+
+impl MarketBlend {
+    pub fn single(market: &str) -> Self {
+        Self {
+            weights: vec![(market.to_string(), 1.0)],
+        }
+    }
+
+    // Parse a blend spec: either a plain regime name (`BullMarket`) or a
+    // comma separated list of `regime:weight` pairs (`BullMarket:70,BearMarket:30`).
+    // Weights may be given as percentages or fractions; they are normalized.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err("empty market spec".to_string());
+        }
+        if !spec.contains(':') && !spec.contains(',') {
+            return Ok(Self::single(spec));
+        }
+        let mut weights: Vec<(String, f64)> = Vec::new();
+        for part in spec.split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let (name, weight) = match part.split_once(':') {
+                Some((n, w)) => {
+                    let w = w
+                        .trim()
+                        .trim_end_matches('%')
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid weight in market spec: '{}'", part))?;
+                    (n.trim().to_string(), w)
+                }
+                None => (part.to_string(), 1.0),
+            };
+            if weight < 0.0 {
+                return Err(format!("negative weight for market '{}'", name));
+            }
+            match weights.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, w)) => *w += weight,
+                None => weights.push((name, weight)),
+            }
+        }
+        Self::normalized(weights)
+    }
+
+    fn normalized(weights: Vec<(String, f64)>) -> Result<Self, String> {
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        if total <= 0.0 {
+            return Err("market weights must sum to a positive value".to_string());
+        }
+        Ok(Self {
+            weights: weights.into_iter().map(|(n, w)| (n, w / total)).collect(),
+        })
+    }
+
+    // Linear interpolation between two blends: `t = 0` is `self`, `t = 1` is `other`.
+    pub fn interpolate(&self, other: &MarketBlend, t: f64) -> MarketBlend {
+        let t = t.clamp(0.0, 1.0);
+        let mut weights: Vec<(String, f64)> = Vec::new();
+        for (name, w) in &self.weights {
+            weights.push((name.clone(), w * (1.0 - t)));
+        }
+        for (name, w) in &other.weights {
+            match weights.iter_mut().find(|(n, _)| n == name) {
+                Some((_, existing)) => *existing += w * t,
+                None => weights.push((name.clone(), w * t)),
+            }
+        }
+        weights.retain(|(_, w)| *w > 0.0);
+        MarketBlend { weights }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let map: serde_json::Map<String, serde_json::Value> = self
+            .weights
+            .iter()
+            .map(|(n, w)| (n.clone(), json!(w)))
+            .collect();
+        serde_json::Value::Object(map)
+    }
+}
+
+// Gradual move from one blend to another over `days` days starting at `start`.
+#[derive(Debug, Clone)]
+pub struct GlidePath {
+    pub from: MarketBlend,
+    pub to: MarketBlend,
+    pub start: NaiveDate,
+    pub days: u32,
+}
+
+impl GlidePath {
+    // Fraction of the glide path completed at `date` (0.0 before start, 1.0 once finished).
+    pub fn progress(&self, date: NaiveDate) -> f64 {
+        if self.days == 0 {
+            return if date >= self.start { 1.0 } else { 0.0 };
+        }
+        let elapsed = (date - self.start).num_days() as f64;
+        (elapsed / self.days as f64).clamp(0.0, 1.0)
+    }
+
+    pub fn weights_at(&self, date: NaiveDate) -> MarketBlend {
+        self.from.interpolate(&self.to, self.progress(date))
+    }
+}
+
+// What the glide settings are called where they came from, for error messages
+struct GlideNames {
+    to: &'static str,
+    start: &'static str,
+    days: &'static str,
+}
+
+const ENV_NAMES: GlideNames = GlideNames {
+    to: "MARKET_GLIDE_TO",
+    start: "MARKET_GLIDE_START",
+    days: "MARKET_GLIDE_DAYS",
+};
+
+// Portfolio and household fields
+const FIELD_NAMES: GlideNames = GlideNames {
+    to: "market_glide_to",
+    start: "market_glide_start",
+    days: "market_glide_days",
+};
+
+// Market regime selection: a fixed blend, optionally gliding towards another blend.
+#[derive(Debug, Clone)]
+pub struct MarketSelection {
+    pub blend: MarketBlend,
+    pub glide: Option<GlidePath>,
+}
+
+impl MarketSelection {
+    // Build the selection from `CURRENT_MARKET` plus the optional glide path settings
+    // `MARKET_GLIDE_TO`, `MARKET_GLIDE_START` (YYYY-MM-DD) and `MARKET_GLIDE_DAYS`.
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok();
+        Self::from_settings(
+            var("CURRENT_MARKET").as_deref().unwrap_or("BullMarket"),
+            var("MARKET_GLIDE_TO").as_deref(),
+            var("MARKET_GLIDE_START").as_deref(),
+            var("MARKET_GLIDE_DAYS").as_deref(),
+            &ENV_NAMES,
+        )
+    }
+
+    // A portfolio's own regime settings (same syntax as the environment variables); a
+    // portfolio without `market` uses the environment
+    pub fn for_portfolio(p: &Portfolio) -> Result<Self, String> {
+        Self::or_env(
+            p.market.as_deref(),
+            p.market_glide_to.as_deref(),
+            p.market_glide_start.as_deref(),
+            p.market_glide_days,
+        )
+    }
+
+    // Same for a household
+    pub fn for_household(h: &Household) -> Result<Self, String> {
+        Self::or_env(
+            h.market.as_deref(),
+            h.market_glide_to.as_deref(),
+            h.market_glide_start.as_deref(),
+            h.market_glide_days,
+        )
+    }
+
+    fn or_env(
+        market: Option<&str>,
+        glide_to: Option<&str>,
+        glide_start: Option<&str>,
+        glide_days: Option<i64>,
+    ) -> Result<Self, String> {
+        match market.map(str::trim).filter(|m| !m.is_empty()) {
+            Some(spec) => Self::from_settings(
+                spec,
+                glide_to,
+                glide_start,
+                glide_days.map(|d| d.to_string()).as_deref(),
+                &FIELD_NAMES,
+            ),
+            None => Self::from_env(),
+        }
+    }
+
+    fn from_settings(
+        spec: &str,
+        glide_to: Option<&str>,
+        glide_start: Option<&str>,
+        glide_days: Option<&str>,
+        names: &GlideNames,
+    ) -> Result<Self, String> {
+        let blend = MarketBlend::parse(spec)?;
+        let glide = match glide_to.filter(|t| !t.trim().is_empty()) {
+            Some(to_spec) => {
+                let to = MarketBlend::parse(to_spec)?;
+                let start = match glide_start {
+                    Some(s) => NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+                        .map_err(|e| format!("invalid {} '{}': {}", names.start, s, e))?,
+                    None => {
+                        return Err(format!("{} is required with {}", names.start, names.to));
+                    }
+                };
+                let days = match glide_days {
+                    Some(d) => d
+                        .trim()
+                        .parse::<u32>()
+                        .map_err(|_| format!("invalid {} '{}'", names.days, d))?,
+                    None => 30,
+                };
+                Some(GlidePath {
+                    from: blend.clone(),
+                    to,
+                    start,
+                    days,
+                })
+            }
+            None => None,
+        };
+        Ok(Self { blend, glide })
+    }
+
+    // Effective regime weights on `date`.
+    pub fn effective_weights(&self, date: NaiveDate) -> MarketBlend {
+        match &self.glide {
+            Some(g) => g.weights_at(date),
+            None => self.blend.clone(),
+        }
+    }
+
+    pub fn to_json(&self, date: NaiveDate) -> serde_json::Value {
+        json!({
+            "weights": self.effective_weights(date).to_json(),
+            "glide": self.glide.as_ref().map(|g| json!({
+                "from": g.from.to_json(),
+                "to": g.to.to_json(),
+                "start": g.start.to_string(),
+                "days": g.days,
+                "progress": g.progress(date),
+            })),
+        })
+    }
+}
+
+// Interpolate barca targets across regimes using the blend weights.
+// A barca missing from a regime counts as a 0% target in that regime.
+pub fn blend_targets(
+    regimes: &HashMap<String, HashMap<String, f64>>,
+    blend: &MarketBlend,
+) -> Result<HashMap<String, f64>, String> {
+    let mut out: BTreeMap<String, f64> = BTreeMap::new();
+    for (market, weight) in &blend.weights {
+        let targets = regimes
+            .get(market)
+            .ok_or_else(|| format!("unknown market regime '{}'", market))?;
+        for (barca, target) in targets {
+            *out.entry(barca.clone()).or_insert(0.0) += weight * target;
+        }
+    }
+    Ok(out.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regimes() -> HashMap<String, HashMap<String, f64>> {
+        let mut bull = HashMap::new();
+        bull.insert("Base".to_string(), 50.0);
+        bull.insert("Altcoins".to_string(), 30.0);
+        bull.insert("Caixa".to_string(), 20.0);
+        let mut bear = HashMap::new();
+        bear.insert("Base".to_string(), 50.0);
+        bear.insert("Caixa".to_string(), 50.0);
+        let mut r = HashMap::new();
+        r.insert("BullMarket".to_string(), bull);
+        r.insert("BearMarket".to_string(), bear);
+        r
+    }
+
+    #[test]
+    fn parse_plain_and_weighted_specs() {
+        assert_eq!(
+            MarketBlend::parse("BullMarket").unwrap(),
+            MarketBlend::single("BullMarket")
+        );
+        let b = MarketBlend::parse("BullMarket:70, BearMarket:30").unwrap();
+        assert_eq!(b.weights.len(), 2);
+        assert!((b.weights[0].1 - 0.7).abs() < 1e-9);
+        assert!(MarketBlend::parse("BullMarket:x").is_err());
+    }
+
+    #[test]
+    fn blend_interpolates_targets() {
+        let b = MarketBlend::parse("BullMarket:70,BearMarket:30").unwrap();
+        let t = blend_targets(&regimes(), &b).unwrap();
+        assert!((t["Base"] - 50.0).abs() < 1e-9);
+        assert!((t["Altcoins"] - 21.0).abs() < 1e-9);
+        assert!((t["Caixa"] - 29.0).abs() < 1e-9);
+        assert!(blend_targets(&regimes(), &MarketBlend::single("Crab")).is_err());
+    }
+
+    #[test]
+    fn glide_path_moves_weights_over_days() {
+        let glide = GlidePath {
+            from: MarketBlend::single("BullMarket"),
+            to: MarketBlend::single("BearMarket"),
+            start: NaiveDate::from_ymd_opt(2025, 1, 1).unwrap(),
+            days: 10,
+        };
+        let before = glide.weights_at(NaiveDate::from_ymd_opt(2024, 12, 1).unwrap());
+        assert_eq!(before.weights, vec![("BullMarket".to_string(), 1.0)]);
+        let mid = glide.weights_at(NaiveDate::from_ymd_opt(2025, 1, 6).unwrap());
+        let bear = mid
+            .weights
+            .iter()
+            .find(|(n, _)| n == "BearMarket")
+            .unwrap()
+            .1;
+        assert!((bear - 0.5).abs() < 1e-9);
+        let after = glide.weights_at(NaiveDate::from_ymd_opt(2025, 3, 1).unwrap());
+        assert_eq!(after.weights, vec![("BearMarket".to_string(), 1.0)]);
+
+        // Portfolio settings report their own field names
+        let portfolio = Portfolio {
+            id: Some(2),
+            name: "Daniela".to_string(),
+            market: Some("BullMarket".to_string()),
+            market_glide_to: Some("BearMarket".to_string()),
+            market_glide_start: None,
+            market_glide_days: None,
+            notes: None,
+            created_at: None,
+        };
+        assert_eq!(
+            MarketSelection::for_portfolio(&portfolio).unwrap_err(),
+            "market_glide_start is required with market_glide_to"
+        );
+    }
+}
@@ -1,3 +1,33 @@
+pub mod accounts_service;
 pub mod allocations_service;
+pub mod analytics_service;
+pub mod auth_service;
 pub mod compute_allocations;
+pub mod correlation;
+pub mod cost_basis;
+pub mod custody;
+pub mod export_service;
 pub mod history_service;
+pub mod households_service;
+pub mod import_batches;
+pub mod import_service;
+pub mod importers;
+pub mod ledger;
+pub mod ledger_service;
+pub mod market_blend;
+pub mod monte_carlo;
+pub mod portfolios_service;
+pub mod returns;
+pub mod risk_metrics;
+pub mod share_service;
+pub mod simulation;
+pub mod stress_tests;
+pub mod tax_br;
+pub mod tax_service;
+pub mod timeseries;
+pub mod value_at_risk;
+pub mod wallet_changes;
+pub mod wallet_diff;
+pub mod wallet_service;
+pub mod yield_income;
+pub mod yield_service;
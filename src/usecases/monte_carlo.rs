@@ -0,0 +1,407 @@
+use crate::usecases::timeseries::{mean, percentile};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    // Resample whole historical return vectors (keeps cross-asset correlation and fat tails)
+    Bootstrap,
+    // Multivariate normal with the historical mean vector and covariance matrix
+    Parametric,
+}
+
+impl Method {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "bootstrap" => Ok(Method::Bootstrap),
+            "parametric" | "normal" => Ok(Method::Parametric),
+            other => Err(format!("unsupported projection method '{}'", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Bootstrap => "bootstrap",
+            Method::Parametric => "parametric",
+        }
+    }
+}
+
+// Current position used as the starting point of the projection
+#[derive(Debug, Clone)]
+pub struct Holding {
+    pub symbol: String,
+    pub barca: String,
+    pub value: f64,
+    pub target_percent: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectionConfig {
+    pub horizon_periods: usize,
+    pub period_days: i64,
+    pub paths: usize,
+    pub method: Method,
+    // Rebalance back to targets every N periods in the rebalanced strategy
+    pub rebalance_every: usize,
+    pub goal_value: Option<f64>,
+    pub seed: u64,
+}
+
+const BANDS: [f64; 3] = [5.0, 50.0, 95.0];
+const MAX_CHECKPOINTS: usize = 40;
+
+// Lower-triangular Cholesky factor of a covariance matrix; a small ridge is added
+// when the sample covariance is not positive definite (e.g. pegged stablecoins).
+fn cholesky(cov: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = cov.len();
+    let mut ridge = 0.0;
+    loop {
+        let mut l = vec![vec![0.0; n]; n];
+        let mut ok = true;
+        'outer: for i in 0..n {
+            for j in 0..=i {
+                let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+                if i == j {
+                    let d = cov[i][i] + ridge - sum;
+                    if d <= 0.0 {
+                        ok = false;
+                        break 'outer;
+                    }
+                    l[i][j] = d.sqrt();
+                } else {
+                    l[i][j] = (cov[i][j] - sum) / l[j][j];
+                }
+            }
+        }
+        if ok {
+            return l;
+        }
+        ridge = if ridge == 0.0 { 1e-12 } else { ridge * 10.0 };
+    }
+}
+
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    // Box-Muller transform
+    let u1: f64 = rng.r#gen::<f64>().max(f64::MIN_POSITIVE);
+    let u2: f64 = rng.r#gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+struct Sampler {
+    method: Method,
+    history: Vec<Vec<f64>>,
+    means: Vec<f64>,
+    chol: Vec<Vec<f64>>,
+}
+
+impl Sampler {
+    fn new(method: Method, history: &[Vec<f64>], n_assets: usize) -> Self {
+        let cols: Vec<Vec<f64>> = (0..n_assets)
+            .map(|a| history.iter().map(|r| r[a]).collect())
+            .collect();
+        let means: Vec<f64> = cols.iter().map(|c| mean(c)).collect();
+        let chol = if method == Method::Parametric {
+            let n = history.len().max(2) as f64;
+            let cov: Vec<Vec<f64>> = (0..n_assets)
+                .map(|i| {
+                    (0..n_assets)
+                        .map(|j| {
+                            cols[i]
+                                .iter()
+                                .zip(&cols[j])
+                                .map(|(x, y)| (x - means[i]) * (y - means[j]))
+                                .sum::<f64>()
+                                / (n - 1.0)
+                        })
+                        .collect()
+                })
+                .collect();
+            cholesky(&cov)
+        } else {
+            Vec::new()
+        };
+        Self {
+            method,
+            history: history.to_vec(),
+            means,
+            chol,
+        }
+    }
+
+    fn draw(&self, rng: &mut StdRng) -> Vec<f64> {
+        match self.method {
+            Method::Bootstrap => self.history[rng.gen_range(0..self.history.len())].clone(),
+            Method::Parametric => {
+                let z: Vec<f64> = (0..self.means.len())
+                    .map(|_| standard_normal(rng))
+                    .collect();
+                self.means
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| {
+                        let shock: f64 = (0..=i).map(|k| self.chol[i][k] * z[k]).sum();
+                        // a price cannot fall below zero
+                        (m + shock).max(-0.99)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// Per-strategy outcome collected at every checkpoint across paths
+struct Collector {
+    totals: Vec<Vec<f64>>,
+    barca_weights: BTreeMap<String, Vec<Vec<f64>>>,
+    max_drift: Vec<Vec<f64>>,
+}
+
+impl Collector {
+    fn new(checkpoints: usize, barcas: &[String]) -> Self {
+        Self {
+            totals: vec![Vec::new(); checkpoints],
+            barca_weights: barcas
+                .iter()
+                .map(|b| (b.clone(), vec![Vec::new(); checkpoints]))
+                .collect(),
+            max_drift: vec![Vec::new(); checkpoints],
+        }
+    }
+
+    fn record(
+        &mut self,
+        cp: usize,
+        values: &[f64],
+        holdings: &[Holding],
+        barca_targets: &BTreeMap<String, f64>,
+    ) {
+        let total: f64 = values.iter().sum();
+        self.totals[cp].push(total);
+        let mut drift: f64 = 0.0;
+        for (barca, series) in self.barca_weights.iter_mut() {
+            let value: f64 = holdings
+                .iter()
+                .zip(values)
+                .filter(|(h, _)| &h.barca == barca)
+                .map(|(_, v)| v)
+                .sum();
+            let weight = if total > 0.0 {
+                value / total * 100.0
+            } else {
+                0.0
+            };
+            series[cp].push(weight);
+            drift = drift.max((weight - barca_targets.get(barca).copied().unwrap_or(0.0)).abs());
+        }
+        self.max_drift[cp].push(drift);
+    }
+
+    fn bands(samples: &[f64]) -> Value {
+        json!({
+            "p5": percentile(samples, BANDS[0]),
+            "p50": percentile(samples, BANDS[1]),
+            "p95": percentile(samples, BANDS[2]),
+        })
+    }
+
+    fn report(&self, days: &[i64], goal_value: Option<f64>) -> Value {
+        let series = |data: &Vec<Vec<f64>>| -> Vec<Value> {
+            days.iter()
+                .zip(data)
+                .map(|(d, samples)| {
+                    let mut b = Self::bands(samples);
+                    b["day"] = json!(d);
+                    b
+                })
+                .collect()
+        };
+        let finals = self.totals.last().cloned().unwrap_or_default();
+        let goal_probability = goal_value.map(|g| {
+            if finals.is_empty() {
+                0.0
+            } else {
+                finals.iter().filter(|v| **v >= g).count() as f64 / finals.len() as f64
+            }
+        });
+        json!({
+            "final_value": Self::bands(&finals),
+            "goal_probability": goal_probability,
+            "total_value": series(&self.totals),
+            "max_barca_drift_percent": series(&self.max_drift),
+            "per_barca_weight_percent": self
+                .barca_weights
+                .iter()
+                .map(|(b, data)| (b.clone(), json!(series(data))))
+                .collect::<serde_json::Map<String, Value>>(),
+        })
+    }
+}
+
+// Simulate `paths` futures of the holdings over the horizon using historical per-period
+// return vectors (`history[period][asset]`, assets aligned with `holdings`). Each path is
+// run twice with the same draws: buy-and-hold and periodic rebalancing to targets.
+pub fn project(
+    holdings: &[Holding],
+    history: &[Vec<f64>],
+    cfg: &ProjectionConfig,
+) -> Result<Value, String> {
+    if holdings.is_empty() {
+        return Err("no holdings to project".to_string());
+    }
+    if history.len() < 2 {
+        return Err("not enough return history to project (need at least 2 periods)".to_string());
+    }
+    if history.iter().any(|r| r.len() != holdings.len()) {
+        return Err("return history does not match holdings".to_string());
+    }
+
+    let initial: Vec<f64> = holdings.iter().map(|h| h.value).collect();
+    let initial_total: f64 = initial.iter().sum();
+    let target_sum: f64 = holdings.iter().map(|h| h.target_percent).sum();
+    // rebalance to asset targets; fall back to the starting weights when no targets are set
+    let target_weights: Vec<f64> = if target_sum > 0.0 {
+        holdings
+            .iter()
+            .map(|h| h.target_percent / target_sum)
+            .collect()
+    } else {
+        initial.iter().map(|v| v / initial_total).collect()
+    };
+    let mut barca_targets: BTreeMap<String, f64> = BTreeMap::new();
+    for (h, w) in holdings.iter().zip(&target_weights) {
+        *barca_targets.entry(h.barca.clone()).or_insert(0.0) += w * 100.0;
+    }
+    let barcas: Vec<String> = barca_targets.keys().cloned().collect();
+
+    let step = cfg.horizon_periods.div_ceil(MAX_CHECKPOINTS).max(1);
+    let mut checkpoint_periods: Vec<usize> = (0..=cfg.horizon_periods).step_by(step).collect();
+    if checkpoint_periods.last() != Some(&cfg.horizon_periods) {
+        checkpoint_periods.push(cfg.horizon_periods);
+    }
+    let days: Vec<i64> = checkpoint_periods
+        .iter()
+        .map(|p| *p as i64 * cfg.period_days)
+        .collect();
+
+    let sampler = Sampler::new(cfg.method, history, holdings.len());
+    let mut rng = StdRng::seed_from_u64(cfg.seed);
+    let mut hold = Collector::new(checkpoint_periods.len(), &barcas);
+    let mut rebalanced = Collector::new(checkpoint_periods.len(), &barcas);
+
+    for _ in 0..cfg.paths {
+        let mut v_hold = initial.clone();
+        let mut v_reb = initial.clone();
+        let mut cp = 0;
+        for period in 0..=cfg.horizon_periods {
+            if period > 0 {
+                let r = sampler.draw(&mut rng);
+                for (i, ri) in r.iter().enumerate() {
+                    v_hold[i] *= 1.0 + ri;
+                    v_reb[i] *= 1.0 + ri;
+                }
+                if cfg.rebalance_every > 0 && period % cfg.rebalance_every == 0 {
+                    let total: f64 = v_reb.iter().sum();
+                    for (v, w) in v_reb.iter_mut().zip(&target_weights) {
+                        *v = total * w;
+                    }
+                }
+            }
+            if checkpoint_periods.get(cp) == Some(&period) {
+                hold.record(cp, &v_hold, holdings, &barca_targets);
+                rebalanced.record(cp, &v_reb, holdings, &barca_targets);
+                cp += 1;
+            }
+        }
+    }
+
+    Ok(json!({
+        "method": cfg.method.as_str(),
+        "paths": cfg.paths,
+        "horizon_days": cfg.horizon_periods as i64 * cfg.period_days,
+        "period_days": cfg.period_days,
+        "rebalance_every_days": cfg.rebalance_every as i64 * cfg.period_days,
+        "history_periods": history.len(),
+        "initial_value": initial_total,
+        "goal_value": cfg.goal_value,
+        "barca_targets_percent": barca_targets,
+        "buy_and_hold": hold.report(&days, cfg.goal_value),
+        "rebalanced": rebalanced.report(&days, cfg.goal_value),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holdings() -> Vec<Holding> {
+        vec![
+            Holding {
+                symbol: "BTC".into(),
+                barca: "Base".into(),
+                value: 50.0,
+                target_percent: 50.0,
+            },
+            Holding {
+                symbol: "USDT".into(),
+                barca: "Caixa".into(),
+                value: 50.0,
+                target_percent: 50.0,
+            },
+        ]
+    }
+
+    fn config(method: Method) -> ProjectionConfig {
+        ProjectionConfig {
+            horizon_periods: 30,
+            period_days: 1,
+            paths: 200,
+            method,
+            rebalance_every: 7,
+            goal_value: Some(100.0),
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn constant_growth_is_deterministic() {
+        // BTC grows 1% every period, USDT flat: every bootstrap path is identical
+        let history = vec![vec![0.01, 0.0]; 10];
+        let out = project(&holdings(), &history, &config(Method::Bootstrap)).unwrap();
+        let hold = &out["buy_and_hold"]["final_value"];
+        let expected = 50.0 * 1.01f64.powi(30) + 50.0;
+        assert!((hold["p5"].as_f64().unwrap() - expected).abs() < 1e-9);
+        assert!((hold["p95"].as_f64().unwrap() - expected).abs() < 1e-9);
+        assert_eq!(out["buy_and_hold"]["goal_probability"].as_f64(), Some(1.0));
+        // rebalancing keeps the barca weights closer to target than buy-and-hold
+        let drift = |s: &str| {
+            out[s]["max_barca_drift_percent"]
+                .as_array()
+                .unwrap()
+                .last()
+                .unwrap()["p50"]
+                .as_f64()
+                .unwrap()
+        };
+        assert!(drift("rebalanced") < drift("buy_and_hold"));
+    }
+
+    #[test]
+    fn parametric_is_seeded_and_banded() {
+        let history = vec![
+            vec![0.05, 0.0],
+            vec![-0.04, 0.001],
+            vec![0.02, -0.001],
+            vec![-0.03, 0.0],
+        ];
+        let a = project(&holdings(), &history, &config(Method::Parametric)).unwrap();
+        let b = project(&holdings(), &history, &config(Method::Parametric)).unwrap();
+        assert_eq!(a, b);
+        let f = &a["rebalanced"]["final_value"];
+        assert!(f["p5"].as_f64().unwrap() <= f["p50"].as_f64().unwrap());
+        assert!(f["p50"].as_f64().unwrap() <= f["p95"].as_f64().unwrap());
+        assert!(project(&holdings(), &history[..1], &config(Method::Bootstrap)).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use crate::csv_store::{AllocationStore, FileCsvStore};
+use crate::domain::models::{BarcaTarget, DEFAULT_PORTFOLIO_ID, Portfolio};
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::market_blend::MarketSelection;
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+pub struct PortfoliosService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+// Trimmed name, blank settings dropped and the regime settings checked by parsing them
+pub fn validate_portfolio(p: &mut Portfolio) -> Result<(), String> {
+    p.name = p.name.trim().to_string();
+    if p.name.is_empty() {
+        return Err("name is required".to_string());
+    }
+    if !trim_market_settings(
+        &mut p.market,
+        &mut p.market_glide_to,
+        &mut p.market_glide_start,
+    )? {
+        return Ok(());
+    }
+    MarketSelection::for_portfolio(p).map(|_| ())
+}
+
+// Blank regime settings dropped; true when a market is set (and the settings need parsing)
+pub fn trim_market_settings(
+    market: &mut Option<String>,
+    glide_to: &mut Option<String>,
+    glide_start: &mut Option<String>,
+) -> Result<bool, String> {
+    for field in [&mut *market, &mut *glide_to, &mut *glide_start] {
+        *field = field
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string);
+    }
+    if market.is_none() && (glide_to.is_some() || glide_start.is_some()) {
+        return Err("market_glide_to and market_glide_start need a market".to_string());
+    }
+    Ok(market.is_some())
+}
+
+// Non-empty names, percentages within 0-100 and one row per market and BARCA
+pub fn validate_targets(rows: &mut [BarcaTarget]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for t in rows.iter_mut() {
+        t.market = t.market.trim().to_string();
+        t.barca = t.barca.trim().to_string();
+        if t.market.is_empty() || t.barca.is_empty() {
+            return Err("market and barca are required".to_string());
+        }
+        if !(0.0..=100.0).contains(&t.target_percent) {
+            return Err(format!(
+                "{} / {}: target_percent {} is not between 0 and 100",
+                t.market, t.barca, t.target_percent
+            ));
+        }
+        if !seen.insert((t.market.to_lowercase(), t.barca.to_lowercase())) {
+            return Err(format!("{} / {} is listed twice", t.market, t.barca));
+        }
+    }
+    Ok(())
+}
+
+// market -> (barca -> target_percent) for the repo's portfolio: its own targets, or
+// wallet_barca.csv while it has none
+pub async fn barca_regimes(
+    repo: &dyn HistoryRepo,
+) -> Result<HashMap<String, HashMap<String, f64>>, Box<dyn std::error::Error + Send + Sync>> {
+    regimes_or_default(repo.fetch_barca_targets().await?)
+}
+
+// Stored target rows as market -> (barca -> target_percent); wallet_barca.csv when there are none
+pub fn regimes_or_default(
+    rows: Vec<BarcaTarget>,
+) -> Result<HashMap<String, HashMap<String, f64>>, Box<dyn std::error::Error + Send + Sync>> {
+    if rows.is_empty() {
+        return FileCsvStore.read_barca_regimes("wallet_barca.csv");
+    }
+    let mut regimes: HashMap<String, HashMap<String, f64>> = HashMap::new();
+    for t in rows {
+        regimes
+            .entry(t.market)
+            .or_default()
+            .insert(t.barca, t.target_percent);
+    }
+    Ok(regimes)
+}
+
+// Target rows sorted by market and BARCA
+pub fn target_rows(regimes: HashMap<String, HashMap<String, f64>>) -> Vec<BarcaTarget> {
+    let mut rows: Vec<BarcaTarget> = regimes
+        .into_iter()
+        .flat_map(|(market, targets)| {
+            targets
+                .into_iter()
+                .map(move |(barca, target_percent)| BarcaTarget {
+                    market: market.clone(),
+                    barca,
+                    target_percent,
+                })
+        })
+        .collect();
+    rows.sort_by(|a, b| (&a.market, &a.barca).cmp(&(&b.market, &b.barca)));
+    rows
+}
+
+impl PortfoliosService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    pub async fn list(&self) -> Result<Vec<Portfolio>, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self.repo.fetch_portfolios().await?;
+        Ok(rows)
+    }
+
+    pub async fn get(
+        &self,
+        id: i64,
+    ) -> Result<Option<Portfolio>, Box<dyn std::error::Error + Send + Sync>> {
+        let p = self.repo.fetch_portfolio(id).await?;
+        Ok(p)
+    }
+
+    pub async fn create(
+        &self,
+        mut p: Portfolio,
+    ) -> Result<Result<Portfolio, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_portfolio(&mut p) {
+            return Ok(Err(e));
+        }
+        if let Some(e) = self.name_taken(&p.name, None).await? {
+            return Ok(Err(e));
+        }
+        let id = self.repo.insert_portfolio(&p).await?;
+        Ok(Ok(self.repo.fetch_portfolio(id).await?.unwrap_or(p)))
+    }
+
+    // Ok(Ok(None)) when the portfolio does not exist
+    pub async fn update(
+        &self,
+        id: i64,
+        mut p: Portfolio,
+    ) -> Result<Result<Option<Portfolio>, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_portfolio(&mut p) {
+            return Ok(Err(e));
+        }
+        if let Some(e) = self.name_taken(&p.name, Some(id)).await? {
+            return Ok(Err(e));
+        }
+        p.id = Some(id);
+        if !self.repo.update_portfolio(&p).await? {
+            return Ok(Ok(None));
+        }
+        let stored = self.repo.fetch_portfolio(id).await?;
+        Ok(Ok(stored))
+    }
+
+    // Only an empty portfolio other than the default one can be deleted (Ok(Err(reason)))
+    pub async fn delete(
+        &self,
+        id: i64,
+    ) -> Result<Result<bool, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if id == DEFAULT_PORTFOLIO_ID {
+            return Ok(Err("the default portfolio cannot be deleted".to_string()));
+        }
+        let refs = self.repo.count_portfolio_references(id).await?;
+        if refs > 0 {
+            return Ok(Err(format!(
+                "portfolio {} still has {} wallet, transaction or import rows",
+                id, refs
+            )));
+        }
+        let deleted = self.repo.delete_portfolio(id).await?;
+        Ok(Ok(deleted))
+    }
+
+    // The repo portfolio's BARCA targets and where they come from
+    pub async fn targets(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let own = self.repo.fetch_barca_targets().await?;
+        let source = if own.is_empty() {
+            "wallet_barca.csv"
+        } else {
+            "portfolio"
+        };
+        Ok(json!({
+            "portfolio_id": self.repo.portfolio_id(),
+            "source": source,
+            "targets": target_rows(regimes_or_default(own)?),
+        }))
+    }
+
+    pub async fn set_targets(
+        &self,
+        mut rows: Vec<BarcaTarget>,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_targets(&mut rows) {
+            return Ok(Err(e));
+        }
+        self.repo.replace_barca_targets(&rows).await?;
+        Ok(Ok(self.targets().await?))
+    }
+
+    async fn name_taken(
+        &self,
+        name: &str,
+        except: Option<i64>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let portfolios = self.repo.fetch_portfolios().await?;
+        Ok(portfolios
+            .iter()
+            .any(|p| p.name.eq_ignore_ascii_case(name) && p.id != except)
+            .then(|| format!("a portfolio named '{}' already exists", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn portfolio(name: &str) -> Portfolio {
+        Portfolio {
+            id: None,
+            name: name.to_string(),
+            market: None,
+            market_glide_to: None,
+            market_glide_start: None,
+            market_glide_days: None,
+            notes: None,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn portfolio_market_settings_are_checked() {
+        let mut p = portfolio("  Daniela ");
+        p.market = Some(" ".to_string());
+        validate_portfolio(&mut p).unwrap();
+        assert_eq!((p.name.as_str(), p.market.as_deref()), ("Daniela", None));
+
+        p.market = Some("BullMarket:60,BearMarket:40".to_string());
+        p.market_glide_to = Some("BearMarket".to_string());
+        assert!(validate_portfolio(&mut p).is_err(), "glide needs a start");
+        p.market_glide_start = Some("2026-01-01".to_string());
+        p.market_glide_days = Some(90);
+        validate_portfolio(&mut p).unwrap();
+        let sel = MarketSelection::for_portfolio(&p).unwrap();
+        assert_eq!(sel.glide.unwrap().days, 90);
+
+        let mut orphan = portfolio("Test");
+        orphan.market_glide_to = Some("BearMarket".to_string());
+        assert!(validate_portfolio(&mut orphan).is_err());
+        assert!(validate_portfolio(&mut portfolio(" ")).is_err());
+    }
+
+    #[test]
+    fn targets_need_names_ranges_and_no_duplicates() {
+        let target = |market: &str, barca: &str, pct: f64| BarcaTarget {
+            market: market.to_string(),
+            barca: barca.to_string(),
+            target_percent: pct,
+        };
+        let mut ok = vec![
+            target(" BullMarket", "Base ", 60.0),
+            target("BullMarket", "Caixa", 40.0),
+        ];
+        validate_targets(&mut ok).unwrap();
+        assert_eq!(ok[0].barca, "Base");
+        assert!(validate_targets(&mut [target("BullMarket", "Base", 120.0)]).is_err());
+        assert!(validate_targets(&mut [target("", "Base", 10.0)]).is_err());
+        assert!(
+            validate_targets(&mut [
+                target("BullMarket", "Base", 10.0),
+                target("bullmarket", "base", 20.0)
+            ])
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use crate::domain::models::{AssetHistoryRow, Transaction};
+use crate::usecases::ledger::{TransactionKind, is_fiat};
+use crate::usecases::timeseries::parse_ts;
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+const DAYS_PER_YEAR: f64 = 365.0;
+
+// Where external cash flows come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowSource {
+    // Deposits/withdrawals and fiat buys/sells from the transaction ledger
+    Ledger,
+    // Quantity changes between snapshots priced at the later snapshot's price
+    Inferred,
+    // Ledger when it has rows, otherwise inferred
+    Auto,
+}
+
+impl FlowSource {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ledger" | "transactions" => Ok(Self::Ledger),
+            "inferred" | "snapshots" => Ok(Self::Inferred),
+            "auto" | "" => Ok(Self::Auto),
+            other => Err(format!("unsupported flow source '{}'", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ledger => "ledger",
+            Self::Inferred => "inferred",
+            Self::Auto => "auto",
+        }
+    }
+}
+
+// One snapshot of a scope: value after the period and the net external flow during it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowPoint {
+    pub at: DateTime<Utc>,
+    pub value: f64,
+    pub flow: f64,
+}
+
+// Time-weighted return: chain sub-period returns with each flow assumed at the end of its period
+pub fn twr(points: &[FlowPoint]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let mut growth = 1.0;
+    let mut periods = 0;
+    for w in points.windows(2) {
+        let (prev, cur) = (&w[0], &w[1]);
+        if prev.value <= 0.0 {
+            continue;
+        }
+        growth *= (cur.value - cur.flow) / prev.value;
+        periods += 1;
+    }
+    (periods > 0).then_some(growth - 1.0)
+}
+
+fn npv(flows: &[(f64, f64)], rate: f64) -> f64 {
+    flows.iter().map(|(t, cf)| cf / (1.0 + rate).powf(*t)).sum()
+}
+
+// Money-weighted annual return (XIRR) of dated cash flows from the investor's point of view
+// (contributions negative, withdrawals and the final value positive). Bisection on the NPV.
+pub fn xirr(flows: &[(DateTime<Utc>, f64)]) -> Option<f64> {
+    let first = flows.iter().map(|(t, _)| *t).min()?;
+    let years: Vec<(f64, f64)> = flows
+        .iter()
+        .map(|(t, cf)| {
+            (
+                (*t - first).num_seconds() as f64 / (DAYS_PER_YEAR * 86_400.0),
+                *cf,
+            )
+        })
+        .collect();
+    let has_pos = years.iter().any(|(_, cf)| *cf > 0.0);
+    let has_neg = years.iter().any(|(_, cf)| *cf < 0.0);
+    if !has_pos || !has_neg || years.iter().all(|(t, _)| *t == 0.0) {
+        return None;
+    }
+    let (mut lo, mut hi) = (-0.9999, 1.0);
+    while npv(&years, hi) > 0.0 {
+        hi *= 2.0;
+        if hi > 1e6 {
+            return None;
+        }
+    }
+    if npv(&years, lo) < 0.0 {
+        return None;
+    }
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        if npv(&years, mid) > 0.0 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+        if hi - lo < 1e-10 {
+            break;
+        }
+    }
+    Some((lo + hi) / 2.0)
+}
+
+// Snapshot rows grouped by timestamp, oldest first
+fn snapshots(rows: &[AssetHistoryRow]) -> Vec<(DateTime<Utc>, Vec<&AssetHistoryRow>)> {
+    let mut by_ts: BTreeMap<DateTime<Utc>, Vec<&AssetHistoryRow>> = BTreeMap::new();
+    for r in rows {
+        if let Some(t) = parse_ts(&r.timestamp) {
+            by_ts.entry(t).or_default().push(r);
+        }
+    }
+    by_ts.into_iter().collect()
+}
+
+fn symbol_quantities(rows: &[&AssetHistoryRow]) -> HashMap<String, f64> {
+    let mut out = HashMap::new();
+    for r in rows {
+        *out.entry(r.symbol.clone()).or_insert(0.0) += r.current_quantity.unwrap_or(0.0);
+    }
+    out
+}
+
+// Signed quantity of external flow for a ledger row (income, fees, transfers and swaps are not flows)
+fn ledger_flow_quantity(tx: &Transaction) -> f64 {
+    let quote = tx.quote_currency.as_deref().unwrap_or("USD");
+    match TransactionKind::parse(&tx.kind) {
+        Ok(TransactionKind::Deposit) => tx.quantity,
+        Ok(TransactionKind::Withdraw) => -tx.quantity,
+        Ok(TransactionKind::Buy) if is_fiat(quote) => tx.quantity,
+        Ok(TransactionKind::Sell) if is_fiat(quote) => -tx.quantity,
+        _ => 0.0,
+    }
+}
+
+// Value and external flow per snapshot for the portfolio, each barca and each group.
+// A symbol's flow is split across its rows in the snapshot by quantity (by the previous snapshot's
+// rows when the symbol was fully removed).
+pub fn scope_flow_points(
+    rows: &[AssetHistoryRow],
+    txs: Option<&[Transaction]>,
+) -> BTreeMap<(String, String), Vec<FlowPoint>> {
+    let snaps = snapshots(rows);
+    let mut out: BTreeMap<(String, String), Vec<FlowPoint>> = BTreeMap::new();
+    let mut prev_rows: Vec<&AssetHistoryRow> = Vec::new();
+    let mut prev_ts: Option<DateTime<Utc>> = None;
+    for (ts, cur_rows) in &snaps {
+        let prices: HashMap<&str, f64> = cur_rows
+            .iter()
+            .chain(prev_rows.iter())
+            .filter_map(|r| r.price.map(|p| (r.symbol.as_str(), p)))
+            .rev()
+            .collect();
+        // flows per symbol (in value) during (prev_ts, ts]
+        let mut symbol_flow: HashMap<String, f64> = HashMap::new();
+        if let Some(prev) = prev_ts {
+            match txs {
+                Some(txs) => {
+                    for tx in txs {
+                        let in_period =
+                            parse_ts(&tx.timestamp).is_some_and(|t| t > prev && t <= *ts);
+                        let qty = ledger_flow_quantity(tx);
+                        if in_period && qty != 0.0 {
+                            let price = prices
+                                .get(tx.symbol.as_str())
+                                .copied()
+                                .or(tx.price)
+                                .unwrap_or(0.0);
+                            *symbol_flow.entry(tx.symbol.clone()).or_insert(0.0) += qty * price;
+                        }
+                    }
+                }
+                None => {
+                    let before = symbol_quantities(&prev_rows);
+                    let after = symbol_quantities(cur_rows);
+                    for symbol in before.keys().chain(after.keys()) {
+                        let dq = after.get(symbol).copied().unwrap_or(0.0)
+                            - before.get(symbol).copied().unwrap_or(0.0);
+                        let price = prices.get(symbol.as_str()).copied().unwrap_or(0.0);
+                        symbol_flow.insert(symbol.clone(), dq * price);
+                    }
+                }
+            }
+        }
+
+        let mut points: HashMap<(String, String), (f64, f64)> = HashMap::new();
+        points.insert(("portfolio".to_string(), String::new()), (0.0, 0.0));
+        for r in cur_rows {
+            let v = r.value.unwrap_or(0.0);
+            for key in scope_keys(r) {
+                points.entry(key).or_default().0 += v;
+            }
+        }
+        for (symbol, flow) in &symbol_flow {
+            points
+                .entry(("portfolio".to_string(), String::new()))
+                .or_default()
+                .1 += flow;
+            let holders: Vec<&&AssetHistoryRow> = {
+                let cur: Vec<_> = cur_rows.iter().filter(|r| &r.symbol == symbol).collect();
+                if cur.is_empty() {
+                    prev_rows.iter().filter(|r| &r.symbol == symbol).collect()
+                } else {
+                    cur
+                }
+            };
+            let total_q: f64 = holders
+                .iter()
+                .map(|r| r.current_quantity.unwrap_or(0.0))
+                .sum();
+            for r in &holders {
+                let share = if total_q > 0.0 {
+                    r.current_quantity.unwrap_or(0.0) / total_q
+                } else {
+                    1.0 / holders.len() as f64
+                };
+                for key in scope_keys(r).into_iter().skip(1) {
+                    points.entry(key).or_default().1 += flow * share;
+                }
+            }
+        }
+        for (key, (value, flow)) in points {
+            out.entry(key).or_default().push(FlowPoint {
+                at: *ts,
+                value,
+                flow,
+            });
+        }
+        prev_rows = cur_rows.clone();
+        prev_ts = Some(*ts);
+    }
+    out
+}
+
+fn scope_keys(r: &AssetHistoryRow) -> Vec<(String, String)> {
+    vec![
+        ("portfolio".to_string(), String::new()),
+        ("barca".to_string(), r.barca.clone().unwrap_or_default()),
+        (
+            "group".to_string(),
+            r.group_name.clone().unwrap_or_default(),
+        ),
+    ]
+}
+
+// TWR, XIRR and flow totals for one scope's series
+pub fn returns_summary(points: &[FlowPoint]) -> Value {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return json!({"observations": 0});
+    };
+    let days = (last.at - first.at).num_seconds() as f64 / 86_400.0;
+    let annualize = |r: f64| {
+        (days >= DAYS_PER_YEAR && r > -1.0).then(|| (1.0 + r).powf(DAYS_PER_YEAR / days) - 1.0)
+    };
+    let time_weighted = twr(points);
+    let mut cash_flows = vec![(first.at, -first.value)];
+    cash_flows.extend(
+        points
+            .iter()
+            .skip(1)
+            .filter(|p| p.flow != 0.0)
+            .map(|p| (p.at, -p.flow)),
+    );
+    cash_flows.push((last.at, last.value));
+    let money_weighted = xirr(&cash_flows);
+    let net_flows: f64 = points.iter().skip(1).map(|p| p.flow).sum();
+    json!({
+        "observations": points.len(),
+        "start": first.at.to_rfc3339(),
+        "end": last.at.to_rfc3339(),
+        "start_value": first.value,
+        "end_value": last.value,
+        "net_flows": net_flows,
+        "gain": last.value - first.value - net_flows,
+        "twr_percent": time_weighted.map(|r| r * 100.0),
+        "twr_annualized_percent": time_weighted.and_then(annualize).map(|r| r * 100.0),
+        "xirr_percent": money_weighted.map(|r| r * 100.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        parse_ts(s).unwrap()
+    }
+
+    fn row(ts: &str, qty: f64, price: f64) -> AssetHistoryRow {
+        AssetHistoryRow {
+            timestamp: ts.to_string(),
+            symbol: "BTC".to_string(),
+            group_name: Some("G".to_string()),
+            barca: Some("B".to_string()),
+            price: Some(price),
+            current_quantity: Some(qty),
+            value: Some(qty * price),
+            target_percent: None,
+            current_percent: None,
+            deviation_percent: None,
+            value_deviation: None,
+        }
+    }
+
+    #[test]
+    fn deposits_do_not_count_as_gains() {
+        // price +10%, then 1 BTC added, then price -10%
+        let rows = vec![
+            row("2025-01-01T00:00:00+00:00", 1.0, 100.0),
+            row("2025-02-01T00:00:00+00:00", 2.0, 110.0),
+            row("2025-03-01T00:00:00+00:00", 2.0, 99.0),
+        ];
+        let points = scope_flow_points(&rows, None);
+        let total = &points[&("portfolio".to_string(), String::new())];
+        assert!((total[1].flow - 110.0).abs() < 1e-9);
+        let r = twr(total).unwrap();
+        assert!((r - (1.1 * 0.9 - 1.0)).abs() < 1e-12);
+        let barca = &points[&("barca".to_string(), "B".to_string())];
+        assert!((twr(barca).unwrap() - r).abs() < 1e-12);
+
+        let deposit = Transaction {
+            id: None,
+            timestamp: "2025-01-15T00:00:00Z".to_string(),
+            kind: "deposit".to_string(),
+            symbol: "BTC".to_string(),
+            quantity: 1.0,
+            price: None,
+            quote_currency: None,
+            fee: None,
+            fee_symbol: None,
+            venue: None,
+            to_venue: None,
+            group_name: None,
+            barca: None,
+            external_id: None,
+            notes: None,
+            created_at: None,
+            income_type: None,
+            account_id: None,
+            to_account_id: None,
+        };
+        let from_ledger = scope_flow_points(&rows, Some(&[deposit]));
+        assert_eq!(
+            from_ledger[&("portfolio".to_string(), String::new())],
+            *total
+        );
+    }
+
+    #[test]
+    fn xirr_matches_simple_annual_growth() {
+        let flows = vec![
+            (at("2024-01-01T00:00:00Z"), -1000.0),
+            (at("2024-12-31T00:00:00Z"), 1100.0),
+        ];
+        assert!((xirr(&flows).unwrap() - 0.1).abs() < 1e-3);
+        assert!(xirr(&[(at("2024-01-01T00:00:00Z"), -1.0)]).is_none());
+        let summary = returns_summary(&[
+            FlowPoint {
+                at: at("2024-01-01T00:00:00Z"),
+                value: 1000.0,
+                flow: 0.0,
+            },
+            FlowPoint {
+                at: at("2024-07-01T00:00:00Z"),
+                value: 2100.0,
+                flow: 1000.0,
+            },
+        ]);
+        assert!((summary["twr_percent"].as_f64().unwrap() - 10.0).abs() < 1e-9);
+        assert!((summary["gain"].as_f64().unwrap() - 100.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::usecases::timeseries::{Interval, mean, simple_returns, std_dev};
+use chrono::NaiveDate;
+use serde_json::{Value, json};
+
+pub fn annualized_volatility(returns: &[f64], periods_per_year: f64) -> f64 {
+    std_dev(returns) * periods_per_year.sqrt()
+}
+
+// Annualized Sharpe ratio; `risk_free` is the annual rate as a fraction (0.04 = 4%)
+pub fn sharpe_ratio(returns: &[f64], periods_per_year: f64, risk_free: f64) -> Option<f64> {
+    let sd = std_dev(returns);
+    if sd == 0.0 {
+        return None;
+    }
+    let excess = mean(returns) - risk_free / periods_per_year;
+    Some(excess / sd * periods_per_year.sqrt())
+}
+
+// Annualized Sortino ratio using downside deviation below the per-period risk-free rate
+pub fn sortino_ratio(returns: &[f64], periods_per_year: f64, risk_free: f64) -> Option<f64> {
+    if returns.is_empty() {
+        return None;
+    }
+    let rf = risk_free / periods_per_year;
+    let downside = returns
+        .iter()
+        .map(|r| (r - rf).min(0.0).powi(2))
+        .sum::<f64>()
+        / returns.len() as f64;
+    let dd = downside.sqrt();
+    if dd == 0.0 {
+        return None;
+    }
+    Some((mean(returns) - rf) / dd * periods_per_year.sqrt())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drawdown {
+    // Largest peak-to-trough decline as a positive fraction (0.25 = -25%)
+    pub max_drawdown: f64,
+    pub peak: Option<NaiveDate>,
+    pub trough: Option<NaiveDate>,
+    // Longest time from a peak until the value is back at it (or the end of the series)
+    pub max_duration_days: i64,
+    pub current_drawdown: f64,
+}
+
+pub fn drawdown(series: &[(NaiveDate, f64)]) -> Drawdown {
+    let mut out = Drawdown {
+        max_drawdown: 0.0,
+        peak: None,
+        trough: None,
+        max_duration_days: 0,
+        current_drawdown: 0.0,
+    };
+    let Some(&(first_date, first_value)) = series.first() else {
+        return out;
+    };
+    let (mut peak_date, mut peak_value) = (first_date, first_value);
+    let mut below = false;
+    for &(date, value) in series {
+        // the point where the value gets back to the peak still counts towards the drawdown
+        if below || value < peak_value {
+            out.max_duration_days = out.max_duration_days.max((date - peak_date).num_days());
+        }
+        below = value < peak_value;
+        if value >= peak_value {
+            peak_date = date;
+            peak_value = value;
+        }
+        let dd = if peak_value > 0.0 {
+            1.0 - value / peak_value
+        } else {
+            0.0
+        };
+        if dd > out.max_drawdown {
+            out.max_drawdown = dd;
+            out.peak = Some(peak_date);
+            out.trough = Some(date);
+        }
+        out.current_drawdown = dd;
+    }
+    out
+}
+
+// Full risk summary for one value series (already resampled to `interval`)
+pub fn risk_summary(series: &[(NaiveDate, f64)], interval: Interval, risk_free: f64) -> Value {
+    let values: Vec<f64> = series.iter().map(|(_, v)| *v).collect();
+    let returns = simple_returns(&values);
+    let ppy = interval.periods_per_year();
+    let dd = drawdown(series);
+    let total_return = match (values.first(), values.last()) {
+        (Some(f), Some(l)) if *f != 0.0 => Some(l / f - 1.0),
+        _ => None,
+    };
+    json!({
+        "observations": series.len(),
+        "start": series.first().map(|(d, _)| d.to_string()),
+        "end": series.last().map(|(d, _)| d.to_string()),
+        "start_value": values.first(),
+        "end_value": values.last(),
+        "total_return_percent": total_return.map(|r| r * 100.0),
+        "annualized_volatility_percent": annualized_volatility(&returns, ppy) * 100.0,
+        "sharpe_ratio": sharpe_ratio(&returns, ppy, risk_free),
+        "sortino_ratio": sortino_ratio(&returns, ppy, risk_free),
+        "max_drawdown_percent": dd.max_drawdown * 100.0,
+        "max_drawdown_peak": dd.peak.map(|d| d.to_string()),
+        "max_drawdown_trough": dd.trough.map(|d| d.to_string()),
+        "max_drawdown_duration_days": dd.max_duration_days,
+        "current_drawdown_percent": dd.current_drawdown * 100.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 1, day).unwrap()
+    }
+
+    #[test]
+    fn drawdown_depth_and_duration() {
+        let series = vec![
+            (d(1), 100.0),
+            (d(2), 120.0),
+            (d(3), 90.0),
+            (d(4), 60.0),
+            (d(5), 100.0),
+            (d(8), 130.0),
+        ];
+        let dd = drawdown(&series);
+        assert!((dd.max_drawdown - 0.5).abs() < 1e-12);
+        assert_eq!(dd.peak, Some(d(2)));
+        assert_eq!(dd.trough, Some(d(4)));
+        // below the d2 peak until d8
+        assert_eq!(dd.max_duration_days, 6);
+        assert_eq!(dd.current_drawdown, 0.0);
+    }
+
+    #[test]
+    fn ratios_handle_flat_and_volatile_series() {
+        assert_eq!(sharpe_ratio(&[0.01, 0.01, 0.01], 365.0, 0.0), None);
+        let returns = [0.02, -0.01, 0.03, -0.02, 0.01];
+        let vol = annualized_volatility(&returns, 365.0);
+        assert!(vol > 0.0);
+        let sharpe = sharpe_ratio(&returns, 365.0, 0.0).unwrap();
+        let sortino = sortino_ratio(&returns, 365.0, 0.0).unwrap();
+        assert!(sharpe > 0.0 && sortino > sharpe);
+    }
+}
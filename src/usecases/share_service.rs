@@ -0,0 +1,416 @@
+use crate::domain::models::ShareLink;
+use crate::domain::repository::HistoryRepo;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use sha2::Sha256;
+use std::sync::Arc;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct ShareService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+// What a share token carries, all of it covered by the signature: the link it belongs to, the
+// portfolio it opens, its expiry (unix seconds) and whether amounts are masked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareClaims {
+    #[serde(rename = "sid")]
+    pub link_id: i64,
+    #[serde(rename = "pid")]
+    pub portfolio_id: i64,
+    #[serde(rename = "exp")]
+    pub expires: i64,
+    #[serde(rename = "m")]
+    pub masked: bool,
+}
+
+const TOKEN_PREFIX: &str = "cms_";
+const SECRET_NAME: &str = "share_links";
+const MAX_DAYS: i64 = 365;
+
+// Routes a share token can read (GET only)
+const SHARED_ROUTES: &[&str] = &["/api/allocations", "/api/history"];
+
+// Report tables of /api/allocations and the row fields a masked share keeps: names,
+// percentages and deviations, never values, quantities or prices
+const REPORT_TABLES: &[&str] = &["per_asset", "per_group", "per_barca", "per_barca_actual"];
+const MASKED_ROW_KEYS: &[&str] = &[
+    "timestamp",
+    "symbol",
+    "group",
+    "barca",
+    "target_percent",
+    "current_percent",
+    "deviation",
+];
+
+pub fn share_readable(method: &str, route: &str) -> bool {
+    matches!(method, "GET" | "HEAD") && SHARED_ROUTES.contains(&route)
+}
+
+// "cms_<payload>.<signature>", both base64url: the claims as JSON and their HMAC-SHA256
+pub fn sign_share_token(secret: &[u8], claims: &ShareClaims) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap_or_default());
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(payload.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    format!("{}{}.{}", TOKEN_PREFIX, payload, signature)
+}
+
+pub fn verify_share_token(
+    secret: &[u8],
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<ShareClaims, String> {
+    let malformed = || "malformed share token".to_string();
+    let (payload, signature) = token
+        .trim()
+        .strip_prefix(TOKEN_PREFIX)
+        .and_then(|t| t.split_once('.'))
+        .ok_or_else(malformed)?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| malformed())?;
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| "invalid share token signature".to_string())?;
+    let claims: ShareClaims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(malformed)?;
+    if claims.expires <= now.timestamp() {
+        return Err("share link expired".to_string());
+    }
+    Ok(claims)
+}
+
+fn keep_keys(row: &Value, keys: &[&str]) -> Value {
+    let kept: Map<String, Value> = keys
+        .iter()
+        .filter_map(|k| row.get(*k).map(|v| (k.to_string(), v.clone())))
+        .collect();
+    Value::Object(kept)
+}
+
+fn rows(v: &Value) -> &[Value] {
+    v.get("rows")
+        .and_then(|r| r.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+// /api/allocations reduced to percentages and deviations; custody, cost basis and P&L go too
+pub fn mask_allocations(report: &Value) -> Value {
+    let mut out = Map::new();
+    for table in REPORT_TABLES {
+        if let Some(rows) = report.get(*table).and_then(|v| v.as_array()) {
+            let masked: Vec<Value> = rows.iter().map(|r| keep_keys(r, MASKED_ROW_KEYS)).collect();
+            out.insert(table.to_string(), json!(masked));
+        }
+    }
+    if let Some(market) = report.get("market") {
+        out.insert("market".to_string(), market.clone());
+    }
+    out.insert("masked".to_string(), json!(true));
+    Value::Object(out)
+}
+
+// /api/history reduced the same way. Totals become an index (first recorded value = 100) and
+// VaR rows a percent of the position.
+pub fn mask_history(history: &Value) -> Value {
+    if history.get("error").is_some() {
+        return history.clone();
+    }
+    let level = history
+        .get("level")
+        .and_then(|l| l.as_str())
+        .unwrap_or("totals");
+    let masked: Vec<Value> = match level {
+        "totals" => {
+            let base = rows(history)
+                .iter()
+                .filter_map(|r| r.get("total_value").and_then(|v| v.as_f64()))
+                .find(|v| *v > 0.0);
+            rows(history)
+                .iter()
+                .map(|r| {
+                    let index = r
+                        .get("total_value")
+                        .and_then(|v| v.as_f64())
+                        .zip(base)
+                        .map(|(v, base)| v / base * 100.0);
+                    json!({"timestamp": r.get("timestamp"), "index": index})
+                })
+                .collect()
+        }
+        "var" => rows(history)
+            .iter()
+            .map(|r| {
+                let position = r
+                    .get("position_value")
+                    .and_then(|v| v.as_f64())
+                    .filter(|p| *p > 0.0);
+                let percent = |key: &str| {
+                    r.get(key)
+                        .and_then(|v| v.as_f64())
+                        .zip(position)
+                        .map(|(v, p)| v / p * 100.0)
+                };
+                let mut row = keep_keys(
+                    r,
+                    &[
+                        "timestamp",
+                        "scope",
+                        "name",
+                        "method",
+                        "confidence",
+                        "horizon_days",
+                    ],
+                );
+                if let Some(obj) = row.as_object_mut() {
+                    obj.insert("var_percent".to_string(), json!(percent("var")));
+                    obj.insert("es_percent".to_string(), json!(percent("es")));
+                }
+                row
+            })
+            .collect(),
+        _ => rows(history)
+            .iter()
+            .map(|r| keep_keys(r, MASKED_ROW_KEYS))
+            .collect(),
+    };
+    json!({"level": level, "rows": masked, "masked": true})
+}
+
+fn link_active(link: &ShareLink, now: DateTime<Utc>) -> bool {
+    link.revoked_at.is_none()
+        && DateTime::parse_from_rfc3339(&link.expires_at)
+            .map(|exp| exp.with_timezone(&Utc) > now)
+            .unwrap_or(false)
+}
+
+fn link_claims(link: &ShareLink) -> Option<ShareClaims> {
+    Some(ShareClaims {
+        link_id: link.id?,
+        portfolio_id: link.portfolio_id,
+        expires: DateTime::parse_from_rfc3339(&link.expires_at)
+            .ok()?
+            .timestamp(),
+        masked: link.masked,
+    })
+}
+
+impl ShareService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // SHARE_LINK_SECRET, or a random key generated once and kept in the database. Changing
+    // it invalidates every share link.
+    async fn secret(&self) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Ok(s) = std::env::var("SHARE_LINK_SECRET")
+            && !s.trim().is_empty()
+        {
+            return Ok(s.trim().as_bytes().to_vec());
+        }
+        let bytes: [u8; 32] = rand::random();
+        let candidate: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        Ok(self
+            .repo
+            .app_secret(SECRET_NAME, &candidate)
+            .await?
+            .into_bytes())
+    }
+
+    fn link_json(&self, link: &ShareLink, secret: &[u8], now: DateTime<Utc>) -> Value {
+        let active = link_active(link, now);
+        let mut v = json!(link);
+        if let Some(obj) = v.as_object_mut() {
+            obj.insert("active".to_string(), json!(active));
+            // The token is derived from the row, so an active link can be copied again
+            if let Some(claims) = link_claims(link).filter(|_| active) {
+                let token = sign_share_token(secret, &claims);
+                let base = format!("/api/portfolios/{}", link.portfolio_id);
+                obj.insert(
+                    "paths".to_string(),
+                    json!({
+                        "allocations": format!("{}/allocations?share={}", base, token),
+                        "history": format!("{}/history?share={}", base, token),
+                    }),
+                );
+                obj.insert("token".to_string(), json!(token));
+            }
+        }
+        v
+    }
+
+    // Share link of this repository's portfolio, valid `expires_in_days` (default 7, at most
+    // 365) and masked unless `masked` is false
+    pub async fn create(
+        &self,
+        created_by: Option<i64>,
+        label: Option<&str>,
+        expires_in_days: Option<i64>,
+        masked: Option<bool>,
+    ) -> Result<Result<Value, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let days = expires_in_days.unwrap_or(7);
+        if !(1..=MAX_DAYS).contains(&days) {
+            return Ok(Err(format!(
+                "expires_in_days must be between 1 and {}",
+                MAX_DAYS
+            )));
+        }
+        let now = Utc::now();
+        let expires = DateTime::from_timestamp((now + Duration::days(days)).timestamp(), 0)
+            .unwrap_or(now + Duration::days(days));
+        let mut link = ShareLink {
+            id: None,
+            portfolio_id: self.repo.portfolio_id(),
+            label: label
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string),
+            masked: masked.unwrap_or(true),
+            expires_at: expires.to_rfc3339(),
+            created_by,
+            last_used_at: None,
+            revoked_at: None,
+            created_at: None,
+        };
+        let id = self.repo.insert_share_link(&link).await?;
+        link.id = Some(id);
+        let link = self.repo.fetch_share_link(id).await?.unwrap_or(link);
+        let secret = self.secret().await?;
+        Ok(Ok(self.link_json(&link, &secret, now)))
+    }
+
+    pub async fn list(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let secret = self.secret().await?;
+        let now = Utc::now();
+        let links: Vec<Value> = self
+            .repo
+            .fetch_share_links()
+            .await?
+            .iter()
+            .map(|l| self.link_json(l, &secret, now))
+            .collect();
+        Ok(json!({"portfolio_id": self.repo.portfolio_id(), "share_links": links}))
+    }
+
+    pub async fn revoke(&self, id: i64) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let revoked = self.repo.revoke_share_link(id).await?;
+        Ok(revoked)
+    }
+
+    // Claims of a valid token whose link still exists and is not revoked
+    pub async fn resolve(
+        &self,
+        token: &str,
+    ) -> Result<Result<ShareClaims, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let secret = self.secret().await?;
+        let claims = match verify_share_token(&secret, token, Utc::now()) {
+            Ok(c) => c,
+            Err(e) => return Ok(Err(e)),
+        };
+        match self.repo.fetch_share_link(claims.link_id).await? {
+            Some(link) if link.portfolio_id == claims.portfolio_id && link.revoked_at.is_none() => {
+                self.repo.touch_share_link(claims.link_id).await?;
+                Ok(Ok(claims))
+            }
+            _ => Ok(Err("share link revoked".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_tokens_reject_tampering_and_expiry() {
+        let now = Utc::now();
+        let claims = ShareClaims {
+            link_id: 3,
+            portfolio_id: 2,
+            expires: now.timestamp() + 3600,
+            masked: true,
+        };
+        let token = sign_share_token(b"secret", &claims);
+        assert_eq!(verify_share_token(b"secret", &token, now), Ok(claims));
+        assert!(verify_share_token(b"other", &token, now).is_err());
+
+        // Claims for another portfolio with the original signature
+        let forged = ShareClaims {
+            portfolio_id: 1,
+            ..claims
+        };
+        let (_, signature) = token.split_once('.').unwrap();
+        let (payload, _) = sign_share_token(b"x", &forged)
+            .split_once('.')
+            .map(|(p, s)| (p.to_string(), s.to_string()))
+            .unwrap();
+        let tampered = format!("{}.{}", payload, signature);
+        assert!(verify_share_token(b"secret", &tampered, now).is_err());
+
+        let later = now + Duration::hours(2);
+        assert_eq!(
+            verify_share_token(b"secret", &token, later),
+            Err("share link expired".to_string())
+        );
+        assert!(share_readable("GET", "/api/history"));
+        assert!(!share_readable("POST", "/api/allocations"));
+        assert!(!share_readable("GET", "/api/transactions"));
+    }
+
+    #[test]
+    fn masking_keeps_only_percentages_and_indexes_totals() {
+        let report = json!({
+            "per_asset": [{"symbol": "BTC", "group": "Base", "barca": "Base", "price": 60000.0,
+                "current_quantity": 0.5, "value": 30000.0, "target_percent": 50.0,
+                "current_percent": 60.0, "deviation": 10.0, "custody": [{"account": "Ledger"}],
+                "cost_basis": 20000.0, "unrealized_pnl": 10000.0}],
+            "per_barca_actual": [{"barca": "Base", "value": 30000.0, "current_percent": 60.0}],
+            "market": {"weights": {"BullMarket": 1.0}},
+            "custody": {"per_account": []},
+        });
+        let masked = mask_allocations(&report);
+        assert_eq!(
+            masked["per_asset"][0],
+            json!({"symbol": "BTC", "group": "Base", "barca": "Base", "target_percent": 50.0,
+                "current_percent": 60.0, "deviation": 10.0})
+        );
+        assert_eq!(
+            masked["per_barca_actual"][0],
+            json!({"barca": "Base", "current_percent": 60.0})
+        );
+        assert!(masked.get("custody").is_none());
+        assert_eq!(masked["masked"], json!(true));
+
+        let totals = json!({"level": "totals", "rows": [
+            {"timestamp": "t1", "total_value": 2000.0},
+            {"timestamp": "t2", "total_value": 2500.0},
+        ]});
+        let masked = mask_history(&totals);
+        assert_eq!(
+            masked["rows"][0],
+            json!({"timestamp": "t1", "index": 100.0})
+        );
+        assert_eq!(
+            masked["rows"][1],
+            json!({"timestamp": "t2", "index": 125.0})
+        );
+
+        let var = json!({"level": "var", "rows": [{"timestamp": "t1", "scope": "total",
+            "name": "total", "method": "historical", "confidence": 0.95, "horizon_days": 1,
+            "position_value": 2000.0, "var": 100.0, "es": 150.0}]});
+        let masked = mask_history(&var);
+        assert_eq!(masked["rows"][0]["var_percent"], json!(5.0));
+        assert_eq!(masked["rows"][0]["es_percent"], json!(7.5));
+        assert!(masked["rows"][0].get("position_value").is_none());
+    }
+}
@@ -0,0 +1,470 @@
+use crate::domain::models::WalletAllocation;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+// Price shock applied on top of the current quotes. The selector is one of
+// symbol / group / barca (none = every held asset); the most specific matching
+// shock wins for each symbol (symbol > group > barca > all).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceShock {
+    #[serde(default)]
+    pub symbol: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub barca: Option<String>,
+    // Relative change in percent (e.g. -40 for a 40% drop)
+    #[serde(default)]
+    pub percent: Option<f64>,
+    // Absolute replacement price in USD (symbol shocks only, not combined with percent)
+    #[serde(default)]
+    pub price: Option<f64>,
+}
+
+impl PriceShock {
+    fn specificity(&self) -> u8 {
+        if self.symbol.is_some() {
+            3
+        } else if self.group.is_some() {
+            2
+        } else if self.barca.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn matches(&self, symbol: &str, allocs: &[WalletAllocation]) -> bool {
+        if let Some(s) = &self.symbol {
+            return s.eq_ignore_ascii_case(symbol);
+        }
+        let rows = allocs.iter().filter(|a| a.symbol == symbol);
+        if let Some(g) = &self.group {
+            return rows
+                .clone()
+                .any(|a| a.group_name.as_deref().unwrap_or("") == g);
+        }
+        if let Some(b) = &self.barca {
+            return rows.clone().any(|a| a.barca.as_deref().unwrap_or("") == b);
+        }
+        true
+    }
+
+    fn apply(&self, price: f64) -> f64 {
+        match (self.price, self.percent) {
+            (Some(p), _) => p,
+            (None, Some(pct)) => (price * (1.0 + pct / 100.0)).max(0.0),
+            (None, None) => price,
+        }
+    }
+}
+
+// Quantity change for a holding. `delta` adds/removes units, `quantity` sets the
+// absolute total. When several rows match (e.g. the same coin in two groups or
+// accounts) the change is spread pro rata to their current quantities.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuantityAdjustment {
+    pub symbol: String,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub barca: Option<String>,
+    #[serde(default)]
+    pub delta: Option<f64>,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub price_shocks: Vec<PriceShock>,
+    #[serde(default)]
+    pub quantity_adjustments: Vec<QuantityAdjustment>,
+}
+
+// Apply a hypothetical scenario to copies of the holdings and quotes.
+pub fn apply_scenario(
+    allocs: &[WalletAllocation],
+    cryptos: &[crate::CryptoData],
+    scenario: &Scenario,
+) -> Result<(Vec<WalletAllocation>, Vec<crate::CryptoData>), String> {
+    if let Some(shock) = scenario
+        .price_shocks
+        .iter()
+        .find(|s| s.price.is_some() && s.symbol.is_none())
+    {
+        let target = match (&shock.group, &shock.barca) {
+            (Some(g), _) => format!("group {g}"),
+            (None, Some(b)) => format!("BARCA {b}"),
+            (None, None) => "all holdings".to_string(),
+        };
+        return Err(format!(
+            "absolute price is only allowed on symbol shocks (got one for {target}); use percent instead"
+        ));
+    }
+
+    if let Some(shock) = scenario
+        .price_shocks
+        .iter()
+        .find(|s| s.price.is_some() && s.percent.is_some())
+    {
+        return Err(format!(
+            "price shock for {} sets both price and percent; use one of them",
+            shock.symbol.as_deref().unwrap_or("all holdings")
+        ));
+    }
+
+    let mut out_allocs: Vec<WalletAllocation> = allocs.to_vec();
+    for adj in &scenario.quantity_adjustments {
+        apply_quantity_adjustment(&mut out_allocs, adj)?;
+    }
+
+    let mut out_cryptos: Vec<crate::CryptoData> = cryptos.to_vec();
+    // absolute prices may introduce quotes for symbols the provider did not return
+    for shock in &scenario.price_shocks {
+        if let (Some(symbol), Some(price)) = (&shock.symbol, shock.price)
+            && !out_cryptos
+                .iter()
+                .any(|c| c.symbol.eq_ignore_ascii_case(symbol))
+        {
+            out_cryptos.push(synthetic_quote(symbol, price));
+        }
+    }
+    for crypto in out_cryptos.iter_mut() {
+        let best = scenario
+            .price_shocks
+            .iter()
+            .filter(|s| s.matches(&crypto.symbol, &out_allocs))
+            .max_by_key(|s| s.specificity());
+        if let Some(shock) = best {
+            crypto.quote.usd.price = shock.apply(crypto.quote.usd.price);
+        }
+    }
+    Ok((out_allocs, out_cryptos))
+}
+
+fn apply_quantity_adjustment(
+    allocs: &mut Vec<WalletAllocation>,
+    adj: &QuantityAdjustment,
+) -> Result<(), String> {
+    let matches = |a: &WalletAllocation| {
+        a.symbol.eq_ignore_ascii_case(&adj.symbol)
+            && adj
+                .group
+                .as_ref()
+                .is_none_or(|g| a.group_name.as_deref().unwrap_or("") == g)
+            && adj
+                .barca
+                .as_ref()
+                .is_none_or(|b| a.barca.as_deref().unwrap_or("") == b)
+    };
+    let held: f64 = allocs
+        .iter()
+        .filter(|a| matches(a))
+        .map(|a| a.current_quantity.unwrap_or(0.0))
+        .sum();
+    let target = match (adj.quantity, adj.delta) {
+        (Some(q), _) => q,
+        (None, Some(d)) => held + d,
+        (None, None) => return Ok(()),
+    };
+    if target < 0.0 {
+        return Err(format!(
+            "cannot remove {} {}: only {} held",
+            held - target,
+            adj.symbol,
+            held
+        ));
+    }
+
+    if !allocs.iter().any(&matches) {
+        if target > 0.0 {
+            allocs.push(WalletAllocation {
+                id: None,
+                symbol: adj.symbol.to_uppercase(),
+                group_name: Some(adj.group.clone().unwrap_or_else(|| "Simulated".to_string())),
+                barca: Some(adj.barca.clone().unwrap_or_else(|| "Simulated".to_string())),
+                target_percent: Some(0.0),
+                current_quantity: Some(target),
+                last_price: None,
+                notes: None,
+                created_at: None,
+                account_id: None,
+                author: None,
+                reason: None,
+                event: None,
+                import_batch_id: None,
+            });
+        }
+        return Ok(());
+    }
+
+    let mut first = true;
+    for a in allocs.iter_mut().filter(|a| matches(a)) {
+        let qty = a.current_quantity.unwrap_or(0.0);
+        let new_qty = if held > 0.0 {
+            qty / held * target
+        } else if first {
+            target
+        } else {
+            0.0
+        };
+        a.current_quantity = Some(new_qty);
+        first = false;
+    }
+    Ok(())
+}
+
+pub(crate) fn synthetic_quote(symbol: &str, price: f64) -> crate::CryptoData {
+    crate::CryptoData {
+        id: 0,
+        name: symbol.to_string(),
+        symbol: symbol.to_uppercase(),
+        cmc_rank: 0,
+        tvl_ratio: None,
+        tvl_usd: None,
+        quote: crate::QuoteData {
+            usd: crate::PriceInfo {
+                price,
+                volume_24h: 0.0,
+                percent_change_24h: 0.0,
+                percent_change_7d: 0.0,
+                market_cap: 0.0,
+                fdv: 0.0,
+                tvl: None,
+            },
+        },
+    }
+}
+
+// Total wallet value of a `compute_allocations` report.
+pub fn report_total_value(report: &Value) -> f64 {
+    report
+        .get("per_asset")
+        .and_then(|v| v.as_array())
+        .map(|rows| {
+            rows.iter()
+                .map(|a| a.get("value").and_then(|v| v.as_f64()).unwrap_or(0.0))
+                .sum()
+        })
+        .unwrap_or(0.0)
+}
+
+fn values_by(report: &Value, table: &str, key: &str) -> BTreeMap<String, f64> {
+    let mut out = BTreeMap::new();
+    if let Some(rows) = report.get(table).and_then(|v| v.as_array()) {
+        for r in rows {
+            let name = r
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let value = r.get("value").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            *out.entry(name).or_insert(0.0) += value;
+        }
+    }
+    out
+}
+
+// Side-by-side value changes between the real and the simulated report.
+pub fn compare_reports(actual: &Value, simulated: &Value) -> Value {
+    let total_actual = report_total_value(actual);
+    let total_simulated = report_total_value(simulated);
+    let change_percent = |before: f64, after: f64| {
+        if before != 0.0 {
+            (after - before) / before * 100.0
+        } else {
+            0.0
+        }
+    };
+
+    let diff_table = |table: &str, key: &str| -> Vec<Value> {
+        let before = values_by(actual, table, key);
+        let after = values_by(simulated, table, key);
+        let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+        names.sort();
+        names.dedup();
+        let pct_before: HashMap<String, f64> = percents_by(actual, table, key);
+        let pct_after: HashMap<String, f64> = percents_by(simulated, table, key);
+        names
+            .into_iter()
+            .map(|name| {
+                let b = before.get(name).copied().unwrap_or(0.0);
+                let a = after.get(name).copied().unwrap_or(0.0);
+                json!({
+                    key: name,
+                    "value_actual": b,
+                    "value_simulated": a,
+                    "value_change": a - b,
+                    "value_change_percent": change_percent(b, a),
+                    "current_percent_actual": pct_before.get(name).copied().unwrap_or(0.0),
+                    "current_percent_simulated": pct_after.get(name).copied().unwrap_or(0.0),
+                })
+            })
+            .collect()
+    };
+
+    json!({
+        "total_value_actual": total_actual,
+        "total_value_simulated": total_simulated,
+        "total_value_change": total_simulated - total_actual,
+        "total_value_change_percent": change_percent(total_actual, total_simulated),
+        "per_barca": diff_table("per_barca_actual", "barca"),
+        "per_group": diff_table("per_group", "group"),
+    })
+}
+
+fn percents_by(report: &Value, table: &str, key: &str) -> HashMap<String, f64> {
+    let mut out = HashMap::new();
+    if let Some(rows) = report.get(table).and_then(|v| v.as_array()) {
+        for r in rows {
+            let name = r
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let pct = r
+                .get("current_percent")
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0);
+            *out.entry(name).or_insert(0.0) += pct;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alloc(symbol: &str, group: &str, barca: &str, qty: f64) -> WalletAllocation {
+        WalletAllocation {
+            id: None,
+            symbol: symbol.to_string(),
+            group_name: Some(group.to_string()),
+            barca: Some(barca.to_string()),
+            target_percent: Some(0.0),
+            current_quantity: Some(qty),
+            last_price: None,
+            notes: None,
+            created_at: None,
+            account_id: None,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        }
+    }
+
+    #[test]
+    fn most_specific_price_shock_wins() {
+        let allocs = vec![
+            alloc("BTC", "Holding", "Base", 1.0),
+            alloc("SOL", "Trading", "Altcoins", 10.0),
+            alloc("USDT", "Holding", "Caixa", 100.0),
+        ];
+        let cryptos = vec![
+            synthetic_quote("BTC", 100.0),
+            synthetic_quote("SOL", 10.0),
+            synthetic_quote("USDT", 1.0),
+        ];
+        let scenario = Scenario {
+            price_shocks: vec![
+                PriceShock {
+                    symbol: None,
+                    group: None,
+                    barca: None,
+                    percent: Some(-50.0),
+                    price: None,
+                },
+                PriceShock {
+                    symbol: None,
+                    group: None,
+                    barca: Some("Altcoins".into()),
+                    percent: Some(-90.0),
+                    price: None,
+                },
+                PriceShock {
+                    symbol: Some("USDT".into()),
+                    group: None,
+                    barca: None,
+                    percent: Some(0.0),
+                    price: None,
+                },
+            ],
+            quantity_adjustments: vec![],
+        };
+        let (_, shocked) = apply_scenario(&allocs, &cryptos, &scenario).unwrap();
+        let price = |s: &str| {
+            shocked
+                .iter()
+                .find(|c| c.symbol == s)
+                .unwrap()
+                .quote
+                .usd
+                .price
+        };
+        assert!((price("BTC") - 50.0).abs() < 1e-9);
+        assert!((price("SOL") - 1.0).abs() < 1e-9);
+        assert!((price("USDT") - 1.0).abs() < 1e-9);
+
+        let group_price = Scenario {
+            price_shocks: vec![PriceShock {
+                symbol: None,
+                group: Some("Holding".into()),
+                barca: None,
+                percent: None,
+                price: Some(1.0),
+            }],
+            quantity_adjustments: vec![],
+        };
+        assert!(apply_scenario(&allocs, &cryptos, &group_price).is_err());
+
+        let price_and_percent = Scenario {
+            price_shocks: vec![PriceShock {
+                symbol: Some("BTC".into()),
+                group: None,
+                barca: None,
+                percent: Some(-10.0),
+                price: Some(50.0),
+            }],
+            quantity_adjustments: vec![],
+        };
+        assert!(apply_scenario(&allocs, &cryptos, &price_and_percent).is_err());
+    }
+
+    #[test]
+    fn quantity_adjustments_spread_pro_rata_and_reject_oversell() {
+        let allocs = vec![
+            alloc("ETH", "Holding", "Base", 3.0),
+            alloc("ETH", "Trading", "Altcoins", 1.0),
+        ];
+        let sell_two = Scenario {
+            price_shocks: vec![],
+            quantity_adjustments: vec![QuantityAdjustment {
+                symbol: "ETH".into(),
+                group: None,
+                barca: None,
+                delta: Some(-2.0),
+                quantity: None,
+            }],
+        };
+        let (out, _) = apply_scenario(&allocs, &[], &sell_two).unwrap();
+        assert!((out[0].current_quantity.unwrap() - 1.5).abs() < 1e-9);
+        assert!((out[1].current_quantity.unwrap() - 0.5).abs() < 1e-9);
+
+        let oversell = Scenario {
+            price_shocks: vec![],
+            quantity_adjustments: vec![QuantityAdjustment {
+                symbol: "ETH".into(),
+                group: None,
+                barca: None,
+                delta: Some(-5.0),
+                quantity: None,
+            }],
+        };
+        assert!(apply_scenario(&allocs, &[], &oversell).is_err());
+    }
+}
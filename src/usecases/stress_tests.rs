@@ -0,0 +1,169 @@
+use crate::usecases::simulation::{Scenario, compare_reports};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+// Named stress scenario loaded from the scenarios config file (stress_scenarios.json)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StressScenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub scenario: Scenario,
+}
+
+pub fn load_scenarios(
+    path: &str,
+) -> Result<Vec<StressScenario>, Box<dyn std::error::Error + Send + Sync>> {
+    let raw = std::fs::read_to_string(path)?;
+    let scenarios: Vec<StressScenario> = serde_json::from_str(&raw)?;
+    Ok(scenarios)
+}
+
+fn rows_by<'a>(report: &'a Value, table: &str, key: &str) -> HashMap<String, &'a Value> {
+    report
+        .get(table)
+        .and_then(|v| v.as_array())
+        .map(|rows| {
+            rows.iter()
+                .map(|r| {
+                    (
+                        r.get(key)
+                            .and_then(|v| v.as_str())
+                            .unwrap_or("")
+                            .to_string(),
+                        r,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn num(row: Option<&&Value>, field: &str) -> f64 {
+    row.and_then(|r| r.get(field))
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.0)
+}
+
+// Impact of one scenario: total loss, per-barca value impact and the resulting
+// deviations from barca and group targets after the shock.
+pub fn scenario_impact(scenario: &StressScenario, actual: &Value, simulated: &Value) -> Value {
+    let comparison = compare_reports(actual, simulated);
+    let total_change = comparison
+        .get("total_value_change")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.0);
+
+    let targets_after = rows_by(simulated, "per_barca", "barca");
+    let targets_before = rows_by(actual, "per_barca", "barca");
+    let per_barca: Vec<Value> = comparison
+        .get("per_barca")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|mut row| {
+            let barca = row
+                .get("barca")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            if let Some(obj) = row.as_object_mut() {
+                obj.insert(
+                    "target_percent".to_string(),
+                    json!(num(targets_after.get(&barca), "target_percent")),
+                );
+                obj.insert(
+                    "deviation_actual".to_string(),
+                    json!(num(targets_before.get(&barca), "deviation")),
+                );
+                obj.insert(
+                    "deviation_simulated".to_string(),
+                    json!(num(targets_after.get(&barca), "deviation")),
+                );
+            }
+            row
+        })
+        .collect();
+
+    let groups_after = rows_by(simulated, "per_group", "group");
+    let per_group: Vec<Value> = {
+        let mut names: Vec<&String> = groups_after.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|g| {
+                json!({
+                    "group": g,
+                    "target_percent": num(groups_after.get(g), "target_percent"),
+                    "current_percent": num(groups_after.get(g), "current_percent"),
+                    "deviation": num(groups_after.get(g), "deviation"),
+                })
+            })
+            .collect()
+    };
+
+    json!({
+        "name": scenario.name,
+        "description": scenario.description,
+        "total_value_actual": comparison.get("total_value_actual"),
+        "total_value_simulated": comparison.get("total_value_simulated"),
+        "total_loss": -total_change,
+        "total_loss_percent": -comparison
+            .get("total_value_change_percent")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0),
+        "per_barca": per_barca,
+        "per_group": per_group,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenarios_file_parses_with_flattened_shocks() {
+        let raw = r#"[{"name": "winter", "description": "d", "price_shocks": [{"percent": -90}, {"symbol": "BTC", "percent": -70}]}]"#;
+        let parsed: Vec<StressScenario> = serde_json::from_str(raw).unwrap();
+        assert_eq!(parsed[0].name, "winter");
+        assert_eq!(parsed[0].scenario.price_shocks.len(), 2);
+        assert!(parsed[0].scenario.quantity_adjustments.is_empty());
+    }
+
+    #[test]
+    fn impact_reports_loss_and_deviation() {
+        let actual = json!({
+            "per_asset": [{"value": 100.0}],
+            "per_group": [{"group": "Holding", "value": 100.0, "current_percent": 100.0, "target_percent": 100.0, "deviation": 0.0}],
+            "per_barca": [{"barca": "Base", "value": 100.0, "current_percent": 100.0, "target_percent": 60.0, "deviation": 40.0}],
+            "per_barca_actual": [{"barca": "Base", "value": 100.0, "current_percent": 100.0}],
+        });
+        let simulated = json!({
+            "per_asset": [{"value": 30.0}],
+            "per_group": [{"group": "Holding", "value": 30.0, "current_percent": 100.0, "target_percent": 100.0, "deviation": 0.0}],
+            "per_barca": [{"barca": "Base", "value": 30.0, "current_percent": 100.0, "target_percent": 60.0, "deviation": 40.0}],
+            "per_barca_actual": [{"barca": "Base", "value": 30.0, "current_percent": 100.0}],
+        });
+        let scenario = StressScenario {
+            name: "crash".into(),
+            description: None,
+            scenario: Scenario::default(),
+        };
+        let impact = scenario_impact(&scenario, &actual, &simulated);
+        assert!((impact["total_loss"].as_f64().unwrap() - 70.0).abs() < 1e-9);
+        assert!((impact["total_loss_percent"].as_f64().unwrap() - 70.0).abs() < 1e-9);
+        assert_eq!(
+            impact["per_barca"][0]["value_change"].as_f64().unwrap(),
+            -70.0
+        );
+        assert_eq!(
+            impact["per_barca"][0]["deviation_simulated"]
+                .as_f64()
+                .unwrap(),
+            40.0
+        );
+    }
+}
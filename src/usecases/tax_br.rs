@@ -0,0 +1,452 @@
+use crate::domain::models::{FxRate, Transaction};
+use crate::usecases::cost_basis::{CostMethod, Disposal, replay_lots, usd_equivalent, year_start};
+use crate::usecases::ledger::derive_holdings;
+use crate::usecases::timeseries::parse_ts;
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+// Monthly sales up to this amount on domestic exchanges are exempt from capital gains tax
+pub const MONTHLY_EXEMPTION_BRL: f64 = 35_000.0;
+// Brazilian exchanges used when BR_DOMESTIC_VENUES is not set
+const DEFAULT_DOMESTIC_VENUES: &[&str] = &[
+    "mercadobitcoin",
+    "foxbit",
+    "novadax",
+    "bitpreco",
+    "brasilbitcoin",
+];
+// Progressive GCAP rates on capital gains: (upper bound of the bracket, rate)
+const GAIN_BRACKETS: &[(f64, f64)] = &[
+    (5_000_000.0, 0.15),
+    (10_000_000.0, 0.175),
+    (30_000_000.0, 0.20),
+    (f64::INFINITY, 0.225),
+];
+
+// Daily BRL conversion rates; a day without a rate uses the latest earlier one
+#[derive(Debug, Clone, Default)]
+pub struct RateTable {
+    rates: HashMap<String, BTreeMap<NaiveDate, f64>>,
+}
+
+impl RateTable {
+    pub fn from_rows(rows: &[FxRate]) -> Self {
+        let mut rates: HashMap<String, BTreeMap<NaiveDate, f64>> = HashMap::new();
+        for r in rows {
+            if let Ok(d) = NaiveDate::parse_from_str(&r.date, "%Y-%m-%d") {
+                rates
+                    .entry(r.currency.to_ascii_uppercase())
+                    .or_default()
+                    .insert(d, r.brl_rate);
+            }
+        }
+        Self { rates }
+    }
+
+    pub fn rate(&self, currency: &str, date: NaiveDate) -> Option<f64> {
+        self.rates
+            .get(&currency.to_ascii_uppercase())
+            .and_then(|m| m.range(..=date).next_back())
+            .map(|(_, r)| *r)
+    }
+
+    // BRL value of `amount` of `currency` at `timestamp`; USD stablecoins use the USD rate
+    pub fn to_brl(&self, timestamp: &str, amount: f64, currency: &str) -> Result<f64, String> {
+        if currency.eq_ignore_ascii_case("BRL") {
+            return Ok(amount);
+        }
+        let date = parse_ts(timestamp)
+            .map(|t| t.date_naive())
+            .ok_or_else(|| format!("invalid timestamp '{}'", timestamp))?;
+        let lookup = if usd_equivalent(currency) {
+            "USD"
+        } else {
+            currency
+        };
+        self.rate(lookup, date)
+            .map(|r| amount * r)
+            .ok_or_else(|| format!("no BRL rate for {} on or before {}", lookup, date))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaxConfig {
+    pub domestic_venues: Vec<String>,
+}
+
+fn normalize_venue(v: &str) -> String {
+    v.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+impl TaxConfig {
+    // BR_DOMESTIC_VENUES: comma separated venue names treated as Brazilian exchanges
+    pub fn from_env() -> Self {
+        let venues = match std::env::var("BR_DOMESTIC_VENUES") {
+            Ok(s) if !s.trim().is_empty() => s.split(',').map(normalize_venue).collect(),
+            _ => DEFAULT_DOMESTIC_VENUES
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+        };
+        Self {
+            domestic_venues: venues,
+        }
+    }
+
+    pub fn is_domestic(&self, venue: Option<&str>) -> bool {
+        venue.is_some_and(|v| self.domestic_venues.contains(&normalize_venue(v)))
+    }
+}
+
+// Tax on a month's taxable gain using the progressive brackets
+pub fn capital_gains_tax(gain: f64) -> f64 {
+    let mut tax = 0.0;
+    let mut lower = 0.0;
+    for (upper, rate) in GAIN_BRACKETS {
+        if gain <= lower {
+            break;
+        }
+        tax += (gain.min(*upper) - lower) * rate;
+        lower = *upper;
+    }
+    tax
+}
+
+// DARF (code 4600) is due on the last business day of the month after the sale
+fn darf_due(year: i32, month: u32) -> Option<NaiveDate> {
+    let (y, m) = if month >= 11 {
+        (year + 1, month - 10)
+    } else {
+        (year, month + 2)
+    };
+    let mut d = NaiveDate::from_ymd_opt(y, m, 1)? - Duration::days(1);
+    while matches!(d.weekday(), Weekday::Sat | Weekday::Sun) {
+        d -= Duration::days(1);
+    }
+    Some(d)
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MonthlyTax {
+    pub month: String,
+    pub disposals: usize,
+    pub domestic_sales_brl: f64,
+    pub foreign_sales_brl: f64,
+    pub domestic_gain_brl: f64,
+    pub foreign_gain_brl: f64,
+    // Domestic sales within the R$35k limit make the month's domestic gains exempt
+    pub exempt: bool,
+    pub exempt_gain_brl: f64,
+    pub taxable_gain_brl: f64,
+    pub estimated_tax_brl: f64,
+    pub darf_due: Option<String>,
+}
+
+// Group BRL-valued disposals of `year` by month and apply the exemption and GCAP rates.
+// Foreign (non-domestic or unknown venue) disposals never qualify for the exemption.
+pub fn monthly_report(disposals: &[Disposal], year: i32, cfg: &TaxConfig) -> Vec<MonthlyTax> {
+    let mut months: BTreeMap<u32, MonthlyTax> = BTreeMap::new();
+    for d in disposals {
+        let Some(t) = parse_ts(&d.timestamp) else {
+            continue;
+        };
+        if t.year() != year {
+            continue;
+        }
+        let m = months.entry(t.month()).or_insert_with(|| MonthlyTax {
+            month: format!("{}-{:02}", year, t.month()),
+            disposals: 0,
+            domestic_sales_brl: 0.0,
+            foreign_sales_brl: 0.0,
+            domestic_gain_brl: 0.0,
+            foreign_gain_brl: 0.0,
+            exempt: false,
+            exempt_gain_brl: 0.0,
+            taxable_gain_brl: 0.0,
+            estimated_tax_brl: 0.0,
+            darf_due: darf_due(year, t.month()).map(|d| d.to_string()),
+        });
+        m.disposals += 1;
+        let gain = d.proceeds - d.cost;
+        if cfg.is_domestic(d.venue.as_deref()) {
+            m.domestic_sales_brl += d.proceeds;
+            m.domestic_gain_brl += gain;
+        } else {
+            m.foreign_sales_brl += d.proceeds;
+            m.foreign_gain_brl += gain;
+        }
+    }
+    months
+        .into_values()
+        .map(|mut m| {
+            m.exempt = m.domestic_sales_brl <= MONTHLY_EXEMPTION_BRL;
+            let domestic = m.domestic_gain_brl.max(0.0);
+            if m.exempt {
+                m.exempt_gain_brl = domestic;
+            }
+            m.taxable_gain_brl =
+                if m.exempt { 0.0 } else { domestic } + m.foreign_gain_brl.max(0.0);
+            m.estimated_tax_brl = capital_gains_tax(m.taxable_gain_brl);
+            m
+        })
+        .collect()
+}
+
+// CSV export of report rows (header from the field names)
+pub fn rows_csv<T: Serialize>(
+    rows: &[T],
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for r in rows {
+        wtr.serialize(r)?;
+    }
+    Ok(String::from_utf8(wtr.into_inner()?)?)
+}
+
+// Receita group 08 (Criptoativos) codes: 01 Bitcoin, 02 other cryptocurrencies, 03 stablecoins
+fn asset_code(symbol: &str) -> &'static str {
+    if symbol.eq_ignore_ascii_case("BTC") {
+        "01"
+    } else if usd_equivalent(symbol) {
+        "03"
+    } else {
+        "02"
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BemDireito {
+    pub group: String,
+    pub code: String,
+    pub symbol: String,
+    pub quantity: f64,
+    pub description: String,
+    // Acquisition cost at average price, as declared ("situação em 31/12")
+    pub previous_year_brl: f64,
+    pub current_year_brl: f64,
+}
+
+// Year-end position per asset at average acquisition cost in BRL, with the previous year-end
+pub fn bens_e_direitos(
+    txs: &[Transaction],
+    rates: &RateTable,
+    year: i32,
+) -> (Vec<BemDireito>, Vec<String>) {
+    let (Some(start), Some(end)) = (year_start(year), year_start(year + 1)) else {
+        return (Vec::new(), vec![format!("invalid year {}", year)]);
+    };
+    let until = |limit| -> Vec<Transaction> {
+        txs.iter()
+            .filter(|t| parse_ts(&t.timestamp).is_some_and(|ts| ts < limit))
+            .cloned()
+            .collect()
+    };
+    let convert = |ts: &str, amount: f64, currency: &str| rates.to_brl(ts, amount, currency);
+    let (prev_txs, cur_txs) = (until(start), until(end));
+    let prev = replay_lots(&prev_txs, CostMethod::Average, start, &convert);
+    let cur = replay_lots(&cur_txs, CostMethod::Average, start, &convert);
+    let custody = derive_holdings(&cur_txs);
+
+    let mut symbols: Vec<&String> = prev.positions.keys().chain(cur.positions.keys()).collect();
+    symbols.sort();
+    symbols.dedup();
+    let rows = symbols
+        .into_iter()
+        .filter_map(|symbol| {
+            let previous = prev
+                .positions
+                .get(symbol)
+                .map(|p| p.cost_basis())
+                .unwrap_or(0.0);
+            let current = cur.positions.get(symbol);
+            let quantity = current.map(|p| p.quantity()).unwrap_or(0.0);
+            let cost = current.map(|p| p.cost_basis()).unwrap_or(0.0);
+            if quantity <= 1e-12 && previous <= 0.0 {
+                return None;
+            }
+            let venues: Vec<String> = custody
+                .positions
+                .iter()
+                .filter(|((s, _), q)| s == symbol && **q > 1e-12)
+                .map(|((_, v), q)| format!("{} ({})", v, q))
+                .collect();
+            let description = format!(
+                "{} {} adquiridos ao custo medio de R$ {:.2}. Custodia: {}",
+                quantity,
+                symbol,
+                if quantity > 0.0 { cost / quantity } else { 0.0 },
+                if venues.is_empty() {
+                    "-".to_string()
+                } else {
+                    venues.join(", ")
+                }
+            );
+            Some(BemDireito {
+                group: "08".to_string(),
+                code: asset_code(symbol).to_string(),
+                symbol: symbol.clone(),
+                quantity,
+                description,
+                previous_year_brl: previous,
+                current_year_brl: cost,
+            })
+        })
+        .collect();
+    let mut warnings = prev.warnings;
+    warnings.extend(cur.warnings);
+    warnings.sort();
+    warnings.dedup();
+    (rows, warnings)
+}
+
+// Monthly report JSON for `year` (disposals valued in BRL at average cost)
+pub fn monthly_tax_report(
+    txs: &[Transaction],
+    rates: &RateTable,
+    year: i32,
+    cfg: &TaxConfig,
+) -> (Vec<MonthlyTax>, Value) {
+    let convert = |ts: &str, amount: f64, currency: &str| rates.to_brl(ts, amount, currency);
+    let start = year_start(year).unwrap_or_default();
+    let lots = replay_lots(txs, CostMethod::Average, start, &convert);
+    let months = monthly_report(&lots.disposals, year, cfg);
+    let summary = json!({
+        "year": year,
+        "method": "average_cost",
+        "exemption_limit_brl": MONTHLY_EXEMPTION_BRL,
+        "domestic_venues": cfg.domestic_venues,
+        "total_taxable_gain_brl": months.iter().map(|m| m.taxable_gain_brl).sum::<f64>(),
+        "total_estimated_tax_brl": months.iter().map(|m| m.estimated_tax_brl).sum::<f64>(),
+        "complete": lots.warnings.is_empty(),
+        "warnings": lots.warnings,
+    });
+    (months, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(kind: &str, ts: &str, symbol: &str, qty: f64, price: f64, venue: &str) -> Transaction {
+        Transaction {
+            id: None,
+            timestamp: ts.to_string(),
+            kind: kind.to_string(),
+            symbol: symbol.to_string(),
+            quantity: qty,
+            price: Some(price),
+            quote_currency: Some("BRL".to_string()),
+            fee: None,
+            fee_symbol: None,
+            venue: Some(venue.to_string()),
+            to_venue: None,
+            group_name: None,
+            barca: None,
+            external_id: None,
+            notes: None,
+            created_at: None,
+            income_type: None,
+            account_id: None,
+            to_account_id: None,
+        }
+    }
+
+    fn cfg() -> TaxConfig {
+        TaxConfig {
+            domestic_venues: vec!["mercadobitcoin".to_string()],
+        }
+    }
+
+    #[test]
+    fn exemption_applies_per_month_to_domestic_sales() {
+        let txs = vec![
+            tx(
+                "buy",
+                "2025-01-05T00:00:00Z",
+                "BTC",
+                2.0,
+                100_000.0,
+                "Mercado Bitcoin",
+            ),
+            // R$30k sale in February: exempt
+            tx(
+                "sell",
+                "2025-02-10T00:00:00Z",
+                "BTC",
+                0.2,
+                150_000.0,
+                "Mercado Bitcoin",
+            ),
+            // R$45k sale in March: taxable gain 0.3 * 50k = 15k
+            tx(
+                "sell",
+                "2025-03-10T00:00:00Z",
+                "BTC",
+                0.3,
+                150_000.0,
+                "mercadobitcoin",
+            ),
+            // foreign sale never exempt
+            tx(
+                "sell",
+                "2025-04-10T00:00:00Z",
+                "BTC",
+                0.1,
+                120_000.0,
+                "binance",
+            ),
+        ];
+        let (months, summary) = monthly_tax_report(&txs, &RateTable::default(), 2025, &cfg());
+        assert_eq!(months.len(), 3);
+        assert!(months[0].exempt);
+        assert!((months[0].exempt_gain_brl - 10_000.0).abs() < 1e-6);
+        assert_eq!(months[0].taxable_gain_brl, 0.0);
+        assert!(!months[1].exempt);
+        assert!((months[1].taxable_gain_brl - 15_000.0).abs() < 1e-6);
+        assert!((months[1].estimated_tax_brl - 2_250.0).abs() < 1e-6);
+        assert_eq!(months[1].darf_due.as_deref(), Some("2025-04-30"));
+        assert!((months[2].taxable_gain_brl - 2_000.0).abs() < 1e-6);
+        assert_eq!(summary["complete"], json!(true));
+        assert!(rows_csv(&months).unwrap().starts_with("month,disposals,"));
+    }
+
+    #[test]
+    fn usd_trades_use_rates_and_bens_e_direitos_at_average_cost() {
+        let rates = RateTable::from_rows(&[
+            FxRate {
+                id: None,
+                date: "2024-12-01".to_string(),
+                currency: "USD".to_string(),
+                brl_rate: 5.0,
+                source: None,
+                created_at: None,
+            },
+            FxRate {
+                id: None,
+                date: "2025-06-01".to_string(),
+                currency: "USD".to_string(),
+                brl_rate: 6.0,
+                source: None,
+                created_at: None,
+            },
+        ]);
+        let mut a = tx("buy", "2024-12-10T00:00:00Z", "ETH", 1.0, 1_000.0, "ledger");
+        a.quote_currency = Some("USD".to_string());
+        let mut b = tx("buy", "2025-07-10T00:00:00Z", "ETH", 1.0, 2_000.0, "ledger");
+        b.quote_currency = Some("USDT".to_string());
+        let (rows, warnings) = bens_e_direitos(&[a, b.clone()], &rates, 2025);
+        // USDT spent without a prior balance only warns about missing lots
+        assert!(warnings.iter().all(|w| w.contains("without matching lots")));
+        let eth = rows.iter().find(|r| r.symbol == "ETH").unwrap();
+        assert_eq!(eth.code, "02");
+        assert!((eth.previous_year_brl - 5_000.0).abs() < 1e-6);
+        assert!((eth.current_year_brl - 17_000.0).abs() < 1e-6);
+        assert!(rates.to_brl("2024-01-01T00:00:00Z", 1.0, "USD").is_err());
+        assert!((capital_gains_tax(6_000_000.0) - 925_000.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::domain::models::FxRate;
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::tax_br::{
+    BemDireito, MonthlyTax, RateTable, TaxConfig, bens_e_direitos, monthly_tax_report,
+};
+use chrono::NaiveDate;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub struct TaxService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl TaxService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // Validate all rates before storing any of them
+    pub async fn record_rates(
+        &self,
+        mut rates: Vec<FxRate>,
+    ) -> Result<Result<usize, Vec<Value>>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut errors = Vec::new();
+        for (i, r) in rates.iter_mut().enumerate() {
+            r.currency = r.currency.trim().to_ascii_uppercase();
+            if NaiveDate::parse_from_str(&r.date, "%Y-%m-%d").is_err() {
+                errors.push(json!({"index": i, "error": format!("invalid date '{}' (expected YYYY-MM-DD)", r.date)}));
+            } else if r.currency.is_empty() || !(r.brl_rate.is_finite() && r.brl_rate > 0.0) {
+                errors.push(
+                    json!({"index": i, "error": "currency and a positive brl_rate are required"}),
+                );
+            }
+        }
+        if !errors.is_empty() {
+            return Ok(Err(errors));
+        }
+        for r in &rates {
+            self.repo.upsert_fx_rate(r).await?;
+        }
+        Ok(Ok(rates.len()))
+    }
+
+    pub async fn rates(
+        &self,
+        currency: Option<&str>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let currency = currency.map(|c| c.trim().to_ascii_uppercase());
+        let rows = self.repo.fetch_fx_rates(currency.as_deref()).await?;
+        Ok(json!({"rates": rows}))
+    }
+
+    async fn rate_table(&self) -> Result<RateTable, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(RateTable::from_rows(&self.repo.fetch_fx_rates(None).await?))
+    }
+
+    pub async fn monthly(
+        &self,
+        year: i32,
+    ) -> Result<(Vec<MonthlyTax>, Value), Box<dyn std::error::Error + Send + Sync>> {
+        let txs = self.repo.fetch_transactions(None, None, None).await?;
+        let rates = self.rate_table().await?;
+        Ok(monthly_tax_report(
+            &txs,
+            &rates,
+            year,
+            &TaxConfig::from_env(),
+        ))
+    }
+
+    pub async fn bens_e_direitos(
+        &self,
+        year: i32,
+    ) -> Result<(Vec<BemDireito>, Value), Box<dyn std::error::Error + Send + Sync>> {
+        let txs = self.repo.fetch_transactions(None, None, None).await?;
+        let rates = self.rate_table().await?;
+        let (rows, warnings) = bens_e_direitos(&txs, &rates, year);
+        let summary = json!({
+            "year": year,
+            "complete": warnings.is_empty(),
+            "warnings": warnings,
+        });
+        Ok((rows, summary))
+    }
+}
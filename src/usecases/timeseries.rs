@@ -0,0 +1,159 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+// Resampling interval for history series (snapshots are taken whenever prices are refreshed,
+// so they are irregular; analytics work on the last value of each bucket)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Daily,
+    Weekly,
+}
+
+impl Interval {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "1d" | "d" | "day" | "daily" => Ok(Interval::Daily),
+            "1w" | "w" | "week" | "weekly" => Ok(Interval::Weekly),
+            other => Err(format!("unsupported interval '{}'", other)),
+        }
+    }
+
+    // Crypto trades every day, so a year is 365 daily or 52 weekly periods
+    pub fn periods_per_year(&self) -> f64 {
+        match self {
+            Interval::Daily => 365.0,
+            Interval::Weekly => 52.0,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::Daily => "daily",
+            Interval::Weekly => "weekly",
+        }
+    }
+
+    fn bucket(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Daily => date,
+            Interval::Weekly => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+        }
+    }
+}
+
+pub fn parse_ts(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+// Lookback window such as `30d`, `12w`, `6m`, `1y` or `all` -> start timestamp (RFC3339)
+pub fn window_start(window: &str, now: DateTime<Utc>) -> Result<Option<String>, String> {
+    let w = window.trim().to_ascii_lowercase();
+    if w.is_empty() || w == "all" {
+        return Ok(None);
+    }
+    let invalid = || format!("invalid window '{}'", window);
+    let (num, factor) = [('d', 1), ('w', 7), ('m', 30), ('y', 365)]
+        .iter()
+        .find_map(|(unit, factor)| w.strip_suffix(*unit).map(|num| (num, *factor)))
+        .ok_or_else(invalid)?;
+    let n: i64 = num.parse().map_err(|_| invalid())?;
+    // Out of range windows are rejected rather than overflowing
+    let start = n
+        .checked_mul(factor)
+        .and_then(Duration::try_days)
+        .and_then(|d| now.checked_sub_signed(d))
+        .ok_or_else(invalid)?;
+    Ok(Some(start.to_rfc3339()))
+}
+
+// Keep the last observation of each interval bucket, ordered by bucket date.
+pub fn resample_last(points: &[(String, f64)], interval: Interval) -> Vec<(NaiveDate, f64)> {
+    let mut buckets: BTreeMap<NaiveDate, (DateTime<Utc>, f64)> = BTreeMap::new();
+    for (ts, value) in points {
+        let Some(t) = parse_ts(ts) else { continue };
+        let key = interval.bucket(t.date_naive());
+        match buckets.get(&key) {
+            Some((existing, _)) if *existing > t => {}
+            _ => {
+                buckets.insert(key, (t, *value));
+            }
+        }
+    }
+    buckets.into_iter().map(|(d, (_, v))| (d, v)).collect()
+}
+
+// Simple period returns; periods starting from a zero value are skipped.
+pub fn simple_returns(values: &[f64]) -> Vec<f64> {
+    values
+        .windows(2)
+        .filter(|w| w[0] != 0.0)
+        .map(|w| w[1] / w[0] - 1.0)
+        .collect()
+}
+
+pub fn mean(xs: &[f64]) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    xs.iter().sum::<f64>() / xs.len() as f64
+}
+
+// Sample standard deviation (n - 1)
+pub fn std_dev(xs: &[f64]) -> f64 {
+    if xs.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(xs);
+    let var = xs.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (xs.len() - 1) as f64;
+    var.sqrt()
+}
+
+// Linear-interpolated percentile (p in 0..=100) of an unsorted sample
+pub fn percentile(xs: &[f64], p: f64) -> f64 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = xs.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_keeps_last_value_per_day() {
+        let points = vec![
+            ("2025-01-01T10:00:00+00:00".to_string(), 100.0),
+            ("2025-01-01T18:00:00+00:00".to_string(), 110.0),
+            ("2025-01-02T09:00:00+00:00".to_string(), 121.0),
+        ];
+        let daily = resample_last(&points, Interval::Daily);
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[0].1, 110.0);
+        let returns = simple_returns(&daily.iter().map(|(_, v)| *v).collect::<Vec<_>>());
+        assert!((returns[0] - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn window_parsing() {
+        let now = parse_ts("2025-03-31T00:00:00+00:00").unwrap();
+        assert_eq!(window_start("all", now).unwrap(), None);
+        assert_eq!(
+            window_start("30d", now).unwrap().unwrap(),
+            "2025-03-01T00:00:00+00:00"
+        );
+        assert!(window_start("abc", now).is_err());
+        // Multibyte units and out of range lengths are errors, not panics
+        assert!(window_start("1é", now).is_err());
+        assert!(window_start("é", now).is_err());
+        assert!(window_start("99999999999y", now).is_err());
+        assert!(window_start(&format!("{}w", i64::MAX), now).is_err());
+        assert!(window_start("-99999999999d", now).is_err());
+    }
+}
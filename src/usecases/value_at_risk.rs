@@ -0,0 +1,135 @@
+use crate::usecases::timeseries::{mean, percentile, std_dev};
+
+// Inverse of the standard normal CDF (Acklam's rational approximation, |error| < 1.2e-9)
+pub fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    let p = p.clamp(1e-12, 1.0 - 1e-12);
+    let p_low = 0.02425;
+    if p < p_low {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - p_low {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -inverse_normal_cdf(1.0 - p)
+    }
+}
+
+fn normal_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+// Profit and loss of positions (`values[asset]`) over overlapping windows of `horizon`
+// periods of historical returns (`returns[period][asset]`), compounding within the window.
+pub fn horizon_pnl(returns: &[Vec<f64>], values: &[f64], horizon: usize) -> Vec<f64> {
+    let horizon = horizon.max(1);
+    if returns.len() < horizon {
+        return Vec::new();
+    }
+    returns
+        .windows(horizon)
+        .map(|w| {
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let growth: f64 = w.iter().map(|r| 1.0 + r[i]).product();
+                    v * (growth - 1.0)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// Historical-simulation VaR and Expected Shortfall at `confidence` (e.g. 0.95),
+// both reported as positive loss amounts.
+pub fn historical_var_es(pnl: &[f64], confidence: f64) -> Option<(f64, f64)> {
+    if pnl.is_empty() {
+        return None;
+    }
+    let losses: Vec<f64> = pnl.iter().map(|p| -p).collect();
+    let var = percentile(&losses, confidence * 100.0);
+    let tail: Vec<f64> = losses.iter().copied().filter(|l| *l >= var).collect();
+    let es = if tail.is_empty() { var } else { mean(&tail) };
+    Some((var.max(0.0), es.max(0.0)))
+}
+
+// Parametric (normal) VaR and Expected Shortfall from one-period P&L,
+// scaled to `horizon` periods with the square-root-of-time rule.
+pub fn parametric_var_es(pnl: &[f64], confidence: f64, horizon: usize) -> Option<(f64, f64)> {
+    if pnl.len() < 2 {
+        return None;
+    }
+    let h = horizon.max(1) as f64;
+    let mu = mean(pnl) * h;
+    let sigma = std_dev(pnl) * h.sqrt();
+    let z = inverse_normal_cdf(confidence);
+    let var = -(mu - z * sigma);
+    let es = -(mu - sigma * normal_pdf(z) / (1.0 - confidence));
+    Some((var.max(0.0), es.max(0.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_normal_matches_known_quantiles() {
+        assert!((inverse_normal_cdf(0.95) - 1.644_853_6).abs() < 1e-6);
+        assert!((inverse_normal_cdf(0.99) - 2.326_347_9).abs() < 1e-6);
+        assert!(inverse_normal_cdf(0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn historical_var_and_es_from_pnl() {
+        // 100 outcomes: losses of 1..=100
+        let pnl: Vec<f64> = (1..=100).map(|i| -(i as f64)).collect();
+        let (var, es) = historical_var_es(&pnl, 0.95).unwrap();
+        assert!((var - 95.05).abs() < 1e-9);
+        assert!((es - 98.0).abs() < 1e-9);
+        assert!(historical_var_es(&[], 0.95).is_none());
+    }
+
+    #[test]
+    fn horizon_pnl_compounds_overlapping_windows() {
+        let returns = vec![vec![0.1, 0.0], vec![0.1, 0.0], vec![-0.5, 0.0]];
+        let pnl = horizon_pnl(&returns, &[100.0, 50.0], 2);
+        assert_eq!(pnl.len(), 2);
+        assert!((pnl[0] - 21.0).abs() < 1e-9);
+        assert!((pnl[1] - (-45.0)).abs() < 1e-9);
+        let (var, es) = parametric_var_es(&[-1.0, 1.0, -2.0, 2.0], 0.95, 4).unwrap();
+        assert!(var > 0.0 && es > var);
+    }
+}
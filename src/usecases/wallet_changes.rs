@@ -0,0 +1,503 @@
+use crate::domain::models::WalletAllocation;
+use serde::Deserialize;
+
+// One edit of the wallet ledger: identifies a holding and the fields to change
+#[derive(Debug, Clone, Deserialize)]
+pub struct HoldingChange {
+    pub symbol: String,
+    #[serde(default, alias = "group")]
+    pub group_name: Option<String>,
+    #[serde(default)]
+    pub barca: Option<String>,
+    #[serde(default)]
+    pub account_id: Option<i64>,
+    // Account name, resolved to `account_id` by the service
+    #[serde(default)]
+    pub account: Option<String>,
+    #[serde(default)]
+    pub current_quantity: Option<f64>,
+    #[serde(default)]
+    pub target_percent: Option<f64>,
+    #[serde(default)]
+    pub last_price: Option<f64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub reason: String,
+    // Destination of a move (group/barca/account) or the account a merge keeps
+    #[serde(default, alias = "to_group")]
+    pub to_group_name: Option<String>,
+    #[serde(default)]
+    pub to_barca: Option<String>,
+    #[serde(default)]
+    pub to_account_id: Option<i64>,
+    #[serde(default)]
+    pub to_account: Option<String>,
+}
+
+// Ledger events; the last three are tombstones that close a holding
+pub const EVENT_IMPORT: &str = "import";
+pub const EVENT_ADD: &str = "add";
+pub const EVENT_UPDATE: &str = "update";
+pub const EVENT_MOVE_IN: &str = "move_in";
+pub const EVENT_MERGE_IN: &str = "merge_in";
+pub const EVENT_RETIRE: &str = "retire";
+pub const EVENT_MOVE_OUT: &str = "move_out";
+pub const EVENT_MERGE_OUT: &str = "merge_out";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    Invalid(String),
+    NotFound(String),
+    Conflict(String),
+}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) | Self::NotFound(e) | Self::Conflict(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn trimmed(v: &Option<String>) -> Option<String> {
+    v.as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+// Normalize the change and check the audit fields and numeric ranges
+pub fn validate_change(change: &mut HoldingChange) -> Result<(), EditError> {
+    let invalid = |e: &str| Err(EditError::Invalid(e.to_string()));
+    change.symbol = change.symbol.trim().to_ascii_uppercase();
+    change.group_name = trimmed(&change.group_name);
+    change.barca = trimmed(&change.barca);
+    change.account = trimmed(&change.account);
+    change.to_group_name = trimmed(&change.to_group_name);
+    change.to_barca = trimmed(&change.to_barca);
+    change.to_account = trimmed(&change.to_account);
+    change.author = change.author.trim().to_string();
+    change.reason = change.reason.trim().to_string();
+    if change.symbol.is_empty() {
+        return invalid("symbol is required");
+    }
+    if change.author.is_empty() || change.reason.is_empty() {
+        return invalid("author and reason are required");
+    }
+    if change
+        .current_quantity
+        .is_some_and(|q| !q.is_finite() || q < 0.0)
+    {
+        return invalid("current_quantity must be a non-negative number");
+    }
+    if change
+        .target_percent
+        .is_some_and(|t| !(0.0..=100.0).contains(&t))
+    {
+        return invalid("target_percent must be between 0 and 100");
+    }
+    if change.last_price.is_some_and(|p| !p.is_finite() || p < 0.0) {
+        return invalid("last_price must be a non-negative number");
+    }
+    Ok(())
+}
+
+fn describe(row: &WalletAllocation) -> String {
+    format!(
+        "{} (group {}, barca {}, account {})",
+        row.symbol,
+        row.group_name.as_deref().unwrap_or("-"),
+        row.barca.as_deref().unwrap_or("-"),
+        row.account_id.map_or("-".to_string(), |id| id.to_string()),
+    )
+}
+
+fn same_key(row: &WalletAllocation, change: &HoldingChange) -> bool {
+    row.symbol == change.symbol
+        && change
+            .group_name
+            .as_ref()
+            .is_none_or(|g| row.group_name.as_ref() == Some(g))
+        && change
+            .barca
+            .as_ref()
+            .is_none_or(|b| row.barca.as_ref() == Some(b))
+        && change
+            .account_id
+            .is_none_or(|id| row.account_id == Some(id))
+}
+
+// The single current row (per symbol/group/barca/account) the change refers to; fields left
+// out of the change match anything, so they must be given when the symbol has several rows
+pub fn find_holding<'a>(
+    rows: &'a [WalletAllocation],
+    change: &HoldingChange,
+) -> Result<&'a WalletAllocation, EditError> {
+    let matches: Vec<&WalletAllocation> = rows.iter().filter(|r| same_key(r, change)).collect();
+    match matches.as_slice() {
+        [] => Err(EditError::NotFound(format!(
+            "no current holding of {} matches",
+            change.symbol
+        ))),
+        [row] => Ok(row),
+        many => Err(EditError::Invalid(format!(
+            "{} matches several holdings, pass group_name, barca or account_id: {}",
+            change.symbol,
+            many.iter()
+                .map(|r| describe(r))
+                .collect::<Vec<_>>()
+                .join("; ")
+        ))),
+    }
+}
+
+fn audited(mut row: WalletAllocation, change: &HoldingChange, event: &str) -> WalletAllocation {
+    row.id = None;
+    row.created_at = None;
+    row.import_batch_id = None;
+    row.author = Some(change.author.clone());
+    row.reason = Some(change.reason.clone());
+    row.event = Some(event.to_string());
+    row
+}
+
+fn same_holding(a: &WalletAllocation, b: &WalletAllocation) -> bool {
+    a.symbol == b.symbol
+        && a.group_name == b.group_name
+        && a.barca == b.barca
+        && a.account_id == b.account_id
+}
+
+// Notes used to carry the custody location; drop them when the account changes
+fn rehomed(mut row: WalletAllocation, account_id: Option<i64>) -> WalletAllocation {
+    if row.account_id != account_id {
+        row.account_id = account_id;
+        row.notes = None;
+    }
+    row
+}
+
+// Row to append for a new holding; the same key must not be held already
+pub fn added_row(
+    rows: &[WalletAllocation],
+    change: &HoldingChange,
+) -> Result<WalletAllocation, EditError> {
+    let (Some(group), Some(barca)) = (&change.group_name, &change.barca) else {
+        return Err(EditError::Invalid(
+            "group_name and barca are required for a new holding".to_string(),
+        ));
+    };
+    if let Some(existing) = rows.iter().find(|r| {
+        r.symbol == change.symbol
+            && r.group_name.as_ref() == Some(group)
+            && r.barca.as_ref() == Some(barca)
+            && r.account_id == change.account_id
+            && (r.current_quantity.unwrap_or(0.0) != 0.0 || r.target_percent.unwrap_or(0.0) != 0.0)
+    }) {
+        return Err(EditError::Conflict(format!(
+            "{} is already held, update it instead",
+            describe(existing)
+        )));
+    }
+    let row = WalletAllocation {
+        id: None,
+        symbol: change.symbol.clone(),
+        group_name: Some(group.clone()),
+        barca: Some(barca.clone()),
+        target_percent: Some(change.target_percent.unwrap_or(0.0)),
+        current_quantity: Some(change.current_quantity.unwrap_or(0.0)),
+        last_price: change.last_price,
+        notes: trimmed(&change.notes),
+        created_at: None,
+        account_id: change.account_id,
+        author: None,
+        reason: None,
+        event: None,
+        import_batch_id: None,
+    };
+    Ok(audited(row, change, EVENT_ADD))
+}
+
+// Current row with the changed quantity, target, price or notes
+pub fn updated_row(
+    current: &WalletAllocation,
+    change: &HoldingChange,
+) -> Result<WalletAllocation, EditError> {
+    if change.current_quantity.is_none()
+        && change.target_percent.is_none()
+        && change.last_price.is_none()
+        && change.notes.is_none()
+    {
+        return Err(EditError::Invalid(
+            "nothing to change: pass current_quantity, target_percent, last_price or notes"
+                .to_string(),
+        ));
+    }
+    let mut row = current.clone();
+    if let Some(q) = change.current_quantity {
+        row.current_quantity = Some(q);
+    }
+    if let Some(t) = change.target_percent {
+        row.target_percent = Some(t);
+    }
+    if let Some(p) = change.last_price {
+        row.last_price = Some(p);
+    }
+    if change.notes.is_some() {
+        row.notes = trimmed(&change.notes);
+    }
+    Ok(audited(row, change, EVENT_UPDATE))
+}
+
+// Tombstone closing the holding (quantity and target zeroed for older readers)
+fn tombstone(current: &WalletAllocation, change: &HoldingChange, event: &str) -> WalletAllocation {
+    let mut row = current.clone();
+    row.current_quantity = Some(0.0);
+    row.target_percent = Some(0.0);
+    audited(row, change, event)
+}
+
+pub fn retired_row(current: &WalletAllocation, change: &HoldingChange) -> WalletAllocation {
+    tombstone(current, change, EVENT_RETIRE)
+}
+
+// Rows appended to move a holding to another group, barca or account: the destination
+// (added to an existing holding there, keeping its target unless one is given) and the
+// tombstone of the source
+pub fn moved_rows(
+    rows: &[WalletAllocation],
+    current: &WalletAllocation,
+    change: &HoldingChange,
+) -> Result<Vec<WalletAllocation>, EditError> {
+    let mut dest = rehomed(current.clone(), change.to_account_id.or(current.account_id));
+    if let Some(g) = &change.to_group_name {
+        dest.group_name = Some(g.clone());
+    }
+    if let Some(b) = &change.to_barca {
+        dest.barca = Some(b.clone());
+    }
+    if same_holding(&dest, current) {
+        return Err(EditError::Invalid(
+            "pass to_group_name, to_barca or to_account_id to move the holding".to_string(),
+        ));
+    }
+    let qty = current.current_quantity.unwrap_or(0.0);
+    if let Some(existing) = rows.iter().find(|r| same_holding(r, &dest)) {
+        dest = existing.clone();
+        dest.current_quantity = Some(existing.current_quantity.unwrap_or(0.0) + qty);
+    }
+    if let Some(t) = change.target_percent {
+        dest.target_percent = Some(t);
+    }
+    Ok(vec![
+        audited(dest, change, EVENT_MOVE_IN),
+        tombstone(current, change, EVENT_MOVE_OUT),
+    ])
+}
+
+// Rows appended to merge every account's holding of a symbol (within one group and barca)
+// into `to_account_id`: the summed holding there and tombstones for the other accounts
+pub fn merged_rows(
+    rows: &[WalletAllocation],
+    change: &HoldingChange,
+) -> Result<Vec<WalletAllocation>, EditError> {
+    let Some(target) = change.to_account_id else {
+        return Err(EditError::Invalid(
+            "to_account_id or to_account is required to merge".to_string(),
+        ));
+    };
+    let holdings: Vec<&WalletAllocation> = rows
+        .iter()
+        .filter(|r| {
+            r.symbol == change.symbol
+                && change
+                    .group_name
+                    .as_ref()
+                    .is_none_or(|g| r.group_name.as_ref() == Some(g))
+                && change
+                    .barca
+                    .as_ref()
+                    .is_none_or(|b| r.barca.as_ref() == Some(b))
+        })
+        .collect();
+    let Some(first) = holdings.first() else {
+        return Err(EditError::NotFound(format!(
+            "no current holding of {} matches",
+            change.symbol
+        )));
+    };
+    if holdings
+        .iter()
+        .any(|r| r.group_name != first.group_name || r.barca != first.barca)
+    {
+        return Err(EditError::Invalid(format!(
+            "{} is held in several groups or barcas, pass group_name and barca",
+            change.symbol
+        )));
+    }
+    let sources: Vec<&WalletAllocation> = holdings
+        .iter()
+        .copied()
+        .filter(|r| r.account_id != Some(target))
+        .collect();
+    if sources.is_empty() {
+        return Err(EditError::Invalid(format!(
+            "{} is only held at account {}, nothing to merge",
+            change.symbol, target
+        )));
+    }
+    let kept = holdings
+        .iter()
+        .find(|r| r.account_id == Some(target))
+        .copied()
+        .unwrap_or(first);
+    let mut merged = rehomed(kept.clone(), Some(target));
+    merged.current_quantity = Some(
+        holdings
+            .iter()
+            .map(|r| r.current_quantity.unwrap_or(0.0))
+            .sum(),
+    );
+    // Targets are per symbol/group/barca, repeated on every account's row
+    merged.target_percent = holdings
+        .iter()
+        .filter_map(|r| r.target_percent)
+        .reduce(f64::max);
+    let mut out = vec![audited(merged, change, EVENT_MERGE_IN)];
+    out.extend(
+        sources
+            .into_iter()
+            .map(|r| tombstone(r, change, EVENT_MERGE_OUT)),
+    );
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(symbol: &str, group: &str, account: Option<i64>, qty: f64) -> WalletAllocation {
+        WalletAllocation {
+            id: Some(1),
+            symbol: symbol.to_string(),
+            group_name: Some(group.to_string()),
+            barca: Some("Core".to_string()),
+            target_percent: Some(10.0),
+            current_quantity: Some(qty),
+            last_price: None,
+            notes: None,
+            created_at: Some("2025-01-01T00:00:00Z".to_string()),
+            account_id: account,
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        }
+    }
+
+    fn change(symbol: &str) -> HoldingChange {
+        HoldingChange {
+            symbol: symbol.to_string(),
+            group_name: None,
+            barca: None,
+            account_id: None,
+            account: None,
+            current_quantity: None,
+            target_percent: None,
+            last_price: None,
+            notes: None,
+            author: "ana".to_string(),
+            reason: "rebalance".to_string(),
+            to_group_name: None,
+            to_barca: None,
+            to_account_id: None,
+            to_account: None,
+        }
+    }
+
+    #[test]
+    fn update_needs_an_unambiguous_holding() {
+        let rows = vec![
+            row("BTC", "Hodl", Some(1), 1.0),
+            row("BTC", "Hodl", Some(2), 0.5),
+        ];
+        let mut c = change(" btc ");
+        c.current_quantity = Some(0.8);
+        validate_change(&mut c).unwrap();
+        assert!(matches!(
+            find_holding(&rows, &c),
+            Err(EditError::Invalid(_))
+        ));
+        c.account_id = Some(2);
+        let current = find_holding(&rows, &c).unwrap();
+        let next = updated_row(current, &c).unwrap();
+        assert_eq!(next.current_quantity, Some(0.8));
+        assert_eq!(next.target_percent, Some(10.0));
+        assert_eq!(next.account_id, Some(2));
+        assert_eq!(next.author.as_deref(), Some("ana"));
+        assert!(next.id.is_none() && next.created_at.is_none());
+
+        let retired = retired_row(current, &c);
+        assert_eq!(retired.current_quantity, Some(0.0));
+        assert_eq!(retired.target_percent, Some(0.0));
+    }
+
+    #[test]
+    fn add_requires_audit_fields_and_a_new_key() {
+        let rows = vec![row("ETH", "Hodl", None, 2.0)];
+        let mut c = change("eth");
+        c.author = " ".to_string();
+        assert!(validate_change(&mut c).is_err());
+        let mut c = change("eth");
+        c.target_percent = Some(120.0);
+        assert!(validate_change(&mut c).is_err());
+
+        let mut c = change("eth");
+        c.group_name = Some("Hodl".to_string());
+        c.barca = Some("Core".to_string());
+        c.current_quantity = Some(1.0);
+        validate_change(&mut c).unwrap();
+        assert!(matches!(added_row(&rows, &c), Err(EditError::Conflict(_))));
+        c.account_id = Some(3);
+        let added = added_row(&rows, &c).unwrap();
+        assert_eq!(added.current_quantity, Some(1.0));
+        assert_eq!(added.target_percent, Some(0.0));
+        assert_eq!(added.reason.as_deref(), Some("rebalance"));
+    }
+
+    #[test]
+    fn move_and_merge_append_tombstones() {
+        let rows = vec![
+            row("BTC", "Hodl", Some(1), 1.0),
+            row("BTC", "Hodl", Some(2), 0.5),
+            row("BTC", "Trading", Some(1), 0.2),
+        ];
+        let mut c = change("BTC");
+        c.group_name = Some("Trading".to_string());
+        c.to_group_name = Some("Hodl".to_string());
+        validate_change(&mut c).unwrap();
+        let current = find_holding(&rows, &c).unwrap();
+        let moved = moved_rows(&rows, current, &c).unwrap();
+        assert_eq!(moved[0].group_name.as_deref(), Some("Hodl"));
+        assert_eq!(moved[0].current_quantity, Some(1.2));
+        assert_eq!(moved[0].event.as_deref(), Some(EVENT_MOVE_IN));
+        assert_eq!(moved[1].group_name.as_deref(), Some("Trading"));
+        assert_eq!(moved[1].event.as_deref(), Some(EVENT_MOVE_OUT));
+
+        let mut c = change("BTC");
+        c.group_name = Some("Hodl".to_string());
+        c.to_account_id = Some(2);
+        let merged = merged_rows(&rows, &c).unwrap();
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].account_id, Some(2));
+        assert_eq!(merged[0].current_quantity, Some(1.5));
+        assert_eq!(merged[0].target_percent, Some(10.0));
+        assert_eq!(merged[1].account_id, Some(1));
+        assert_eq!(merged[1].event.as_deref(), Some(EVENT_MERGE_OUT));
+        c.group_name = None;
+        assert!(matches!(merged_rows(&rows, &c), Err(EditError::Invalid(_))));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::domain::models::{Account, WalletAllocation};
+use crate::usecases::ledger::UNSPECIFIED_VENUE;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+// Quantity changes smaller than this are treated as unchanged
+const EPSILON: f64 = 1e-12;
+
+type HoldingKey = (String, String, String, String);
+
+// A holding counts while it has a quantity or a target (older ledgers retired holdings by
+// zeroing them; tombstoned holdings are already left out of the rows)
+pub fn is_active(row: &WalletAllocation) -> bool {
+    row.current_quantity.unwrap_or(0.0).abs() > EPSILON || row.target_percent.unwrap_or(0.0) != 0.0
+}
+
+// Custody account name of a row: the linked account, else the location in notes
+pub fn account_label(row: &WalletAllocation, names: &HashMap<i64, String>) -> String {
+    row.account_id
+        .and_then(|id| names.get(&id).cloned())
+        .or_else(|| {
+            row.notes
+                .as_deref()
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| UNSPECIFIED_VENUE.to_string())
+}
+
+fn account_names(accounts: &[Account]) -> HashMap<i64, String> {
+    accounts
+        .iter()
+        .filter_map(|a| a.id.map(|id| (id, a.name.clone())))
+        .collect()
+}
+
+fn keyed<'a>(
+    rows: &'a [WalletAllocation],
+    names: &HashMap<i64, String>,
+) -> BTreeMap<HoldingKey, &'a WalletAllocation> {
+    rows.iter()
+        .filter(|r| is_active(r))
+        .map(|r| {
+            let key = (
+                r.symbol.clone(),
+                r.group_name.clone().unwrap_or_default(),
+                r.barca.clone().unwrap_or_default(),
+                account_label(r, names),
+            );
+            (key, r)
+        })
+        .collect()
+}
+
+fn holding_json(key: &HoldingKey, row: &WalletAllocation) -> Value {
+    json!({
+        "symbol": key.0,
+        "group": key.1,
+        "barca": key.2,
+        "account": key.3,
+        "account_id": row.account_id,
+        "current_quantity": row.current_quantity.unwrap_or(0.0),
+        "target_percent": row.target_percent.unwrap_or(0.0),
+        "last_price": row.last_price,
+        "updated_at": row.created_at,
+        "author": row.author,
+        "reason": row.reason,
+        "event": row.event,
+    })
+}
+
+// Active holdings of a point-in-time state plus quantity totals per symbol
+pub fn wallet_state(rows: &[WalletAllocation], accounts: &[Account]) -> Value {
+    let names = account_names(accounts);
+    let holdings = keyed(rows, &names);
+    let mut per_symbol: BTreeMap<&str, f64> = BTreeMap::new();
+    for (key, row) in &holdings {
+        *per_symbol.entry(key.0.as_str()).or_insert(0.0) += row.current_quantity.unwrap_or(0.0);
+    }
+    json!({
+        "holdings": holdings.iter().map(|(k, r)| holding_json(k, r)).collect::<Vec<_>>(),
+        "per_symbol_quantity": per_symbol,
+    })
+}
+
+// Holdings added, retired and changed between two point-in-time states, and the net
+// quantity change per symbol
+pub fn diff_wallets(
+    before: &[WalletAllocation],
+    after: &[WalletAllocation],
+    accounts: &[Account],
+) -> Value {
+    let names = account_names(accounts);
+    let old = keyed(before, &names);
+    let new = keyed(after, &names);
+    let (mut added, mut retired, mut changed) = (Vec::new(), Vec::new(), Vec::new());
+    let mut unchanged = 0usize;
+    let mut per_symbol: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+
+    for (key, row) in &old {
+        per_symbol.entry(key.0.clone()).or_default().0 += row.current_quantity.unwrap_or(0.0);
+        if !new.contains_key(key) {
+            retired.push(holding_json(key, row));
+        }
+    }
+    for (key, row) in &new {
+        let qty = row.current_quantity.unwrap_or(0.0);
+        let target = row.target_percent.unwrap_or(0.0);
+        per_symbol.entry(key.0.clone()).or_default().1 += qty;
+        let Some(prev) = old.get(key) else {
+            added.push(holding_json(key, row));
+            continue;
+        };
+        let prev_qty = prev.current_quantity.unwrap_or(0.0);
+        let prev_target = prev.target_percent.unwrap_or(0.0);
+        if (qty - prev_qty).abs() <= EPSILON && target == prev_target {
+            unchanged += 1;
+            continue;
+        }
+        changed.push(json!({
+            "symbol": key.0,
+            "group": key.1,
+            "barca": key.2,
+            "account": key.3,
+            "account_id": row.account_id,
+            "quantity_before": prev_qty,
+            "quantity_after": qty,
+            "quantity_delta": qty - prev_qty,
+            "target_before": prev_target,
+            "target_after": target,
+            "updated_at": row.created_at,
+            "author": row.author,
+            "reason": row.reason,
+            "event": row.event,
+        }));
+    }
+
+    let per_symbol: Vec<Value> = per_symbol
+        .into_iter()
+        .filter(|(_, (b, a))| (a - b).abs() > EPSILON)
+        .map(|(symbol, (b, a))| {
+            json!({
+                "symbol": symbol,
+                "quantity_before": b,
+                "quantity_after": a,
+                "quantity_delta": a - b,
+            })
+        })
+        .collect();
+    json!({
+        "summary": {
+            "added": added.len(),
+            "retired": retired.len(),
+            "changed": changed.len(),
+            "unchanged": unchanged,
+        },
+        "added": added,
+        "retired": retired,
+        "changed": changed,
+        "per_symbol": per_symbol,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(symbol: &str, account: i64, qty: f64, target: f64) -> WalletAllocation {
+        WalletAllocation {
+            id: None,
+            symbol: symbol.to_string(),
+            group_name: Some("Hodl".to_string()),
+            barca: Some("Core".to_string()),
+            target_percent: Some(target),
+            current_quantity: Some(qty),
+            last_price: None,
+            notes: None,
+            created_at: None,
+            account_id: Some(account),
+            author: None,
+            reason: None,
+            event: None,
+            import_batch_id: None,
+        }
+    }
+
+    fn accounts() -> Vec<Account> {
+        [(1, "Ledger"), (2, "Binance")]
+            .into_iter()
+            .map(|(id, name)| Account {
+                id: Some(id),
+                name: name.to_string(),
+                account_type: "other".to_string(),
+                address: None,
+                notes: None,
+                created_at: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_reports_added_retired_and_changed_holdings() {
+        let before = vec![
+            row("BTC", 1, 1.0, 40.0),
+            row("BTC", 2, 0.5, 0.0),
+            row("ETH", 2, 3.0, 20.0),
+            row("DOGE", 2, 1000.0, 2.0),
+        ];
+        let after = vec![
+            row("BTC", 1, 1.4, 40.0),
+            row("BTC", 2, 0.0, 0.0),
+            row("ETH", 2, 3.0, 25.0),
+            row("DOGE", 2, 1000.0, 2.0),
+            row("SOL", 1, 10.0, 5.0),
+        ];
+        let d = diff_wallets(&before, &after, &accounts());
+        assert_eq!(
+            d["summary"],
+            json!({"added": 1, "retired": 1, "changed": 2, "unchanged": 1})
+        );
+        assert_eq!(d["added"][0]["symbol"], json!("SOL"));
+        assert_eq!(d["retired"][0]["account"], json!("Binance"));
+        assert_eq!(d["changed"][0]["symbol"], json!("BTC"));
+        assert!((d["changed"][0]["quantity_delta"].as_f64().unwrap() - 0.4).abs() < 1e-9);
+        assert_eq!(d["changed"][1]["target_after"], json!(25.0));
+        // BTC nets 1.5 -> 1.4; ETH and DOGE quantities did not move
+        let per_symbol = d["per_symbol"].as_array().unwrap();
+        assert_eq!(per_symbol.len(), 2);
+        assert!((per_symbol[0]["quantity_delta"].as_f64().unwrap() + 0.1).abs() < 1e-9);
+        assert_eq!(per_symbol[1]["symbol"], json!("SOL"));
+    }
+
+    #[test]
+    fn state_skips_retired_rows_and_totals_per_symbol() {
+        let rows = vec![
+            row("BTC", 1, 1.0, 40.0),
+            row("BTC", 2, 0.5, 0.0),
+            row("ETH", 2, 0.0, 0.0),
+        ];
+        let s = wallet_state(&rows, &accounts());
+        assert_eq!(s["holdings"].as_array().unwrap().len(), 2);
+        assert_eq!(s["per_symbol_quantity"], json!({"BTC": 1.5}));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::domain::models::WalletAllocation;
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::wallet_changes::{
+    EditError, HoldingChange, added_row, find_holding, merged_rows, moved_rows, retired_row,
+    updated_row, validate_change,
+};
+use crate::usecases::wallet_diff::{diff_wallets, wallet_state};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+// Cut-off in the format of wallet_allocations.created_at, so it compares as text
+fn created_at_key(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+type EditResult =
+    Result<Result<WalletAllocation, EditError>, Box<dyn std::error::Error + Send + Sync>>;
+type MultiEditResult =
+    Result<Result<Vec<WalletAllocation>, EditError>, Box<dyn std::error::Error + Send + Sync>>;
+
+pub struct WalletService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl WalletService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    // Current holdings, one row per symbol/group/barca/account
+    pub async fn current(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        Ok(json!({"allocations": rows}))
+    }
+
+    // Every appended row for the symbol, newest first
+    pub async fn history(
+        &self,
+        symbol: &str,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let symbol = symbol.trim().to_ascii_uppercase();
+        let rows = self.repo.fetch_wallet_allocation_history(&symbol).await?;
+        Ok(json!({"symbol": symbol, "rows": rows}))
+    }
+
+    // Holdings as they were at `at`, rebuilt from the append-only ledger
+    pub async fn as_of(
+        &self,
+        at: DateTime<Utc>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let rows = self
+            .repo
+            .fetch_wallet_allocations_as_of(&created_at_key(at))
+            .await?;
+        let accounts = self.repo.fetch_accounts().await?;
+        let mut out = wallet_state(&rows, &accounts);
+        out["as_of"] = json!(created_at_key(at));
+        Ok(out)
+    }
+
+    // What changed in the holdings between two points in time
+    pub async fn diff(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let before = self
+            .repo
+            .fetch_wallet_allocations_as_of(&created_at_key(from))
+            .await?;
+        let after = self
+            .repo
+            .fetch_wallet_allocations_as_of(&created_at_key(to))
+            .await?;
+        let accounts = self.repo.fetch_accounts().await?;
+        let mut out = diff_wallets(&before, &after, &accounts);
+        out["from"] = json!(created_at_key(from));
+        out["to"] = json!(created_at_key(to));
+        Ok(out)
+    }
+
+    pub async fn add(&self, change: HoldingChange) -> EditResult {
+        let change = match self.prepare(change, true).await? {
+            Ok(c) => c,
+            Err(e) => return Ok(Err(e)),
+        };
+        let rows = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        let row = match added_row(&rows, &change) {
+            Ok(r) => r,
+            Err(e) => return Ok(Err(e)),
+        };
+        self.append(row).await
+    }
+
+    pub async fn update(&self, change: HoldingChange) -> EditResult {
+        self.edit(change, updated_row).await
+    }
+
+    pub async fn retire(&self, change: HoldingChange) -> EditResult {
+        self.edit(change, |current, change| Ok(retired_row(current, change)))
+            .await
+    }
+
+    // Move a holding to another group, barca or account (destination row plus source tombstone)
+    pub async fn move_holding(&self, change: HoldingChange) -> MultiEditResult {
+        let change = match self.prepare(change, false).await? {
+            Ok(c) => c,
+            Err(e) => return Ok(Err(e)),
+        };
+        let rows = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        let appended = match find_holding(&rows, &change)
+            .and_then(|current| moved_rows(&rows, current, &change))
+        {
+            Ok(r) => r,
+            Err(e) => return Ok(Err(e)),
+        };
+        self.repo.insert_wallet_allocations(&appended).await?;
+        Ok(Ok(appended))
+    }
+
+    // Merge a symbol's holdings across accounts into `to_account_id`
+    pub async fn merge(&self, change: HoldingChange) -> MultiEditResult {
+        let change = match self.prepare(change, false).await? {
+            Ok(c) => c,
+            Err(e) => return Ok(Err(e)),
+        };
+        let rows = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        let appended = match merged_rows(&rows, &change) {
+            Ok(r) => r,
+            Err(e) => return Ok(Err(e)),
+        };
+        self.repo.insert_wallet_allocations(&appended).await?;
+        Ok(Ok(appended))
+    }
+
+    async fn edit(
+        &self,
+        change: HoldingChange,
+        next: impl Fn(&WalletAllocation, &HoldingChange) -> Result<WalletAllocation, EditError>,
+    ) -> EditResult {
+        let change = match self.prepare(change, false).await? {
+            Ok(c) => c,
+            Err(e) => return Ok(Err(e)),
+        };
+        let rows = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?;
+        let row = match find_holding(&rows, &change).and_then(|current| next(current, &change)) {
+            Ok(r) => r,
+            Err(e) => return Ok(Err(e)),
+        };
+        self.append(row).await
+    }
+
+    // Validate the change and resolve account names to ids
+    async fn prepare(
+        &self,
+        mut change: HoldingChange,
+        create_account: bool,
+    ) -> Result<Result<HoldingChange, EditError>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Err(e) = validate_change(&mut change) {
+            return Ok(Err(e));
+        }
+        // Holdings are looked up by existing accounts; destinations and new holdings may
+        // name an account that does not exist yet
+        match self
+            .resolve_account(change.account_id, change.account.as_deref(), create_account)
+            .await?
+        {
+            Ok(id) => change.account_id = id,
+            Err(e) => return Ok(Err(e)),
+        }
+        match self
+            .resolve_account(change.to_account_id, change.to_account.as_deref(), true)
+            .await?
+        {
+            Ok(id) => change.to_account_id = id,
+            Err(e) => return Ok(Err(e)),
+        }
+        Ok(Ok(change))
+    }
+
+    async fn resolve_account(
+        &self,
+        id: Option<i64>,
+        name: Option<&str>,
+        create: bool,
+    ) -> Result<Result<Option<i64>, EditError>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(id) = id {
+            if self.repo.fetch_account(id).await?.is_none() {
+                return Ok(Err(EditError::Invalid(format!("unknown account {}", id))));
+            }
+            return Ok(Ok(Some(id)));
+        }
+        let Some(name) = name else {
+            return Ok(Ok(None));
+        };
+        let found = self
+            .repo
+            .fetch_accounts()
+            .await?
+            .into_iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+            .and_then(|a| a.id);
+        Ok(match (found, create) {
+            (Some(id), _) => Ok(Some(id)),
+            (None, true) => Ok(Some(self.repo.ensure_account(name).await?)),
+            (None, false) => Err(EditError::NotFound(format!("unknown account '{}'", name))),
+        })
+    }
+
+    // Append the row and return the resulting current row
+    async fn append(&self, row: WalletAllocation) -> EditResult {
+        self.repo.insert_wallet_allocation(&row).await?;
+        let current = self
+            .repo
+            .fetch_current_wallet_allocations_by_account()
+            .await?
+            .into_iter()
+            .find(|r| {
+                r.symbol == row.symbol
+                    && r.group_name == row.group_name
+                    && r.barca == row.barca
+                    && (r.account_id == row.account_id
+                        || row.account_id.is_none() && r.notes == row.notes)
+            });
+        Ok(Ok(current.unwrap_or(row)))
+    }
+}
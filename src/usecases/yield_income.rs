@@ -0,0 +1,281 @@
+use crate::domain::models::{Transaction, YieldPosition};
+use crate::usecases::ledger::{UNASSIGNED, derive_holdings};
+use crate::usecases::tax_br::RateTable;
+use crate::usecases::timeseries::parse_ts;
+use chrono::{DateTime, Datelike, Utc};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 86_400.0;
+
+fn is_income(tx: &Transaction) -> bool {
+    tx.kind == "income"
+}
+
+// Whether `tx` touches the position (venue '' = every venue of the symbol)
+fn matches(tx: &Transaction, symbol: &str, venue: &str) -> bool {
+    tx.symbol == symbol && (venue.is_empty() || tx.venue.as_deref() == Some(venue))
+}
+
+// Quantity change of the position caused by one transaction
+fn delta(tx: &Transaction, symbol: &str, venue: &str) -> f64 {
+    derive_holdings(std::slice::from_ref(tx))
+        .positions
+        .iter()
+        .filter(|((s, v), _)| s == symbol && (venue.is_empty() || v == venue))
+        .map(|(_, q)| q)
+        .sum()
+}
+
+// Time-weighted average quantity held over [from, to] and the quantity at `to`
+pub fn average_quantity(
+    txs: &[Transaction],
+    symbol: &str,
+    venue: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> (f64, f64) {
+    let span = (to - from).num_seconds() as f64;
+    let mut qty = 0.0;
+    let mut area = 0.0;
+    let mut cursor = from;
+    for tx in txs {
+        let Some(t) = parse_ts(&tx.timestamp) else {
+            continue;
+        };
+        if t > to {
+            break;
+        }
+        if t > cursor {
+            area += qty * (t - cursor).num_seconds() as f64;
+            cursor = t;
+        }
+        qty += delta(tx, symbol, venue);
+    }
+    area += qty * (to - cursor).num_seconds().max(0) as f64;
+    let avg = if span > 0.0 { area / span } else { qty };
+    (avg, qty)
+}
+
+// Realized vs expected yield of one configured position over [from, to]
+pub fn position_yield(
+    txs: &[Transaction],
+    pos: &YieldPosition,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Value {
+    let in_window: Vec<&Transaction> = txs
+        .iter()
+        .filter(|t| is_income(t) && matches(t, &pos.symbol, &pos.venue))
+        .filter(|t| parse_ts(&t.timestamp).is_some_and(|ts| ts >= from && ts <= to))
+        .collect();
+    let income_qty: f64 = in_window.iter().map(|t| t.quantity).sum();
+    let income_usd: f64 = in_window
+        .iter()
+        .map(|t| t.quantity * t.price.unwrap_or(0.0))
+        .sum();
+    let (avg_qty, qty) = average_quantity(txs, &pos.symbol, &pos.venue, from, to);
+    let years = (to - from).num_seconds() as f64 / SECONDS_PER_YEAR;
+    // Income received in the window compounds into the average, as it would on-chain
+    let principal = avg_qty.max(0.0);
+    let realized_apy =
+        (principal > 0.0 && years > 0.0).then(|| income_qty / principal / years * 100.0);
+    let expected_income = principal * pos.expected_apy / 100.0 * years;
+    json!({
+        "symbol": pos.symbol,
+        "venue": pos.venue,
+        "expected_apy": pos.expected_apy,
+        "realized_apy": realized_apy,
+        "average_quantity": avg_qty,
+        "quantity": qty,
+        "income_events": in_window.len(),
+        "income_quantity": income_qty,
+        "income_usd": income_usd,
+        "expected_income_quantity": expected_income,
+        "income_vs_expected_percent": (expected_income > 0.0).then(|| income_qty / expected_income * 100.0),
+    })
+}
+
+// Income received per month, in USD (fair value at receipt) and BRL, split by income type
+pub fn monthly_income(
+    txs: &[Transaction],
+    rates: &RateTable,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<Value> {
+    #[derive(Default)]
+    struct Month {
+        usd: f64,
+        brl: f64,
+        missing_brl: bool,
+        by_type: BTreeMap<String, f64>,
+        by_symbol: BTreeMap<String, f64>,
+    }
+    let mut months: BTreeMap<String, Month> = BTreeMap::new();
+    for tx in txs.iter().filter(|t| is_income(t)) {
+        let Some(t) = parse_ts(&tx.timestamp) else {
+            continue;
+        };
+        if t < from || t > to {
+            continue;
+        }
+        let m = months
+            .entry(format!("{}-{:02}", t.year(), t.month()))
+            .or_default();
+        let usd = tx.quantity * tx.price.unwrap_or(0.0);
+        let quote = tx.quote_currency.as_deref().unwrap_or("USD");
+        m.usd += usd;
+        match rates.to_brl(&tx.timestamp, usd, quote) {
+            Ok(v) => m.brl += v,
+            Err(_) => m.missing_brl = true,
+        }
+        let kind = tx
+            .income_type
+            .clone()
+            .unwrap_or_else(|| "other".to_string());
+        *m.by_type.entry(kind).or_insert(0.0) += usd;
+        *m.by_symbol.entry(tx.symbol.clone()).or_insert(0.0) += usd;
+    }
+    months
+        .into_iter()
+        .map(|(month, m)| {
+            json!({
+                "month": month,
+                "income_usd": m.usd,
+                "income_brl": (!m.missing_brl).then_some(m.brl),
+                "by_type_usd": m.by_type,
+                "by_symbol_usd": m.by_symbol,
+            })
+        })
+        .collect()
+}
+
+// Projected annual income per barca from current quantities, expected APY and latest prices
+pub fn projected_annual_income(
+    positions: &[YieldPosition],
+    txs: &[Transaction],
+    prices: &HashMap<String, f64>,
+    barca_of: &HashMap<String, String>,
+) -> Value {
+    let holdings = derive_holdings(txs);
+    let mut per_barca: BTreeMap<String, (f64, Vec<Value>)> = BTreeMap::new();
+    for pos in positions {
+        let qty: f64 = holdings
+            .positions
+            .iter()
+            .filter(|((s, v), _)| *s == pos.symbol && (pos.venue.is_empty() || *v == pos.venue))
+            .map(|(_, q)| q.max(0.0))
+            .sum();
+        let price = prices.get(&pos.symbol).copied();
+        let annual_qty = qty * pos.expected_apy / 100.0;
+        let annual_usd = price.map(|p| annual_qty * p);
+        let barca = pos
+            .barca
+            .clone()
+            .or_else(|| barca_of.get(&pos.symbol).cloned())
+            .unwrap_or_else(|| UNASSIGNED.to_string());
+        let entry = per_barca.entry(barca).or_default();
+        entry.0 += annual_usd.unwrap_or(0.0);
+        entry.1.push(json!({
+            "symbol": pos.symbol,
+            "venue": pos.venue,
+            "quantity": qty,
+            "expected_apy": pos.expected_apy,
+            "price": price,
+            "annual_income_quantity": annual_qty,
+            "annual_income_usd": annual_usd,
+        }));
+    }
+    let total: f64 = per_barca.values().map(|(v, _)| v).sum();
+    let barcas: Vec<Value> = per_barca
+        .into_iter()
+        .map(|(barca, (usd, positions))| {
+            json!({"barca": barca, "annual_income_usd": usd, "positions": positions})
+        })
+        .collect();
+    json!({"total_annual_income_usd": total, "per_barca": barcas})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(kind: &str, ts: &str, qty: f64, price: f64) -> Transaction {
+        Transaction {
+            id: None,
+            timestamp: ts.to_string(),
+            kind: kind.to_string(),
+            symbol: "SOL".to_string(),
+            quantity: qty,
+            price: Some(price),
+            quote_currency: Some("USD".to_string()),
+            fee: None,
+            fee_symbol: None,
+            venue: Some("ledger".to_string()),
+            to_venue: None,
+            group_name: None,
+            barca: None,
+            external_id: None,
+            notes: None,
+            created_at: None,
+            income_type: (kind == "income").then(|| "staking".to_string()),
+            account_id: None,
+            to_account_id: None,
+        }
+    }
+
+    fn position() -> YieldPosition {
+        YieldPosition {
+            id: None,
+            symbol: "SOL".to_string(),
+            venue: "ledger".to_string(),
+            barca: None,
+            expected_apy: 7.0,
+            notes: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn time_weighted_quantity_and_realized_apy() {
+        let txs = vec![
+            tx("deposit", "2025-01-01T00:00:00Z", 100.0, 100.0),
+            tx("deposit", "2025-07-02T12:00:00Z", 100.0, 100.0),
+        ];
+        let from = parse_ts("2025-01-01T00:00:00Z").unwrap();
+        let to = parse_ts("2026-01-01T00:00:00Z").unwrap();
+        let (avg, qty) = average_quantity(&txs, "SOL", "ledger", from, to);
+        assert!((avg - 150.0).abs() < 0.5);
+        assert_eq!(qty, 200.0);
+
+        let mut txs = vec![tx("deposit", "2025-01-01T00:00:00Z", 100.0, 100.0)];
+        txs.push(tx("income", "2025-12-31T23:59:59Z", 6.0, 150.0));
+        let y = position_yield(&txs, &position(), from, to);
+        assert!((y["realized_apy"].as_f64().unwrap() - 6.0).abs() < 0.01);
+        assert!((y["income_usd"].as_f64().unwrap() - 900.0).abs() < 1e-9);
+        assert!((y["expected_income_quantity"].as_f64().unwrap() - 7.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn monthly_income_and_projection_per_barca() {
+        let txs = vec![
+            tx("deposit", "2025-01-01T00:00:00Z", 100.0, 100.0),
+            tx("income", "2025-02-01T00:00:00Z", 1.0, 100.0),
+            tx("income", "2025-02-15T00:00:00Z", 1.0, 120.0),
+        ];
+        let from = parse_ts("2025-01-01T00:00:00Z").unwrap();
+        let to = parse_ts("2025-12-31T00:00:00Z").unwrap();
+        let months = monthly_income(&txs, &RateTable::default(), from, to);
+        assert_eq!(months.len(), 1);
+        assert!((months[0]["income_usd"].as_f64().unwrap() - 220.0).abs() < 1e-9);
+        assert!(months[0]["income_brl"].is_null());
+        assert!((months[0]["by_type_usd"]["staking"].as_f64().unwrap() - 220.0).abs() < 1e-9);
+
+        let prices = HashMap::from([("SOL".to_string(), 200.0)]);
+        let barcas = HashMap::from([("SOL".to_string(), "RendaPassiva".to_string())]);
+        let p = projected_annual_income(&[position()], &txs, &prices, &barcas);
+        assert_eq!(p["per_barca"][0]["barca"], json!("RendaPassiva"));
+        // 102 SOL * 7% * $200
+        assert!((p["total_annual_income_usd"].as_f64().unwrap() - 1428.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::domain::models::YieldPosition;
+use crate::domain::repository::HistoryRepo;
+use crate::usecases::tax_br::RateTable;
+use crate::usecases::timeseries::parse_ts;
+use crate::usecases::yield_income::{monthly_income, position_yield, projected_annual_income};
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct YieldService {
+    pub repo: Arc<dyn HistoryRepo>,
+}
+
+impl YieldService {
+    pub fn new(repo: Arc<dyn HistoryRepo>) -> Self {
+        Self { repo }
+    }
+
+    pub async fn positions(&self) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(json!({"positions": self.repo.fetch_yield_positions().await?}))
+    }
+
+    pub async fn set_position(
+        &self,
+        mut pos: YieldPosition,
+    ) -> Result<Result<YieldPosition, String>, Box<dyn std::error::Error + Send + Sync>> {
+        pos.symbol = pos.symbol.trim().to_ascii_uppercase();
+        pos.venue = pos.venue.trim().to_string();
+        if pos.symbol.is_empty() {
+            return Ok(Err("symbol is required".to_string()));
+        }
+        if !(pos.expected_apy.is_finite() && pos.expected_apy >= 0.0) {
+            return Ok(Err(
+                "expected_apy must be a non-negative percentage".to_string()
+            ));
+        }
+        self.repo.upsert_yield_position(&pos).await?;
+        Ok(Ok(pos))
+    }
+
+    pub async fn remove_position(
+        &self,
+        symbol: &str,
+        venue: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let deleted = self
+            .repo
+            .delete_yield_position(&symbol.trim().to_ascii_uppercase(), venue.trim())
+            .await?;
+        Ok(deleted)
+    }
+
+    // Realized vs expected yield per position, monthly income and projected annual income per barca
+    pub async fn report(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let txs = self.repo.fetch_transactions(None, None, None).await?;
+        let positions = self.repo.fetch_yield_positions().await?;
+        let rates = RateTable::from_rows(&self.repo.fetch_fx_rates(None).await?);
+
+        // latest stored price per symbol, falling back to the latest priced ledger row
+        let mut prices: HashMap<String, (String, f64)> = HashMap::new();
+        for tx in txs.iter().filter(|t| t.price.is_some_and(|p| p > 0.0)) {
+            prices.insert(
+                tx.symbol.clone(),
+                (tx.timestamp.clone(), tx.price.unwrap_or(0.0)),
+            );
+        }
+        for row in self.repo.fetch_assets(None, None).await? {
+            let (Some(price), Some(t)) = (row.price, parse_ts(&row.timestamp)) else {
+                continue;
+            };
+            let newer = prices
+                .get(&row.symbol)
+                .and_then(|(ts, _)| parse_ts(ts))
+                .is_none_or(|existing| t >= existing);
+            if newer && price > 0.0 {
+                prices.insert(row.symbol.clone(), (row.timestamp.clone(), price));
+            }
+        }
+        let prices: HashMap<String, f64> = prices.into_iter().map(|(s, (_, p))| (s, p)).collect();
+
+        let mut barca_of: HashMap<String, String> = HashMap::new();
+        for a in self.repo.fetch_current_wallet_allocations().await? {
+            if let Some(b) = a.barca.filter(|b| !b.is_empty()) {
+                barca_of.entry(a.symbol).or_insert(b);
+            }
+        }
+
+        let per_position: Vec<Value> = positions
+            .iter()
+            .map(|p| position_yield(&txs, p, from, to))
+            .collect();
+        Ok(json!({
+            "from": from.to_rfc3339(),
+            "to": to.to_rfc3339(),
+            "positions": per_position,
+            "monthly_income": monthly_income(&txs, &rates, from, to),
+            "projected_annual": projected_annual_income(&positions, &txs, &prices, &barca_of),
+        }))
+    }
+}
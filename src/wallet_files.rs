@@ -0,0 +1,479 @@
+// Wallet data as plain tables in the layouts we import (wallet_allocations.csv,
+// wallet_barca.csv), read from and written to CSV in any dialect, JSON (an array of objects) or
+// XLSX (first sheet), so an export can be edited in a spreadsheet and imported again.
+// Self-contained apart from csv_dialect so the export binary can include it as well.
+use crate::csv_dialect::{CsvDialect, record_line, strip_bom};
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const XLSX_MAGIC: &[u8] = b"PK\x03\x04";
+
+pub const HOLDING_COLUMNS: [&str; 8] = [
+    "symbol",
+    "group",
+    "barca",
+    "target_percent",
+    "current_quantity",
+    "last_price",
+    "comments",
+    "account",
+];
+pub const TARGET_COLUMNS: [&str; 3] = ["market", "group", "target_percent"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Csv,
+    Json,
+    Xlsx,
+}
+
+impl FileFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(FileFormat::Csv),
+            "json" => Ok(FileFormat::Json),
+            "xlsx" => Ok(FileFormat::Xlsx),
+            other => Err(format!("unknown format '{}' (csv, json or xlsx)", other)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileFormat::Csv => "csv",
+            FileFormat::Json => "json",
+            FileFormat::Xlsx => "xlsx",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FileFormat::Csv => "text/csv; charset=utf-8",
+            FileFormat::Json => "application/json",
+            FileFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Text(String),
+    Number(f64),
+    Empty,
+}
+
+impl Cell {
+    fn text(s: Option<&str>) -> Self {
+        s.map_or(Cell::Empty, |s| Cell::Text(s.to_string()))
+    }
+
+    fn number(n: Option<f64>) -> Self {
+        n.map_or(Cell::Empty, Cell::Number)
+    }
+
+    fn from_json(v: Option<&Value>) -> Self {
+        match v {
+            Some(Value::Number(n)) => Cell::number(n.as_f64()),
+            Some(Value::String(s)) => Cell::Text(s.clone()),
+            Some(Value::Bool(b)) => Cell::Text(b.to_string()),
+            _ => Cell::Empty,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    // File stem and sheet name
+    pub name: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+// One current holding in the wallet_allocations.csv layout
+#[derive(Debug, Clone, Default)]
+pub struct Holding {
+    pub symbol: String,
+    pub group: Option<String>,
+    pub barca: Option<String>,
+    pub target_percent: Option<f64>,
+    pub current_quantity: Option<f64>,
+    pub last_price: Option<f64>,
+    pub comments: Option<String>,
+    pub account: Option<String>,
+}
+
+pub fn holdings_table(holdings: &[Holding]) -> Table {
+    Table {
+        name: "wallet_allocations".to_string(),
+        columns: HOLDING_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        rows: holdings
+            .iter()
+            .map(|h| {
+                vec![
+                    Cell::Text(h.symbol.clone()),
+                    Cell::text(h.group.as_deref()),
+                    Cell::text(h.barca.as_deref()),
+                    Cell::number(h.target_percent),
+                    Cell::number(h.current_quantity),
+                    Cell::number(h.last_price),
+                    Cell::text(h.comments.as_deref()),
+                    Cell::text(h.account.as_deref()),
+                ]
+            })
+            .collect(),
+    }
+}
+
+// market -> barca -> target_percent, sorted by market and barca
+pub fn targets_table(regimes: &HashMap<String, HashMap<String, f64>>) -> Table {
+    let sorted: BTreeMap<&String, BTreeMap<&String, f64>> = regimes
+        .iter()
+        .map(|(m, t)| (m, t.iter().map(|(g, p)| (g, *p)).collect()))
+        .collect();
+    Table {
+        name: "wallet_barca".to_string(),
+        columns: TARGET_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        rows: sorted
+            .iter()
+            .flat_map(|(market, targets)| {
+                targets.iter().map(|(group, pct)| {
+                    vec![
+                        Cell::Text(market.to_string()),
+                        Cell::Text(group.to_string()),
+                        Cell::Number(*pct),
+                    ]
+                })
+            })
+            .collect(),
+    }
+}
+
+// One level of an allocations report (`asset`, `group` or `barca`). The asset level keeps the
+// holdings columns, so it can be imported back as a wallet file.
+pub fn report_table(report: &Value, level: &str) -> Result<Table, String> {
+    let (key, columns): (&str, &[&str]) = match level {
+        "asset" => (
+            "per_asset",
+            &[
+                "symbol",
+                "group",
+                "barca",
+                "current_quantity",
+                "price",
+                "value",
+                "target_percent",
+                "current_percent",
+                "deviation",
+            ],
+        ),
+        "group" => (
+            "per_group",
+            &[
+                "group",
+                "value",
+                "target_percent",
+                "current_percent",
+                "deviation",
+            ],
+        ),
+        "barca" => (
+            "per_barca",
+            &[
+                "barca",
+                "value",
+                "target_percent",
+                "current_percent",
+                "deviation",
+            ],
+        ),
+        other => return Err(format!("unknown level '{}' (asset, group or barca)", other)),
+    };
+    let items = report
+        .get(key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("report has no {}", key))?;
+    Ok(Table {
+        name: format!("allocation_report_{}", level),
+        columns: columns.iter().map(|c| c.to_string()).collect(),
+        rows: items
+            .iter()
+            .map(|item| {
+                columns
+                    .iter()
+                    .map(|c| Cell::from_json(item.get(*c)))
+                    .collect()
+            })
+            .collect(),
+    })
+}
+
+pub fn render(table: &Table, format: FileFormat, dialect: &CsvDialect) -> Result<Vec<u8>, String> {
+    match format {
+        FileFormat::Csv => render_csv(table, dialect),
+        FileFormat::Json => render_json(table),
+        FileFormat::Xlsx => render_xlsx(table),
+    }
+}
+
+// Numbers in the dialect's decimal separator and without thousands separators, at full
+// precision so they read back unchanged
+fn render_csv(table: &Table, dialect: &CsvDialect) -> Result<Vec<u8>, String> {
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(dialect.delimiter as u8)
+        .from_writer(Vec::new());
+    wtr.write_record(&table.columns)
+        .map_err(|e| e.to_string())?;
+    for row in &table.rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|c| match c {
+                Cell::Text(s) => s.clone(),
+                Cell::Number(n) => n.to_string().replace('.', &dialect.decimal.to_string()),
+                Cell::Empty => String::new(),
+            })
+            .collect();
+        wtr.write_record(&fields).map_err(|e| e.to_string())?;
+    }
+    wtr.into_inner().map_err(|e| e.to_string())
+}
+
+fn render_json(table: &Table) -> Result<Vec<u8>, String> {
+    let rows: Vec<Value> = table
+        .rows
+        .iter()
+        .map(|row| {
+            let obj: Map<String, Value> = table
+                .columns
+                .iter()
+                .zip(row)
+                .map(|(c, cell)| {
+                    let v = match cell {
+                        Cell::Text(s) => Value::String(s.clone()),
+                        Cell::Number(n) => serde_json::json!(n),
+                        Cell::Empty => Value::Null,
+                    };
+                    (c.clone(), v)
+                })
+                .collect();
+            Value::Object(obj)
+        })
+        .collect();
+    serde_json::to_vec_pretty(&rows).map_err(|e| e.to_string())
+}
+
+fn render_xlsx(table: &Table) -> Result<Vec<u8>, String> {
+    use rust_xlsxwriter::{Format, Workbook};
+    let xlsx_err = |e: rust_xlsxwriter::XlsxError| e.to_string();
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(&table.name).map_err(xlsx_err)?;
+    for (col, name) in table.columns.iter().enumerate() {
+        sheet
+            .write_string_with_format(0, col as u16, name, &bold)
+            .map_err(xlsx_err)?;
+    }
+    for (i, row) in table.rows.iter().enumerate() {
+        let r = i as u32 + 1;
+        for (col, cell) in row.iter().enumerate() {
+            match cell {
+                Cell::Text(s) => sheet.write_string(r, col as u16, s).map(|_| ()),
+                Cell::Number(n) => sheet.write_number(r, col as u16, *n).map(|_| ()),
+                Cell::Empty => Ok(()),
+            }
+            .map_err(xlsx_err)?;
+        }
+    }
+    sheet.set_freeze_panes(1, 0).map_err(xlsx_err)?;
+    workbook.save_to_buffer().map_err(xlsx_err)
+}
+
+// Every record of a CSV, JSON or XLSX file, header row included, with its line (the sheet row
+// for XLSX, the 1-based array position for JSON) and the dialect its numbers are written in
+pub fn read_records(content: &[u8]) -> Result<(CsvDialect, Vec<(u64, StringRecord)>), String> {
+    let content = strip_bom(content);
+    if content.starts_with(XLSX_MAGIC) {
+        return Ok((CsvDialect::default(), read_xlsx(content)?));
+    }
+    if content.trim_ascii_start().starts_with(b"[") {
+        return Ok((CsvDialect::default(), read_json(content)?));
+    }
+    let dialect = CsvDialect::from_env(content)?;
+    let mut rdr = dialect
+        .reader_builder()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content);
+    let mut records = Vec::new();
+    for result in rdr.records() {
+        let record = result.map_err(|e| format!("invalid CSV: {}", e))?;
+        records.push((record_line(content, &record), record));
+    }
+    Ok((dialect, records))
+}
+
+fn read_xlsx(content: &[u8]) -> Result<Vec<(u64, StringRecord)>, String> {
+    use calamine::{Reader, Xlsx};
+    let mut workbook =
+        Xlsx::new(std::io::Cursor::new(content)).map_err(|e| format!("invalid XLSX: {}", e))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or("XLSX file has no sheet")?
+        .map_err(|e| format!("invalid XLSX: {}", e))?;
+    let first_row = range.start().map_or(0, |(row, _)| row as u64);
+    Ok(range
+        .rows()
+        .enumerate()
+        .map(|(i, cells)| {
+            let record: StringRecord = cells.iter().map(|c| c.to_string()).collect();
+            (first_row + i as u64 + 1, record)
+        })
+        .collect())
+}
+
+// An array of objects; the header is every key in order of first appearance
+fn read_json(content: &[u8]) -> Result<Vec<(u64, StringRecord)>, String> {
+    let items: Vec<Map<String, Value>> =
+        serde_json::from_slice(content).map_err(|e| format!("invalid JSON: {}", e))?;
+    let mut columns: Vec<&String> = Vec::new();
+    for key in items.iter().flat_map(|o| o.keys()) {
+        if !columns.contains(&key) {
+            columns.push(key);
+        }
+    }
+    let mut records = vec![(0, columns.iter().map(|c| c.as_str()).collect())];
+    for (i, item) in items.iter().enumerate() {
+        let record: StringRecord = columns
+            .iter()
+            .map(|c| match item.get(*c) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            })
+            .collect();
+        records.push((i as u64 + 1, record));
+    }
+    Ok(records)
+}
+
+// wallet_barca rows (market, group, target_percent) from any of the formats; every bad row is
+// reported with its line
+pub fn read_targets(content: &[u8]) -> Result<Vec<(String, String, f64)>, String> {
+    let (dialect, records) = read_records(content)?;
+    let mut records = records.into_iter();
+    let headers: Vec<String> = records
+        .next()
+        .map(|(_, h)| h.iter().map(|c| c.trim().to_lowercase()).collect())
+        .unwrap_or_default();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| format!("missing column '{}'", name))
+    };
+    let (market, group, target) = (
+        column("market")?,
+        column("group")?,
+        column("target_percent")?,
+    );
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (line, record) in records {
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        let field = |i: usize| record.get(i).unwrap_or_default().trim();
+        match dialect.parse_number(field(target)) {
+            Ok(pct) => rows.push((field(market).to_string(), field(group).to_string(), pct)),
+            Err(e) => errors.push(format!("line {}: target_percent: {}", line, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holdings() -> Vec<Holding> {
+        vec![
+            Holding {
+                symbol: "BTC".to_string(),
+                group: Some("Holding".to_string()),
+                barca: Some("Base".to_string()),
+                target_percent: Some(37.89),
+                current_quantity: Some(0.123456789012),
+                last_price: None,
+                comments: Some("cold, \"vault\"".to_string()),
+                account: Some("Ledger".to_string()),
+            },
+            Holding {
+                symbol: "USDT".to_string(),
+                current_quantity: Some(60000.5),
+                ..Holding::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn every_format_reads_back_the_same_cells() {
+        let table = holdings_table(&holdings());
+        let br = CsvDialect::default()
+            .with_overrides(Some(";"), Some(","), None)
+            .unwrap();
+        for (format, dialect) in [
+            (FileFormat::Csv, CsvDialect::default()),
+            (FileFormat::Csv, br),
+            (FileFormat::Json, CsvDialect::default()),
+            (FileFormat::Xlsx, CsvDialect::default()),
+        ] {
+            let bytes = render(&table, format, &dialect).unwrap();
+            let (read_dialect, records) = read_records(&bytes).unwrap();
+            assert_eq!(read_dialect.decimal, dialect.decimal, "{:?}", format);
+            let header: Vec<&str> = records[0].1.iter().collect();
+            let btc = &records[1].1;
+            let cell = |name: &str| {
+                let i = header.iter().position(|h| *h == name).unwrap();
+                btc.get(i).unwrap().to_string()
+            };
+            assert_eq!(cell("comments"), "cold, \"vault\"", "{:?}", format);
+            assert_eq!(cell("account"), "Ledger");
+            assert_eq!(
+                read_dialect
+                    .parse_number(&cell("current_quantity"))
+                    .unwrap(),
+                0.123456789012,
+                "{:?}",
+                format
+            );
+            assert_eq!(cell("last_price"), "");
+            assert_eq!(records.len(), 3);
+        }
+    }
+
+    #[test]
+    fn targets_round_trip_and_report_bad_rows() {
+        let mut regimes = HashMap::new();
+        regimes.insert(
+            "BullMarket".to_string(),
+            HashMap::from([("Base".to_string(), 50.5), ("Caixa".to_string(), 49.5)]),
+        );
+        let table = targets_table(&regimes);
+        let csv = render(&table, FileFormat::Csv, &CsvDialect::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv.clone()).unwrap(),
+            "market,group,target_percent\nBullMarket,Base,50.5\nBullMarket,Caixa,49.5\n"
+        );
+        assert_eq!(read_targets(&csv).unwrap()[1].2, 49.5);
+        let xlsx = render(&table, FileFormat::Xlsx, &CsvDialect::default()).unwrap();
+        assert_eq!(read_targets(&xlsx).unwrap(), read_targets(&csv).unwrap());
+
+        let err = read_targets(b"market;group;target_percent\nBear;Base;cinquenta\n").unwrap_err();
+        assert_eq!(err, "line 2: target_percent: invalid number 'cinquenta'");
+        assert!(read_targets(b"market,target_percent\n").is_err());
+        assert!(report_table(&serde_json::json!({}), "asset").is_err());
+    }
+}